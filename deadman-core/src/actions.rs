@@ -0,0 +1,273 @@
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+use crate::config::Config;
+use crate::script::Trigger;
+use crate::units::UnitAction;
+use crate::workloads::{ContainerAction, VmAction};
+use crate::{lock, network, script, scrub, sessions};
+
+/// Pause between attempts at a failed action.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// What the daemon does when a tethered device is removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Action {
+    /// Lock every session, or those of `lock_users`, using the first lock
+    /// backend that works.
+    #[default]
+    Lock,
+    /// Clear the clipboard and lock GNOME Keyring / KWallet, then lock like
+    /// `Lock`.
+    Scrub,
+    /// Suspend the machine via systemd.
+    Suspend,
+    /// Bring down the interfaces listed in `kill_interfaces`.
+    KillNetwork,
+    /// Terminate remote sessions, ssh logins included, through logind.
+    TerminateRemote,
+    /// Stop, and optionally mask, systemd units.
+    Systemd(UnitAction),
+    /// Pause libvirt domains, and optionally resume them.
+    Vm(VmAction),
+    /// Stop or pause Docker or Podman containers, and optionally start them.
+    Containers(ContainerAction),
+    /// Run an executable by absolute path, as its `[scripts]` entry says.
+    Custom(PathBuf),
+}
+
+impl Action {
+    /// Run the action with the settings it needs from `config`, for
+    /// `trigger`.
+    pub fn run(&self, config: &Config, trigger: &Trigger) -> Result<(), String> {
+        match self {
+            Action::Lock => lock::lock(&config.lock_backends, &config.lock_users),
+            Action::Scrub => {
+                scrub::scrub();
+                lock::lock(&config.lock_backends, &config.lock_users)
+            }
+            Action::Suspend => run_command(Command::new("systemctl").arg("suspend")),
+            Action::KillNetwork => network::kill(&config.kill_interfaces),
+            Action::TerminateRemote => sessions::terminate_remote(),
+            Action::Systemd(units) => units.stop(),
+            Action::Vm(domains) => domains.pause(),
+            Action::Containers(containers) => containers.stop(),
+            Action::Custom(path) => script::run(
+                path,
+                &config.script(path),
+                trigger,
+                &config.audit_dir,
+                config.action_timeout(),
+            ),
+        }
+    }
+
+    /// Run the action, retrying it `action_retries` times and then running
+    /// `fallback_action` if it still fails. `Ok(Some(_))` says the fallback
+    /// stood in; `Err` means nothing that was tried worked.
+    pub fn run_supervised(
+        &self,
+        config: &Config,
+        trigger: &Trigger,
+    ) -> Result<Option<String>, String> {
+        let mut result = self.run(config, trigger);
+        for attempt in 1..=config.action_retries {
+            let Err(err) = &result else { break };
+            warn!(action = %self, attempt, error = %err, "removal action failed; retrying");
+            thread::sleep(RETRY_DELAY);
+            result = self.run(config, trigger);
+        }
+        let Err(err) = result else {
+            return Ok(None);
+        };
+
+        match &config.fallback_action {
+            Some(fallback) if fallback != self => {
+                warn!(action = %self, fallback = %fallback, error = %err, "removal action failed; running the fallback");
+                match fallback.run(config, trigger) {
+                    Ok(()) => Ok(Some(format!("{err}; ran fallback action {fallback}"))),
+                    Err(fallback_err) => Err(format!(
+                        "{err}; fallback action {fallback} failed too: {fallback_err}"
+                    )),
+                }
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// What running the action would do, one step each, without doing it.
+    /// `Err` steps would fail.
+    pub fn dry_run(&self, config: &Config) -> Vec<Result<String, String>> {
+        match self {
+            Action::Lock => lock::dry_run(&config.lock_backends, &config.lock_users),
+            Action::Scrub => {
+                let mut steps = scrub::dry_run();
+                steps.extend(lock::dry_run(&config.lock_backends, &config.lock_users));
+                steps
+            }
+            Action::Suspend => {
+                if lock::on_path("systemctl") {
+                    vec![Ok("would run systemctl suspend".to_string())]
+                } else {
+                    vec![Err("systemctl not found in PATH".to_string())]
+                }
+            }
+            Action::KillNetwork => network::dry_run(&config.kill_interfaces),
+            Action::TerminateRemote => sessions::dry_run(),
+            Action::Systemd(units) => units.dry_run(),
+            Action::Vm(domains) => domains.dry_run(),
+            Action::Containers(containers) => containers.dry_run(),
+            Action::Custom(path) => vec![
+                self.check_ready(config)
+                    .map(|()| script::describe(path, &config.script(path))),
+            ],
+        }
+    }
+
+    /// A cheap check, without running anything, that nothing the action
+    /// needs is known to be missing.
+    pub fn check_ready(&self, config: &Config) -> Result<(), String> {
+        let locks = || {
+            if config
+                .lock_backends
+                .iter()
+                .any(|backend| backend.is_available().is_ok())
+            {
+                Ok(())
+            } else {
+                Err("no configured lock backend is available".to_string())
+            }
+        };
+        match self {
+            Action::Lock | Action::Scrub => locks(),
+            Action::KillNetwork if config.kill_interfaces.is_empty() => {
+                Err("kill_interfaces is empty".to_string())
+            }
+            Action::TerminateRemote if !lock::on_path("loginctl") => {
+                Err("loginctl not found in PATH".to_string())
+            }
+            Action::Vm(_) if !lock::on_path("virsh") => Err("virsh not found in PATH".to_string()),
+            Action::Containers(containers) => containers.check(),
+            Action::Custom(path) => match fs::metadata(path) {
+                Ok(metadata) if is_executable(&metadata) => script::check(&config.script(path)),
+                Ok(_) => Err(format!("{} is not an executable file", path.display())),
+                Err(err) => Err(format!("cannot access {}: {err}", path.display())),
+            },
+            Action::Suspend
+            | Action::KillNetwork
+            | Action::TerminateRemote
+            | Action::Vm(_)
+            | Action::Systemd(_) => Ok(()),
+        }
+    }
+
+    /// Whether the action locks the screen.
+    pub fn locks(&self) -> bool {
+        matches!(self, Action::Lock | Action::Scrub)
+    }
+
+    /// Whether the action asked to be undone once the device is back.
+    pub fn undoes_on_reattach(&self) -> bool {
+        match self {
+            Action::Systemd(units) => units.restart,
+            Action::Vm(domains) => domains.resume,
+            Action::Containers(containers) => containers.start,
+            _ => false,
+        }
+    }
+
+    /// Undo the action after the device returns.
+    pub fn undo(&self) {
+        match self {
+            Action::Systemd(units) => units.restart(),
+            Action::Vm(domains) => domains.resume(),
+            Action::Containers(containers) => containers.start(),
+            _ => {}
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "lock" => Ok(Action::Lock),
+            "scrub" => Ok(Action::Scrub),
+            "suspend" => Ok(Action::Suspend),
+            "kill-network" => Ok(Action::KillNetwork),
+            "terminate-remote" => Ok(Action::TerminateRemote),
+            other if other.starts_with("systemd:") => {
+                Ok(Action::Systemd(other["systemd:".len()..].parse()?))
+            }
+            other if other.starts_with("vm:") => Ok(Action::Vm(other["vm:".len()..].parse()?)),
+            other if other.starts_with("container:") => {
+                Ok(Action::Containers(other["container:".len()..].parse()?))
+            }
+            other => match other.strip_prefix("custom:") {
+                Some(path) if path.starts_with('/') => Ok(Action::Custom(PathBuf::from(path))),
+                Some(path) => Err(format!("custom action path must be absolute: {path}")),
+                None => Err(format!("unknown action: {other}")),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        action.to_string()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Lock => write!(f, "lock"),
+            Action::Scrub => write!(f, "scrub"),
+            Action::Suspend => write!(f, "suspend"),
+            Action::KillNetwork => write!(f, "kill-network"),
+            Action::TerminateRemote => write!(f, "terminate-remote"),
+            Action::Systemd(units) => write!(f, "systemd:{units}"),
+            Action::Vm(domains) => write!(f, "vm:{domains}"),
+            Action::Containers(containers) => write!(f, "container:{containers}"),
+            Action::Custom(path) => write!(f, "custom:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(metadata: &Metadata) -> bool {
+    metadata.is_file()
+}
+
+fn run_command(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    command::run(command, HELPER_TIMEOUT)?;
+    info!(program = %program, "action command completed");
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, ThreadId};
+
+type Job<S> = Box<dyn FnOnce(&mut S) + Send>;
+
+/// Owns a value on a thread of its own and runs the jobs sent to it one at
+/// a time, in the order they arrive. Nothing else can reach the value, so
+/// no caller can keep it held while it blocks on something else, and a job
+/// that panics is reported to its caller instead of poisoning the value for
+/// everyone.
+pub struct Actor<S> {
+    jobs: Sender<Job<S>>,
+    thread: Arc<OnceLock<ThreadId>>,
+}
+
+impl<S> Clone for Actor<S> {
+    fn clone(&self) -> Self {
+        Self {
+            jobs: self.jobs.clone(),
+            thread: Arc::clone(&self.thread),
+        }
+    }
+}
+
+impl<S: Send + 'static> Actor<S> {
+    /// Start the thread, called `name`, that owns `state`.
+    pub fn spawn(name: &str, mut state: S) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job<S>>();
+        let thread = Arc::new(OnceLock::new());
+        let spawned = thread::Builder::new().name(name.to_string()).spawn({
+            let thread = Arc::clone(&thread);
+            move || {
+                let _ = thread.set(thread::current().id());
+                for job in queue {
+                    job(&mut state);
+                }
+            }
+        });
+        if let Err(err) = spawned {
+            panic!("failed to start the {name} thread: {err}");
+        }
+        Self { jobs, thread }
+    }
+
+    /// Run `job` on the state and wait for what it returns. A panic in
+    /// `job` is raised again here. Jobs must not call back into the actor,
+    /// which would wait on itself; that panics instead.
+    pub fn call<R: Send + 'static>(&self, job: impl FnOnce(&mut S) -> R + Send + 'static) -> R {
+        assert!(
+            self.thread.get() != Some(&thread::current().id()),
+            "a state job called back into the state actor"
+        );
+        let (reply, result) = mpsc::channel();
+        let job: Job<S> = Box::new(move |state| {
+            let _ = reply.send(panic::catch_unwind(AssertUnwindSafe(|| job(state))));
+        });
+        if self.jobs.send(job).is_err() {
+            panic!("the state actor has stopped");
+        }
+        match result.recv() {
+            Ok(Ok(value)) => value,
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => panic!("the state actor has stopped"),
+        }
+    }
+}
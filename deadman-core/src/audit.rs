@@ -0,0 +1,205 @@
+use std::env;
+use std::os::fd::AsRawFd;
+use std::thread;
+
+use deadman_ipc::events::{ActionResult, Event, EventKind};
+use nix::sys::socket::{
+    AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType, sendto, socket,
+};
+use tracing::{debug, warn};
+
+use crate::events::EVENTS;
+
+/// `AUDIT_USER` from `linux/audit.h`: a message from a userspace program.
+const AUDIT_USER: u16 = 1005;
+/// `NLM_F_REQUEST`; no acknowledgement is asked for.
+const NLM_F_REQUEST: u16 = 1;
+/// Size of `struct nlmsghdr`.
+const HEADER_LEN: usize = 16;
+
+/// Send tether lifecycle and trigger events to the kernel audit log as
+/// `AUDIT_USER` records while `enabled` says so, where auditd and whatever
+/// reads its logs pick them up.
+pub fn forward(enabled: impl Fn() -> bool + Send + 'static) {
+    let events = EVENTS.subscribe();
+    thread::spawn(move || {
+        for line in events {
+            if !enabled() {
+                continue;
+            }
+            let Ok(event) = serde_json::from_str::<Event>(&line) else {
+                continue;
+            };
+            let Some(record) = record(&event) else {
+                continue;
+            };
+            match send(&record) {
+                Ok(()) => debug!(record = %record, "sent audit record"),
+                Err(err) => warn!(error = %err, "failed to send audit record"),
+            }
+        }
+    });
+}
+
+/// The record text for `event`, in the `key=value` form libaudit's
+/// `audit_log_user_message` writes; `None` for events that aren't about a
+/// tether.
+fn record(event: &Event) -> Option<String> {
+    let op = match event.kind {
+        EventKind::Tethered
+        | EventKind::Removed
+        | EventKind::Reattached
+        | EventKind::Cancelled
+        | EventKind::Triggered
+        | EventKind::Cleared
+        | EventKind::Suppressed
+        | EventKind::Rearmed => format!("deadman-{}", event.kind),
+        EventKind::ConfigChanged | EventKind::ConfigRejected | EventKind::Degraded => return None,
+    };
+
+    let mut fields = vec![format!("op={op}")];
+    if let (Some(bus), Some(address)) = (event.bus, event.address) {
+        fields.push(format!("bus={bus} address={address}"));
+    }
+    if let Some(device) = &event.device {
+        fields.push(format!("device={}", encode(device)));
+    }
+    if let Some(action) = &event.action {
+        fields.push(format!("action={}", encode(action)));
+    }
+    if let Some(message) = &event.message {
+        fields.push(format!("reason={}", encode(message)));
+    }
+    if let Some(uid) = event.uid {
+        fields.push(format!("uid={uid}"));
+    }
+    let exe = env::current_exe()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| "deadmand".to_string());
+    fields.push(format!("exe={}", encode(&exe)));
+    fields.push("hostname=? addr=? terminal=?".to_string());
+    // a fallback that ran still protected the machine
+    let failed = event.result == Some(ActionResult::Failed);
+    fields.push(format!("res={}", if failed { "failed" } else { "success" }));
+    Some(fields.join(" "))
+}
+
+/// Quote `value`, or hex encode it when it has spaces, quotes or control
+/// characters, as auditd expects of untrusted strings.
+fn encode(value: &str) -> String {
+    if value
+        .bytes()
+        .any(|byte| byte <= b' ' || byte == b'"' || byte >= 0x7f)
+    {
+        value.bytes().map(|byte| format!("{byte:02X}")).collect()
+    } else {
+        format!("\"{value}\"")
+    }
+}
+
+/// Send one record over a fresh `NETLINK_AUDIT` socket; needs
+/// `CAP_AUDIT_WRITE`.
+fn send(record: &str) -> Result<(), String> {
+    let socket = socket(
+        AddressFamily::Netlink,
+        SockType::Raw,
+        SockFlag::SOCK_CLOEXEC,
+        SockProtocol::NetlinkAudit,
+    )
+    .map_err(|err| format!("failed to open audit socket: {err}"))?;
+
+    let payload = record.as_bytes();
+    let length = u32::try_from(HEADER_LEN + payload.len() + 1)
+        .map_err(|_| "audit record too long".to_string())?;
+    let mut message = Vec::with_capacity(length as usize);
+    message.extend_from_slice(&length.to_ne_bytes());
+    message.extend_from_slice(&AUDIT_USER.to_ne_bytes());
+    message.extend_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes()); // sequence
+    message.extend_from_slice(&0u32.to_ne_bytes()); // port id, filled in by the kernel
+    message.extend_from_slice(payload);
+    message.push(0);
+
+    sendto(
+        socket.as_raw_fd(),
+        &message,
+        &NetlinkAddr::new(0, 0),
+        MsgFlags::empty(),
+    )
+    .map_err(|err| format!("failed to send audit record: {err}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `record` without the `exe=` field, which names the test binary.
+    fn record_without_exe(event: &Event) -> Option<String> {
+        let record = record(event)?;
+        let (head, tail) = record.split_once(" exe=")?;
+        let (_, rest) = tail.split_once(' ')?;
+        Some(format!("{head} {rest}"))
+    }
+
+    #[test]
+    fn test_encode_quotes_plain_values() {
+        assert_eq!(encode("lock"), "\"lock\"");
+        assert_eq!(encode("a=b"), "\"a=b\"");
+        assert_eq!(encode(""), "\"\"");
+    }
+
+    #[test]
+    fn test_encode_hex_encodes_untrusted_values() {
+        assert_eq!(encode("Key A"), "4B65792041");
+        assert_eq!(encode("a\nb"), "610A62");
+        assert_eq!(encode("say \"hi\""), "7361792022686922");
+        assert_eq!(encode("é"), "C3A9");
+    }
+
+    #[test]
+    fn test_record_with_every_field() {
+        let mut event = Event::new(EventKind::Triggered);
+        event.bus = Some(1);
+        event.address = Some(4);
+        event.device = Some("YubiKey\nop=forged".to_string());
+        event.action = Some("lock".to_string());
+        event.message = Some("retries=2".to_string());
+        event.uid = Some(1000);
+        event.result = Some(ActionResult::Failed);
+        assert_eq!(
+            record_without_exe(&event).unwrap(),
+            "op=deadman-triggered bus=1 address=4 device=597562694B65790A6F703D666F72676564 \
+             action=\"lock\" reason=\"retries=2\" uid=1000 hostname=? addr=? terminal=? res=failed"
+        );
+    }
+
+    #[test]
+    fn test_record_without_optional_fields() {
+        let mut event = Event::new(EventKind::Removed);
+        // an address without a bus says nothing on its own
+        event.address = Some(4);
+        assert_eq!(
+            record_without_exe(&event).unwrap(),
+            "op=deadman-removed hostname=? addr=? terminal=? res=success"
+        );
+    }
+
+    #[test]
+    fn test_fallback_counts_as_success() {
+        let mut event = Event::new(EventKind::Triggered);
+        event.result = Some(ActionResult::FallbackRan);
+        assert!(record(&event).unwrap().ends_with(" res=success"));
+    }
+
+    #[test]
+    fn test_events_not_about_tethers_have_no_record() {
+        for kind in [
+            EventKind::ConfigChanged,
+            EventKind::ConfigRejected,
+            EventKind::Degraded,
+        ] {
+            assert_eq!(record(&Event::new(kind)), None);
+        }
+    }
+}
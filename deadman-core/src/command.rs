@@ -0,0 +1,181 @@
+use std::io::Read;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// How long the helpers an action runs (`loginctl`, `busctl`, `ip`...) get
+/// before they are killed; they normally finish well within a second.
+pub const HELPER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a running command is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the output of a command that has exited is waited for; longer
+/// means something it left running in the background holds the pipes.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Run `command` with stdin closed, capturing its output, and kill it if it
+/// has not exited within `timeout`. The command leads a process group of its
+/// own, and a timeout kills the whole group, so what it started goes too.
+pub fn output(command: &mut Command, timeout: Duration) -> Result<Output, String> {
+    let program = program(command);
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("failed to run {program}: {err}"))?;
+
+    // drain both pipes so a chatty command can't block on a full one
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = wait(&mut child, timeout).map_err(|err| {
+        kill(&mut child);
+        let _ = child.wait();
+        format!("{program} {err}")
+    })?;
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    Ok(Output {
+        status,
+        stdout: collect(&program, &stdout, deadline),
+        stderr: collect(&program, &stderr, deadline),
+    })
+}
+
+/// Like [`output`], but only whether the command succeeded, with its exit
+/// status and stderr when it did not.
+pub fn run(command: &mut Command, timeout: Duration) -> Result<(), String> {
+    let program = program(command);
+    let output = output(command, timeout)?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!(
+            "{program} exited with status {}: {}",
+            output.status,
+            stderr.trim()
+        ))
+    }
+}
+
+fn wait(child: &mut Child, timeout: Duration) -> Result<std::process::ExitStatus, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Ok(status),
+            Ok(None) if Instant::now() >= deadline => {
+                warn!(
+                    pid = child.id(),
+                    timeout_secs = timeout.as_secs(),
+                    "killing command that timed out"
+                );
+                return Err(format!("timed out after {}s", timeout.as_secs()));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => return Err(format!("could not be waited for: {err}")),
+        }
+    }
+}
+
+/// Kill the child's process group, or just the child where there are none.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{Signal, killpg};
+        use nix::unistd::Pid;
+
+        if killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL).is_ok() {
+            return;
+        }
+    }
+    let _ = child.kill();
+}
+
+/// Read `pipe` to its end on a thread of its own.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        let _ = sender.send(buffer);
+    });
+    receiver
+}
+
+/// What [`drain`] read, or nothing if the pipe is still open at `deadline`;
+/// the draining thread then finishes whenever the pipe closes.
+fn collect(program: &str, drained: &Receiver<Vec<u8>>, deadline: Instant) -> Vec<u8> {
+    let left = deadline.saturating_duration_since(Instant::now());
+    drained.recv_timeout(left).unwrap_or_else(|_| {
+        warn!(program, "output dropped; the command left its pipes open");
+        Vec::new()
+    })
+}
+
+fn program(command: &Command) -> String {
+    command.get_program().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captures_output() {
+        let output = output(
+            Command::new("sh").args(["-c", "echo out; echo err >&2"]),
+            HELPER_TIMEOUT,
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[test]
+    fn test_timeout_kills_the_process_group() {
+        let started = Instant::now();
+        let err = output(
+            Command::new("sh").args(["-c", "sleep 30 & sleep 30"]),
+            Duration::from_millis(200),
+        )
+        .unwrap_err();
+        assert!(err.contains("timed out"), "{err}");
+        // the backgrounded sleep held the pipes, so it was killed as well
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_background_children_do_not_hold_up_the_output() {
+        let started = Instant::now();
+        let output = output(
+            Command::new("sh").args(["-c", "sleep 30 & exit 3"]),
+            HELPER_TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(output.stdout.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_run_reports_stderr_of_a_failure() {
+        let err = run(
+            Command::new("sh").args(["-c", "echo nope >&2; exit 1"]),
+            HELPER_TIMEOUT,
+        )
+        .unwrap_err();
+        assert!(err.ends_with(": nope"), "{err}");
+    }
+}
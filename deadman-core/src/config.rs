@@ -0,0 +1,737 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::actions::Action;
+use crate::lock::LockBackend;
+use crate::logging::LogConfig;
+use crate::script::ScriptConfig;
+use crate::siem::SiemConfig;
+
+/// Where the daemon reads its configuration unless `DEADMAN_CONFIG` is set.
+const DEFAULT_CONFIG_PATH: &str = "/etc/deadman/config.toml";
+
+/// Pause after a change notification so editors finish writing first.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Keys accepted by `config get` and `config set`.
+pub const KEYS: &[&str] = &[
+    "default_action",
+    "default_grace_secs",
+    "default_cooldown_secs",
+    "max_triggers_per_hour",
+    "usb_retry_limit",
+    "removal_settle_ms",
+    "ignore_bus_glitches",
+    "poll_interval_ms",
+    "action_timeout_secs",
+    "action_retries",
+    "fallback_action",
+    "pre_trigger_hook",
+    "on_reattach",
+    "relock_until_reattach",
+    "pam_services",
+    "usbguard_block",
+    "lock_backends",
+    "lock_users",
+    "kill_interfaces",
+    "capture_evidence",
+    "audit_dir",
+    "linux_audit",
+    "snapshot_signing_key",
+    "snapshot_trusted_key",
+    "metrics_textfile",
+    "metrics_interval_secs",
+    "state_journal",
+    "client_group",
+    "log.target",
+    "log.file",
+    "log.max_bytes",
+    "log.keep",
+    "log.otlp_endpoint",
+    "siem.address",
+    "siem.transport",
+    "siem.format",
+    "siem.ca_file",
+];
+
+/// Daemon configuration, stored as TOML. Missing keys take their defaults.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Action for tethers that don't name one.
+    pub default_action: Action,
+    /// Grace period for tethers that don't set one.
+    pub default_grace_secs: u64,
+    /// Cooldown for tethers that don't set one.
+    pub default_cooldown_secs: u64,
+    /// Removal actions a tether may run per hour before further triggers
+    /// are suppressed; 0 means no limit.
+    pub max_triggers_per_hour: u32,
+    /// Consecutive libusb failures a monitor retries before running its
+    /// action rather than leave the device unwatched.
+    pub usb_retry_limit: u32,
+    /// How long after a removal the bus is looked at again to judge how
+    /// sure it is that the device was unplugged; at most 2000.
+    pub removal_settle_ms: u64,
+    /// Keep watching instead of acting when a removed device turns out to
+    /// be attached at the same address after `removal_settle_ms`, as when a
+    /// controller briefly drops it during selective suspend.
+    pub ignore_bus_glitches: bool,
+    /// How often a polling tether enumerates the bus to look for its
+    /// device; at least 100. Tethers poll when asked to or when libusb has
+    /// no hotplug support.
+    pub poll_interval_ms: u64,
+    /// How long a `custom:` script may run before it is killed and counted
+    /// as failed.
+    pub action_timeout_secs: u64,
+    /// Extra attempts at a removal action that failed.
+    pub action_retries: u32,
+    /// Run instead when the removal action still fails after its retries.
+    pub fallback_action: Option<Action>,
+    /// Executable asked, once the grace period is over, whether the action
+    /// should run: exit 0 to go ahead, 1 to veto it, 2 to ask again after
+    /// the seconds it prints. Runs like a `custom:` script.
+    pub pre_trigger_hook: Option<PathBuf>,
+    /// Actions run, in order, when a device whose action fired comes back,
+    /// e.g. a `custom:` script that brings the VPN up again. `config set`
+    /// takes them separated by `;`.
+    pub on_reattach: Vec<Action>,
+    /// After a lock action, lock again any session unlocked before the
+    /// device returns, so the device is needed to use the machine.
+    pub relock_until_reattach: bool,
+    /// PAM services, e.g. `sudo` or `gdm-password`, where `deadman pam-check`
+    /// refuses authentication while a tethered device is absent.
+    pub pam_services: Vec<String>,
+    /// Switch usbguard to blocking new devices when a tether fires, and
+    /// restore its policy when the device returns.
+    pub usbguard_block: bool,
+    /// Ways to lock the screen, tried in order until one works.
+    pub lock_backends: Vec<LockBackend>,
+    /// Users, by name or UID, whose sessions a system daemon locks; empty
+    /// locks every session. Only the logind backends can honour it.
+    pub lock_users: Vec<String>,
+    /// Interfaces, WireGuard tunnels included, that the `kill-network`
+    /// action brings down.
+    pub kill_interfaces: Vec<String>,
+    /// Take a webcam frame and a screenshot into `audit_dir` whenever a
+    /// removal action runs.
+    pub capture_evidence: bool,
+    /// Where evidence captures are stored.
+    pub audit_dir: PathBuf,
+    /// Send tether and trigger events to the Linux audit subsystem as
+    /// `AUDIT_USER` records.
+    pub linux_audit: bool,
+    /// PEM private key that signs `deadman export` snapshots.
+    pub snapshot_signing_key: Option<PathBuf>,
+    /// PEM public key that `deadman import` snapshots must be signed for.
+    pub snapshot_trusted_key: Option<PathBuf>,
+    /// Where to keep node_exporter textfile-collector metrics, e.g.
+    /// `/var/lib/node_exporter/textfile/deadman.prom`; none by default.
+    pub metrics_textfile: Option<PathBuf>,
+    /// How often the metrics textfile is rewritten.
+    pub metrics_interval_secs: u64,
+    /// Journal tether changes beside the pidfile, so a daemon restarted
+    /// after a crash takes its tethers back up. The journal lives in
+    /// `/run` and so does not outlast a reboot.
+    pub state_journal: bool,
+    /// Group whose members may use the daemon's sockets as well as its own
+    /// user and root. Each may then only clear, cancel or change the tethers
+    /// they made themselves. Read when the daemon starts.
+    pub client_group: Option<String>,
+    pub log: LogConfig,
+    /// Where trigger events are sent as CEF or LEEF, if anywhere.
+    pub siem: SiemConfig,
+    /// How each `custom:` script runs, keyed by its path.
+    pub scripts: BTreeMap<PathBuf, ScriptConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_action: Action::default(),
+            default_grace_secs: 0,
+            default_cooldown_secs: 0,
+            max_triggers_per_hour: 0,
+            usb_retry_limit: 5,
+            removal_settle_ms: 250,
+            ignore_bus_glitches: false,
+            poll_interval_ms: 500,
+            action_timeout_secs: 30,
+            action_retries: 0,
+            fallback_action: None,
+            pre_trigger_hook: None,
+            on_reattach: Vec::new(),
+            relock_until_reattach: false,
+            pam_services: Vec::new(),
+            usbguard_block: false,
+            lock_backends: LockBackend::ALL.to_vec(),
+            lock_users: Vec::new(),
+            kill_interfaces: Vec::new(),
+            capture_evidence: false,
+            audit_dir: default_audit_dir(),
+            linux_audit: false,
+            snapshot_signing_key: None,
+            snapshot_trusted_key: None,
+            metrics_textfile: None,
+            metrics_interval_secs: 15,
+            state_journal: false,
+            client_group: None,
+            log: LogConfig::default(),
+            siem: SiemConfig::default(),
+            scripts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// `DEADMAN_CONFIG` if set, otherwise `/etc/deadman/<instance>.toml` for
+    /// a named instance or the default path. A user daemon looks in
+    /// `$XDG_CONFIG_HOME/deadman/` instead of `/etc/deadman/`.
+    pub fn path() -> PathBuf {
+        if let Some(path) = env::var_os("DEADMAN_CONFIG") {
+            return PathBuf::from(path);
+        }
+        let file_name = match deadman_ipc::instance() {
+            Some(name) => format!("{name}.toml"),
+            None => "config.toml".to_string(),
+        };
+        if deadman_ipc::user_mode()
+            && let Some(dir) = user_config_dir()
+        {
+            return dir.join("deadman").join(file_name);
+        }
+        match deadman_ipc::instance() {
+            Some(_) => PathBuf::from("/etc/deadman").join(file_name),
+            None => PathBuf::from(DEFAULT_CONFIG_PATH),
+        }
+    }
+
+    /// Read the config file, falling back to defaults when it doesn't exist.
+    pub fn load() -> Result<Self, String> {
+        let path = Self::path();
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)
+                .map_err(|err| format!("invalid config {}: {err}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("failed to read {}: {err}", path.display())),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents).map_err(|err| err.message().to_string())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+        }
+        fs::write(&path, self.to_toml()?)
+            .map_err(|err| format!("failed to write {}: {err}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|err| format!("failed to serialize config: {err}"))
+    }
+
+    pub fn get(&self, key: &str) -> Result<String, String> {
+        match key {
+            "default_action" => Ok(self.default_action.to_string()),
+            "default_grace_secs" => Ok(self.default_grace_secs.to_string()),
+            "default_cooldown_secs" => Ok(self.default_cooldown_secs.to_string()),
+            "max_triggers_per_hour" => Ok(self.max_triggers_per_hour.to_string()),
+            "usb_retry_limit" => Ok(self.usb_retry_limit.to_string()),
+            "removal_settle_ms" => Ok(self.removal_settle_ms.to_string()),
+            "ignore_bus_glitches" => Ok(self.ignore_bus_glitches.to_string()),
+            "poll_interval_ms" => Ok(self.poll_interval_ms.to_string()),
+            "relock_until_reattach" => Ok(self.relock_until_reattach.to_string()),
+            "pam_services" => Ok(self.pam_services.join(",")),
+            "usbguard_block" => Ok(self.usbguard_block.to_string()),
+            "action_timeout_secs" => Ok(self.action_timeout_secs.to_string()),
+            "action_retries" => Ok(self.action_retries.to_string()),
+            "fallback_action" => Ok(self
+                .fallback_action
+                .as_ref()
+                .map_or_else(|| "none".to_string(), Action::to_string)),
+            "pre_trigger_hook" => Ok(display_path(self.pre_trigger_hook.as_deref())),
+            "on_reattach" => Ok(self
+                .on_reattach
+                .iter()
+                .map(Action::to_string)
+                .collect::<Vec<_>>()
+                .join(";")),
+            "lock_backends" => Ok(self
+                .lock_backends
+                .iter()
+                .map(LockBackend::to_string)
+                .collect::<Vec<_>>()
+                .join(",")),
+            "lock_users" => Ok(self.lock_users.join(",")),
+            "kill_interfaces" => Ok(self.kill_interfaces.join(",")),
+            "capture_evidence" => Ok(self.capture_evidence.to_string()),
+            "audit_dir" => Ok(self.audit_dir.display().to_string()),
+            "linux_audit" => Ok(self.linux_audit.to_string()),
+            "snapshot_signing_key" => Ok(display_path(self.snapshot_signing_key.as_deref())),
+            "snapshot_trusted_key" => Ok(display_path(self.snapshot_trusted_key.as_deref())),
+            "metrics_textfile" => Ok(display_path(self.metrics_textfile.as_deref())),
+            "metrics_interval_secs" => Ok(self.metrics_interval_secs.to_string()),
+            "state_journal" => Ok(self.state_journal.to_string()),
+            "client_group" => Ok(self.client_group.clone().unwrap_or_default()),
+            "log.target" => Ok(self.log.target.to_string()),
+            "log.file" => Ok(self.log.file.display().to_string()),
+            "log.max_bytes" => Ok(self.log.max_bytes.to_string()),
+            "log.keep" => Ok(self.log.keep.to_string()),
+            "log.otlp_endpoint" => Ok(self.log.otlp_endpoint.clone().unwrap_or_default()),
+            "siem.address" => Ok(self.siem.address.clone().unwrap_or_default()),
+            "siem.transport" => Ok(self.siem.transport.to_string()),
+            "siem.format" => Ok(self.siem.format.to_string()),
+            "siem.ca_file" => Ok(display_path(self.siem.ca_file.as_deref())),
+            other => Err(unknown_key(other)),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "default_action" => self.default_action = value.parse()?,
+            "default_grace_secs" => {
+                self.default_grace_secs = value
+                    .parse()
+                    .map_err(|_| format!("invalid grace period: {value}"))?;
+            }
+            "default_cooldown_secs" => {
+                self.default_cooldown_secs = value
+                    .parse()
+                    .map_err(|_| format!("invalid cooldown: {value}"))?;
+            }
+            "max_triggers_per_hour" => {
+                self.max_triggers_per_hour = value
+                    .parse()
+                    .map_err(|_| format!("invalid trigger limit: {value}"))?;
+            }
+            "usb_retry_limit" => {
+                self.usb_retry_limit = value
+                    .parse()
+                    .map_err(|_| format!("invalid retry limit: {value}"))?;
+            }
+            "removal_settle_ms" => {
+                self.removal_settle_ms = value
+                    .parse()
+                    .map_err(|_| format!("invalid settle time: {value}"))?;
+            }
+            "ignore_bus_glitches" => {
+                self.ignore_bus_glitches = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false: {value}"))?;
+            }
+            "poll_interval_ms" => {
+                self.poll_interval_ms = value
+                    .parse()
+                    .map_err(|_| format!("invalid poll interval: {value}"))?;
+            }
+            "relock_until_reattach" => {
+                self.relock_until_reattach = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false: {value}"))?;
+            }
+            "usbguard_block" => {
+                self.usbguard_block = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false: {value}"))?;
+            }
+            "pam_services" => {
+                self.pam_services = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|service| !service.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "action_timeout_secs" => {
+                self.action_timeout_secs = value
+                    .parse()
+                    .map_err(|_| format!("invalid timeout: {value}"))?;
+            }
+            "action_retries" => {
+                self.action_retries = value
+                    .parse()
+                    .map_err(|_| format!("invalid retry count: {value}"))?;
+            }
+            "fallback_action" => {
+                self.fallback_action = match value {
+                    "" | "none" => None,
+                    action => Some(action.parse()?),
+                };
+            }
+            "pre_trigger_hook" => {
+                self.pre_trigger_hook = optional_path("pre-trigger hook", value)?;
+            }
+            "on_reattach" => {
+                self.on_reattach = value
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|action| !action.is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?;
+            }
+            "lock_backends" => {
+                self.lock_backends = value
+                    .split(',')
+                    .map(|backend| backend.trim().parse())
+                    .collect::<Result<_, _>>()?;
+            }
+            "lock_users" => {
+                self.lock_users = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|user| !user.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "kill_interfaces" => {
+                self.kill_interfaces = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|interface| !interface.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "capture_evidence" => {
+                self.capture_evidence = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false: {value}"))?;
+            }
+            "metrics_textfile" => {
+                self.metrics_textfile = optional_path("metrics textfile", value)?;
+            }
+            "metrics_interval_secs" => {
+                self.metrics_interval_secs = value
+                    .parse()
+                    .map_err(|_| format!("invalid interval: {value}"))?;
+            }
+            "snapshot_signing_key" => {
+                self.snapshot_signing_key = optional_path("signing key", value)?;
+            }
+            "snapshot_trusted_key" => {
+                self.snapshot_trusted_key = optional_path("trusted key", value)?;
+            }
+            "state_journal" => {
+                self.state_journal = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false: {value}"))?;
+            }
+            "client_group" => {
+                self.client_group = match value {
+                    "" | "none" => None,
+                    group => Some(group.to_string()),
+                };
+            }
+            "linux_audit" => {
+                self.linux_audit = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false: {value}"))?;
+            }
+            "audit_dir" => {
+                if !value.starts_with('/') {
+                    return Err(format!("audit directory must be absolute: {value}"));
+                }
+                self.audit_dir = PathBuf::from(value);
+            }
+            "log.target" => self.log.target = value.parse()?,
+            "log.file" => {
+                if !value.starts_with('/') {
+                    return Err(format!("log file path must be absolute: {value}"));
+                }
+                self.log.file = PathBuf::from(value);
+            }
+            "log.max_bytes" => {
+                self.log.max_bytes = value
+                    .parse()
+                    .map_err(|_| format!("invalid byte count: {value}"))?;
+            }
+            "log.keep" => {
+                self.log.keep = value
+                    .parse()
+                    .map_err(|_| format!("invalid file count: {value}"))?;
+            }
+            "log.otlp_endpoint" => {
+                self.log.otlp_endpoint = match value {
+                    "" | "none" => None,
+                    endpoint if endpoint.starts_with("http://") => Some(endpoint.to_string()),
+                    endpoint => return Err(format!("expected http://host:port: {endpoint}")),
+                };
+            }
+            "siem.address" => {
+                self.siem.address = match value {
+                    "" | "none" => None,
+                    address if address.rsplit_once(':').is_some() => Some(address.to_string()),
+                    address => return Err(format!("expected host:port: {address}")),
+                };
+            }
+            "siem.transport" => self.siem.transport = value.parse()?,
+            "siem.format" => self.siem.format = value.parse()?,
+            "siem.ca_file" => self.siem.ca_file = optional_path("CA file", value)?,
+            other => return Err(unknown_key(other)),
+        }
+        Ok(())
+    }
+
+    /// The `[scripts]` entry for `path`, or the defaults.
+    pub fn script(&self, path: &Path) -> ScriptConfig {
+        self.scripts.get(path).cloned().unwrap_or_default()
+    }
+
+    pub fn action_timeout(&self) -> Duration {
+        Duration::from_secs(self.action_timeout_secs)
+    }
+
+    pub fn default_grace(&self) -> Duration {
+        Duration::from_secs(self.default_grace_secs)
+    }
+
+    pub fn default_cooldown(&self) -> Duration {
+        Duration::from_secs(self.default_cooldown_secs)
+    }
+
+    /// Describe each setting that differs in `other`, as `key: old -> new`.
+    pub fn diff(&self, other: &Config) -> Vec<String> {
+        KEYS.iter()
+            .filter_map(|key| {
+                let old = self.get(key).ok()?;
+                let new = other.get(key).ok()?;
+                (old != new).then(|| format!("{key}: {old} -> {new}"))
+            })
+            .collect()
+    }
+}
+
+/// An optional path setting as shown by `config get`; empty when unset.
+fn display_path(path: Option<&Path>) -> String {
+    path.map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+/// An optional absolute path from `config set`; `none` or empty unsets it.
+fn optional_path(what: &str, value: &str) -> Result<Option<PathBuf>, String> {
+    match value {
+        "" | "none" => Ok(None),
+        path if path.starts_with('/') => Ok(Some(PathBuf::from(path))),
+        path => Err(format!("{what} path must be absolute: {path}")),
+    }
+}
+
+/// `/var/lib/deadman/audit`, or `$XDG_STATE_HOME/deadman/audit` for a user
+/// daemon.
+fn default_audit_dir() -> PathBuf {
+    if deadman_ipc::user_mode() {
+        let state_dir = env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")));
+        if let Some(dir) = state_dir {
+            return dir.join("deadman").join("audit");
+        }
+    }
+    PathBuf::from("/var/lib/deadman/audit")
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// Call `on_change` from a background thread whenever the config file is
+/// written, renamed into place, or removed. Watches the parent directory so
+/// editors that save by replacing the file are picked up too.
+#[cfg(target_os = "linux")]
+pub fn watch(on_change: impl Fn() + Send + 'static) -> Result<(), String> {
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+    let path = Config::path();
+    let dir = path
+        .parent()
+        .ok_or_else(|| format!("{} has no parent directory", path.display()))?
+        .to_path_buf();
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("{} has no file name", path.display()))?
+        .to_os_string();
+
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC)
+        .map_err(|err| format!("failed to initialise inotify: {err}"))?;
+    inotify
+        .add_watch(
+            &dir,
+            AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_DELETE,
+        )
+        .map_err(|err| format!("failed to watch {}: {err}", dir.display()))?;
+
+    thread::Builder::new()
+        .name("config-watch".to_string())
+        .spawn(move || {
+            loop {
+                match inotify.read_events() {
+                    Ok(events) => {
+                        if events
+                            .iter()
+                            .any(|event| event.name.as_ref() == Some(&name))
+                        {
+                            thread::sleep(WATCH_DEBOUNCE);
+                            on_change();
+                        }
+                    }
+                    Err(err) => {
+                        tracing::error!(error = %err, "config watcher stopped");
+                        return;
+                    }
+                }
+            }
+        })
+        .map_err(|err| format!("failed to start config watcher: {err}"))?;
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch(_on_change: impl Fn() + Send + 'static) -> Result<(), String> {
+    Err("config watching is only supported on Linux".to_string())
+}
+
+fn unknown_key(key: &str) -> String {
+    format!(
+        "unknown config key: {key} (expected one of {})",
+        KEYS.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_key_can_be_read() {
+        let config = Config::default();
+        for key in KEYS {
+            assert!(config.get(key).is_ok(), "{key} has no getter");
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let mut config = Config::default();
+        for key in ["", "grace", "log", "log.nope", "scripts"] {
+            assert!(
+                config
+                    .get(key)
+                    .unwrap_err()
+                    .starts_with("unknown config key")
+            );
+            assert!(
+                config
+                    .set(key, "1")
+                    .unwrap_err()
+                    .starts_with("unknown config key")
+            );
+        }
+        assert_eq!(config, Config::default());
+        assert!(Config::parse("grace = 1").is_err());
+        assert!(Config::parse("[log]\nlevel = \"debug\"").is_err());
+    }
+
+    #[test]
+    fn test_wrong_value_types_are_rejected() {
+        let mut config = Config::default();
+        for (key, value) in [
+            ("default_grace_secs", "soon"),
+            ("default_grace_secs", "-1"),
+            ("max_triggers_per_hour", "1.5"),
+            ("ignore_bus_glitches", "yes"),
+            ("default_action", "explode"),
+            ("lock_backends", "logind,nope"),
+            ("audit_dir", "relative/dir"),
+            ("pre_trigger_hook", "hook.sh"),
+            ("log.target", "nowhere"),
+            ("log.otlp_endpoint", "https://collector"),
+            ("siem.address", "no-port"),
+            ("siem.transport", "pigeon"),
+        ] {
+            assert!(
+                config.set(key, value).is_err(),
+                "{key} = {value:?} was accepted"
+            );
+        }
+        assert_eq!(config, Config::default());
+        assert!(Config::parse("default_grace_secs = \"30\"").is_err());
+    }
+
+    #[test]
+    fn test_set_then_get_round_trips() {
+        let mut config = Config::default();
+        for (key, value) in [
+            ("default_grace_secs", "30"),
+            ("ignore_bus_glitches", "true"),
+            ("fallback_action", "suspend"),
+            (
+                "on_reattach",
+                "custom:/usr/local/bin/a;custom:/usr/local/bin/b",
+            ),
+            ("lock_backends", "logind,swaylock"),
+            ("pam_services", "sudo,gdm-password"),
+            ("pre_trigger_hook", "/usr/local/bin/hook"),
+            ("client_group", "deadman"),
+            ("log.keep", "3"),
+            ("siem.address", "siem.example.com:6514"),
+        ] {
+            config.set(key, value).unwrap();
+            assert_eq!(config.get(key).unwrap(), value, "{key}");
+        }
+    }
+
+    #[test]
+    fn test_set_trims_lists_and_unsets_options() {
+        let mut config = Config::default();
+        config.set("kill_interfaces", " wg0 , ,wlp3s0").unwrap();
+        assert_eq!(config.kill_interfaces, ["wg0", "wlp3s0"]);
+
+        config.set("fallback_action", "suspend").unwrap();
+        config.set("fallback_action", "none").unwrap();
+        assert_eq!(config.fallback_action, None);
+        assert_eq!(config.get("fallback_action").unwrap(), "none");
+
+        config.set("metrics_textfile", "/tmp/deadman.prom").unwrap();
+        config.set("metrics_textfile", "").unwrap();
+        assert_eq!(config.get("metrics_textfile").unwrap(), "");
+    }
+
+    #[test]
+    fn test_diff_of_identical_configs_is_empty() {
+        assert!(Config::default().diff(&Config::default()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_lists_changed_keys_in_order() {
+        let old = Config::default();
+        let mut new = old.clone();
+        new.set("siem.format", "leef").unwrap();
+        new.set("default_grace_secs", "30").unwrap();
+        new.set("pre_trigger_hook", "/usr/local/bin/hook").unwrap();
+        assert_eq!(
+            old.diff(&new),
+            [
+                "default_grace_secs: 0 -> 30",
+                "pre_trigger_hook:  -> /usr/local/bin/hook",
+                "siem.format: cef -> leef",
+            ]
+        );
+        assert_eq!(new.diff(&old)[0], "default_grace_secs: 30 -> 0");
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::{ForkResult, Pid, chdir, dup2_stderr, dup2_stdin, dup2_stdout, fork, setsid};
+
+/// Where `--daemonize` records the daemon's pid unless told otherwise.
+pub const DEFAULT_PIDFILE: &str = "/run/deadman.pid";
+
+/// The pidfile for the selected daemon instance, in the session's runtime
+/// directory for a user daemon.
+pub fn default_pidfile() -> PathBuf {
+    let file_name = match deadman_ipc::instance() {
+        Some(name) => format!("deadman-{name}.pid"),
+        None => "deadman.pid".to_string(),
+    };
+    if deadman_ipc::user_mode() {
+        deadman_ipc::runtime_dir().join(file_name)
+    } else {
+        PathBuf::from("/run").join(file_name)
+    }
+}
+
+/// Detach from the controlling terminal with the classic double fork and
+/// record the final pid in `pidfile`. Must be called before any threads are
+/// started; only the daemon process returns. Does nothing after `re-exec`.
+pub fn daemonize(pidfile: &Path) -> Result<(), String> {
+    // a re-exec keeps the pid, so this process is already the detached daemon
+    if crate::reexec::restoring() {
+        return Ok(());
+    }
+
+    if let Some(pid) = running_pid(pidfile) {
+        return Err(format!("deadmand is already running (pid {pid})"));
+    }
+
+    // open before forking so permission problems are reported to the caller
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(pidfile)
+        .map_err(|err| format!("failed to open {}: {err}", pidfile.display()))?;
+
+    fork_and_exit_parent()?;
+    setsid().map_err(|err| format!("setsid failed: {err}"))?;
+    fork_and_exit_parent()?;
+
+    chdir("/").map_err(|err| format!("failed to chdir to /: {err}"))?;
+    redirect_stdio()?;
+
+    writeln!(file, "{}", std::process::id())
+        .map_err(|err| format!("failed to write {}: {err}", pidfile.display()))
+}
+
+fn fork_and_exit_parent() -> Result<(), String> {
+    // SAFETY: called before the daemon spawns any threads
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { .. }) => std::process::exit(0),
+        Ok(ForkResult::Child) => Ok(()),
+        Err(err) => Err(format!("fork failed: {err}")),
+    }
+}
+
+fn redirect_stdio() -> Result<(), String> {
+    let null = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .map_err(|err| format!("failed to open /dev/null: {err}"))?;
+
+    dup2_stdin(&null)
+        .and_then(|_| dup2_stdout(&null))
+        .and_then(|_| dup2_stderr(&null))
+        .map_err(|err| format!("failed to redirect standard streams: {err}"))
+}
+
+/// The pid in `pidfile`, if that process is still alive.
+fn running_pid(pidfile: &Path) -> Option<i32> {
+    let pid = std::fs::read_to_string(pidfile).ok()?.trim().parse().ok()?;
+    match kill(Pid::from_raw(pid), None) {
+        Ok(()) | Err(Errno::EPERM) => Some(pid),
+        Err(_) => None,
+    }
+}
@@ -0,0 +1,141 @@
+//! USB string descriptors without opening every device. Linux exposes the
+//! strings the kernel read at enumeration in sysfs; elsewhere, or when sysfs
+//! lacks them, the device is opened once and the strings are cached for as
+//! long as it stays at its bus address.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use rusb::{Context, Device, DeviceDescriptor, DeviceHandle};
+use tracing::debug;
+
+/// The product, manufacturer and serial strings of a device, each `None`
+/// when the device has none or it could not be read.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceStrings {
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub serial: Option<String>,
+}
+
+/// A device at one bus address. Addresses are reused only after the bus
+/// wraps around, so with the VID:PID this names a single connection.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct CacheKey {
+    bus: u8,
+    address: u8,
+    vendor_id: u16,
+    product_id: u16,
+}
+
+fn cache() -> &'static Mutex<HashMap<CacheKey, DeviceStrings>> {
+    static CACHE: OnceLock<Mutex<HashMap<CacheKey, DeviceStrings>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// The strings of `device`, from the cache, sysfs, or the device itself.
+pub fn strings(device: &Device<Context>, descriptor: &DeviceDescriptor) -> DeviceStrings {
+    let key = CacheKey {
+        bus: device.bus_number(),
+        address: device.address(),
+        vendor_id: descriptor.vendor_id(),
+        product_id: descriptor.product_id(),
+    };
+    if let Some(strings) = cache()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(&key)
+    {
+        return strings.clone();
+    }
+
+    // a device that could not be opened is tried again next time
+    let Some(strings) = sysfs_strings(device).or_else(|| read_strings(device, descriptor)) else {
+        return DeviceStrings::default();
+    };
+    cache()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(key, strings.clone());
+    strings
+}
+
+/// Drop cached strings of devices that are no longer connected, given the
+/// `(bus, address)` of every device that is.
+pub fn retain_connected(connected: &[(u8, u8)]) {
+    cache()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .retain(|key, _| connected.contains(&(key.bus, key.address)));
+}
+
+/// Read the strings from `/sys/bus/usb/devices`, where a device is named by
+/// its bus and port path (`1-2.4`) and root hubs are `usb1`. `None` when the
+/// device has no sysfs entry or the entry has no product string.
+#[cfg(target_os = "linux")]
+fn sysfs_strings(device: &Device<Context>) -> Option<DeviceStrings> {
+    use std::fs;
+    use std::path::Path;
+
+    let ports = device.port_numbers().ok()?;
+    let name = if ports.is_empty() {
+        format!("usb{}", device.bus_number())
+    } else {
+        let ports: Vec<String> = ports.iter().map(u8::to_string).collect();
+        format!("{}-{}", device.bus_number(), ports.join("."))
+    };
+    let dir = Path::new("/sys/bus/usb/devices").join(name);
+
+    // a stale port path would name whatever is plugged in there now
+    let devnum: u8 = fs::read_to_string(dir.join("devnum"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    if devnum != device.address() {
+        return None;
+    }
+
+    let read = |file: &str| {
+        fs::read_to_string(dir.join(file))
+            .ok()
+            .map(|value| value.trim_end_matches('\n').to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let strings = DeviceStrings {
+        product: read("product"),
+        manufacturer: read("manufacturer"),
+        serial: read("serial"),
+    };
+    strings.product.is_some().then_some(strings)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sysfs_strings(_device: &Device<Context>) -> Option<DeviceStrings> {
+    None
+}
+
+/// Open the device and ask it for its strings, or `None` if it cannot be
+/// opened.
+fn read_strings(device: &Device<Context>, descriptor: &DeviceDescriptor) -> Option<DeviceStrings> {
+    let handle = match device.open() {
+        Ok(handle) => handle,
+        Err(err) => {
+            debug!(
+                bus = device.bus_number(),
+                address = device.address(),
+                error = %err,
+                "could not open device for its strings"
+            );
+            return None;
+        }
+    };
+    let read = |read: fn(&DeviceHandle<Context>, &DeviceDescriptor) -> rusb::Result<String>| {
+        read(&handle, descriptor).ok()
+    };
+    Some(DeviceStrings {
+        product: read(DeviceHandle::read_product_string_ascii),
+        manufacturer: read(DeviceHandle::read_manufacturer_string_ascii),
+        serial: read(DeviceHandle::read_serial_number_string_ascii),
+    })
+}
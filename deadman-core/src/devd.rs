@@ -0,0 +1,143 @@
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+use tracing::{debug, warn};
+
+/// devd's stream socket, which replays every event it matches to each client.
+const DEVD_PIPE: &str = "/var/run/devd.pipe";
+
+/// A USB device arriving or leaving, as reported by devd.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DevdEvent {
+    pub attached: bool,
+    pub bus: u8,
+    pub address: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+
+/// Follow devd's USB attach and detach notifications, calling `on_event`
+/// for each. FreeBSD's libusb only sees hotplug events through devd too, so
+/// this keeps tethers watched when libusb's own hotplug thread is not
+/// running.
+pub fn watch(on_event: impl Fn(DevdEvent) + Send + 'static) -> Result<(), String> {
+    let stream = UnixStream::connect(DEVD_PIPE)
+        .map_err(|err| format!("failed to connect to {DEVD_PIPE}: {err}"))?;
+
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if let Some(event) = parse_event(&line) {
+                debug!(?event, "devd USB event");
+                on_event(event);
+            }
+        }
+        warn!("devd connection closed; USB events now rely on libusb alone");
+    });
+
+    Ok(())
+}
+
+/// Parse a devd notification such as
+/// `!system=USB subsystem=DEVICE type=DETACH ugen=ugen0.2 cdev=ugen0.2 vendor=0x046d product=0xc52b ...`.
+/// The `ugen` name is `ugen<bus>.<address>`.
+fn parse_event(line: &str) -> Option<DevdEvent> {
+    let fields = line.strip_prefix('!')?;
+    let mut system = None;
+    let mut subsystem = None;
+    let mut kind = None;
+    let mut ugen = None;
+    let mut vendor_id = None;
+    let mut product_id = None;
+    for field in fields.split_whitespace() {
+        let Some((name, value)) = field.split_once('=') else {
+            continue;
+        };
+        match name {
+            "system" => system = Some(value),
+            "subsystem" => subsystem = Some(value),
+            "type" => kind = Some(value),
+            "ugen" => ugen = Some(value),
+            "vendor" => vendor_id = parse_hex(value),
+            "product" => product_id = parse_hex(value),
+            _ => {}
+        }
+    }
+
+    if system != Some("USB") || subsystem != Some("DEVICE") {
+        return None;
+    }
+    let attached = match kind? {
+        "ATTACH" => true,
+        "DETACH" => false,
+        _ => return None,
+    };
+    let (bus, address) = ugen?.strip_prefix("ugen")?.split_once('.')?;
+
+    Some(DevdEvent {
+        attached,
+        bus: bus.parse().ok()?,
+        address: address.parse().ok()?,
+        vendor_id: vendor_id?,
+        product_id: product_id?,
+    })
+}
+
+fn parse_hex(value: &str) -> Option<u16> {
+    u16::from_str_radix(value.strip_prefix("0x").unwrap_or(value), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attach() {
+        let line = "!system=USB subsystem=DEVICE type=ATTACH ugen=ugen0.2 cdev=ugen0.2 vendor=0x1050 product=0x0407 devclass=0x00 devsubclass=0x00 sernum=\"\" release=0x0543 mode=host port=1 parent=ugen0.1";
+        assert_eq!(
+            parse_event(line),
+            Some(DevdEvent {
+                attached: true,
+                bus: 0,
+                address: 2,
+                vendor_id: 0x1050,
+                product_id: 0x0407,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_detach() {
+        let line = "!system=USB subsystem=DEVICE type=DETACH ugen=ugen1.12 cdev=ugen1.12 vendor=0x046d product=0xc52b";
+        assert_eq!(
+            parse_event(line),
+            Some(DevdEvent {
+                attached: false,
+                bus: 1,
+                address: 12,
+                vendor_id: 0x046d,
+                product_id: 0xc52b,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_other_and_malformed_lines() {
+        for line in [
+            "",
+            "garbage",
+            // interface events come with every device attach
+            "!system=USB subsystem=INTERFACE type=ATTACH ugen=ugen0.2 vendor=0x1050 product=0x0407",
+            "!system=IFNET subsystem=em0 type=LINK_UP",
+            "+uhub0 at bus=0 ...",
+            "!system=USB subsystem=DEVICE type=SUSPEND ugen=ugen0.2 vendor=0x1050 product=0x0407",
+            "!system=USB subsystem=DEVICE type=ATTACH ugen=usb0.2 vendor=0x1050 product=0x0407",
+            "!system=USB subsystem=DEVICE type=ATTACH ugen=ugen0.x vendor=0x1050 product=0x0407",
+            "!system=USB subsystem=DEVICE type=ATTACH ugen=ugen0.2 vendor=0xzz product=0x0407",
+            "!system=USB subsystem=DEVICE type=DETACH ugen=ugen0.2 vendor=0x1050",
+        ] {
+            assert_eq!(parse_event(line), None, "{line:?}");
+        }
+    }
+}
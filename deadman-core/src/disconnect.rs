@@ -0,0 +1,162 @@
+//! Tells a device that was really unplugged from one that a controller
+//! briefly reported gone, as some do during selective suspend or an xHCI
+//! reset.
+
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use rusb::{Context, UsbContext};
+
+use crate::descriptors;
+use crate::sleep;
+
+/// Longest pause before a removal is checked, however it is configured.
+pub const MAX_SETTLE: Duration = Duration::from_secs(2);
+
+/// How long after the system sleeps or resumes a removal is suspect.
+const AROUND_SLEEP: Duration = Duration::from_secs(30);
+
+/// How sure the daemon is that a removal was an unplug.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Confidence {
+    High,
+    Low,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Confidence::High => "high",
+            Confidence::Low => "low",
+        })
+    }
+}
+
+pub struct Judgement {
+    pub confidence: Confidence,
+    /// Why, for the `removed` event and the log.
+    pub reason: String,
+    /// The device is attached at its old address after all, so the
+    /// removal was a glitch rather than a re-enumeration.
+    pub still_attached: bool,
+}
+
+/// What the monitor knows of the device that libusb said left.
+pub struct Departed<'a> {
+    pub bus: u8,
+    pub address: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial: Option<&'a str>,
+}
+
+/// Wait `settle` and look at the bus again for the `departed` device.
+pub fn judge(departed: &Departed<'_>, settle: Duration) -> Judgement {
+    thread::sleep(settle.min(MAX_SETTLE));
+
+    let Departed {
+        bus,
+        address,
+        vendor_id,
+        product_id,
+        ..
+    } = *departed;
+    if attached(bus, address, vendor_id, product_id) {
+        return Judgement {
+            confidence: Confidence::Low,
+            reason: "device is still attached at the same address".to_string(),
+            still_attached: true,
+        };
+    }
+    if let Some(new_address) = re_enumerated(departed) {
+        return Judgement {
+            confidence: Confidence::Low,
+            reason: format!(
+                "a device with the same ids appeared at address {new_address:03}, as after a bus reset"
+            ),
+            still_attached: false,
+        };
+    }
+    if let Some(since) = sleep::since_transition().filter(|since| *since < AROUND_SLEEP) {
+        return Judgement {
+            confidence: Confidence::Low,
+            reason: format!("{}s after the system slept or resumed", since.as_secs()),
+            still_attached: false,
+        };
+    }
+    Judgement {
+        confidence: Confidence::High,
+        reason: "device is gone from the bus".to_string(),
+        still_attached: false,
+    }
+}
+
+/// Whether the kernel still lists the device, by its sysfs entry where
+/// there is one and through libusb otherwise.
+#[cfg(target_os = "linux")]
+fn attached(bus: u8, address: u8, vendor_id: u16, product_id: u16) -> bool {
+    use std::fs;
+
+    let Ok(entries) = fs::read_dir("/sys/bus/usb/devices") else {
+        return libusb_attached(bus, address, vendor_id, product_id);
+    };
+    entries.flatten().any(|entry| {
+        let dir = entry.path();
+        let read = |file: &str| {
+            fs::read_to_string(dir.join(file))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        read("busnum").parse() == Ok(bus)
+            && read("devnum").parse() == Ok(address)
+            && u16::from_str_radix(&read("idVendor"), 16) == Ok(vendor_id)
+            && u16::from_str_radix(&read("idProduct"), 16) == Ok(product_id)
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn attached(bus: u8, address: u8, vendor_id: u16, product_id: u16) -> bool {
+    libusb_attached(bus, address, vendor_id, product_id)
+}
+
+fn libusb_attached(bus: u8, address: u8, vendor_id: u16, product_id: u16) -> bool {
+    let Ok(context) = Context::new() else {
+        return false;
+    };
+    let Ok(devices) = context.devices() else {
+        return false;
+    };
+    devices.iter().any(|device| {
+        device.bus_number() == bus
+            && device.address() == address
+            && device.device_descriptor().is_ok_and(|descriptor| {
+                descriptor.vendor_id() == vendor_id && descriptor.product_id() == product_id
+            })
+    })
+}
+
+/// The new address of the same device on the same bus, if it has come back
+/// at one. Without a serial to go by, another device of the same model
+/// counts too.
+fn re_enumerated(departed: &Departed<'_>) -> Option<u8> {
+    let context = Context::new().ok()?;
+    let devices = context.devices().ok()?;
+    devices.iter().find_map(|device| {
+        if device.bus_number() != departed.bus || device.address() == departed.address {
+            return None;
+        }
+        let descriptor = device.device_descriptor().ok()?;
+        if descriptor.vendor_id() != departed.vendor_id
+            || descriptor.product_id() != departed.product_id
+        {
+            return None;
+        }
+        if let Some(serial) = departed.serial
+            && descriptors::strings(&device, &descriptor).serial.as_deref() != Some(serial)
+        {
+            return None;
+        }
+        Some(device.address())
+    })
+}
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use deadman_ipc::events::Event;
+use tracing::{debug, error};
+
+/// Daemon-wide event bus. Every `subscribe` connection gets its own channel;
+/// channels whose client has gone away are dropped on the next publish.
+pub static EVENTS: EventBus = EventBus::new();
+
+/// Number of past events kept for the `log` command.
+const HISTORY_LIMIT: usize = 256;
+
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<String>>>,
+    history: Mutex<VecDeque<Event>>,
+}
+
+impl EventBus {
+    const fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The most recent events, oldest first.
+    pub fn recent(&self, limit: usize) -> Vec<Event> {
+        let history = self.history.lock().unwrap_or_else(|err| err.into_inner());
+        let skip = history.len().saturating_sub(limit);
+        history.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: Event) {
+        let line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(err) => {
+                error!(error = %err, "failed to serialize event");
+                return;
+            }
+        };
+
+        debug!(event = %line, "publishing event");
+
+        {
+            let mut history = self.history.lock().unwrap_or_else(|err| err.into_inner());
+            if history.len() == HISTORY_LIMIT {
+                history.pop_front();
+            }
+            history.push_back(event);
+        }
+
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .retain(|subscriber| subscriber.send(line.clone()).is_ok());
+    }
+}
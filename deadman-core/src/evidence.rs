@@ -0,0 +1,114 @@
+use std::fs::DirBuilder;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use tracing::{info, warn};
+
+use crate::lock::on_path;
+
+/// The webcam frames are taken from.
+const CAMERA_DEVICE: &str = "/dev/video0";
+
+/// Grab a webcam frame and a screenshot into `dir` from a background thread,
+/// so the removal action isn't held up. Files are named
+/// `<timestamp>-<name>-camera.jpg` and `<timestamp>-<name>-screen.png`.
+pub fn capture(dir: PathBuf, timestamp: u64, name: String) {
+    thread::spawn(move || {
+        if let Err(err) = DirBuilder::new().recursive(true).mode(0o700).create(&dir) {
+            warn!(dir = %dir.display(), error = %err, "failed to create the audit directory");
+            return;
+        }
+
+        let camera = dir.join(format!("{timestamp}-{name}-camera.jpg"));
+        match snapshot(&camera) {
+            Ok(()) => info!(path = %camera.display(), "saved webcam snapshot"),
+            Err(err) => warn!(error = %err, "failed to take a webcam snapshot"),
+        }
+
+        let screen = dir.join(format!("{timestamp}-{name}-screen.png"));
+        match screenshot(&screen) {
+            Ok(()) => info!(path = %screen.display(), "saved screenshot"),
+            Err(err) => warn!(error = %err, "failed to take a screenshot"),
+        }
+    });
+}
+
+/// Dry-run steps: the camera and the capture tools are there.
+pub fn dry_run(dir: &Path) -> Vec<Result<String, String>> {
+    let camera = if !Path::new(CAMERA_DEVICE).exists() {
+        Err(format!("{CAMERA_DEVICE} does not exist"))
+    } else if on_path("ffmpeg") || on_path("fswebcam") {
+        Ok(format!(
+            "would save a {CAMERA_DEVICE} frame to {}",
+            dir.display()
+        ))
+    } else {
+        Err("neither ffmpeg nor fswebcam is in PATH".to_string())
+    };
+    let tool = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        "grim"
+    } else {
+        "import"
+    };
+    let screen = if on_path(tool) {
+        Ok(format!("would save a screenshot with {tool}"))
+    } else {
+        Err(format!("{tool} not found in PATH"))
+    };
+    vec![camera, screen]
+}
+
+/// One frame from the v4l2 camera, with ffmpeg or else fswebcam.
+fn snapshot(path: &Path) -> Result<(), String> {
+    if !Path::new(CAMERA_DEVICE).exists() {
+        return Err(format!("{CAMERA_DEVICE} does not exist"));
+    }
+    run(Command::new("ffmpeg")
+        .args([
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            "video4linux2",
+            "-i",
+            CAMERA_DEVICE,
+        ])
+        .args(["-frames:v", "1"])
+        .arg(path))
+    .or_else(|_| {
+        run(Command::new("fswebcam")
+            .args(["--no-banner", "--quiet", "--device", CAMERA_DEVICE])
+            .arg(path))
+    })
+}
+
+/// The daemon's session screen: `grim` on Wayland, ImageMagick's `import`
+/// on X11.
+fn screenshot(path: &Path) -> Result<(), String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        run(Command::new("grim").arg(path))
+    } else {
+        run(Command::new("import").args(["-window", "root"]).arg(path))
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|err| format!("failed to run {program}: {err}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!(
+            "{program} exited with status {}: {}",
+            output.status,
+            stderr.trim()
+        ))
+    }
+}
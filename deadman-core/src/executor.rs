@@ -0,0 +1,55 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use tracing::error;
+
+/// Actions that may run at once. More than one, so a hung script does not
+/// hold up every other tether's action until it times out.
+const WORKERS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Run `job` on one of the action workers and return a receiver for its
+/// result, so the monitor that triggered it can keep servicing its USB
+/// context meanwhile. Jobs start in the order they were submitted.
+pub fn submit<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> Receiver<T> {
+    let (done, result) = mpsc::channel();
+    let job: Job = Box::new(move || {
+        let _ = done.send(job());
+    });
+    if let Err(mpsc::SendError(job)) = queue().send(job) {
+        // no worker could be started; run it here rather than not at all
+        job();
+    }
+    result
+}
+
+fn queue() -> &'static Sender<Job> {
+    static QUEUE: OnceLock<Sender<Job>> = OnceLock::new();
+    QUEUE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker in 0..WORKERS {
+            let receiver = Arc::clone(&receiver);
+            let spawned = thread::Builder::new()
+                .name(format!("action-{worker}"))
+                .spawn(move || {
+                    loop {
+                        let job = receiver
+                            .lock()
+                            .unwrap_or_else(|err| err.into_inner())
+                            .recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => return,
+                        }
+                    }
+                });
+            if let Err(err) = spawned {
+                error!(error = %err, "failed to start an action worker");
+            }
+        }
+        sender
+    })
+}
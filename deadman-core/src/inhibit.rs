@@ -0,0 +1,48 @@
+use std::process::{Child, Command, Stdio};
+
+use tracing::{debug, warn};
+
+/// A logind sleep inhibitor held through `systemd-inhibit`. The lock lives as
+/// long as the child does; the child is `cat` reading our pipe, so it exits
+/// and releases the lock when this is dropped or the daemon dies.
+pub struct SleepInhibitor {
+    child: Child,
+}
+
+impl SleepInhibitor {
+    /// Take a delay inhibitor for sleep. Returns `None`, after logging, when
+    /// logind is unavailable; the countdown still runs without it.
+    pub fn take(why: &str) -> Option<Self> {
+        let spawned = Command::new("systemd-inhibit")
+            .args(["--what=sleep", "--mode=delay", "--who=deadman"])
+            .arg(format!("--why={why}"))
+            .arg("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        match spawned {
+            Ok(child) => {
+                debug!(why, "took sleep inhibitor");
+                Some(Self { child })
+            }
+            Err(err) => {
+                warn!(error = %err, "failed to take a sleep inhibitor");
+                None
+            }
+        }
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        // closing stdin ends `cat`, which ends systemd-inhibit
+        drop(self.child.stdin.take());
+        if let Err(err) = self.child.wait() {
+            warn!(error = %err, "failed to release sleep inhibitor");
+        } else {
+            debug!("released sleep inhibitor");
+        }
+    }
+}
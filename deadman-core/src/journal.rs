@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::reexec::{SavedRule, SavedState, SavedTether, SavedTriggers};
+
+/// Tether changes, written ahead so a daemon restarted after a crash picks
+/// up exactly the tethers it had.
+pub static JOURNAL: Journal = Journal::new();
+
+/// Records appended before the journal is rewritten from its live state.
+const COMPACT_AFTER: usize = 256;
+
+/// Trigger times older than this no longer count towards any limit.
+const TRIGGER_HISTORY_SECS: u64 = 60 * 60;
+
+/// One line of the journal, after its checksum.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+enum Record {
+    /// A tether was created, or its settings changed.
+    Tether(SavedTether),
+    /// The tether on this bus address is gone, or its action has started.
+    Untether { bus: u8, address: u8 },
+    /// A removal action ran, at this many seconds since the Unix epoch.
+    Trigger { bus: u8, address: u8, at: u64 },
+    /// A rule was added.
+    Rule(SavedRule),
+    /// The rule with this ID was removed.
+    Unrule { id: u64 },
+}
+
+pub struct Journal {
+    open: Mutex<Option<Open>>,
+}
+
+/// The journal file and what replaying it gives, for rewriting it compactly.
+struct Open {
+    path: PathBuf,
+    file: File,
+    appended: usize,
+    tethers: BTreeMap<(u8, u8), SavedTether>,
+    triggers: BTreeMap<(u8, u8), Vec<u64>>,
+    rules: BTreeMap<u64, SavedRule>,
+}
+
+/// What a journal replayed to, and where it stopped early if it did.
+pub struct Replayed {
+    pub state: SavedState,
+    /// Why records at the end were ignored, for a torn or damaged file.
+    pub corrupt: Option<String>,
+}
+
+impl Journal {
+    const fn new() -> Self {
+        Self {
+            open: Mutex::new(None),
+        }
+    }
+
+    /// Start journaling to `path`, which is rewritten to hold `state`.
+    pub fn start(&self, path: &Path, state: &SavedState) -> Result<(), String> {
+        let now = unix_now();
+        let tethers = state
+            .tethers
+            .iter()
+            .map(|tether| ((tether.bus, tether.address), tether.clone()))
+            .collect();
+        let triggers = state
+            .triggers
+            .iter()
+            .map(|history| {
+                let times = history
+                    .ages_ms
+                    .iter()
+                    .map(|age| now.saturating_sub(age / 1000))
+                    .collect();
+                ((history.bus, history.address), times)
+            })
+            .collect();
+        let rules = state
+            .rules
+            .iter()
+            .map(|rule| (rule.id, rule.clone()))
+            .collect();
+        let mut open = Open {
+            path: path.to_path_buf(),
+            file: create(path)?,
+            appended: 0,
+            tethers,
+            triggers,
+            rules,
+        };
+        open.compact()?;
+        info!(path = %path.display(), "journaling tethers");
+        *self.open.lock().unwrap_or_else(|err| err.into_inner()) = Some(open);
+        Ok(())
+    }
+
+    /// Stop journaling and remove the file, so nothing is restored from it.
+    pub fn stop(&self) {
+        if let Some(open) = self
+            .open
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+        {
+            let _ = fs::remove_file(&open.path);
+            info!("stopped journaling tethers");
+        }
+    }
+
+    pub fn tethered(&self, tether: SavedTether) {
+        self.append(Record::Tether(tether));
+    }
+
+    pub fn untethered(&self, bus: u8, address: u8) {
+        self.append(Record::Untether { bus, address });
+    }
+
+    pub fn triggered(&self, bus: u8, address: u8) {
+        self.append(Record::Trigger {
+            bus,
+            address,
+            at: unix_now(),
+        });
+    }
+
+    pub fn ruled(&self, rule: SavedRule) {
+        self.append(Record::Rule(rule));
+    }
+
+    pub fn unruled(&self, id: u64) {
+        self.append(Record::Unrule { id });
+    }
+
+    fn append(&self, record: Record) {
+        let mut guard = self.open.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(open) = guard.as_mut() else {
+            return;
+        };
+        open.apply(&record);
+        let written = if open.appended >= COMPACT_AFTER {
+            open.compact()
+        } else {
+            open.write(&record)
+        };
+        if let Err(err) = written {
+            warn!(path = %open.path.display(), error = %err, "failed to write the tether journal");
+        }
+    }
+}
+
+impl Open {
+    fn apply(&mut self, record: &Record) {
+        match record {
+            Record::Tether(tether) => {
+                self.tethers
+                    .insert((tether.bus, tether.address), tether.clone());
+            }
+            Record::Untether { bus, address } => {
+                self.tethers.remove(&(*bus, *address));
+            }
+            Record::Trigger { bus, address, at } => {
+                self.triggers.entry((*bus, *address)).or_default().push(*at);
+            }
+            Record::Rule(rule) => {
+                self.rules.insert(rule.id, rule.clone());
+            }
+            Record::Unrule { id } => {
+                self.rules.remove(id);
+            }
+        }
+    }
+
+    /// Append one record and wait for it to reach the disk.
+    fn write(&mut self, record: &Record) -> Result<(), String> {
+        self.file
+            .write_all(encode(record)?.as_bytes())
+            .and_then(|()| self.file.sync_data())
+            .map_err(|err| err.to_string())?;
+        self.appended += 1;
+        Ok(())
+    }
+
+    /// Replace the file with one record per rule, live tether and recent
+    /// trigger.
+    fn compact(&mut self) -> Result<(), String> {
+        let cutoff = unix_now().saturating_sub(TRIGGER_HISTORY_SECS);
+        self.triggers.retain(|_, times| {
+            times.retain(|at| *at >= cutoff);
+            !times.is_empty()
+        });
+
+        let mut contents = String::new();
+        for rule in self.rules.values() {
+            contents.push_str(&encode(&Record::Rule(rule.clone()))?);
+        }
+        for tether in self.tethers.values() {
+            contents.push_str(&encode(&Record::Tether(tether.clone()))?);
+        }
+        for (&(bus, address), times) in &self.triggers {
+            for &at in times {
+                contents.push_str(&encode(&Record::Trigger { bus, address, at })?);
+            }
+        }
+
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".tmp");
+        let partial = PathBuf::from(partial);
+        let mut file = create(&partial)?;
+        file.write_all(contents.as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|err| format!("failed to write {}: {err}", partial.display()))?;
+        fs::rename(&partial, &self.path)
+            .map_err(|err| format!("failed to replace {}: {err}", self.path.display()))?;
+        self.file = file;
+        self.appended = 0;
+        Ok(())
+    }
+}
+
+/// Read the journal at `path` back into the state it records. Replay stops
+/// at the first record whose checksum or contents don't hold up, as after a
+/// crash mid-write; everything before it is kept.
+pub fn replay(path: &Path) -> Result<Option<Replayed>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("failed to open {}: {err}", path.display())),
+    };
+
+    let mut tethers: BTreeMap<(u8, u8), SavedTether> = BTreeMap::new();
+    let mut triggers: BTreeMap<(u8, u8), Vec<u64>> = BTreeMap::new();
+    let mut rules: BTreeMap<u64, SavedRule> = BTreeMap::new();
+    let mut corrupt = None;
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let record = line
+            .map_err(|err| err.to_string())
+            .and_then(|line| decode(&line));
+        match record {
+            Ok(Record::Tether(tether)) => {
+                tethers.insert((tether.bus, tether.address), tether);
+            }
+            Ok(Record::Untether { bus, address }) => {
+                tethers.remove(&(bus, address));
+            }
+            Ok(Record::Trigger { bus, address, at }) => {
+                triggers.entry((bus, address)).or_default().push(at);
+            }
+            Ok(Record::Rule(rule)) => {
+                rules.insert(rule.id, rule);
+            }
+            Ok(Record::Unrule { id }) => {
+                rules.remove(&id);
+            }
+            Err(err) => {
+                corrupt = Some(format!("line {}: {err}", number + 1));
+                break;
+            }
+        }
+    }
+
+    let now = unix_now();
+    let triggers = triggers
+        .into_iter()
+        .map(|((bus, address), times)| SavedTriggers {
+            bus,
+            address,
+            ages_ms: times
+                .into_iter()
+                .filter(|at| now.saturating_sub(*at) < TRIGGER_HISTORY_SECS)
+                .map(|at| now.saturating_sub(at) * 1000)
+                .collect(),
+        })
+        .collect();
+    Ok(Some(Replayed {
+        state: SavedState {
+            tethers: tethers.into_values().collect(),
+            triggers,
+            rules: rules.into_values().collect(),
+        },
+        corrupt,
+    }))
+}
+
+/// `<crc32 in hex> <json>`, newline terminated.
+fn encode(record: &Record) -> Result<String, String> {
+    let json = serde_json::to_string(record)
+        .map_err(|err| format!("failed to serialize journal record: {err}"))?;
+    Ok(format!("{:08x} {json}\n", crc32(json.as_bytes())))
+}
+
+fn decode(line: &str) -> Result<Record, String> {
+    let (checksum, json) = line
+        .split_once(' ')
+        .ok_or_else(|| "record has no checksum".to_string())?;
+    let checksum =
+        u32::from_str_radix(checksum, 16).map_err(|_| "record has no checksum".to_string())?;
+    if crc32(json.as_bytes()) != checksum {
+        return Err("checksum mismatch".to_string());
+    }
+    serde_json::from_str(json).map_err(|err| format!("invalid record: {err}"))
+}
+
+/// CRC-32 as used by zlib and Ethernet.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Open `path` for appending, empty and readable only by the daemon's user.
+fn create(path: &Path) -> Result<File, String> {
+    let mut options = OpenOptions::new();
+    options.create(true).truncate(true).write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .map_err(|err| format!("failed to open {}: {err}", path.display()))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tether(bus: u8, address: u8) -> SavedTether {
+        SavedTether {
+            id: u64::from(address),
+            bus,
+            address,
+            vendor_id: 0x1050,
+            product_id: 0x0407,
+            product: Some("YubiKey".to_string()),
+            serial: None,
+            action: "lock".to_string(),
+            grace_secs: 5,
+            cooldown_secs: 0,
+            rearm: false,
+            poll: false,
+            owner: 1000,
+            rule: None,
+        }
+    }
+
+    /// Replay `contents` from a journal file of its own.
+    fn replay_contents(name: &str, contents: &str) -> Replayed {
+        let path = std::env::temp_dir().join(format!(
+            "deadman-journal-{name}-{}.jsonl",
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        let replayed = replay(&path).unwrap().expect("the journal exists");
+        fs::remove_file(&path).unwrap();
+        replayed
+    }
+
+    fn addresses(replayed: &Replayed) -> Vec<(u8, u8)> {
+        replayed
+            .state
+            .tethers
+            .iter()
+            .map(|tether| (tether.bus, tether.address))
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_valid_journal() {
+        let contents = [
+            Record::Tether(tether(1, 4)),
+            Record::Tether(tether(1, 5)),
+            Record::Untether { bus: 1, address: 4 },
+            Record::Trigger {
+                bus: 1,
+                address: 5,
+                at: unix_now(),
+            },
+        ]
+        .iter()
+        .map(|record| encode(record).unwrap())
+        .collect::<String>();
+
+        let replayed = replay_contents("valid", &contents);
+        assert!(replayed.corrupt.is_none());
+        assert_eq!(addresses(&replayed), [(1, 5)]);
+        assert_eq!(replayed.state.triggers.len(), 1);
+        assert_eq!(replayed.state.triggers[0].ages_ms.len(), 1);
+    }
+
+    #[test]
+    fn test_replay_keeps_records_before_a_truncated_last_line() {
+        let mut contents = encode(&Record::Tether(tether(1, 4))).unwrap();
+        let torn = encode(&Record::Tether(tether(1, 5))).unwrap();
+        contents.push_str(&torn[..torn.len() / 2]);
+
+        let replayed = replay_contents("truncated", &contents);
+        assert_eq!(addresses(&replayed), [(1, 4)]);
+        assert_eq!(
+            replayed.corrupt.as_deref(),
+            Some("line 2: checksum mismatch")
+        );
+    }
+
+    #[test]
+    fn test_replay_stops_at_a_garbage_line() {
+        let contents = [
+            encode(&Record::Tether(tether(1, 4))).unwrap(),
+            "not a journal record\n".to_string(),
+            encode(&Record::Tether(tether(1, 5))).unwrap(),
+        ]
+        .concat();
+
+        let replayed = replay_contents("garbage", &contents);
+        assert_eq!(addresses(&replayed), [(1, 4)]);
+        assert_eq!(
+            replayed.corrupt.as_deref(),
+            Some("line 2: record has no checksum")
+        );
+    }
+
+    #[test]
+    fn test_replay_missing_journal() {
+        let path = std::env::temp_dir().join("deadman-journal-missing.jsonl");
+        assert!(replay(&path).unwrap().is_none());
+    }
+}
@@ -0,0 +1,4217 @@
+//! The deadman daemon: USB hotplug monitoring, removal actions, and the IPC
+//! command handlers. Shared by the `deadmand` binary and `deadman daemon`.
+
+mod actions;
+mod actor;
+#[cfg(target_os = "linux")]
+mod audit;
+mod command;
+mod config;
+#[cfg(unix)]
+mod daemonize;
+mod descriptors;
+#[cfg(target_os = "freebsd")]
+mod devd;
+mod disconnect;
+mod events;
+#[cfg(unix)]
+mod evidence;
+mod executor;
+mod inhibit;
+mod journal;
+mod lifecycle;
+mod lock;
+mod logging;
+mod metrics;
+mod network;
+mod otlp;
+#[cfg(unix)]
+mod privileges;
+mod reexec;
+mod relock;
+mod script;
+mod scrub;
+mod selector;
+mod sessions;
+mod siem;
+mod sleep;
+mod snapshot;
+mod units;
+mod usbguard;
+mod workloads;
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use deadman_ipc::devices::{Configuration, DeviceDescription, Endpoint, Interface, UsbDevice};
+use deadman_ipc::events::{ActionResult, Event, EventKind};
+use deadman_ipc::fleet::{Report, TetherReport};
+use deadman_ipc::router::{Args, FromArgs, Router};
+use deadman_ipc::server::{ClientGroup, PeerInfo, Reply, Request, start_ipc_server};
+use rusb::{Context, Device, Hotplug, HotplugBuilder, Registration, UsbContext};
+use tracing::field::Empty;
+use tracing::{Span, debug, error, info, info_span, warn};
+
+use crate::actions::Action;
+use crate::actor::Actor;
+use crate::config::Config;
+use crate::events::EVENTS;
+use crate::inhibit::SleepInhibitor;
+use crate::journal::JOURNAL;
+use crate::selector::{Pattern, Scope, Selector};
+
+#[cfg(unix)]
+pub use crate::daemonize::{DEFAULT_PIDFILE, daemonize, default_pidfile};
+pub use crate::lifecycle::{Lifecycle, Phase};
+
+/// How long a monitor blocks in libusb before re-checking its flags.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Without a heartbeat for this long a monitor thread is presumed stuck or
+/// dead and the watchdog restarts it.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// First and longest pause before recreating a USB context after an error.
+const USB_RETRY_BASE: Duration = Duration::from_millis(500);
+const USB_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// How many finished tethers `status` reports, and for how long unless
+/// they fired and wait to be acknowledged.
+const FINISHED_KEPT: usize = 32;
+const FINISHED_KEPT_FOR: Duration = Duration::from_secs(60 * 60);
+
+/// How often a monitor checks that its device is still enumerated, in case
+/// the hotplug callback missed the removal, and how often once it has.
+const PRESENCE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Floor for `poll_interval_ms`, so a typo cannot keep a core busy.
+const MIN_POLL_INTERVAL_MS: u64 = 100;
+
+/// How often arriving devices are looked for while a tether waits to
+/// re-arm or a rule is in place.
+const ARRIVAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest a pre-trigger hook may put the action off, all delays
+/// together; after that the action runs.
+const MAX_HOOK_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// How long sleep is held up for removal actions, inside logind's default
+/// five second `InhibitDelayMaxSec`.
+const SLEEP_ACTION_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// How long `re-exec` waits after replying before replacing the process.
+#[cfg(unix)]
+const REEXEC_DELAY: Duration = Duration::from_millis(100);
+
+/// Run the daemon in the foreground until the IPC server exits.
+pub fn run() {
+    let saved = reexec::take();
+    let loaded = Config::load();
+    let log_config = loaded
+        .as_ref()
+        .map(|config| config.log.clone())
+        .unwrap_or_default();
+    for err in logging::init(&log_config) {
+        warn!(error = %err, "logging is not set up as configured");
+    }
+
+    info!("deadmand starting");
+
+    let config = loaded.unwrap_or_else(|err| {
+        error!(error = %err, "failed to load config; using defaults");
+        Config::default()
+    });
+
+    check_privileges(&config);
+    share_sockets(&config);
+    lock::detect(&config.lock_backends);
+
+    if !rusb::has_hotplug() {
+        warn!("libusb hotplug support is not available; tether commands will fail");
+    }
+    let state = Actor::spawn(
+        "state",
+        DaemonState {
+            config,
+            ..DaemonState::default()
+        },
+    );
+
+    let saved = match saved {
+        Some(Ok(saved)) => Some(saved),
+        Some(Err(err)) => {
+            error!(error = %err, "failed to restore state after re-exec");
+            None
+        }
+        None => None,
+    };
+    #[cfg(unix)]
+    let saved = saved.or_else(|| recover_journal(&state));
+    if let Some(saved) = saved {
+        restore(&state, saved);
+    }
+    #[cfg(unix)]
+    {
+        state.call(|guard| {
+            if guard.config.state_journal {
+                start_journal(guard);
+            }
+        });
+    }
+
+    if let Err(err) = config::watch({
+        let state = state.clone();
+        move || reload_config(&state)
+    }) {
+        warn!(error = %err, "config changes will need `deadman config reload`");
+    }
+
+    thread::spawn({
+        let state = state.clone();
+        move || supervise_monitors(state)
+    });
+
+    thread::spawn({
+        let state = state.clone();
+        move || watch_arrivals(&state)
+    });
+
+    metrics::REGISTRY.count_events();
+    thread::spawn({
+        let state = state.clone();
+        move || export_metrics(&state)
+    });
+
+    siem::forward({
+        let state = state.clone();
+        move || state.call(|guard| guard.config.siem.clone())
+    });
+
+    #[cfg(target_os = "linux")]
+    audit::forward({
+        let state = state.clone();
+        move || state.call(|guard| guard.config.linux_audit)
+    });
+
+    #[cfg(target_os = "freebsd")]
+    if let Err(err) = devd::watch({
+        let state = state.clone();
+        move |event| apply_devd_event(&state, event)
+    }) {
+        warn!(error = %err, "USB removals are only seen through libusb");
+    }
+
+    if let Err(err) = sleep::watch({
+        let state = state.clone();
+        move || act_before_sleep(&state)
+    }) {
+        warn!(error = %err, "tethers will not be checked before the system sleeps");
+    }
+
+    #[cfg(not(target_vendor = "apple"))]
+    thread::spawn({
+        let state = state.clone();
+        move || {
+            let router = router(&state);
+            let served = deadman_ipc::server::start_seqpacket_server(move |command, peer| {
+                handle_command(command, peer, &router)
+            });
+            if let Err(err) = served {
+                warn!(error = %err, "seqpacket IPC socket unavailable");
+            }
+        }
+    });
+
+    let router = router(&state);
+    start_ipc_server(move |command, peer| handle_command(command, peer, &router));
+}
+
+/// Root can do everything. Without root, make sure the default action can
+/// actually run and warn about capabilities that degrade device listing.
+#[cfg(unix)]
+fn check_privileges(config: &Config) {
+    if privileges::is_root() {
+        return;
+    }
+
+    if deadman_ipc::user_mode() {
+        info!("running as a user daemon; lock actions only lock this session");
+    } else {
+        info!("running without root; checking capabilities for the configured actions");
+    }
+
+    if cfg!(target_os = "linux")
+        && !deadman_ipc::user_mode()
+        && !privileges::has_capability(privileges::CAP_DAC_OVERRIDE)
+    {
+        warn!(
+            "CAP_DAC_OVERRIDE is missing; product names need the udev rule from `deadman install`"
+        );
+    }
+
+    if let Err(err) = privileges::check_action(&config.default_action) {
+        error!(action = %config.default_action, error = %err, "default action cannot run");
+        eprintln!(
+            "Error: default action {} cannot run: {err}",
+            config.default_action
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Open the IPC sockets to `client_group`, if one is configured.
+#[cfg(unix)]
+fn share_sockets(config: &Config) {
+    use nix::unistd::{Group, User};
+
+    let Some(name) = &config.client_group else {
+        return;
+    };
+    match Group::from_name(name) {
+        Ok(Some(group)) => {
+            let members = group
+                .mem
+                .iter()
+                .filter_map(|member| User::from_name(member).ok().flatten())
+                .map(|user| user.uid.as_raw())
+                .collect();
+            info!(group = %name, "members of the client group may connect");
+            deadman_ipc::server::share_with_group(ClientGroup {
+                gid: group.gid.as_raw(),
+                members,
+            });
+        }
+        Ok(None) => {
+            warn!(group = %name, "client group does not exist; only this user and root may connect")
+        }
+        Err(err) => warn!(group = %name, error = %err, "failed to look up client group"),
+    }
+}
+
+#[cfg(not(unix))]
+fn share_sockets(_config: &Config) {}
+
+#[cfg(not(unix))]
+fn check_privileges(_config: &Config) {
+    // On non-Unix systems, skip the privilege check
+    warn!("Privilege checking is not implemented for this platform");
+}
+
+fn handle_command(request: &Request, peer: &PeerInfo, router: &Router) -> Result<Reply, String> {
+    if changes_state(request) {
+        info!(
+            command = %request,
+            request_id = request.id(),
+            uid = peer.uid,
+            gid = peer.gid,
+            pid = peer.pid,
+            "received IPC command"
+        );
+    } else {
+        debug!(
+            command = %request,
+            request_id = request.id(),
+            uid = peer.uid,
+            pid = peer.pid,
+            "received IPC command"
+        );
+    }
+
+    router.handle(request, peer)
+}
+
+/// A device named on the command line by bus number and address.
+#[derive(Clone, Copy, Debug)]
+struct DeviceArg {
+    bus: u8,
+    address: u8,
+}
+
+impl FromArgs for DeviceArg {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        Ok(Self {
+            bus: args.required("bus number")?,
+            address: args.required("device id")?,
+        })
+    }
+}
+
+/// Which tether a management command is about: `id=<n>` as returned by
+/// `tether`, or the tethered device's bus number and address.
+#[derive(Clone, Copy, Debug)]
+enum TetherRef {
+    Id(u64),
+    Device(DeviceArg),
+}
+
+impl FromArgs for TetherRef {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        let word = args
+            .next_word()
+            .ok_or_else(|| "missing bus number or id=<tether id>".to_string())?;
+        if let Some(id) = word.strip_prefix("id=") {
+            return id
+                .parse()
+                .map(TetherRef::Id)
+                .map_err(|_| format!("invalid tether id: {id}"));
+        }
+        Ok(TetherRef::Device(DeviceArg {
+            bus: word
+                .parse()
+                .map_err(|_| format!("invalid bus number: {word}"))?,
+            address: args.required("device id")?,
+        }))
+    }
+}
+
+impl TetherRef {
+    /// The key of the tether this refers to, which must exist.
+    fn resolve(self, state: &DaemonState) -> Result<DeviceKey, String> {
+        match self {
+            TetherRef::Id(id) => state
+                .monitors
+                .iter()
+                .find(|(_, monitor)| monitor.id == id)
+                .map(|(key, _)| *key)
+                .ok_or_else(|| format!("no tether with id {id}")),
+            TetherRef::Device(DeviceArg { bus, address }) => {
+                let key = DeviceKey::new(bus, address);
+                if state.monitors.contains_key(&key) {
+                    Ok(key)
+                } else {
+                    Err(format!("device {bus:03}:{address:03} is not tethered"))
+                }
+            }
+        }
+    }
+}
+
+/// The optional event count of a `log` command; every retained event when
+/// absent.
+struct LogLimit(usize);
+
+impl FromArgs for LogLimit {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        Ok(Self(args.optional("event count")?.unwrap_or(usize::MAX)))
+    }
+}
+
+/// Whether `status` should add how each device is watched: `verbose`.
+struct StatusDetail(bool);
+
+impl FromArgs for StatusDetail {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        match args.next_word() {
+            None => Ok(Self(false)),
+            Some("verbose") => Ok(Self(true)),
+            Some(other) => Err(format!("unknown status option: {other}")),
+        }
+    }
+}
+
+/// The fired tether an `ack` is for, as `id=<n>`; every one when absent.
+struct AckTarget(Option<u64>);
+
+impl FromArgs for AckTarget {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        let Some(word) = args.next_word() else {
+            return Ok(Self(None));
+        };
+        let id = word
+            .strip_prefix("id=")
+            .ok_or_else(|| format!("expected id=<tether id>: {word}"))?;
+        id.parse()
+            .map(|id| Self(Some(id)))
+            .map_err(|_| format!("invalid tether id: {id}"))
+    }
+}
+
+/// The service and user of a `pam-check`, as PAM names them.
+#[derive(Clone)]
+struct PamRequest {
+    service: String,
+    user: String,
+}
+
+impl FromArgs for PamRequest {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        Ok(Self {
+            service: args.required("PAM service")?,
+            user: args.required("user name")?,
+        })
+    }
+}
+
+/// The device an `ensure-tether` converges on or `describe` reports.
+struct EnsureTarget(Selector);
+
+impl FromArgs for EnsureTarget {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        let word = args
+            .next_word()
+            .ok_or_else(|| "missing device selector".to_string())?;
+        word.parse().map(Self)
+    }
+}
+
+/// The devices a `tether-rule` covers.
+struct RulePattern(Pattern);
+
+impl FromArgs for RulePattern {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        let word = args
+            .next_word()
+            .ok_or_else(|| "missing device pattern".to_string())?;
+        word.parse().map(Self)
+    }
+}
+
+/// The rule an `untether-rule` removes.
+struct RuleId(u64);
+
+impl FromArgs for RuleId {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        args.required("rule id").map(Self)
+    }
+}
+
+/// The snapshot file of an `import`, and whether it may be unsigned.
+struct ImportArgs {
+    path: PathBuf,
+    allow_unsigned: bool,
+}
+
+impl FromArgs for ImportArgs {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        let mut words = args.rest();
+        let allow_unsigned = words.last() == Some(&"allow-unsigned");
+        if allow_unsigned {
+            words.pop();
+        }
+        let path = PathBuf::from(words.join(" "));
+        if !path.is_absolute() {
+            return Err(format!(
+                "snapshot path must be absolute: {}",
+                path.display()
+            ));
+        }
+        Ok(Self {
+            path,
+            allow_unsigned,
+        })
+    }
+}
+
+/// The IPC commands and their handlers.
+fn router(state: &State) -> Router {
+    Router::new()
+        .on("status", {
+            let state = state.clone();
+            move |StatusDetail(verbose), _: &Request, _: &PeerInfo| {
+                handle_status(verbose, state.clone())
+            }
+        })
+        .on("doctor", {
+            let state = state.clone();
+            move |(), _: &Request, _: &PeerInfo| handle_doctor(state.clone())
+        })
+        .on("test-action", {
+            let state = state.clone();
+            move |args: Vec<String>, _: &Request, _: &PeerInfo| {
+                handle_test_action(args.iter().map(String::as_str), state.clone())
+            }
+        })
+        .on("protection", {
+            let state = state.clone();
+            move |(), _: &Request, _: &PeerInfo| handle_protection(state.clone())
+        })
+        .on("pam-check", {
+            let state = state.clone();
+            move |request: PamRequest, _: &Request, _: &PeerInfo| {
+                handle_pam_check(&request, state.clone())
+            }
+        })
+        .on("report", {
+            let state = state.clone();
+            move |(), _: &Request, _: &PeerInfo| handle_report(state.clone())
+        })
+        .on("ping", {
+            let state = state.clone();
+            move |(), _: &Request, _: &PeerInfo| handle_ping(state.clone())
+        })
+        .on("tether", {
+            let state = state.clone();
+            move |(device, args): (DeviceArg, Vec<String>), request: &Request, peer: &PeerInfo| {
+                let defaults = state.call(|guard| TetherOptions {
+                    action: guard.config.default_action.clone(),
+                    grace: guard.config.default_grace(),
+                    cooldown: guard.config.default_cooldown(),
+                    rearm: false,
+                    poll: false,
+                });
+                let (options, replace) =
+                    parse_tether_options(defaults, args.iter().map(String::as_str))?;
+                handle_tether(device, options, replace, request, peer, state.clone())
+            }
+        })
+        .on("tether-rule", {
+            let state = state.clone();
+            move |(pattern, args): (RulePattern, Vec<String>), _: &Request, peer: &PeerInfo| {
+                handle_tether_rule(pattern.0, &args, peer, state.clone())
+            }
+        })
+        .on("untether-rule", {
+            let state = state.clone();
+            move |RuleId(id), request: &Request, peer: &PeerInfo| {
+                handle_untether_rule(id, request, peer, state.clone())
+            }
+        })
+        .on("ensure-tether", {
+            let state = state.clone();
+            move |(selector, args): (EnsureTarget, Vec<String>),
+                  request: &Request,
+                  peer: &PeerInfo| {
+                handle_ensure_tether(&selector.0, &args, request, peer, state.clone())
+            }
+        })
+        .on("set", {
+            let state = state.clone();
+            move |(target, args): (TetherRef, Vec<String>), _: &Request, peer: &PeerInfo| {
+                handle_set(target, args.iter().map(String::as_str), peer, state.clone())
+            }
+        })
+        .on("untether", {
+            let state = state.clone();
+            move |target: TetherRef, request: &Request, peer: &PeerInfo| {
+                handle_untether(target, request, peer, state.clone())
+            }
+        })
+        .on("ack", {
+            let state = state.clone();
+            move |AckTarget(id), _: &Request, peer: &PeerInfo| handle_ack(id, peer, state.clone())
+        })
+        .on("severe", {
+            let state = state.clone();
+            move |scope: Scope, request: &Request, peer: &PeerInfo| {
+                handle_severe(&scope, request, peer, state.clone())
+            }
+        })
+        .on("cancel", {
+            let state = state.clone();
+            move |(), _: &Request, peer: &PeerInfo| handle_cancel(peer, state.clone())
+        })
+        .on("list-devices", {
+            let state = state.clone();
+            move |(), _: &Request, _: &PeerInfo| handle_list_devices(state.clone())
+        })
+        .on("describe", {
+            let state = state.clone();
+            move |EnsureTarget(selector), _: &Request, _: &PeerInfo| {
+                handle_describe(&selector, state.clone())
+            }
+        })
+        .on("export", {
+            let state = state.clone();
+            move |(), _: &Request, _: &PeerInfo| handle_export(state.clone())
+        })
+        .on("re-exec", {
+            let state = state.clone();
+            move |(), _: &Request, peer: &PeerInfo| {
+                #[cfg(unix)]
+                return handle_reexec(peer, state.clone());
+                #[cfg(not(unix))]
+                {
+                    let _ = (peer, &state);
+                    Err("re-exec is not supported on this platform".to_string())
+                }
+            }
+        })
+        .on("import", {
+            let state = state.clone();
+            move |args: ImportArgs, request: &Request, peer: &PeerInfo| {
+                handle_import(&args, request, peer, state.clone())
+            }
+        })
+        .on("log", |LogLimit(limit), _: &Request, _: &PeerInfo| {
+            handle_log(limit)
+        })
+        .on("config", {
+            let state = state.clone();
+            move |args: Vec<String>, _: &Request, _: &PeerInfo| {
+                handle_config(args.iter().map(String::as_str), state.clone())
+            }
+        })
+        .on(
+            "log-level",
+            |args: Vec<String>, _: &Request, _: &PeerInfo| handle_log_level(&args.join(",")),
+        )
+        .on("subscribe", |(), _: &Request, _: &PeerInfo| {
+            info!("event subscriber connected");
+            Ok(Reply::Stream(EVENTS.subscribe()))
+        })
+}
+
+/// Commands worth an audit line at info level, as opposed to queries.
+fn changes_state(request: &Request) -> bool {
+    match request.command() {
+        "tether" | "tether-rule" | "untether-rule" | "ensure-tether" | "set" | "untether"
+        | "severe" | "cancel" | "log-level" | "import" | "re-exec" | "ack" => true,
+        "config" => matches!(request.args().next(), Some("set" | "reload")),
+        _ => false,
+    }
+}
+
+/// One line per tether, then one per rule and per recently finished
+/// tether, prefixed `rule` and `finished:` so scripts counting `bus` lines
+/// skip them. Finished tethers that fired are listed until `ack`. When
+/// `verbose`, a `backend:` line comes first and each tether is followed by
+/// an indented `watch:` line saying how its device is watched.
+fn handle_status(verbose: bool, state: State) -> Result<String, String> {
+    state.call(move |guard| {
+        let mut lines = Vec::with_capacity(guard.monitors.len() + guard.finished.len() + 1);
+        if verbose {
+            lines.push(backend_summary(guard.config.poll_interval_ms));
+        }
+        if guard.monitors.is_empty() && guard.rules.is_empty() && guard.finished.is_empty() {
+            lines.push("no active tethers".to_string());
+            return Ok(lines.join("\n"));
+        }
+
+        for (key, monitor) in guard.monitors.iter() {
+            let status = monitor.state();
+
+            let summary = format_device_summary(
+                *key,
+                monitor.vendor_id,
+                monitor.product_id,
+                monitor.product_name.as_deref(),
+            );
+
+            let TetherOptions {
+                action,
+                grace,
+                cooldown,
+                rearm,
+                poll,
+            } = monitor.options();
+            let mut settings = vec![
+                format!("id {}", monitor.id),
+                format!("owner {}", user_name(monitor.owner)),
+            ];
+            if action != Action::Lock {
+                settings.push(format!("action {action}"));
+            }
+            if !grace.is_zero() {
+                settings.push(format!("grace {}s", grace.as_secs()));
+            }
+            if !cooldown.is_zero() {
+                settings.push(format!("cooldown {}s", cooldown.as_secs()));
+            }
+            if rearm {
+                settings.push("rearm".to_string());
+            }
+            if poll {
+                settings.push("poll".to_string());
+            }
+            if let Some(rule) = monitor.rule {
+                settings.push(format!("rule {rule}"));
+            }
+            if monitor.restarts > 0 {
+                settings.push(format!("restarted {}x", monitor.restarts));
+            }
+
+            let mut line = format!("{summary} [{status}]");
+            if !settings.is_empty() {
+                line.push_str(&format!(" ({})", settings.join(", ")));
+            }
+
+            lines.push(line);
+            if verbose {
+                let watch = monitor
+                    .flags
+                    .watch
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner());
+                lines.push(format!(
+                    "  watch: {}",
+                    watch.describe(guard.config.poll_interval_ms)
+                ));
+            }
+        }
+        for rule in &guard.rules {
+            let tethers = guard
+                .monitors
+                .values()
+                .filter(|monitor| monitor.rule == Some(rule.id))
+                .count();
+            let mut settings = vec![
+                format!("owner {}", user_name(rule.owner)),
+                format!("action {}", rule.options.action),
+            ];
+            if !rule.options.grace.is_zero() {
+                settings.push(format!("grace {}s", rule.options.grace.as_secs()));
+            }
+            if !rule.options.cooldown.is_zero() {
+                settings.push(format!("cooldown {}s", rule.options.cooldown.as_secs()));
+            }
+            settings.push(format!("{tethers} tether(s)"));
+            lines.push(format!(
+                "rule {}: {} ({})",
+                rule.id,
+                rule.pattern,
+                settings.join(", ")
+            ));
+        }
+        for finished in &guard.finished {
+            let mut details = vec![
+                format!("id {}", finished.id),
+                format!("action {}", finished.action),
+                format!(
+                    "at {} ({}s ago)",
+                    finished.timestamp,
+                    finished.at.elapsed().as_secs()
+                ),
+            ];
+            if let Some(result) = &finished.result {
+                details.push(result.clone());
+            }
+            if guard.rearm.iter().any(|rearm| rearm.id == finished.id) {
+                details.push("re-arms on return".to_string());
+            }
+            if finished.fired() {
+                details.push("unacknowledged".to_string());
+            }
+            lines.push(format!(
+                "finished: {} [{}] ({})",
+                finished.summary,
+                finished.outcome,
+                details.join(", ")
+            ));
+        }
+
+        Ok(lines.join("\n"))
+    })
+}
+
+/// The libusb in use and how devices are watched with it, for `status
+/// verbose`.
+fn backend_summary(poll_interval_ms: u64) -> String {
+    let version = rusb::version();
+    let mut line = format!(
+        "backend: libusb {}.{}.{}.{}{}",
+        version.major(),
+        version.minor(),
+        version.micro(),
+        version.nano(),
+        version.rc().unwrap_or_default()
+    );
+    if rusb::has_hotplug() {
+        line.push_str(", hotplug supported");
+    } else {
+        line.push_str(&format!(
+            ", no hotplug support; every tether polls every {}ms",
+            poll_interval_ms.max(MIN_POLL_INTERVAL_MS)
+        ));
+    }
+    #[cfg(target_os = "freebsd")]
+    line.push_str(", devd events");
+    line
+}
+
+/// Dry-run an action: the one passed as `action=`, else the tethered
+/// device's, else the default. One `ok:` or `fail:` line per step.
+fn handle_test_action<'a>(
+    args: impl Iterator<Item = &'a str>,
+    state: State,
+) -> Result<String, String> {
+    let mut action = None;
+    let mut tether = None;
+    let mut device = Vec::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("action", value)) => action = Some(value.parse::<Action>()?),
+            Some(("id", value)) => {
+                let id = value
+                    .parse()
+                    .map_err(|_| format!("invalid tether id: {value}"))?;
+                tether = Some(TetherRef::Id(id));
+            }
+            Some((other, _)) => return Err(format!("unknown test-action option: {other}")),
+            None => device.push(arg),
+        }
+    }
+    match (device.as_slice(), tether) {
+        ([], _) => {}
+        ([bus, address], None) => {
+            tether = Some(TetherRef::Device(DeviceArg {
+                bus: bus
+                    .parse()
+                    .map_err(|_| format!("invalid bus number: {bus}"))?,
+                address: address
+                    .parse()
+                    .map_err(|_| format!("invalid device id: {address}"))?,
+            }));
+        }
+        _ => {
+            return Err("expected a bus and device id, or id=<tether id>, or neither".to_string());
+        }
+    }
+    let (action, config) = state.call(move |guard| {
+        let action = match (action, tether) {
+            (Some(action), None) => action,
+            (None, None) => guard.config.default_action.clone(),
+            (action, Some(tether)) => {
+                let key = tether.resolve(guard)?;
+                action.unwrap_or_else(|| guard.monitors[&key].options().action)
+            }
+        };
+        Ok::<_, String>((action, guard.config.clone()))
+    })?;
+
+    let mut steps = Vec::new();
+    #[cfg(unix)]
+    steps.push(
+        privileges::check_action(&action).map(|()| "the daemon is permitted to run it".to_string()),
+    );
+    steps.extend(action.dry_run(&config));
+    #[cfg(unix)]
+    if config.capture_evidence {
+        steps.extend(evidence::dry_run(&config.audit_dir));
+    }
+    if config.usbguard_block {
+        steps.push(usbguard::dry_run());
+    }
+
+    let mut lines = vec![format!("dry run of {action}")];
+    lines.extend(steps.into_iter().map(|step| match step {
+        Ok(message) => format!("ok: {message}"),
+        Err(message) => format!("fail: {message}"),
+    }));
+    Ok(lines.join("\n"))
+}
+
+/// A report on the daemon's environment: how it runs and which of the
+/// configured lock backends can be used.
+fn handle_doctor(state: State) -> Result<String, String> {
+    let lock_backends = state.call(|guard| guard.config.lock_backends.clone());
+
+    let mode = if deadman_ipc::user_mode() {
+        "user"
+    } else {
+        "system"
+    };
+    let mut lines = vec![
+        format!("mode: {mode}"),
+        format!(
+            "hotplug: {}",
+            if rusb::has_hotplug() {
+                "supported"
+            } else {
+                "unsupported (tethers poll)"
+            }
+        ),
+    ];
+    lines.extend(lock::health(&lock_backends));
+    Ok(lines.join("\n"))
+}
+
+/// Daemon health: `ok`, or `degraded` with the tethers whose monitor threads
+/// are stalled or had to be restarted by the watchdog.
+fn handle_ping(state: State) -> Result<String, String> {
+    state.call(move |guard| {
+        let problems = monitor_problems(guard);
+        if problems.is_empty() {
+            Ok(format!("ok: {} tether(s) healthy", guard.monitors.len()))
+        } else {
+            Ok(format!("degraded\n{}", problems.join("\n")))
+        }
+    })
+}
+
+/// Monitors that are stalled or were restarted by the watchdog.
+fn monitor_problems(state: &DaemonState) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (key, monitor) in state.monitors.iter() {
+        let summary = format_device_summary(
+            *key,
+            monitor.vendor_id,
+            monitor.product_id,
+            monitor.product_name.as_deref(),
+        );
+        let silence = monitor.flags.silence();
+        if silence >= WATCHDOG_TIMEOUT {
+            problems.push(format!(
+                "{summary}: monitor silent for {}s",
+                silence.as_secs()
+            ));
+        } else if monitor.restarts > 0 {
+            problems.push(format!(
+                "{summary}: monitor restarted {} time(s)",
+                monitor.restarts
+            ));
+        }
+        if monitor.flags.polling.load(Ordering::SeqCst) {
+            problems.push(format!(
+                "{summary}: hotplug events unverified; polling for removal"
+            ));
+        }
+    }
+    problems
+}
+
+/// Whether `user` may authenticate to `service`: `allow`, or `deny` and one
+/// line per tethered device that is absent. Only services listed in
+/// `pam_services` are checked, against the user's own tethers and root's.
+fn handle_pam_check(request: &PamRequest, state: State) -> Result<String, String> {
+    let request = request.clone();
+    state.call(move |guard| {
+        if !guard.config.pam_services.contains(&request.service) {
+            return Ok(format!(
+                "allow: {} is not a checked service",
+                request.service
+            ));
+        }
+
+        let mut absent: Vec<_> = guard
+            .monitors
+            .iter()
+            .filter(|(_, monitor)| monitor.owner == 0 || user_name(monitor.owner) == request.user)
+            .filter(|(_, monitor)| monitor.is_absent())
+            .map(|(key, monitor)| {
+                format_device_summary(
+                    *key,
+                    monitor.vendor_id,
+                    monitor.product_id,
+                    monitor.product_name.as_deref(),
+                )
+            })
+            .collect();
+        if absent.is_empty() {
+            return Ok("allow".to_string());
+        }
+        absent.sort();
+        warn!(
+            service = %request.service,
+            user = %request.user,
+            "denying authentication while a tethered device is absent"
+        );
+        Ok(format!("deny\n{}", absent.join("\n")))
+    })
+}
+
+/// The overall protection level: `armed` when every tether is watched and
+/// its action can run, `degraded` when some of that is in doubt, and
+/// `unarmed` when no removal would be acted on. The first line is the level,
+/// followed by one line per reason.
+fn handle_protection(state: State) -> Result<String, String> {
+    state.call(move |guard| match protection(guard) {
+        ("armed", _) => Ok(format!("armed: {} tether(s) ready", guard.monitors.len())),
+        (level, problems) => Ok(format!("{level}\n{}", problems.join("\n"))),
+    })
+}
+
+/// The protection level and tethers as one JSON line, for fleet dashboards.
+fn handle_report(state: State) -> Result<String, String> {
+    state.call(move |guard| {
+        let (level, reasons) = protection(guard);
+        let mut tethers: Vec<_> = guard
+            .monitors
+            .iter()
+            .map(|(key, monitor)| TetherReport {
+                id: monitor.id,
+                device: format_device_summary(
+                    *key,
+                    monitor.vendor_id,
+                    monitor.product_id,
+                    monitor.product_name.as_deref(),
+                ),
+                state: monitor.state().to_string(),
+            })
+            .collect();
+        tethers.sort_by_key(|tether| tether.id);
+        let report = Report {
+            host: hostname(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            level: level.to_string(),
+            reasons,
+            tethers,
+        };
+        serde_json::to_string(&report).map_err(|err| format!("failed to serialize report: {err}"))
+    })
+}
+
+/// The protection level and the reasons it is not `armed`.
+fn protection(guard: &DaemonState) -> (&'static str, Vec<String>) {
+    let mut problems = monitor_problems(guard);
+    if !rusb::has_hotplug() {
+        problems.push("libusb hotplug support is not available; tethers poll instead".to_string());
+    }
+    let mut runnable = 0;
+    for (key, monitor) in guard.monitors.iter() {
+        let action = monitor.options().action;
+        match action.check_ready(&guard.config) {
+            Ok(()) => runnable += 1,
+            Err(err) => {
+                let summary = format_device_summary(
+                    *key,
+                    monitor.vendor_id,
+                    monitor.product_id,
+                    monitor.product_name.as_deref(),
+                );
+                problems.push(format!("{summary}: action {action} cannot run: {err}"));
+            }
+        }
+    }
+    if deadman_ipc::user_mode() {
+        problems.push("user daemon: only this session is protected".to_string());
+    }
+
+    let level = if guard.monitors.is_empty() {
+        problems.insert(0, "no active tethers".to_string());
+        "unarmed"
+    } else if runnable == 0 {
+        "unarmed"
+    } else if problems.is_empty() {
+        "armed"
+    } else {
+        "degraded"
+    };
+    (level, problems)
+}
+
+/// Parse the `key=value` arguments of a `tether` or `set` command on top of
+/// `options`. Also returns whether `replace=true` asked for an existing
+/// tether's settings to be swapped in place.
+fn parse_tether_options<'a>(
+    mut options: TetherOptions,
+    args: impl Iterator<Item = &'a str>,
+) -> Result<(TetherOptions, bool), String> {
+    let mut replace = false;
+
+    for arg in args {
+        let (name, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("unexpected argument: {arg}"))?;
+
+        match name {
+            "action" => options.action = value.parse()?,
+            "grace" => {
+                let secs = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid grace period: {value}"))?;
+                options.grace = Duration::from_secs(secs);
+            }
+            "cooldown" => {
+                let secs = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid cooldown: {value}"))?;
+                options.cooldown = Duration::from_secs(secs);
+            }
+            "rearm" => {
+                options.rearm = value
+                    .parse::<bool>()
+                    .map_err(|_| format!("invalid rearm flag: {value}"))?;
+            }
+            "poll" => {
+                options.poll = value
+                    .parse::<bool>()
+                    .map_err(|_| format!("invalid poll flag: {value}"))?;
+            }
+            "replace" => {
+                replace = value
+                    .parse::<bool>()
+                    .map_err(|_| format!("invalid replace flag: {value}"))?;
+            }
+            other => return Err(format!("unknown tether option: {other}")),
+        }
+    }
+
+    Ok((options, replace))
+}
+
+fn handle_tether(
+    device: DeviceArg,
+    options: TetherOptions,
+    replace: bool,
+    request: &Request,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let DeviceArg {
+        bus: bus_number,
+        address: device_address,
+    } = device;
+    let key = DeviceKey::new(bus_number, device_address);
+
+    #[cfg(unix)]
+    privileges::check_action(&options.action)
+        .map_err(|err| format!("action {} cannot run: {err}", options.action))?;
+
+    let caller = *peer;
+    let updated = state.call({
+        let options = options.clone();
+        move |guard| {
+            guard
+                .monitors
+                .get(&key)
+                .map(|monitor| update_tether(key, monitor, options, replace, &caller))
+        }
+    });
+    if let Some(reply) = updated {
+        return reply;
+    }
+
+    let device_info = lookup_device(bus_number, device_address)?;
+    let summary = format_device_summary(
+        key,
+        device_info.vendor_id,
+        device_info.product_id,
+        device_info.product_name.as_deref(),
+    );
+
+    let flags = MonitorFlags::new();
+    let settings = Arc::new(Mutex::new(options));
+    let id = NEXT_TETHER_ID.fetch_add(1, Ordering::Relaxed);
+
+    let monitor = DeviceMonitor {
+        id,
+        vendor_id: device_info.vendor_id,
+        product_id: device_info.product_id,
+        product_name: device_info.product_name.clone(),
+        serial: device_info.serial.clone(),
+        settings: Arc::clone(&settings),
+        owner: peer.uid,
+        flags: flags.clone(),
+        restarts: 0,
+        rule: None,
+    };
+    let raced = state.call(move |guard| {
+        if let Some(existing) = guard.monitors.get(&key) {
+            let options = current_options(&monitor.settings);
+            return Some(update_tether(key, existing, options, replace, &caller));
+        }
+        JOURNAL.tethered(saved_tether(key, &monitor));
+        guard.monitors.insert(key, monitor);
+        None
+    });
+    if let Some(reply) = raced {
+        return reply;
+    }
+
+    // a tether nothing watches would look armed in status; take it back
+    if let Err(err) = spawn_monitor(state.clone(), key, device_info, settings, flags) {
+        state.call(move |guard| {
+            if guard
+                .monitors
+                .get(&key)
+                .is_some_and(|monitor| monitor.id == id)
+            {
+                guard.monitors.remove(&key);
+                JOURNAL.untethered(key.bus, key.address);
+            }
+        });
+        return Err(err);
+    }
+
+    info!(device = %summary, id, owner = peer.uid, "tether activated");
+    let mut event = device_event(EventKind::Tethered, key, &summary);
+    requested_by(&mut event, request, peer);
+    EVENTS.publish(event);
+
+    Ok(format!("tether active for {summary} (id {id})"))
+}
+
+/// Make sure the device matching `selector` is tethered with the given
+/// settings, creating or updating the tether only when needed. Settings left
+/// out keep their current values, or the defaults for a new tether. Replies
+/// with one JSON object saying whether anything `changed`.
+fn handle_ensure_tether(
+    selector: &Selector,
+    args: &[String],
+    request: &Request,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let args = || args.iter().map(String::as_str);
+    let found = state.call({
+        let selector = selector.clone();
+        move |guard| {
+            let mut existing = guard.monitors.iter().filter(|(_, monitor)| {
+                selector.matches(
+                    monitor.id,
+                    monitor.vendor_id,
+                    monitor.product_id,
+                    monitor.serial.as_deref(),
+                )
+            });
+            match (existing.next(), existing.next()) {
+                (Some(_), Some(_)) => Err(format!("more than one tether matches {selector}")),
+                (Some((key, monitor)), None) => Ok((Some(*key), monitor.options())),
+                (None, _) => Ok((
+                    None,
+                    TetherOptions {
+                        action: guard.config.default_action.clone(),
+                        grace: guard.config.default_grace(),
+                        cooldown: guard.config.default_cooldown(),
+                        rearm: false,
+                        poll: false,
+                    },
+                )),
+            }
+        }
+    })?;
+    let (key, changed) = match found {
+        (Some(key), current) => {
+            let (options, replace) = parse_tether_options(current.clone(), args())?;
+            if replace {
+                return Err("unknown ensure-tether option: replace".to_string());
+            }
+            let changed = options != current;
+            #[cfg(unix)]
+            if options.action != current.action {
+                privileges::check_action(&options.action)
+                    .map_err(|err| format!("action {} cannot run: {err}", options.action))?;
+            }
+            let caller = *peer;
+            state.call(move |guard| match guard.monitors.get(&key) {
+                Some(monitor) => update_tether(key, monitor, options, true, &caller),
+                None => Err("the tether went away before it could be updated".to_string()),
+            })?;
+            (key, changed)
+        }
+        (None, _) if matches!(selector, Selector::Id(_)) => {
+            return Err(format!("no tether matches {selector}"));
+        }
+        (None, defaults) => {
+            let (options, replace) = parse_tether_options(defaults, args())?;
+            if replace {
+                return Err("unknown ensure-tether option: replace".to_string());
+            }
+            let devices: Vec<_> = enumerate_devices()?
+                .into_iter()
+                .filter(|device| {
+                    selector.matches(
+                        0,
+                        device.vendor_id,
+                        device.product_id,
+                        device.serial.as_deref(),
+                    )
+                })
+                .collect();
+            let device = match devices.as_slice() {
+                [device] => device,
+                [] => return Err(format!("no connected device matches {selector}")),
+                _ => return Err(format!("more than one connected device matches {selector}")),
+            };
+            let target = DeviceArg {
+                bus: device.bus,
+                address: device.address,
+            };
+            handle_tether(target, options, false, request, peer, state.clone())?;
+            (DeviceKey::new(device.bus, device.address), true)
+        }
+    };
+
+    state.call(move |guard| {
+        let monitor = guard
+            .monitors
+            .get(&key)
+            .ok_or_else(|| "the tether went away before it could be reported".to_string())?;
+        let options = monitor.options();
+        let reply = serde_json::json!({
+            "changed": changed,
+            "id": monitor.id,
+            "device": format_device_summary(
+                key,
+                monitor.vendor_id,
+                monitor.product_id,
+                monitor.product_name.as_deref(),
+            ),
+            "action": options.action.to_string(),
+            "grace_secs": options.grace.as_secs(),
+            "cooldown_secs": options.cooldown.as_secs(),
+            "rearm": options.rearm,
+            "poll": options.poll,
+        });
+        Ok(reply.to_string())
+    })
+}
+
+/// Change some of a live tether's settings. The monitor thread keeps its
+/// hotplug registration and picks the new values up on its next read.
+fn handle_set<'a>(
+    target: TetherRef,
+    args: impl Iterator<Item = &'a str>,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let mut args = args.peekable();
+    if args.peek().is_none() {
+        return Err(
+            "nothing to set; pass action=<action>, grace=<secs>, cooldown=<secs>, rearm=<bool> and/or poll=<bool>".to_string(),
+        );
+    }
+    let (key, current) = state.call(move |guard| {
+        let key = target.resolve(guard)?;
+        Ok::<_, String>((key, guard.monitors[&key].options()))
+    })?;
+    let (options, replace) = parse_tether_options(current.clone(), args)?;
+    if replace {
+        return Err("unknown set option: replace".to_string());
+    }
+
+    #[cfg(unix)]
+    if options.action != current.action {
+        privileges::check_action(&options.action)
+            .map_err(|err| format!("action {} cannot run: {err}", options.action))?;
+    }
+
+    let caller = *peer;
+    state.call(move |guard| match guard.monitors.get(&key) {
+        Some(monitor) => update_tether(key, monitor, options, true, &caller),
+        None => Err("the tether went away before it could be updated".to_string()),
+    })
+}
+
+/// Re-tethering an already tethered device succeeds without change when the
+/// settings match, and with `replace` swaps them in place so the device is
+/// never left unprotected.
+fn update_tether(
+    key: DeviceKey,
+    monitor: &DeviceMonitor,
+    options: TetherOptions,
+    replace: bool,
+    peer: &PeerInfo,
+) -> Result<String, String> {
+    let summary = format_device_summary(
+        key,
+        monitor.vendor_id,
+        monitor.product_id,
+        monitor.product_name.as_deref(),
+    );
+
+    let mut settings = monitor
+        .settings
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if *settings == options {
+        return Ok(format!(
+            "tether already active for {summary} (id {})",
+            monitor.id
+        ));
+    }
+    if !replace {
+        return Err(format!(
+            "device {:03}:{:03} is already tethered with different settings; pass replace=true to update them",
+            key.bus, key.address
+        ));
+    }
+    if !monitor.managed_by(peer) {
+        return Err(format!(
+            "tether on {summary} is owned by {}",
+            user_name(monitor.owner)
+        ));
+    }
+
+    info!(
+        device = %summary,
+        action = %options.action,
+        grace_secs = options.grace.as_secs(),
+        "tether settings replaced"
+    );
+    *settings = options;
+    drop(settings);
+    JOURNAL.tethered(saved_tether(key, monitor));
+
+    Ok(format!("tether settings updated for {summary}"))
+}
+
+/// Clear one tether, leaving the others in place.
+fn handle_untether(
+    target: TetherRef,
+    request: &Request,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let (request, peer) = (request.clone(), *peer);
+    state.call(move |guard| {
+        let (request, peer) = (&request, &peer);
+        let key = target.resolve(guard)?;
+        let monitor = &guard.monitors[&key];
+        let summary = format_device_summary(
+            key,
+            monitor.vendor_id,
+            monitor.product_id,
+            monitor.product_name.as_deref(),
+        );
+        if !monitor.managed_by(peer) {
+            return Err(format!(
+                "tether on {summary} is owned by {}",
+                user_name(monitor.owner)
+            ));
+        }
+
+        monitor.flags.lifecycle.clear();
+        info!(device = %summary, id = monitor.id, "untethered");
+        guard.monitors.remove(&key);
+        JOURNAL.untethered(key.bus, key.address);
+
+        let mut event = device_event(EventKind::Cleared, key, &summary);
+        requested_by(&mut event, request, peer);
+        EVENTS.publish(event);
+
+        Ok(format!("untethered {summary}"))
+    })
+}
+
+/// Add a rule tethering every device matching `pattern`, those attached
+/// now and those attached later, with the settings in `args`.
+fn handle_tether_rule(
+    pattern: Pattern,
+    args: &[String],
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let defaults = state.call(|guard| TetherOptions {
+        action: guard.config.default_action.clone(),
+        grace: guard.config.default_grace(),
+        cooldown: guard.config.default_cooldown(),
+        rearm: false,
+        poll: false,
+    });
+    let (options, replace) = parse_tether_options(defaults, args.iter().map(String::as_str))?;
+    if replace {
+        return Err("unknown tether-rule option: replace".to_string());
+    }
+    if options.rearm {
+        return Err("rules tether returning devices already; rearm does not apply".to_string());
+    }
+    #[cfg(unix)]
+    privileges::check_action(&options.action)
+        .map_err(|err| format!("action {} cannot run: {err}", options.action))?;
+
+    // listed first so a failure leaves no rule behind
+    let present = present_classes().ok_or_else(|| "failed to list USB devices".to_string())?;
+    let id = NEXT_RULE_ID.fetch_add(1, Ordering::Relaxed);
+    let rule = Rule {
+        id,
+        pattern: pattern.clone(),
+        options,
+        owner: peer.uid,
+    };
+    state.call(move |guard| {
+        JOURNAL.ruled(saved_rule(&rule));
+        guard.rules.push(rule);
+    });
+    info!(rule = id, %pattern, owner = peer.uid, "rule added");
+
+    let tethered = present
+        .into_iter()
+        .filter(|(_, device)| pattern.matches(device.vendor_id, device.product_id, &device.classes))
+        .filter_map(|(key, _)| tether_for_rule(&state, key, id))
+        .count();
+    Ok(format!(
+        "rule {id} tethers {pattern}; tethered {tethered} attached device(s)"
+    ))
+}
+
+/// Remove a rule and clear the tethers it made.
+fn handle_untether_rule(
+    id: u64,
+    request: &Request,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let (request, peer) = (request.clone(), *peer);
+    state.call(move |guard| {
+        let (request, peer) = (&request, &peer);
+        let position = guard
+            .rules
+            .iter()
+            .position(|rule| rule.id == id)
+            .ok_or_else(|| format!("no rule with id {id}"))?;
+        let owner = guard.rules[position].owner;
+        if !peer.is_root() && peer.uid != owner {
+            return Err(format!("rule {id} is owned by {}", user_name(owner)));
+        }
+        let rule = guard.rules.remove(position);
+        JOURNAL.unruled(id);
+
+        let keys: Vec<DeviceKey> = guard
+            .monitors
+            .iter()
+            .filter(|(_, monitor)| monitor.rule == Some(id))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &keys {
+            let Some(monitor) = guard.monitors.remove(key) else {
+                continue;
+            };
+            monitor.flags.lifecycle.clear();
+            JOURNAL.untethered(key.bus, key.address);
+            let summary = format_device_summary(
+                *key,
+                monitor.vendor_id,
+                monitor.product_id,
+                monitor.product_name.as_deref(),
+            );
+            let mut event = device_event(EventKind::Cleared, *key, &summary);
+            requested_by(&mut event, request, peer);
+            EVENTS.publish(event);
+        }
+
+        info!(rule = id, pattern = %rule.pattern, cleared = keys.len(), "rule removed");
+        Ok(format!(
+            "removed rule {id} ({}) and cleared {} tether(s)",
+            rule.pattern,
+            keys.len()
+        ))
+    })
+}
+
+/// Dismiss fired tethers from `status` once someone has seen them: the one
+/// with `id`, or every one the peer may manage.
+fn handle_ack(id: Option<u64>, peer: &PeerInfo, state: State) -> Result<String, String> {
+    let peer = *peer;
+    state.call(move |guard| {
+        let peer = &peer;
+        let matches =
+            |finished: &FinishedTether| finished.fired() && id.is_none_or(|id| finished.id == id);
+        if let Some(id) = id {
+            let finished = guard
+                .finished
+                .iter()
+                .find(|finished| matches(finished))
+                .ok_or_else(|| format!("tether {id} has not fired, or was already acknowledged"))?;
+            if !peer.is_root() && peer.uid != finished.owner {
+                return Err(format!(
+                    "tether {id} is owned by {}",
+                    user_name(finished.owner)
+                ));
+            }
+        }
+
+        let before = guard.finished.len();
+        guard.finished.retain(|finished| {
+            !(matches(finished) && (peer.is_root() || peer.uid == finished.owner))
+        });
+        let acknowledged = before - guard.finished.len();
+        info!(acknowledged, uid = peer.uid, "fired tethers acknowledged");
+        Ok(format!("acknowledged {acknowledged} fired tether(s)"))
+    })
+}
+
+/// Clear every tether in `scope` that the peer may manage.
+fn handle_severe(
+    scope: &Scope,
+    request: &Request,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    warn!(
+        uid = peer.uid,
+        scope = %scope,
+        "received severe command; clearing active tethers"
+    );
+
+    let (scope, request, peer) = (scope.clone(), request.clone(), *peer);
+    state.call(move |guard| {
+        let (scope, request, peer) = (&scope, &request, &peer);
+        // clearing everything also stops rules from tethering what comes next
+        if scope.is_everything() {
+            guard.rules.retain(|rule| {
+                let dropped = peer.is_root() || peer.uid == rule.owner;
+                if dropped {
+                    info!(rule = rule.id, "rule removed");
+                    JOURNAL.unruled(rule.id);
+                }
+                !dropped
+            });
+        }
+
+        if guard.monitors.is_empty() {
+            info!("no tethers to clear");
+            return Ok("no active tethers".to_string());
+        }
+
+        let in_scope = |monitor: &DeviceMonitor| {
+            scope.includes(
+                monitor.id,
+                monitor.vendor_id,
+                monitor.product_id,
+                monitor.serial.as_deref(),
+            )
+        };
+        let mut cleared = 0;
+        let mut skipped = 0;
+        let mut fired = 0;
+        for (key, monitor) in guard.monitors.iter() {
+            if !in_scope(monitor) {
+                continue;
+            }
+            if !monitor.managed_by(peer) {
+                skipped += 1;
+                continue;
+            }
+
+            cleared += 1;
+            // from here on the monitor cannot start the action; one that
+            // already has is only reported
+            if matches!(
+                monitor.flags.lifecycle.clear(),
+                Phase::Triggered | Phase::Rearmed
+            ) {
+                fired += 1;
+            }
+            info!(
+                bus = key.bus,
+                address = key.address,
+                vendor_id = monitor.vendor_id,
+                product_id = monitor.product_id,
+                "clearing tether"
+            );
+
+            let summary = format_device_summary(
+                *key,
+                monitor.vendor_id,
+                monitor.product_id,
+                monitor.product_name.as_deref(),
+            );
+            let mut event = device_event(EventKind::Cleared, *key, &summary);
+            requested_by(&mut event, request, peer);
+            EVENTS.publish(event);
+            JOURNAL.untethered(key.bus, key.address);
+        }
+
+        guard
+            .monitors
+            .retain(|_, monitor| !(in_scope(monitor) && monitor.managed_by(peer)));
+        // fired tethers in scope stay fired rather than re-arming later
+        guard.rearm.retain(|rearm| {
+            !(scope.includes(
+                rearm.id,
+                rearm.vendor_id,
+                rearm.product_id,
+                rearm.serial.as_deref(),
+            ) && (peer.is_root() || peer.uid == rearm.owner))
+        });
+
+        if cleared == 0 && skipped == 0 {
+            return Ok(format!("no tethers match {scope}"));
+        }
+        let mut reply = format!("cleared {cleared} tether(s)");
+        if fired > 0 {
+            reply.push_str(&format!(", {fired} of them after their action ran"));
+        }
+        if skipped > 0 {
+            reply.push_str(&format!(", left {skipped} owned by other users"));
+        }
+        Ok(reply)
+    })
+}
+
+fn handle_config<'a>(
+    mut args: impl Iterator<Item = &'a str>,
+    state: State,
+) -> Result<String, String> {
+    let subcommand = args.next().unwrap_or("show").to_string();
+    let mut args = args.map(str::to_string).collect::<Vec<_>>().into_iter();
+    state.call(move |guard| match subcommand.as_str() {
+        "show" => guard
+            .config
+            .to_toml()
+            .map(|text| text.trim_end().to_string()),
+        "get" => {
+            let key = args
+                .next()
+                .ok_or_else(|| "missing config key".to_string())?;
+            guard.config.get(&key)
+        }
+        "set" => {
+            let key = args
+                .next()
+                .ok_or_else(|| "missing config key".to_string())?;
+            let value = args.collect::<Vec<_>>().join(" ");
+            if value.is_empty() {
+                return Err(format!("missing value for {key}"));
+            }
+
+            let mut config = guard.config.clone();
+            config.set(&key, &value)?;
+            config.save()?;
+            apply_config(guard, config);
+
+            Ok(format!("{key} = {}", guard.config.get(&key)?))
+        }
+        "validate" => {
+            Config::load()?;
+            Ok(format!("{} is valid", Config::path().display()))
+        }
+        "reload" => {
+            let changes = apply_config(guard, Config::load()?);
+            if changes.is_empty() {
+                Ok("config reloaded, nothing changed".to_string())
+            } else {
+                Ok(format!("config reloaded: {}", changes.join(", ")))
+            }
+        }
+        other => Err(format!("unknown config command: {other}")),
+    })
+}
+
+/// The settings and tethers as a snapshot, signed when
+/// `snapshot_signing_key` is set.
+fn handle_export(state: State) -> Result<String, String> {
+    let host = hostname();
+    let mut snapshot = state.call(move |guard| {
+        let mut monitors: Vec<_> = guard.monitors.values().collect();
+        monitors.sort_by_key(|monitor| monitor.id);
+        let tethers = monitors
+            .into_iter()
+            .map(|monitor| {
+                let options = monitor.options();
+                snapshot::TetherSpec {
+                    vendor_id: monitor.vendor_id,
+                    product_id: monitor.product_id,
+                    serial: monitor.serial.clone(),
+                    product: monitor.product_name.clone(),
+                    action: options.action.to_string(),
+                    grace_secs: options.grace.as_secs(),
+                    cooldown_secs: options.cooldown.as_secs(),
+                    rearm: options.rearm,
+                    poll: options.poll,
+                }
+            })
+            .collect();
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        snapshot::Snapshot::new(created, host, guard.config.clone(), tethers)
+    });
+    if let Some(key) = snapshot.config.snapshot_signing_key.clone() {
+        snapshot.sign(&key)?;
+    }
+    snapshot.to_json()
+}
+
+/// Apply a snapshot from `export`: its settings replace the config file,
+/// except for the snapshot keys, which stay as this machine has them, and
+/// each tether is recreated on a connected device that matches it.
+fn handle_import(
+    args: &ImportArgs,
+    request: &Request,
+    peer: &PeerInfo,
+    state: State,
+) -> Result<String, String> {
+    let snapshot = snapshot::Snapshot::load(&args.path)?;
+    let trusted_key = state.call(|guard| guard.config.snapshot_trusted_key.clone());
+    match (&trusted_key, &snapshot.signature) {
+        (Some(key), Some(_)) => snapshot.verify(key)?,
+        _ if args.allow_unsigned => {
+            warn!(path = %args.path.display(), "importing a snapshot without checking its signature");
+        }
+        (Some(_), None) => return Err("snapshot is not signed".to_string()),
+        (None, _) => {
+            return Err(
+                "set snapshot_trusted_key to import snapshots, or allow unsigned ones".to_string(),
+            );
+        }
+    }
+    let tethers: Vec<(snapshot::TetherSpec, TetherOptions)> = snapshot
+        .tethers
+        .iter()
+        .map(|spec| {
+            Ok((
+                spec.clone(),
+                TetherOptions {
+                    action: spec.action.parse()?,
+                    grace: Duration::from_secs(spec.grace_secs),
+                    cooldown: Duration::from_secs(spec.cooldown_secs),
+                    rearm: spec.rearm,
+                    poll: spec.poll,
+                },
+            ))
+        })
+        .collect::<Result<_, String>>()?;
+
+    let mut config = snapshot.config.clone();
+    let changes = state.call(move |guard| {
+        config.snapshot_signing_key = guard.config.snapshot_signing_key.clone();
+        config.snapshot_trusted_key = guard.config.snapshot_trusted_key.clone();
+        config.save()?;
+        Ok::<_, String>(apply_config(guard, config))
+    })?;
+    let mut lines = vec![format!(
+        "imported settings from {}: {} change(s)",
+        snapshot.host,
+        changes.len()
+    )];
+
+    let mut devices = if tethers.is_empty() {
+        Vec::new()
+    } else {
+        enumerate_devices()?
+    };
+    for (spec, options) in tethers {
+        let label = format!(
+            "{:04x}:{:04x}{}",
+            spec.vendor_id,
+            spec.product_id,
+            spec.serial
+                .as_deref()
+                .map(|serial| format!(" serial {serial}"))
+                .unwrap_or_default()
+        );
+        let found = devices.iter().position(|device| {
+            device.vendor_id == spec.vendor_id
+                && device.product_id == spec.product_id
+                && (spec.serial.is_none() || device.serial == spec.serial)
+        });
+        let Some(index) = found else {
+            lines.push(format!("skipped {label}: not connected"));
+            continue;
+        };
+        // each device takes at most one tether from the snapshot
+        let device = devices.swap_remove(index);
+        let target = DeviceArg {
+            bus: device.bus,
+            address: device.address,
+        };
+        match handle_tether(target, options, true, request, peer, state.clone()) {
+            Ok(message) => lines.push(message),
+            Err(err) => lines.push(format!("failed {label}: {err}")),
+        }
+    }
+    info!(path = %args.path.display(), host = %snapshot.host, "imported snapshot");
+    Ok(lines.join("\n"))
+}
+
+/// Re-read the config file after the watcher saw it change. An invalid file is
+/// reported and ignored so the running config stays in force.
+fn reload_config(state: &State) {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            warn!(error = %err, "ignoring invalid config change");
+            let mut event = Event::new(EventKind::ConfigRejected);
+            event.message = Some(err);
+            EVENTS.publish(event);
+            return;
+        }
+    };
+
+    state.call(move |guard| {
+        apply_config(guard, config);
+    })
+}
+
+/// Swap in `config`, publishing what changed. Returns the change list.
+fn apply_config(state: &mut DaemonState, config: Config) -> Vec<String> {
+    let changes = state.config.diff(&config);
+    if changes.is_empty() {
+        return changes;
+    }
+
+    if state.config.lock_backends != config.lock_backends {
+        lock::detect(&config.lock_backends);
+    }
+    let journal =
+        (state.config.state_journal != config.state_journal).then_some(config.state_journal);
+    state.config = config;
+    match journal {
+        #[cfg(unix)]
+        Some(true) => start_journal(state),
+        Some(false) => JOURNAL.stop(),
+        _ => {}
+    }
+    info!(changes = %changes.join(", "), "config applied");
+
+    let mut event = Event::new(EventKind::ConfigChanged);
+    event.message = Some(changes.join(", "));
+    EVENTS.publish(event);
+    changes
+}
+
+fn handle_log_level(directives: &str) -> Result<String, String> {
+    if !directives.is_empty() {
+        logging::set_filter(directives)?;
+        warn!(filter = directives, "log filter changed");
+    }
+    logging::filter().map(|filter| format!("log level: {filter}"))
+}
+
+fn handle_log(limit: usize) -> Result<String, String> {
+    let lines = EVENTS
+        .recent(limit)
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("failed to serialize events: {err}"))?;
+
+    Ok(lines.join("\n"))
+}
+
+/// Every connected USB device, with the tether on it, if any.
+fn connected_devices(state: State) -> Result<Vec<UsbDevice>, String> {
+    let devices = enumerate_devices()?;
+    Ok(state.call(move |guard| {
+        let mut devices = devices;
+        for device in &mut devices {
+            let key = DeviceKey {
+                bus: device.bus,
+                address: device.address,
+            };
+            if let Some(monitor) = guard.monitors.get(&key) {
+                device.tether_id = Some(monitor.id);
+                device.tether_state = Some(monitor.state().to_string());
+            }
+        }
+        devices
+    }))
+}
+
+/// Every connected USB device as one JSON object per line, with the strings
+/// only the daemon can usually read and the tether on it, if any.
+fn handle_list_devices(state: State) -> Result<String, String> {
+    let devices = connected_devices(state)?;
+
+    let lines = devices
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("failed to serialize devices: {err}"))?;
+    Ok(lines.join("\n"))
+}
+
+/// Every descriptor of the one connected device `selector` matches, as a
+/// single JSON line. `id=<n>` picks the device under that tether.
+fn handle_describe(selector: &Selector, state: State) -> Result<String, String> {
+    let devices = connected_devices(state)?;
+    // untethered devices have no ID for `id=<n>` to match
+    let mut matching = devices.into_iter().filter(|device| {
+        (device.tether_id.is_some() || !matches!(selector, Selector::Id(_)))
+            && selector.matches(
+                device.tether_id.unwrap_or_default(),
+                device.vendor_id,
+                device.product_id,
+                device.serial.as_deref(),
+            )
+    });
+    let device = match (matching.next(), matching.next()) {
+        (Some(device), None) => device,
+        (None, _) => return Err(format!("no connected device matches {selector}")),
+        (Some(_), Some(_)) => {
+            return Err(format!("more than one connected device matches {selector}"));
+        }
+    };
+
+    let description = DeviceDescription {
+        configurations: device_configurations(device.bus, device.address)?,
+        device,
+    };
+    serde_json::to_string(&description).map_err(|err| format!("failed to serialize device: {err}"))
+}
+
+fn handle_cancel(peer: &PeerInfo, state: State) -> Result<String, String> {
+    let peer = *peer;
+    state.call(move |guard| {
+        let mut cancelled = 0;
+        let mut denied = 0;
+        for (key, monitor) in guard.monitors.iter() {
+            if monitor.is_pending() && !monitor.managed_by(&peer) {
+                denied += 1;
+            } else if monitor.is_pending() {
+                monitor.flags.cancelled.store(true, Ordering::SeqCst);
+                info!(
+                    bus = key.bus,
+                    address = key.address,
+                    "cancelling pending removal action"
+                );
+                cancelled += 1;
+            }
+        }
+
+        if cancelled == 0 && denied > 0 {
+            return Err(format!(
+                "{denied} pending action(s) belong to other users; only the owner or root can cancel them"
+            ));
+        }
+        if cancelled == 0 {
+            return Ok("no pending actions".to_string());
+        }
+
+        Ok(format!("cancelled {cancelled} pending action(s)"))
+    })
+}
+
+/// Start the monitor thread for a registered tether. A tether whose thread
+/// failed to start still has its heartbeat, so the watchdog tries again
+/// once that goes stale.
+fn spawn_monitor(
+    state: State,
+    key: DeviceKey,
+    device_info: DeviceInfo,
+    settings: Arc<Mutex<TetherOptions>>,
+    flags: MonitorFlags,
+) -> Result<(), String> {
+    let generation = flags.generation.load(Ordering::SeqCst);
+    flags.beat();
+    thread::Builder::new()
+        .spawn(move || {
+            monitor_device(state, key, device_info, settings, flags, generation);
+        })
+        .map(|_| ())
+        .map_err(|err| format!("failed to start a monitor thread: {err}"))
+}
+
+/// Watch one tethered device until its action runs or the tether goes away.
+/// A thread superseded by the watchdog (`generation` moved on) exits quietly
+/// and leaves the tether to its replacement.
+fn monitor_device(
+    state: State,
+    key: DeviceKey,
+    device_info: DeviceInfo,
+    settings: Arc<Mutex<TetherOptions>>,
+    flags: MonitorFlags,
+    generation: u64,
+) {
+    let DeviceInfo {
+        vendor_id,
+        product_id,
+        product_name,
+        serial,
+        ..
+    } = device_info;
+    let device_label = format_device_summary(key, vendor_id, product_id, product_name.as_deref());
+    let id = state.call(move |guard| guard.monitors.get(&key).map(|monitor| monitor.id));
+    // the tether's lifetime on this thread, for the logs and OTLP export
+    let span = info_span!(
+        "tether",
+        id,
+        device = %device_label,
+        bus = key.bus,
+        address = key.address,
+        vendor_id,
+        product_id,
+        generation,
+        outcome = Empty,
+    );
+    let _entered = span.enter();
+    let watcher = SelectedDeviceWatcher {
+        key,
+        vendor_id,
+        product_id,
+        product_name,
+        lifecycle: flags.lifecycle.clone(),
+        report: Arc::clone(&flags.watch),
+    };
+
+    let mut watch = match Watch::open(&watcher, poll_interval(&state, &settings)) {
+        Ok(watch) => watch,
+        Err(err) => {
+            error!(device = %device_label, error = %err, "failed to start USB monitoring");
+            remove_monitor(&state, key, generation, "monitoring failed", Some(err));
+            return;
+        }
+    };
+
+    // a restarted monitor may have missed the unplug while its predecessor
+    // hung, or the daemon was re-executing
+    if generation > 0 && watcher.is_missing() {
+        warn!(device = %device_label, "device disappeared while it was not being watched");
+        flags.lifecycle.departed();
+    } else {
+        check_hotplug(&watch, &flags, key, &device_label);
+    }
+
+    info!(device = %device_label, polling = watch.is_polling(), "monitoring device for removal");
+
+    let mut outcome = None;
+    let mut inhibitor = None;
+    let mut failures = 0;
+    let mut polled = Instant::now();
+    // of the last removal, for the trigger event
+    let mut confidence = None;
+    while outcome.is_none() {
+        flags.beat();
+        if flags.superseded(generation) {
+            outcome = Some(GraceOutcome::Superseded);
+            break;
+        }
+
+        if let Err(err) = watch.handle_events(Some(EVENT_POLL_INTERVAL)) {
+            failures += 1;
+            let limit = state.call(|guard| guard.config.usb_retry_limit);
+            if failures > limit {
+                // fail secure: an unwatched tether is treated as a removed one
+                error!(
+                    device = %device_label,
+                    error = %err,
+                    failures,
+                    "USB monitoring could not be recovered; running the removal action"
+                );
+                flags.lifecycle.departed();
+                outcome = Some(if flags.lifecycle.trigger() == Phase::Triggered {
+                    GraceOutcome::Elapsed
+                } else {
+                    GraceOutcome::Cleared
+                });
+                break;
+            }
+
+            let delay = usb_retry_delay(failures);
+            warn!(
+                device = %device_label,
+                error = %err,
+                failures,
+                retry_ms = delay.as_millis() as u64,
+                "error while handling USB events; recreating the USB context"
+            );
+            let mut event = device_event(EventKind::Degraded, key, &device_label);
+            event.message = Some(format!("{err}; retry {failures} of {limit}"));
+            EVENTS.publish(event);
+
+            if !backoff(&flags, generation, delay) {
+                continue;
+            }
+            match Watch::open(&watcher, poll_interval(&state, &settings)) {
+                Ok(new_watch) => {
+                    watch = new_watch;
+                    info!(device = %device_label, "USB context recreated");
+                    if watcher.is_missing() {
+                        flags.lifecycle.departed();
+                    } else {
+                        check_hotplug(&watch, &flags, key, &device_label);
+                    }
+                }
+                Err(err) => {
+                    warn!(device = %device_label, error = %err, "failed to recreate USB context");
+                }
+            }
+            continue;
+        }
+        failures = 0;
+
+        let interval = if flags.polling.load(Ordering::SeqCst) {
+            FALLBACK_POLL_INTERVAL
+        } else {
+            PRESENCE_POLL_INTERVAL
+        };
+        if polled.elapsed() >= interval {
+            polled = Instant::now();
+            // `set poll=` takes effect while the device is attached
+            let wanted = poll_interval(&state, &settings);
+            if flags.lifecycle.phase() == Phase::Armed && wanted.is_some() != watch.is_polling() {
+                match Watch::open(&watcher, wanted) {
+                    Ok(new_watch) => {
+                        watch = new_watch;
+                        info!(device = %device_label, polling = watch.is_polling(), "monitoring mode changed");
+                    }
+                    Err(err) => {
+                        warn!(device = %device_label, error = %err, "failed to change monitoring mode");
+                    }
+                }
+            }
+            if !watch.is_polling()
+                && matches!(flags.lifecycle.phase(), Phase::Armed | Phase::Rearmed)
+                && watcher.is_missing()
+            {
+                if !flags.polling.swap(true, Ordering::SeqCst) {
+                    warn!(device = %device_label, "hotplug missed the removal; polling for the device from now on");
+                    let mut event = device_event(EventKind::Degraded, key, &device_label);
+                    event.message = Some("hotplug missed a removal; polling instead".to_string());
+                    EVENTS.publish(event);
+                }
+                WatchReport::record(&flags.watch, "left");
+                flags.lifecycle.departed();
+            }
+        }
+
+        match flags.lifecycle.phase() {
+            Phase::Armed | Phase::Rearmed => continue,
+            Phase::Cleared => {
+                outcome = Some(GraceOutcome::Cleared);
+                break;
+            }
+            Phase::GracePeriod | Phase::Triggered => {}
+        }
+
+        // keep the machine from sleeping through the countdown and the action
+        inhibitor = SleepInhibitor::take(&format!("grace countdown for {device_label}"));
+        let (settle, ignore_glitches) = state.call(|guard| {
+            (
+                Duration::from_millis(guard.config.removal_settle_ms),
+                guard.config.ignore_bus_glitches,
+            )
+        });
+        // no time to spare with the system about to sleep
+        let expedited = flags.expedite.load(Ordering::SeqCst);
+        let settle = if expedited { Duration::ZERO } else { settle };
+        let judgement = disconnect::judge(
+            &disconnect::Departed {
+                bus: key.bus,
+                address: key.address,
+                vendor_id,
+                product_id,
+                serial: serial.as_deref(),
+            },
+            settle,
+        );
+        if judgement.still_attached && ignore_glitches && !expedited {
+            warn!(device = %device_label, reason = %judgement.reason, "removal looks like a bus glitch; still watching");
+            let mut event = device_event(EventKind::Degraded, key, &device_label);
+            event.message = Some(format!("removal ignored: {}", judgement.reason));
+            event.confidence = Some(judgement.confidence.to_string());
+            EVENTS.publish(event);
+            flags.lifecycle.arrived();
+            drop(inhibitor.take());
+            continue;
+        }
+
+        let grace = current_options(&settings).grace;
+        info!(
+            device = %device_label,
+            grace_secs = grace.as_secs(),
+            confidence = %judgement.confidence,
+            reason = %judgement.reason,
+            "device removal detected"
+        );
+        let mut event = device_event(EventKind::Removed, key, &device_label);
+        event.grace_secs = Some(grace.as_secs());
+        event.message = Some(judgement.reason);
+        event.confidence = Some(judgement.confidence.to_string());
+        confidence.clone_from(&event.confidence);
+        EVENTS.publish(event);
+
+        let mut waited = wait_out_grace(&watch, &settings, &flags, generation);
+        if matches!(waited, GraceOutcome::Elapsed) {
+            let asking = script::Trigger {
+                tether_id: id,
+                vendor_id,
+                product_id,
+                serial: serial.clone(),
+                event: EventKind::Removed.to_string(),
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .unwrap_or_default(),
+            };
+            waited = consult_hook(&state, &watch, &flags, generation, &asking);
+        }
+        if matches!(waited, GraceOutcome::Elapsed) {
+            // the hook may have run a while: catch up on a return it sat through
+            if let Err(err) = watch.handle_events(Some(Duration::ZERO)) {
+                debug!(error = %err, "error while handling USB events after the pre-trigger hook");
+            }
+            // the last word on a return or a severe that raced the countdown
+            waited = match flags.lifecycle.trigger() {
+                Phase::Triggered => GraceOutcome::Elapsed,
+                Phase::Cleared => GraceOutcome::Cleared,
+                _ => GraceOutcome::Reattached,
+            };
+        }
+        match waited {
+            GraceOutcome::Reattached => {
+                drop(inhibitor.take());
+                info!(device = %device_label, "device returned during grace period");
+                EVENTS.publish(device_event(EventKind::Reattached, key, &device_label));
+            }
+            other => outcome = Some(other),
+        }
+    }
+
+    if matches!(outcome, Some(GraceOutcome::Elapsed))
+        && let Err(reason) = record_trigger(&state, key, current_options(&settings).cooldown)
+    {
+        outcome = Some(GraceOutcome::Suppressed(reason));
+    }
+    // the tether is spent: a daemon restarted from here on must not take
+    // it back up and run the action a second time
+    if !matches!(
+        outcome,
+        Some(GraceOutcome::Superseded | GraceOutcome::Reattached) | None
+    ) {
+        JOURNAL.untethered(key.bus, key.address);
+    }
+
+    let mut finished = "cleared";
+    let mut result = None;
+    match outcome {
+        Some(GraceOutcome::Elapsed) => {
+            finished = "triggered";
+            let action = current_options(&settings).action;
+            let trigger = info_span!(
+                "trigger",
+                action = %action,
+                confidence = confidence.as_deref().unwrap_or("unknown"),
+                result = Empty,
+            );
+            let _triggering = trigger.enter();
+            if action == Action::Suspend {
+                // our own inhibitor would only hold up the suspend we asked for
+                drop(inhibitor.take());
+            }
+            info!(device = %device_label, action = %action, "running removal action");
+            let mut event = device_event(EventKind::Triggered, key, &device_label);
+            event.action = Some(action.to_string());
+            event.confidence = confidence;
+            let config = state.call(|guard| guard.config.clone());
+            #[cfg(unix)]
+            if config.capture_evidence {
+                let name = format!("{}-{}", key.bus, key.address);
+                evidence::capture(config.audit_dir.clone(), event.timestamp, name);
+            }
+            let on_reattach = !config.on_reattach.is_empty();
+            let relock_backends = (config.relock_until_reattach && action.locks())
+                .then(|| (config.lock_backends.clone(), config.lock_users.clone()));
+            // block new devices before the action, which may take a while
+            let usbguard = config.usbguard_block.then(|| {
+                usbguard::Block::start()
+                    .inspect_err(|err| warn!(device = %device_label, error = %err, "cannot switch usbguard to block-all"))
+                    .ok()
+            }).flatten();
+            let cause = script::Trigger {
+                tether_id: id,
+                vendor_id,
+                product_id,
+                serial: serial.clone(),
+                event: EventKind::Triggered.to_string(),
+                timestamp: event.timestamp,
+            };
+            let done = executor::submit({
+                let action = action.clone();
+                let span = Span::current();
+                move || span.in_scope(|| action.run_supervised(&config, &cause))
+            });
+            match await_action(&watch, &flags, &done) {
+                Ok(None) => event.result = Some(ActionResult::Ran),
+                Ok(Some(note)) => {
+                    warn!(device = %device_label, action = %action, note = %note, "removal action failed; fallback ran");
+                    event.message = Some(note);
+                    event.result = Some(ActionResult::FallbackRan);
+                    finished = "fallback ran";
+                }
+                Err(err) => {
+                    error!(device = %device_label, action = %action, error = %err, "removal action failed");
+                    event.message = Some(err);
+                    event.result = Some(ActionResult::Failed);
+                    finished = "action failed";
+                }
+            }
+            result = event.message.clone();
+            trigger.record("result", finished);
+            EVENTS.publish(event);
+
+            // with relock_until_reattach, unlocking is refused until the device is back
+            let relock = relock_backends.and_then(|(backends, users)| {
+                relock::Relock::start(backends, users)
+                    .inspect_err(|err| warn!(device = %device_label, error = %err, "cannot re-lock unlocked sessions"))
+                    .ok()
+            });
+
+            if action.undoes_on_reattach() || on_reattach || relock.is_some() || usbguard.is_some()
+            {
+                drop(inhibitor.take());
+                info!(device = %device_label, "waiting for the device to return");
+                let returned = wait_for_reattach(&watch, &flags, generation);
+                if let (GraceOutcome::Reattached, Some(usbguard)) = (&returned, &usbguard) {
+                    usbguard.allow(vendor_id, product_id, serial.as_deref());
+                }
+                match returned {
+                    GraceOutcome::Reattached if action.undoes_on_reattach() => {
+                        info!(device = %device_label, action = %action, "device returned; undoing removal action");
+                        action.undo();
+                        EVENTS.publish(device_event(EventKind::Reattached, key, &device_label));
+                    }
+                    GraceOutcome::Reattached => {
+                        info!(device = %device_label, "device returned; sessions may be unlocked again");
+                        EVENTS.publish(device_event(EventKind::Reattached, key, &device_label));
+                    }
+                    GraceOutcome::Superseded => return,
+                    _ => {}
+                }
+                if matches!(returned, GraceOutcome::Reattached) && on_reattach {
+                    let cause = script::Trigger {
+                        tether_id: id,
+                        vendor_id,
+                        product_id,
+                        serial: serial.clone(),
+                        event: EventKind::Reattached.to_string(),
+                        timestamp: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|elapsed| elapsed.as_secs())
+                            .unwrap_or_default(),
+                    };
+                    run_reattach_actions(&state, &watch, &flags, &device_label, &cause);
+                }
+            }
+            drop(relock);
+            drop(usbguard);
+        }
+        Some(GraceOutcome::Suppressed(reason)) => {
+            finished = "suppressed";
+            let action = current_options(&settings).action;
+            warn!(device = %device_label, action = %action, reason = %reason, "removal action suppressed");
+            let mut event = device_event(EventKind::Suppressed, key, &device_label);
+            event.action = Some(action.to_string());
+            event.message = Some(reason.clone());
+            result = Some(reason);
+            EVENTS.publish(event);
+        }
+        Some(GraceOutcome::Cancelled) => {
+            finished = "cancelled";
+            info!(device = %device_label, "removal action cancelled");
+            EVENTS.publish(device_event(EventKind::Cancelled, key, &device_label));
+        }
+        Some(GraceOutcome::Cleared) => {
+            info!(device = %device_label, "tether cleared without running its action");
+        }
+        Some(GraceOutcome::Superseded) => {
+            info!(device = %device_label, "monitor thread replaced by the watchdog");
+            return;
+        }
+        Some(GraceOutcome::Reattached) | None => {}
+    }
+
+    drop(watch);
+    drop(inhibitor);
+    span.record("outcome", finished);
+    remove_monitor(&state, key, generation, finished, result);
+}
+
+/// Run the `on_reattach` actions for a device that came back after its
+/// action fired, one after another, each with its retries and fallback.
+fn run_reattach_actions(
+    state: &State,
+    watch: &Watch,
+    flags: &MonitorFlags,
+    device_label: &str,
+    cause: &script::Trigger,
+) {
+    let config = state.call(|guard| guard.config.clone());
+    for action in config.on_reattach.clone() {
+        info!(device = %device_label, action = %action, "running reattach action");
+        let done = executor::submit({
+            let config = config.clone();
+            let cause = cause.clone();
+            let action = action.clone();
+            let span = Span::current();
+            move || span.in_scope(|| action.run_supervised(&config, &cause))
+        });
+        match await_action(watch, flags, &done) {
+            Ok(None) => {}
+            Ok(Some(note)) => {
+                warn!(device = %device_label, action = %action, note = %note, "reattach action failed; fallback ran");
+            }
+            Err(err) => {
+                error!(device = %device_label, action = %action, error = %err, "reattach action failed");
+            }
+        }
+    }
+}
+
+/// Keep servicing USB events and feeding the watchdog until the action
+/// handed to the executor finishes.
+fn await_action(
+    watch: &Watch,
+    flags: &MonitorFlags,
+    done: &Receiver<Result<Option<String>, String>>,
+) -> Result<Option<String>, String> {
+    loop {
+        flags.beat();
+        match done.try_recv() {
+            Ok(result) => return result,
+            Err(TryRecvError::Disconnected) => {
+                return Err("the action worker stopped before the action finished".to_string());
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+        if watch.handle_events(Some(EVENT_POLL_INTERVAL)).is_err() {
+            thread::sleep(EVENT_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Whether `settings` ask for a polling monitor, or hotplug is missing so
+/// every monitor polls, and if so how often.
+fn poll_interval(state: &State, settings: &Mutex<TetherOptions>) -> Option<Duration> {
+    if !current_options(settings).poll && rusb::has_hotplug() {
+        return None;
+    }
+    let millis = state.call(|guard| guard.config.poll_interval_ms);
+    Some(Duration::from_millis(millis.max(MIN_POLL_INTERVAL_MS)))
+}
+
+/// How a monitor hears about its device: a libusb hotplug callback, or
+/// enumerating the bus every so often and comparing it with what it saw.
+enum Watch {
+    Hotplug {
+        context: Context,
+        /// Unregisters the callback when dropped.
+        _registration: Registration<Context>,
+    },
+    Poll {
+        watcher: SelectedDeviceWatcher,
+        interval: Duration,
+        next: Cell<Instant>,
+        /// What the last poll found, to tell arrivals and removals apart.
+        present: Cell<Option<bool>>,
+    },
+}
+
+impl Watch {
+    /// A fresh libusb context with a hotplug callback for `watcher`'s
+    /// device, or a poll every `poll` when that is set.
+    fn open(watcher: &SelectedDeviceWatcher, poll: Option<Duration>) -> Result<Self, String> {
+        if let Some(interval) = poll {
+            let mut report = watcher.report.lock().unwrap_or_else(|err| err.into_inner());
+            report.backend = "polling";
+            report.registration_error = None;
+            return Ok(Watch::Poll {
+                watcher: watcher.clone(),
+                interval,
+                next: Cell::new(Instant::now() + interval),
+                present: Cell::new(None),
+            });
+        }
+
+        let context =
+            Context::new().map_err(|err| format!("failed to create USB context: {err}"))?;
+        let mut builder = HotplugBuilder::new();
+        builder
+            .vendor_id(watcher.vendor_id)
+            .product_id(watcher.product_id);
+        let registered = builder.register(&context, Box::new(watcher.clone()));
+        let mut report = watcher.report.lock().unwrap_or_else(|err| err.into_inner());
+        report.backend = "hotplug";
+        report.registration_error = registered.as_ref().err().map(ToString::to_string);
+        let registration =
+            registered.map_err(|err| format!("failed to register hotplug callback: {err}"))?;
+
+        Ok(Watch::Hotplug {
+            context,
+            _registration: registration,
+        })
+    }
+
+    fn is_polling(&self) -> bool {
+        matches!(self, Watch::Poll { .. })
+    }
+
+    /// Wait up to `timeout` for the device to come or go, passing what
+    /// happened to its lifecycle the way the hotplug callback does. A poll
+    /// that cannot enumerate the bus is an error, like a failing context.
+    fn handle_events(&self, timeout: Option<Duration>) -> rusb::Result<()> {
+        match self {
+            Watch::Hotplug { context, .. } => context.handle_events(timeout),
+            Watch::Poll {
+                watcher,
+                interval,
+                next,
+                present,
+            } => {
+                let wake = timeout.map_or(next.get(), |timeout| {
+                    next.get().min(Instant::now() + timeout)
+                });
+                thread::sleep(wake.saturating_duration_since(Instant::now()));
+                if Instant::now() < next.get() {
+                    return Ok(());
+                }
+                next.set(Instant::now() + *interval);
+                let Some(now_present) = watcher.is_present() else {
+                    return Err(rusb::Error::Other);
+                };
+                if present
+                    .replace(Some(now_present))
+                    .is_some_and(|was| was != now_present)
+                {
+                    WatchReport::record(
+                        &watcher.report,
+                        if now_present { "arrived" } else { "left" },
+                    );
+                }
+                if now_present {
+                    watcher.lifecycle.arrived();
+                } else {
+                    watcher.lifecycle.departed();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check the device is visible to the context its hotplug callback was
+/// registered on. Some platforms accept the registration and then never
+/// deliver events; a context that cannot even enumerate the device is one
+/// of them, so the monitor falls back to polling for it.
+fn check_hotplug(watch: &Watch, flags: &MonitorFlags, key: DeviceKey, device_label: &str) {
+    let Watch::Hotplug { context, .. } = watch else {
+        return;
+    };
+    let visible = context.devices().is_ok_and(|devices| {
+        devices
+            .iter()
+            .any(|device| DeviceKey::new(device.bus_number(), device.address()) == key)
+    });
+    if visible {
+        flags.polling.store(false, Ordering::SeqCst);
+        return;
+    }
+    if !flags.polling.swap(true, Ordering::SeqCst) {
+        warn!(device = %device_label, "hotplug context cannot see the device; polling for it instead");
+        let mut event = device_event(EventKind::Degraded, key, device_label);
+        event.message = Some("hotplug context cannot see the device; polling instead".to_string());
+        EVENTS.publish(event);
+    }
+}
+
+/// Exponential backoff between attempts to recreate a failed USB context.
+fn usb_retry_delay(failures: u32) -> Duration {
+    USB_RETRY_BASE
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(USB_RETRY_MAX)
+}
+
+/// Sleep for `delay` while keeping the watchdog fed. Returns false if the
+/// watchdog replaced this thread in the meantime.
+fn backoff(flags: &MonitorFlags, generation: u64, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        flags.beat();
+        if flags.superseded(generation) {
+            return false;
+        }
+        thread::sleep(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .min(EVENT_POLL_INTERVAL),
+        );
+    }
+    true
+}
+
+/// Keep servicing USB events until the grace period runs out or something
+/// stops the countdown. A zero grace period elapses immediately. The grace
+/// period is re-read on every pass so replaced settings apply mid-countdown,
+/// and `expedite` cuts it short when the system is about to sleep.
+fn wait_out_grace(
+    watch: &Watch,
+    settings: &Mutex<TetherOptions>,
+    flags: &MonitorFlags,
+    generation: u64,
+) -> GraceOutcome {
+    let removed_at = Instant::now();
+    wait_until(watch, flags, generation, || {
+        removed_at + current_options(settings).grace
+    })
+}
+
+/// Ask `pre_trigger_hook`, if set, whether the action may run now, waiting
+/// out the delays it asks for. An expedited removal doesn't ask.
+fn consult_hook(
+    state: &State,
+    watch: &Watch,
+    flags: &MonitorFlags,
+    generation: u64,
+    trigger: &script::Trigger,
+) -> GraceOutcome {
+    let config = state.call(|guard| guard.config.clone());
+    let Some(hook) = &config.pre_trigger_hook else {
+        return GraceOutcome::Elapsed;
+    };
+
+    let started = Instant::now();
+    loop {
+        if flags.expedite.load(Ordering::SeqCst) {
+            return GraceOutcome::Elapsed;
+        }
+        match script::consult(hook, &config.script(hook), trigger, command::HELPER_TIMEOUT) {
+            script::Verdict::Proceed => return GraceOutcome::Elapsed,
+            script::Verdict::Veto(reason) => {
+                return GraceOutcome::Suppressed(match reason {
+                    Some(reason) => format!("vetoed by the pre-trigger hook: {reason}"),
+                    None => "vetoed by the pre-trigger hook".to_string(),
+                });
+            }
+            script::Verdict::Delay(delay) => {
+                let left = MAX_HOOK_DELAY.saturating_sub(started.elapsed());
+                if left.is_zero() {
+                    warn!(hook = %hook.display(), "pre-trigger hook has put the action off too long; running it");
+                    return GraceOutcome::Elapsed;
+                }
+                let until = Instant::now() + delay.min(left);
+                info!(hook = %hook.display(), delay_secs = delay.as_secs(), "pre-trigger hook put the action off");
+                match wait_until(watch, flags, generation, || until) {
+                    GraceOutcome::Elapsed => {}
+                    other => return other,
+                }
+            }
+        }
+    }
+}
+
+/// Handle USB events until `deadline` passes or something ends the wait
+/// first: the device returning, the tether going away, or an expedite.
+fn wait_until(
+    watch: &Watch,
+    flags: &MonitorFlags,
+    generation: u64,
+    deadline: impl Fn() -> Instant,
+) -> GraceOutcome {
+    loop {
+        flags.beat();
+        if flags.superseded(generation) {
+            return GraceOutcome::Superseded;
+        }
+        if flags.cancelled.load(Ordering::SeqCst) {
+            return GraceOutcome::Cancelled;
+        }
+        match flags.lifecycle.phase() {
+            Phase::Cleared => return GraceOutcome::Cleared,
+            Phase::Armed => return GraceOutcome::Reattached,
+            Phase::GracePeriod | Phase::Triggered | Phase::Rearmed => {}
+        }
+
+        if flags.expedite.load(Ordering::SeqCst) {
+            return GraceOutcome::Elapsed;
+        }
+
+        let deadline = deadline();
+        let now = Instant::now();
+        if now >= deadline {
+            return GraceOutcome::Elapsed;
+        }
+
+        let wait = (deadline - now).min(EVENT_POLL_INTERVAL);
+        if let Err(err) = watch.handle_events(Some(wait)) {
+            // keep counting down even if libusb is unhappy; the device is already gone
+            debug!(error = %err, "error while handling USB events during grace period");
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// Mirror a devd attach or detach onto the matching tether's removed flag,
+/// the same way the libusb hotplug callback would.
+#[cfg(target_os = "freebsd")]
+fn apply_devd_event(state: &State, event: devd::DevdEvent) {
+    let key = DeviceKey::new(event.bus, event.address);
+    state.call(move |guard| {
+        let Some(monitor) = guard.monitors.get(&key) else {
+            return;
+        };
+        if (monitor.vendor_id, monitor.product_id) != (event.vendor_id, event.product_id) {
+            return;
+        }
+        if event.attached {
+            WatchReport::record(&monitor.flags.watch, "arrived");
+            monitor.flags.lifecycle.arrived();
+        } else {
+            WatchReport::record(&monitor.flags.watch, "left");
+            monitor.flags.lifecycle.departed();
+        }
+    })
+}
+
+/// Note that `key`'s action is about to run, or say why it may not: it ran
+/// less than `cooldown` ago, or already `max_triggers_per_hour` times.
+fn record_trigger(state: &State, key: DeviceKey, cooldown: Duration) -> Result<(), String> {
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    state.call(move |guard| {
+        let limit = guard.config.max_triggers_per_hour;
+        let now = Instant::now();
+        let history = guard.triggers.entry(key).or_default();
+        history.retain(|at| now.duration_since(*at) < HOUR);
+
+        if let Some(last) = history.last()
+            && now.duration_since(*last) < cooldown
+        {
+            return Err(format!(
+                "last ran {}s ago, within the {}s cooldown",
+                now.duration_since(*last).as_secs(),
+                cooldown.as_secs()
+            ));
+        }
+        if limit > 0 && history.len() >= limit as usize {
+            return Err(format!("already ran {limit} time(s) in the last hour"));
+        }
+
+        history.push(now);
+        JOURNAL.triggered(key.bus, key.address);
+        Ok(())
+    })
+}
+
+/// After an action that undoes itself has run, keep watching until the
+/// device returns or the tether is cleared.
+fn wait_for_reattach(watch: &Watch, flags: &MonitorFlags, generation: u64) -> GraceOutcome {
+    loop {
+        flags.beat();
+        if flags.superseded(generation) {
+            return GraceOutcome::Superseded;
+        }
+        match flags.lifecycle.phase() {
+            Phase::Cleared => return GraceOutcome::Cleared,
+            Phase::Rearmed => return GraceOutcome::Reattached,
+            Phase::Armed | Phase::GracePeriod | Phase::Triggered => {}
+        }
+        if let Err(err) = watch.handle_events(Some(EVENT_POLL_INTERVAL)) {
+            debug!(error = %err, "error while handling USB events while waiting for reattach");
+            thread::sleep(EVENT_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Run the action of every tether whose device is already gone before the
+/// system sleeps, including removals libusb has not reported yet, and wait
+/// for the monitors to finish so the machine never sleeps unlocked.
+fn act_before_sleep(state: &State) {
+    let present = present_devices();
+    let expedited = state.call(move |guard| {
+        let mut expedited = Vec::new();
+        for (key, monitor) in guard.monitors.iter() {
+            // tethers that fired or were cleared have nothing left to run
+            let phase = monitor.flags.lifecycle.phase();
+            if !matches!(phase, Phase::Armed | Phase::GracePeriod)
+                || monitor.flags.cancelled.load(Ordering::SeqCst)
+            {
+                continue;
+            }
+            let absent = phase == Phase::GracePeriod
+                || present.as_ref().is_some_and(|present| {
+                    present.get(key) != Some(&(monitor.vendor_id, monitor.product_id))
+                });
+            if absent {
+                monitor.flags.lifecycle.departed();
+                monitor.flags.expedite.store(true, Ordering::SeqCst);
+                expedited.push(*key);
+            }
+        }
+        expedited
+    });
+
+    if expedited.is_empty() {
+        return;
+    }
+    warn!(
+        count = expedited.len(),
+        "tethered devices are missing; running removal actions before sleep"
+    );
+
+    let deadline = Instant::now() + SLEEP_ACTION_TIMEOUT;
+    while Instant::now() < deadline {
+        let pending = expedited.clone();
+        if state.call(move |guard| pending.iter().all(|key| !guard.monitors.contains_key(key))) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    warn!("removal actions are still running as the system goes to sleep");
+}
+
+/// What a rule pattern is matched against.
+struct Attached {
+    vendor_id: u16,
+    product_id: u16,
+    classes: Vec<u8>,
+}
+
+/// Every attached device other than hubs, or `None` if libusb cannot
+/// enumerate them.
+fn present_classes() -> Option<HashMap<DeviceKey, Attached>> {
+    let context = Context::new().ok()?;
+    let devices = context.devices().ok()?;
+    Some(
+        devices
+            .iter()
+            .filter_map(|device| {
+                let descriptor = device.device_descriptor().ok()?;
+                if descriptor.class_code() == 0x09 {
+                    return None;
+                }
+                Some((
+                    DeviceKey::new(device.bus_number(), device.address()),
+                    Attached {
+                        vendor_id: descriptor.vendor_id(),
+                        product_id: descriptor.product_id(),
+                        classes: device_classes(&device, &descriptor),
+                    },
+                ))
+            })
+            .collect(),
+    )
+}
+
+/// Vendor and product of every attached device, or `None` if libusb cannot
+/// enumerate them.
+fn present_devices() -> Option<HashMap<DeviceKey, (u16, u16)>> {
+    let context = Context::new().ok()?;
+    let devices = context.devices().ok()?;
+    Some(
+        devices
+            .iter()
+            .filter_map(|device| {
+                let descriptor = device.device_descriptor().ok()?;
+                Some((
+                    DeviceKey::new(device.bus_number(), device.address()),
+                    (descriptor.vendor_id(), descriptor.product_id()),
+                ))
+            })
+            .collect(),
+    )
+}
+
+fn current_options(settings: &Mutex<TetherOptions>) -> TetherOptions {
+    settings
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// Write the metrics textfile every `metrics_interval_secs` while
+/// `metrics_textfile` is set.
+fn export_metrics(state: &State) {
+    loop {
+        let (path, interval, contents) = state.call(|guard| {
+            let interval = Duration::from_secs(guard.config.metrics_interval_secs.max(1));
+            let snapshot = metrics::Snapshot {
+                tether_states: guard.monitors.values().map(DeviceMonitor::state).collect(),
+                protection: protection(guard).0,
+                monitor_restarts: guard
+                    .monitors
+                    .values()
+                    .map(|monitor| u64::from(monitor.restarts))
+                    .sum(),
+            };
+            (
+                guard.config.metrics_textfile.clone(),
+                interval,
+                metrics::REGISTRY.render(&snapshot),
+            )
+        });
+        if let Some(path) = path
+            && let Err(err) = metrics::write_textfile(&path, &contents)
+        {
+            warn!(error = %err, "failed to write the metrics textfile");
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(unix)]
+fn journal_path() -> PathBuf {
+    reexec::beside_pidfile(".journal")
+}
+
+/// The tethers a crashed predecessor journaled, when `state_journal` is on.
+#[cfg(unix)]
+fn recover_journal(state: &State) -> Option<reexec::SavedState> {
+    if !state.call(|guard| guard.config.state_journal) {
+        return None;
+    }
+    let path = journal_path();
+    match journal::replay(&path) {
+        Ok(Some(replayed)) => {
+            if let Some(corrupt) = &replayed.corrupt {
+                warn!(path = %path.display(), error = %corrupt, "tether journal is damaged; later records ignored");
+            }
+            info!(
+                tethers = replayed.state.tethers.len(),
+                "recovering tethers from the journal"
+            );
+            Some(replayed.state)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            error!(error = %err, "failed to read the tether journal");
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn start_journal(guard: &DaemonState) {
+    if let Err(err) = JOURNAL.start(&journal_path(), &saved_state(guard)) {
+        error!(error = %err, "tethers will not survive a crash");
+    }
+}
+
+/// Replace the daemon with the binary now on disk, as after a package
+/// upgrade, carrying tethers and trigger history over so no device goes
+/// unwatched. Refused while a tethered device is away: its grace period or
+/// relock would not survive the exec.
+#[cfg(unix)]
+fn handle_reexec(peer: &PeerInfo, state: State) -> Result<String, String> {
+    if !peer.is_root() && peer.uid != nix::unistd::getuid().as_raw() {
+        return Err("only root can re-exec the daemon".to_string());
+    }
+    state.call(|guard| check_reexec(guard))?;
+
+    // reply first; the exec closes the connection
+    thread::spawn(move || {
+        thread::sleep(REEXEC_DELAY);
+        // exec on the state thread so nothing changes once the state is saved
+        state.call(|guard| {
+            if let Err(err) = check_reexec(guard) {
+                warn!(error = %err, "re-exec abandoned");
+                return;
+            }
+            info!(tethers = guard.monitors.len(), "re-executing deadmand");
+            if let Err(err) = reexec::exec(&saved_state(guard)) {
+                error!(error = %err, "re-exec failed; carrying on as before");
+            }
+        });
+    });
+    Ok("re-executing deadmand".to_string())
+}
+
+#[cfg(unix)]
+fn check_reexec(guard: &DaemonState) -> Result<(), String> {
+    match guard
+        .monitors
+        .values()
+        .filter(|monitor| monitor.is_absent())
+        .count()
+    {
+        0 => Ok(()),
+        absent => Err(format!(
+            "{absent} tethered device(s) are disconnected; try again once they return or are untethered"
+        )),
+    }
+}
+
+fn saved_state(guard: &DaemonState) -> reexec::SavedState {
+    let now = Instant::now();
+    let tethers = guard
+        .monitors
+        .iter()
+        .filter(|(_, monitor)| monitor.flags.lifecycle.phase() == Phase::Armed)
+        .map(|(key, monitor)| saved_tether(*key, monitor))
+        .collect();
+    let triggers = guard
+        .triggers
+        .iter()
+        .map(|(key, history)| reexec::SavedTriggers {
+            bus: key.bus,
+            address: key.address,
+            ages_ms: history
+                .iter()
+                .map(|at| now.duration_since(*at).as_millis() as u64)
+                .collect(),
+        })
+        .collect();
+    let rules = guard.rules.iter().map(saved_rule).collect();
+    reexec::SavedState {
+        tethers,
+        triggers,
+        rules,
+    }
+}
+
+fn saved_rule(rule: &Rule) -> reexec::SavedRule {
+    reexec::SavedRule {
+        id: rule.id,
+        pattern: rule.pattern.to_string(),
+        action: rule.options.action.to_string(),
+        grace_secs: rule.options.grace.as_secs(),
+        cooldown_secs: rule.options.cooldown.as_secs(),
+        poll: rule.options.poll,
+        owner: rule.owner,
+    }
+}
+
+fn saved_tether(key: DeviceKey, monitor: &DeviceMonitor) -> reexec::SavedTether {
+    let options = monitor.options();
+    reexec::SavedTether {
+        id: monitor.id,
+        bus: key.bus,
+        address: key.address,
+        vendor_id: monitor.vendor_id,
+        product_id: monitor.product_id,
+        product: monitor.product_name.clone(),
+        serial: monitor.serial.clone(),
+        action: options.action.to_string(),
+        grace_secs: options.grace.as_secs(),
+        cooldown_secs: options.cooldown.as_secs(),
+        rearm: options.rearm,
+        poll: options.poll,
+        owner: monitor.owner,
+        rule: monitor.rule,
+    }
+}
+
+/// Take over the tethers of the daemon this one replaced, or journaled
+/// before a crash. Each monitor
+/// starts as if restarted by the watchdog, so a device unplugged during
+/// the exec is noticed as removed.
+fn restore(state: &State, saved: reexec::SavedState) {
+    let now = Instant::now();
+    let spawned = state.call(move |guard| {
+        let mut spawned = Vec::new();
+        for triggers in saved.triggers {
+            let history = triggers
+                .ages_ms
+                .into_iter()
+                .filter_map(|age| now.checked_sub(Duration::from_millis(age)))
+                .collect();
+            guard
+                .triggers
+                .insert(DeviceKey::new(triggers.bus, triggers.address), history);
+        }
+
+        for rule in saved.rules {
+            let parsed = rule
+                .pattern
+                .parse::<Pattern>()
+                .and_then(|pattern| Ok((pattern, rule.action.parse::<Action>()?)));
+            let (pattern, action) = match parsed {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    error!(rule = rule.id, error = %err, "dropping rule that cannot be restored");
+                    continue;
+                }
+            };
+            NEXT_RULE_ID.fetch_max(rule.id + 1, Ordering::Relaxed);
+            guard.rules.push(Rule {
+                id: rule.id,
+                pattern,
+                options: TetherOptions {
+                    action,
+                    grace: Duration::from_secs(rule.grace_secs),
+                    cooldown: Duration::from_secs(rule.cooldown_secs),
+                    rearm: false,
+                    poll: rule.poll,
+                },
+                owner: rule.owner,
+            });
+        }
+
+        for tether in saved.tethers {
+            let key = DeviceKey::new(tether.bus, tether.address);
+            let action = match tether.action.parse::<Action>() {
+                Ok(action) => action,
+                Err(err) => {
+                    error!(id = tether.id, error = %err, "dropping tether with an unknown action");
+                    continue;
+                }
+            };
+            let settings = Arc::new(Mutex::new(TetherOptions {
+                action,
+                grace: Duration::from_secs(tether.grace_secs),
+                cooldown: Duration::from_secs(tether.cooldown_secs),
+                rearm: tether.rearm,
+                poll: tether.poll,
+            }));
+            let flags = MonitorFlags::new();
+            flags.generation.store(1, Ordering::SeqCst);
+            NEXT_TETHER_ID.fetch_max(tether.id + 1, Ordering::Relaxed);
+            guard.monitors.insert(
+                key,
+                DeviceMonitor {
+                    id: tether.id,
+                    vendor_id: tether.vendor_id,
+                    product_id: tether.product_id,
+                    product_name: tether.product.clone(),
+                    serial: tether.serial.clone(),
+                    settings: Arc::clone(&settings),
+                    owner: tether.owner,
+                    flags: flags.clone(),
+                    restarts: 0,
+                    rule: tether.rule,
+                },
+            );
+            spawned.push((
+                key,
+                DeviceInfo {
+                    vendor_id: tether.vendor_id,
+                    product_id: tether.product_id,
+                    product_name: tether.product,
+                    serial: tether.serial,
+                },
+                settings,
+                flags,
+            ));
+        }
+        spawned
+    });
+
+    info!(tethers = spawned.len(), "restored tethers");
+    for (key, device_info, settings, flags) in spawned {
+        if let Err(err) = spawn_monitor(state.clone(), key, device_info, settings, flags) {
+            warn!(bus = key.bus, address = key.address, error = %err, "restored tether is not watched yet");
+        }
+    }
+}
+
+/// Restart monitor threads whose heartbeat stopped, keeping the tether's
+/// flags and settings so a removal seen before the restart is not lost.
+fn supervise_monitors(state: State) {
+    loop {
+        thread::sleep(WATCHDOG_TIMEOUT / 4);
+
+        let stalled = state.call(|guard| {
+            let mut stalled = Vec::new();
+            reap_monitors(guard);
+            for (key, monitor) in guard.monitors.iter_mut() {
+                let silence = monitor.flags.silence();
+                if silence < WATCHDOG_TIMEOUT {
+                    continue;
+                }
+
+                let summary = format_device_summary(
+                    *key,
+                    monitor.vendor_id,
+                    monitor.product_id,
+                    monitor.product_name.as_deref(),
+                );
+                warn!(
+                    device = %summary,
+                    silent_secs = silence.as_secs(),
+                    "monitor thread stopped responding; restarting it"
+                );
+                monitor.restarts += 1;
+                monitor.flags.generation.fetch_add(1, Ordering::SeqCst);
+                stalled.push((
+                    *key,
+                    DeviceInfo {
+                        vendor_id: monitor.vendor_id,
+                        product_id: monitor.product_id,
+                        product_name: monitor.product_name.clone(),
+                        serial: monitor.serial.clone(),
+                    },
+                    Arc::clone(&monitor.settings),
+                    monitor.flags.clone(),
+                ));
+            }
+            stalled
+        });
+
+        for (key, device_info, settings, flags) in stalled {
+            if let Err(err) = spawn_monitor(state.clone(), key, device_info, settings, flags) {
+                warn!(bus = key.bus, address = key.address, error = %err, "monitor restart failed");
+            }
+        }
+    }
+}
+
+/// Drop the tether for `key`, unless the watchdog has since handed it to a
+/// newer monitor thread, and keep a note of how it ended, and why if it
+/// went wrong, for `status`.
+fn remove_monitor(
+    state: &State,
+    key: DeviceKey,
+    generation: u64,
+    outcome: &'static str,
+    result: Option<String>,
+) {
+    state.call(move |guard| {
+        if guard
+            .monitors
+            .get(&key)
+            .is_some_and(|monitor| !monitor.flags.superseded(generation))
+            && let Some(monitor) = guard.monitors.remove(&key)
+        {
+            JOURNAL.untethered(key.bus, key.address);
+            guard.finish(key, &monitor, outcome, result);
+            let options = monitor.options();
+            // a rule tethers the device again anyway when it is attached
+            if options.rearm
+                && monitor.rule.is_none()
+                && guard.finished.back().is_some_and(FinishedTether::fired)
+            {
+                info!(
+                    id = monitor.id,
+                    "tether will re-arm when its device returns"
+                );
+                guard.rearm.push(Rearm {
+                    id: monitor.id,
+                    vendor_id: monitor.vendor_id,
+                    product_id: monitor.product_id,
+                    serial: monitor.serial,
+                    options,
+                    owner: monitor.owner,
+                });
+            }
+        }
+    })
+}
+
+/// Tether devices as they are attached: returning devices of `rearm`
+/// tethers that fired, and devices matching a rule. A device matches a
+/// `rearm` tether with the same vendor, product and, when the tether knew
+/// one, serial; it usually comes back at a new address.
+fn watch_arrivals(state: &State) {
+    // devices already offered to the rules, so one untethered by hand stays so
+    let mut seen: HashSet<DeviceKey> = HashSet::new();
+    loop {
+        thread::sleep(ARRIVAL_POLL_INTERVAL);
+        let (wanted, rules) = state.call(|guard| {
+            let wanted: Vec<(u16, u16)> = guard
+                .rearm
+                .iter()
+                .map(|rearm| (rearm.vendor_id, rearm.product_id))
+                .collect();
+            let rules: Vec<(u64, Pattern)> = guard
+                .rules
+                .iter()
+                .map(|rule| (rule.id, rule.pattern.clone()))
+                .collect();
+            (wanted, rules)
+        });
+        if rules.is_empty() {
+            seen.clear();
+        }
+        if wanted.is_empty() && rules.is_empty() {
+            continue;
+        }
+        let Some(present) = present_classes() else {
+            continue;
+        };
+        seen.retain(|key| present.contains_key(key));
+        for (key, device) in present {
+            if wanted.contains(&(device.vendor_id, device.product_id)) {
+                rearm_device(state, key);
+            }
+            if rules.is_empty() || !seen.insert(key) {
+                continue;
+            }
+            if let Some((rule, _)) = rules.iter().find(|(_, pattern)| {
+                pattern.matches(device.vendor_id, device.product_id, &device.classes)
+            }) {
+                tether_for_rule(state, key, *rule);
+            }
+        }
+    }
+}
+
+fn rearm_device(state: &State, key: DeviceKey) {
+    // strings need the device opened, so only for likely matches and without the lock
+    let Ok(device_info) = lookup_device(key.bus, key.address) else {
+        return;
+    };
+    let summary = format_device_summary(
+        key,
+        device_info.vendor_id,
+        device_info.product_id,
+        device_info.product_name.as_deref(),
+    );
+
+    let flags = MonitorFlags::new();
+    let id = NEXT_TETHER_ID.fetch_add(1, Ordering::Relaxed);
+    let claimed = state.call({
+        let device_info = device_info.clone();
+        let flags = flags.clone();
+        move |guard| {
+            if guard.monitors.contains_key(&key) {
+                return None;
+            }
+            let position = guard.rearm.iter().position(|rearm| {
+                rearm.vendor_id == device_info.vendor_id
+                    && rearm.product_id == device_info.product_id
+                    && (rearm.serial.is_none() || rearm.serial == device_info.serial)
+            })?;
+            let rearm = guard.rearm.remove(position);
+            let settings = Arc::new(Mutex::new(rearm.options));
+            guard.monitors.insert(
+                key,
+                DeviceMonitor {
+                    id,
+                    vendor_id: device_info.vendor_id,
+                    product_id: device_info.product_id,
+                    product_name: device_info.product_name.clone(),
+                    serial: device_info.serial.clone(),
+                    settings: Arc::clone(&settings),
+                    owner: rearm.owner,
+                    flags: flags.clone(),
+                    restarts: 0,
+                    rule: None,
+                },
+            );
+            JOURNAL.tethered(saved_tether(key, &guard.monitors[&key]));
+            Some((rearm.id, settings, rearm.owner))
+        }
+    });
+    let Some((previous, settings, owner)) = claimed else {
+        return;
+    };
+
+    if let Err(err) = spawn_monitor(state.clone(), key, device_info, settings, flags) {
+        warn!(device = %summary, id, error = %err, "re-armed tether is not watched yet");
+    }
+
+    info!(device = %summary, id, previous, owner, "tether re-armed");
+    let mut event = device_event(EventKind::Rearmed, key, &summary);
+    event.message = Some(format!("tether {previous} re-armed as {id}"));
+    event.uid = Some(owner);
+    EVENTS.publish(event);
+}
+
+/// Tether the device at `key` for the rule with `rule_id`, unless it is
+/// tethered already or the rule has gone. Returns the new tether's ID.
+fn tether_for_rule(state: &State, key: DeviceKey, rule_id: u64) -> Option<u64> {
+    let device_info = match lookup_device(key.bus, key.address) {
+        Ok(device_info) => device_info,
+        Err(err) => {
+            debug!(rule = rule_id, error = %err, "matching device went before it was tethered");
+            return None;
+        }
+    };
+    let summary = format_device_summary(
+        key,
+        device_info.vendor_id,
+        device_info.product_id,
+        device_info.product_name.as_deref(),
+    );
+
+    let flags = MonitorFlags::new();
+    let id = NEXT_TETHER_ID.fetch_add(1, Ordering::Relaxed);
+    let (settings, owner) = state.call({
+        let device_info = device_info.clone();
+        let flags = flags.clone();
+        move |guard| {
+            if guard.monitors.contains_key(&key) {
+                return None;
+            }
+            let rule = guard.rules.iter().find(|rule| rule.id == rule_id)?;
+            let settings = Arc::new(Mutex::new(rule.options.clone()));
+            let owner = rule.owner;
+            guard.monitors.insert(
+                key,
+                DeviceMonitor {
+                    id,
+                    vendor_id: device_info.vendor_id,
+                    product_id: device_info.product_id,
+                    product_name: device_info.product_name.clone(),
+                    serial: device_info.serial.clone(),
+                    settings: Arc::clone(&settings),
+                    owner,
+                    flags: flags.clone(),
+                    restarts: 0,
+                    rule: Some(rule_id),
+                },
+            );
+            JOURNAL.tethered(saved_tether(key, &guard.monitors[&key]));
+            Some((settings, owner))
+        }
+    })?;
+
+    if let Err(err) = spawn_monitor(state.clone(), key, device_info, settings, flags) {
+        warn!(device = %summary, id, error = %err, "rule tether is not watched yet");
+    }
+
+    info!(device = %summary, id, rule = rule_id, owner, "tether activated by rule");
+    let mut event = device_event(EventKind::Tethered, key, &summary);
+    event.message = Some(format!("tethered by rule {rule_id} (id {id})"));
+    event.uid = Some(owner);
+    EVENTS.publish(event);
+    Some(id)
+}
+
+/// Drop tethers whose device went away without a monitor thread to finish
+/// them, e.g. one cancelled while its thread was being replaced, and
+/// forget finished tethers once they are old news. Tethers that fired stay
+/// until acknowledged.
+fn reap_monitors(guard: &mut DaemonState) {
+    let stale: Vec<DeviceKey> = guard
+        .monitors
+        .iter()
+        .filter(|(_, monitor)| match monitor.flags.lifecycle.phase() {
+            Phase::Cleared => true,
+            Phase::GracePeriod => monitor.flags.cancelled.load(Ordering::SeqCst),
+            Phase::Armed | Phase::Triggered | Phase::Rearmed => false,
+        })
+        .map(|(key, _)| *key)
+        .collect();
+    for key in stale {
+        if let Some(monitor) = guard.monitors.remove(&key) {
+            debug!(id = monitor.id, "reaped finished tether");
+            JOURNAL.untethered(key.bus, key.address);
+            let outcome = if monitor.flags.cancelled.load(Ordering::SeqCst) {
+                "cancelled"
+            } else {
+                "cleared"
+            };
+            guard.finish(key, &monitor, outcome, None);
+        }
+    }
+    guard
+        .finished
+        .retain(|finished| finished.fired() || finished.at.elapsed() < FINISHED_KEPT_FOR);
+}
+
+fn enumerate_devices() -> Result<Vec<UsbDevice>, String> {
+    let context = Context::new().map_err(|err| format!("failed to create USB context: {err}"))?;
+    let devices = context
+        .devices()
+        .map_err(|err| format!("failed to list USB devices: {err}"))?;
+
+    let mut listed = Vec::new();
+    for device in devices.iter() {
+        let descriptor = match device.device_descriptor() {
+            Ok(descriptor) => descriptor,
+            Err(err) => {
+                warn!(
+                    bus = device.bus_number(),
+                    address = device.address(),
+                    error = %err,
+                    "could not read device descriptor"
+                );
+                continue;
+            }
+        };
+
+        let strings = descriptors::strings(&device, &descriptor);
+        listed.push(UsbDevice {
+            bus: device.bus_number(),
+            address: device.address(),
+            ports: device.port_numbers().unwrap_or_default(),
+            vendor_id: descriptor.vendor_id(),
+            product_id: descriptor.product_id(),
+            product: strings.product,
+            manufacturer: strings.manufacturer,
+            serial: strings.serial,
+            usb_version: descriptor.usb_version().to_string(),
+            device_version: descriptor.device_version().to_string(),
+            class_code: descriptor.class_code(),
+            sub_class_code: descriptor.sub_class_code(),
+            protocol_code: descriptor.protocol_code(),
+            classes: device_classes(&device, &descriptor),
+            max_packet_size: descriptor.max_packet_size(),
+            num_configurations: descriptor.num_configurations(),
+            speed: format!("{:?}", device.speed()),
+            tether_id: None,
+            tether_state: None,
+        });
+    }
+    let connected: Vec<_> = listed
+        .iter()
+        .map(|device| (device.bus, device.address))
+        .collect();
+    descriptors::retain_connected(&connected);
+    Ok(listed)
+}
+
+/// The device class, or the interface classes for devices that declare theirs
+/// per interface (class 0), such as most keyboards and security keys.
+fn device_classes(device: &Device<Context>, descriptor: &rusb::DeviceDescriptor) -> Vec<u8> {
+    if descriptor.class_code() != 0 {
+        return vec![descriptor.class_code()];
+    }
+
+    let mut classes = Vec::new();
+    if let Ok(config) = device.active_config_descriptor() {
+        for interface in config.interfaces() {
+            for setting in interface.descriptors() {
+                if !classes.contains(&setting.class_code()) {
+                    classes.push(setting.class_code());
+                }
+            }
+        }
+    }
+    classes
+}
+
+/// Every configuration of the device at `bus` and `address`, read from the
+/// descriptors libusb caches, so the device is never opened.
+fn device_configurations(bus: u8, address: u8) -> Result<Vec<Configuration>, String> {
+    let context = Context::new().map_err(|err| format!("failed to create USB context: {err}"))?;
+    let devices = context
+        .devices()
+        .map_err(|err| format!("failed to list USB devices: {err}"))?;
+    let device = devices
+        .iter()
+        .find(|device| device.bus_number() == bus && device.address() == address)
+        .ok_or_else(|| format!("no device found on bus {bus:03} address {address:03}"))?;
+    let descriptor = device
+        .device_descriptor()
+        .map_err(|err| format!("failed to read device descriptor: {err}"))?;
+    let active = device
+        .active_config_descriptor()
+        .ok()
+        .map(|config| config.number());
+
+    let mut configurations = Vec::new();
+    for index in 0..descriptor.num_configurations() {
+        let config = match device.config_descriptor(index) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(bus, address, index, error = %err, "could not read configuration descriptor");
+                continue;
+            }
+        };
+        let interfaces = config
+            .interfaces()
+            .flat_map(|interface| interface.descriptors())
+            .map(|setting| Interface {
+                number: setting.interface_number(),
+                alternate_setting: setting.setting_number(),
+                class_code: setting.class_code(),
+                sub_class_code: setting.sub_class_code(),
+                protocol_code: setting.protocol_code(),
+                endpoints: setting
+                    .endpoint_descriptors()
+                    .map(|endpoint| Endpoint {
+                        address: endpoint.address(),
+                        direction: match endpoint.direction() {
+                            rusb::Direction::In => "in",
+                            rusb::Direction::Out => "out",
+                        }
+                        .to_string(),
+                        transfer_type: match endpoint.transfer_type() {
+                            rusb::TransferType::Control => "control",
+                            rusb::TransferType::Isochronous => "isochronous",
+                            rusb::TransferType::Bulk => "bulk",
+                            rusb::TransferType::Interrupt => "interrupt",
+                        }
+                        .to_string(),
+                        max_packet_size: endpoint.max_packet_size(),
+                        interval: endpoint.interval(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        configurations.push(Configuration {
+            number: config.number(),
+            active: active == Some(config.number()),
+            max_power: config.max_power(),
+            self_powered: config.self_powered(),
+            remote_wakeup: config.remote_wakeup(),
+            interfaces,
+        });
+    }
+    Ok(configurations)
+}
+
+fn lookup_device(bus: u8, address: u8) -> Result<DeviceInfo, String> {
+    let context = Context::new().map_err(|err| format!("failed to create USB context: {err}"))?;
+    let devices = context
+        .devices()
+        .map_err(|err| format!("failed to list USB devices: {err}"))?;
+
+    for device in devices.iter() {
+        if device.bus_number() == bus && device.address() == address {
+            let descriptor = device
+                .device_descriptor()
+                .map_err(|err| format!("failed to read device descriptor: {err}"))?;
+
+            let strings = descriptors::strings(&device, &descriptor);
+            if strings.product.is_none() {
+                warn!(
+                    bus = bus,
+                    address = address,
+                    vendor_id = descriptor.vendor_id(),
+                    product_id = descriptor.product_id(),
+                    "could not read product string"
+                );
+            }
+
+            return Ok(DeviceInfo {
+                vendor_id: descriptor.vendor_id(),
+                product_id: descriptor.product_id(),
+                product_name: strings.product,
+                serial: strings.serial,
+            });
+        }
+    }
+
+    Err(format!(
+        "no device found on bus {:03} address {:03}",
+        bus, address
+    ))
+}
+
+/// Attribute `event` to the client request that caused it.
+fn requested_by(event: &mut Event, request: &Request, peer: &PeerInfo) {
+    event.uid = Some(peer.uid);
+    event.request_id = Some(request.id().to_string());
+}
+
+fn device_event(kind: EventKind, key: DeviceKey, summary: &str) -> Event {
+    let mut event = Event::new(kind);
+    event.bus = Some(key.bus);
+    event.address = Some(key.address);
+    event.device = Some(summary.to_string());
+    event
+}
+
+/// Login name for `uid`, or the number if it has no passwd entry.
+fn user_name(uid: u32) -> String {
+    #[cfg(unix)]
+    if let Ok(Some(user)) = nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid)) {
+        return user.name;
+    }
+    uid.to_string()
+}
+
+/// This machine's name, or `-` when it has none.
+fn hostname() -> String {
+    #[cfg(unix)]
+    if let Ok(name) = nix::unistd::gethostname() {
+        return name.to_string_lossy().into_owned();
+    }
+    "-".to_string()
+}
+
+fn format_device_summary(
+    key: DeviceKey,
+    vendor_id: u16,
+    product_id: u16,
+    product_name: Option<&str>,
+) -> String {
+    let mut summary = format!(
+        "bus {:03} address {:03} {:04x}:{:04x}",
+        key.bus, key.address, vendor_id, product_id
+    );
+
+    if let Some(name) = product_name {
+        summary.push_str(" - ");
+        summary.push_str(name);
+    }
+
+    summary
+}
+
+/// Source of tether IDs.
+static NEXT_TETHER_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Source of rule IDs.
+static NEXT_RULE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The daemon's state, owned by its actor thread; handlers and monitors
+/// reach it through [`Actor::call`].
+type State = Actor<DaemonState>;
+
+#[derive(Default)]
+struct DaemonState {
+    monitors: HashMap<DeviceKey, DeviceMonitor>,
+    config: Config,
+    /// When each device's removal action ran within the last hour, kept
+    /// across re-tethers so cooldowns and trigger limits still apply.
+    triggers: HashMap<DeviceKey, Vec<Instant>>,
+    /// Tethers that ended recently, newest last, so `status` can say what
+    /// became of them.
+    finished: VecDeque<FinishedTether>,
+    /// Fired `rearm` tethers waiting for their device to come back.
+    rearm: Vec<Rearm>,
+    /// Patterns whose matching devices are tethered as they are attached.
+    rules: Vec<Rule>,
+}
+
+impl DaemonState {
+    fn finish(
+        &mut self,
+        key: DeviceKey,
+        monitor: &DeviceMonitor,
+        outcome: &'static str,
+        result: Option<String>,
+    ) {
+        if self.finished.len() >= FINISHED_KEPT {
+            // make room by forgetting the oldest tether nobody needs to acknowledge
+            let oldest = self
+                .finished
+                .iter()
+                .position(|finished| !finished.fired())
+                .unwrap_or(0);
+            self.finished.remove(oldest);
+        }
+        self.finished.push_back(FinishedTether {
+            id: monitor.id,
+            summary: format_device_summary(
+                key,
+                monitor.vendor_id,
+                monitor.product_id,
+                monitor.product_name.as_deref(),
+            ),
+            owner: monitor.owner,
+            action: monitor.options().action.to_string(),
+            outcome,
+            result,
+            at: Instant::now(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+        });
+    }
+}
+
+/// Tethers every attached device matching `pattern` with `options`.
+struct Rule {
+    id: u64,
+    pattern: Pattern,
+    options: TetherOptions,
+    owner: u32,
+}
+
+/// What it takes to tether a device again once it returns.
+struct Rearm {
+    /// The tether that fired.
+    id: u64,
+    vendor_id: u16,
+    product_id: u16,
+    serial: Option<String>,
+    options: TetherOptions,
+    owner: u32,
+}
+
+struct FinishedTether {
+    id: u64,
+    summary: String,
+    owner: u32,
+    action: String,
+    /// `triggered`, `action failed`, `suppressed`, `cancelled` and so on.
+    outcome: &'static str,
+    /// What went wrong, or why the action was suppressed.
+    result: Option<String>,
+    at: Instant,
+    /// Seconds since the Unix epoch.
+    timestamp: u64,
+}
+
+impl FinishedTether {
+    /// The removal action ran, successfully or not.
+    fn fired(&self) -> bool {
+        matches!(self.outcome, "triggered" | "fallback ran" | "action failed")
+    }
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct DeviceKey {
+    bus: u8,
+    address: u8,
+}
+
+impl DeviceKey {
+    fn new(bus: u8, address: u8) -> Self {
+        Self { bus, address }
+    }
+}
+
+struct DeviceMonitor {
+    /// Names the tether in `set` and `untether` however the device's bus
+    /// address changes; never reused while the daemon runs.
+    id: u64,
+    vendor_id: u16,
+    product_id: u16,
+    product_name: Option<String>,
+    serial: Option<String>,
+    /// Shared with the monitor thread, which reads it when the device goes.
+    settings: Arc<Mutex<TetherOptions>>,
+    /// UID of the client that created the tether.
+    owner: u32,
+    flags: MonitorFlags,
+    /// How often the watchdog had to restart this tether's monitor thread.
+    restarts: u32,
+    /// The rule that tethered the device, if one did.
+    rule: Option<u64>,
+}
+
+impl DeviceMonitor {
+    /// Only the tether's owner or root may clear or cancel it.
+    fn managed_by(&self, peer: &PeerInfo) -> bool {
+        peer.is_root() || peer.uid == self.owner
+    }
+
+    fn options(&self) -> TetherOptions {
+        current_options(&self.settings)
+    }
+
+    /// The device is gone, as opposed to its tether being cleared.
+    fn is_absent(&self) -> bool {
+        matches!(
+            self.flags.lifecycle.phase(),
+            Phase::GracePeriod | Phase::Triggered
+        )
+    }
+
+    /// The device is gone but the removal action has not run yet.
+    fn is_pending(&self) -> bool {
+        self.flags.lifecycle.phase() == Phase::GracePeriod
+            && !self.flags.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// The state shown by `status` and `list-devices`.
+    fn state(&self) -> &'static str {
+        match self.flags.lifecycle.phase() {
+            Phase::Armed if self.flags.polling.load(Ordering::SeqCst) => "degraded (polling)",
+            Phase::Armed => "watching",
+            Phase::GracePeriod if self.is_pending() => "grace",
+            Phase::Triggered | Phase::Rearmed => "triggered",
+            Phase::GracePeriod | Phase::Cleared => "disconnected",
+        }
+    }
+}
+
+/// Flags a monitor thread shares with its `DeviceMonitor` entry.
+#[derive(Clone)]
+struct MonitorFlags {
+    /// Armed, counting down, fired or cleared; the one place that says.
+    lifecycle: Lifecycle,
+    cancelled: Arc<AtomicBool>,
+    /// Set before sleep to skip the rest of the grace period.
+    expedite: Arc<AtomicBool>,
+    /// Milliseconds since daemon start at the thread's last loop.
+    heartbeat: Arc<AtomicU64>,
+    /// Bumped by the watchdog when it replaces the monitor thread.
+    generation: Arc<AtomicU64>,
+    /// The hotplug callback could not be shown to work, so removals are
+    /// noticed by polling for the device instead.
+    polling: Arc<AtomicBool>,
+    /// How the device is being watched, for `status verbose`.
+    watch: Arc<Mutex<WatchReport>>,
+}
+
+impl MonitorFlags {
+    fn new() -> Self {
+        Self {
+            lifecycle: Lifecycle::default(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            expedite: Arc::new(AtomicBool::new(false)),
+            heartbeat: Arc::new(AtomicU64::new(uptime_millis())),
+            generation: Arc::new(AtomicU64::new(0)),
+            polling: Arc::new(AtomicBool::new(false)),
+            watch: Arc::default(),
+        }
+    }
+
+    fn beat(&self) {
+        self.heartbeat.store(uptime_millis(), Ordering::SeqCst);
+    }
+
+    /// Time since the last heartbeat.
+    fn silence(&self) -> Duration {
+        let last = self.heartbeat.load(Ordering::SeqCst);
+        Duration::from_millis(uptime_millis().saturating_sub(last))
+    }
+
+    fn superseded(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) != generation
+    }
+}
+
+/// What a monitor knows about how it watches its device.
+#[derive(Default)]
+struct WatchReport {
+    /// `hotplug` or `polling`; empty until the monitor starts watching.
+    backend: &'static str,
+    /// Why the hotplug callback could not be registered, if it could not.
+    registration_error: Option<String>,
+    /// The last time the device was seen to arrive or leave: when, as a
+    /// Unix timestamp and an instant, and which.
+    last_event: Option<(u64, Instant, &'static str)>,
+}
+
+impl WatchReport {
+    fn record(report: &Mutex<WatchReport>, event: &'static str) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        report
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .last_event = Some((timestamp, Instant::now(), event));
+    }
+
+    /// One line for `status verbose`.
+    fn describe(&self, poll_interval_ms: u64) -> String {
+        let mut parts = vec![match self.backend {
+            "" => "not watching yet".to_string(),
+            "polling" => format!(
+                "polling every {}ms",
+                poll_interval_ms.max(MIN_POLL_INTERVAL_MS)
+            ),
+            backend => backend.to_string(),
+        }];
+        match (&self.registration_error, self.backend) {
+            (Some(err), _) => parts.push(format!("callback registration failed: {err}")),
+            (None, "hotplug") => parts.push("callback registered".to_string()),
+            (None, _) => {}
+        }
+        parts.push(match self.last_event {
+            Some((timestamp, at, event)) => format!(
+                "last event {event} at {timestamp} ({}s ago)",
+                at.elapsed().as_secs()
+            ),
+            None => "no events yet".to_string(),
+        });
+        parts.join(", ")
+    }
+}
+
+/// Monotonic milliseconds since the daemon first asked.
+fn uptime_millis() -> u64 {
+    static STARTED: OnceLock<Instant> = OnceLock::new();
+    STARTED.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+#[derive(Clone, Default, PartialEq)]
+struct TetherOptions {
+    action: Action,
+    grace: Duration,
+    /// Minimum time between two runs of the action on this device.
+    cooldown: Duration,
+    /// Tether the device again by itself when it returns after the action.
+    rearm: bool,
+    /// Watch by polling the bus instead of through hotplug callbacks.
+    poll: bool,
+}
+
+enum GraceOutcome {
+    Elapsed,
+    Reattached,
+    Cancelled,
+    Cleared,
+    /// The watchdog started a replacement thread.
+    Superseded,
+    /// The grace period ran out but the cooldown, the hourly limit or the
+    /// pre-trigger hook held the action back.
+    Suppressed(String),
+}
+
+#[derive(Clone)]
+struct DeviceInfo {
+    vendor_id: u16,
+    product_id: u16,
+    product_name: Option<String>,
+    serial: Option<String>,
+}
+
+#[derive(Clone)]
+struct SelectedDeviceWatcher {
+    key: DeviceKey,
+    vendor_id: u16,
+    product_id: u16,
+    product_name: Option<String>,
+    lifecycle: Lifecycle,
+    report: Arc<Mutex<WatchReport>>,
+}
+
+impl SelectedDeviceWatcher {
+    fn display_name(&self) -> &str {
+        self.product_name.as_deref().unwrap_or("selected device")
+    }
+
+    /// Whether the device is no longer attached. Hotplug callbacks only
+    /// report changes, so this catches removals missed while not watching.
+    fn is_missing(&self) -> bool {
+        self.is_present() == Some(false)
+    }
+
+    /// Whether the device is attached at its address, or `None` if libusb
+    /// cannot enumerate the bus.
+    fn is_present(&self) -> Option<bool> {
+        present_devices()
+            .map(|present| present.get(&self.key) == Some(&(self.vendor_id, self.product_id)))
+    }
+}
+
+impl Hotplug<Context> for SelectedDeviceWatcher {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        if device.bus_number() == self.key.bus && device.address() == self.key.address {
+            info!(
+                bus = self.key.bus,
+                address = self.key.address,
+                vendor_id = self.vendor_id,
+                product_id = self.product_id,
+                name = %self.display_name(),
+                "device reattached"
+            );
+            WatchReport::record(&self.report, "arrived");
+            self.lifecycle.arrived();
+        }
+    }
+
+    fn device_left(&mut self, device: Device<Context>) {
+        if device.bus_number() == self.key.bus && device.address() == self.key.address {
+            info!(
+                bus = self.key.bus,
+                address = self.key.address,
+                vendor_id = self.vendor_id,
+                product_id = self.product_id,
+                name = %self.display_name(),
+                "device unplugged"
+            );
+            WatchReport::record(&self.report, "left");
+            self.lifecycle.departed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: PeerInfo = PeerInfo {
+        uid: 1000,
+        gid: 1000,
+        pid: 0,
+    };
+    const BOB: PeerInfo = PeerInfo {
+        uid: 1001,
+        gid: 1001,
+        pid: 0,
+    };
+
+    /// A daemon holding one tether, made by `owner`, on device 001:002.
+    fn tethered_by(owner: &PeerInfo) -> (State, u64) {
+        let id = NEXT_TETHER_ID.fetch_add(1, Ordering::Relaxed);
+        let mut state = DaemonState::default();
+        state.monitors.insert(
+            DeviceKey::new(1, 2),
+            DeviceMonitor {
+                id,
+                vendor_id: 0x1050,
+                product_id: 0x0407,
+                product_name: None,
+                serial: None,
+                settings: Arc::default(),
+                owner: owner.uid,
+                flags: MonitorFlags::new(),
+                restarts: 0,
+                rule: None,
+            },
+        );
+        (Actor::spawn("test-state", state), id)
+    }
+
+    #[test]
+    fn test_other_users_cannot_clear_a_tether() {
+        let (state, id) = tethered_by(&ALICE);
+        let request = Request::new(&format!("untether id={id}"));
+
+        let denied = handle_untether(TetherRef::Id(id), &request, &BOB, state.clone());
+        assert!(denied.unwrap_err().contains("is owned by"));
+        assert_eq!(state.call(|guard| guard.monitors.len()), 1);
+
+        handle_untether(TetherRef::Id(id), &request, &ALICE, state.clone()).unwrap();
+        assert!(state.call(|guard| guard.monitors.is_empty()));
+    }
+
+    #[test]
+    fn test_root_can_clear_any_tether() {
+        let (state, id) = tethered_by(&ALICE);
+        let root = PeerInfo {
+            uid: 0,
+            gid: 0,
+            pid: 0,
+        };
+        let request = Request::new(&format!("untether id={id}"));
+
+        handle_untether(TetherRef::Id(id), &request, &root, state.clone()).unwrap();
+        assert!(state.call(|guard| guard.monitors.is_empty()));
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+/// Where a tether is in its life.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Phase {
+    /// The device is attached and watched.
+    #[default]
+    Armed,
+    /// The device is gone and the removal action is waiting on the grace
+    /// period.
+    GracePeriod,
+    /// The removal action is running or has run.
+    Triggered,
+    /// The device came back after the action ran.
+    Rearmed,
+    /// The tether was severed or untethered. Nothing leaves this phase.
+    Cleared,
+}
+
+/// A tether's phase, shared by its monitor thread, the hotplug callback and
+/// the command handlers. Each change is a transition from the current phase
+/// made under one lock, so a severe that lands alongside an unplug or a
+/// reattach is never undone by it.
+#[derive(Clone, Default)]
+pub struct Lifecycle(Arc<Mutex<Phase>>);
+
+impl Lifecycle {
+    pub fn phase(&self) -> Phase {
+        *self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// The device left: an armed tether starts its grace period, and one
+    /// that fired and saw the device back is waiting for it again.
+    pub fn departed(&self) {
+        self.transition(|phase| match phase {
+            Phase::Armed => Phase::GracePeriod,
+            Phase::Rearmed => Phase::Triggered,
+            other => other,
+        });
+    }
+
+    /// The device is back, or its removal turned out to be a glitch: a
+    /// grace period ends, and a tether that fired is rearmed.
+    pub fn arrived(&self) {
+        self.transition(|phase| match phase {
+            Phase::GracePeriod => Phase::Armed,
+            Phase::Triggered => Phase::Rearmed,
+            other => other,
+        });
+    }
+
+    /// The removal action is about to run, which it may only do while the
+    /// device is still away: a tether in its grace period, or one that fired
+    /// and lost the device again, moves to `Triggered`. Any other phase is
+    /// left alone. Returns the phase after the call, so anything but
+    /// `Triggered` means the action must not run.
+    pub fn trigger(&self) -> Phase {
+        self.transition(|phase| match phase {
+            Phase::GracePeriod | Phase::Triggered => Phase::Triggered,
+            other => other,
+        })
+    }
+
+    /// Severe or untether the tether, whatever it was doing, and return the
+    /// phase it was in. Either this comes before
+    /// [`trigger`](Self::trigger) and the action never runs, or after it and this says `Triggered` or
+    /// `Rearmed`.
+    pub fn clear(&self) -> Phase {
+        let mut phase = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        std::mem::replace(&mut *phase, Phase::Cleared)
+    }
+
+    /// Move to `next(current)` and return the new phase.
+    fn transition(&self, next: impl FnOnce(Phase) -> Phase) -> Phase {
+        let mut phase = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        *phase = next(*phase);
+        *phase
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+use crate::sessions::parse_sessions;
+
+/// A way of locking the screen, tried in the order configured by
+/// `lock_backends` until one succeeds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum LockBackend {
+    /// logind's `LockSessions` (or `LockSession` for a user daemon) over D-Bus.
+    Logind,
+    /// `loginctl lock-session` for each session.
+    Loginctl,
+    /// The freedesktop `ScreenSaver.Lock` method on the session bus.
+    Screensaver,
+    Swaylock,
+    Hyprlock,
+    XdgScreensaver,
+    /// LightDM's `dm-tool lock`.
+    DmTool,
+    /// `xlock`, the usual X11 locker on the BSDs where logind is absent.
+    Xlock,
+}
+
+impl LockBackend {
+    pub const ALL: [LockBackend; 8] = [
+        LockBackend::Logind,
+        LockBackend::Loginctl,
+        LockBackend::Screensaver,
+        LockBackend::Swaylock,
+        LockBackend::Hyprlock,
+        LockBackend::XdgScreensaver,
+        LockBackend::DmTool,
+        LockBackend::Xlock,
+    ];
+
+    /// The program the backend runs.
+    fn program(self) -> &'static str {
+        match self {
+            LockBackend::Logind => "busctl",
+            LockBackend::Loginctl => "loginctl",
+            LockBackend::Screensaver => "gdbus",
+            LockBackend::Swaylock => "swaylock",
+            LockBackend::Hyprlock => "hyprlock",
+            LockBackend::XdgScreensaver => "xdg-screensaver",
+            LockBackend::DmTool => "dm-tool",
+            LockBackend::Xlock => "xlock",
+        }
+    }
+
+    /// Whether the backend can be tried here: its program is installed, and
+    /// for the logind backends, systemd-logind is running.
+    pub fn is_available(self) -> Result<(), String> {
+        if !on_path(self.program()) {
+            return Err(format!("{} not found in PATH", self.program()));
+        }
+        if matches!(self, LockBackend::Logind | LockBackend::Loginctl)
+            && !Path::new("/run/systemd/seats").exists()
+        {
+            return Err("systemd-logind is not running".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the backend can be limited to the sessions of some users.
+    fn locks_by_user(self) -> bool {
+        matches!(self, LockBackend::Logind | LockBackend::Loginctl)
+    }
+
+    /// Lock the sessions of `users`, found through logind, rather than
+    /// every session.
+    fn lock_users(self, users: &[String]) -> Result<(), String> {
+        let sessions = sessions_of(users)?;
+        if sessions.is_empty() {
+            info!(users = %users.join(","), "no sessions of lock_users to lock");
+            return Ok(());
+        }
+        let mut failed = Vec::new();
+        for session in &sessions {
+            let result = match self {
+                LockBackend::Logind => run(Command::new("busctl").args([
+                    "call",
+                    "org.freedesktop.login1",
+                    "/org/freedesktop/login1",
+                    "org.freedesktop.login1.Manager",
+                    "LockSession",
+                    "s",
+                    session,
+                ])),
+                _ => run(Command::new("loginctl").arg("lock-session").arg(session)),
+            };
+            match result {
+                Ok(()) => info!(session = %session, "locked session"),
+                Err(err) => {
+                    warn!(session = %session, error = %err, "failed to lock session");
+                    failed.push(format!("session {session}: {err}"));
+                }
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join("; "))
+        }
+    }
+
+    fn lock(self) -> Result<(), String> {
+        let user = deadman_ipc::user_mode();
+        match self {
+            LockBackend::Logind if user => run(Command::new("busctl").args([
+                "call",
+                "org.freedesktop.login1",
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+                "LockSession",
+                "s",
+                "",
+            ])),
+            LockBackend::Logind => run(Command::new("busctl").args([
+                "call",
+                "org.freedesktop.login1",
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+                "LockSessions",
+            ])),
+            LockBackend::Loginctl if user => run(Command::new("loginctl").arg("lock-session")),
+            LockBackend::Loginctl => lock_all_sessions(),
+            LockBackend::Screensaver => run(Command::new("gdbus").args([
+                "call",
+                "--session",
+                "--dest",
+                "org.freedesktop.ScreenSaver",
+                "--object-path",
+                "/org/freedesktop/ScreenSaver",
+                "--method",
+                "org.freedesktop.ScreenSaver.Lock",
+            ])),
+            LockBackend::Swaylock => run(Command::new("swaylock").arg("-f")),
+            // hyprlock stays in the foreground until unlocked
+            LockBackend::Hyprlock => spawn(&mut Command::new("hyprlock")),
+            LockBackend::XdgScreensaver => run(Command::new("xdg-screensaver").arg("lock")),
+            LockBackend::DmTool => run(Command::new("dm-tool").arg("lock")),
+            // like hyprlock, xlock runs until the screen is unlocked
+            LockBackend::Xlock => spawn(Command::new("xlock").args(["-mode", "blank"])),
+        }
+    }
+}
+
+impl FromStr for LockBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        LockBackend::ALL
+            .into_iter()
+            .find(|backend| backend.to_string() == value)
+            .ok_or_else(|| format!("unknown lock backend: {value}"))
+    }
+}
+
+impl TryFrom<String> for LockBackend {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<LockBackend> for String {
+    fn from(backend: LockBackend) -> Self {
+        backend.to_string()
+    }
+}
+
+impl fmt::Display for LockBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LockBackend::Logind => "logind",
+            LockBackend::Loginctl => "loginctl",
+            LockBackend::Screensaver => "screensaver",
+            LockBackend::Swaylock => "swaylock",
+            LockBackend::Hyprlock => "hyprlock",
+            LockBackend::XdgScreensaver => "xdg-screensaver",
+            LockBackend::DmTool => "dm-tool",
+            LockBackend::Xlock => "xlock",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The outcome of each backend's most recent lock attempt.
+fn last_results() -> &'static Mutex<HashMap<LockBackend, Result<(), String>>> {
+    static RESULTS: OnceLock<Mutex<HashMap<LockBackend, Result<(), String>>>> = OnceLock::new();
+    RESULTS.get_or_init(Default::default)
+}
+
+/// Log which of `backends` can be used, so a chain with nothing available
+/// shows up at startup rather than when a device is removed.
+pub fn detect(backends: &[LockBackend]) {
+    let mut usable = 0;
+    for backend in backends {
+        match backend.is_available() {
+            Ok(()) => usable += 1,
+            Err(err) => info!(backend = %backend, reason = %err, "lock backend unavailable"),
+        }
+    }
+    if usable == 0 {
+        warn!("none of the configured lock backends are available; lock actions will fail");
+    }
+}
+
+/// Try `backends` in order, skipping unavailable ones, until one locks.
+/// With `users` set, a system daemon locks only their sessions, which only
+/// the logind backends can do; the others are skipped.
+pub fn lock(backends: &[LockBackend], users: &[String]) -> Result<(), String> {
+    let by_user = !users.is_empty() && !deadman_ipc::user_mode();
+    let mut errors = Vec::new();
+    for &backend in backends {
+        if let Err(err) = backend.is_available() {
+            errors.push(format!("{backend}: {err}"));
+            continue;
+        }
+        if by_user && !backend.locks_by_user() {
+            errors.push(format!("{backend}: cannot be limited to lock_users"));
+            continue;
+        }
+
+        let result = if by_user {
+            backend.lock_users(users)
+        } else {
+            backend.lock()
+        };
+        last_results()
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(backend, result.clone());
+        match result {
+            Ok(()) => {
+                info!(backend = %backend, "screen locked");
+                return Ok(());
+            }
+            Err(err) => {
+                warn!(backend = %backend, error = %err, "lock backend failed");
+                errors.push(format!("{backend}: {err}"));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        return Err("no lock backends configured".to_string());
+    }
+    Err(format!("every lock backend failed ({})", errors.join("; ")))
+}
+
+/// One line per backend in `backends` with its availability and the result
+/// of its last lock attempt, for `doctor`.
+pub fn health(backends: &[LockBackend]) -> Vec<String> {
+    let results = last_results().lock().unwrap_or_else(|err| err.into_inner());
+    backends
+        .iter()
+        .map(|backend| {
+            let available = match backend.is_available() {
+                Ok(()) => "available".to_string(),
+                Err(err) => format!("unavailable: {err}"),
+            };
+            let last = match results.get(backend) {
+                Some(Ok(())) => ", last lock ok",
+                Some(Err(_)) => ", last lock failed",
+                None => "",
+            };
+            format!("lock backend {backend}: {available}{last}")
+        })
+        .collect()
+}
+
+/// Dry-run steps for locking with `backends`, limited to the sessions of
+/// `users` if any: fine as long as one of them is available.
+pub fn dry_run(backends: &[LockBackend], users: &[String]) -> Vec<Result<String, String>> {
+    let by_user = !users.is_empty() && !deadman_ipc::user_mode();
+    let usable = |backend: &LockBackend| {
+        backend.is_available().and_then(|()| {
+            if by_user && !backend.locks_by_user() {
+                Err("cannot be limited to lock_users".to_string())
+            } else {
+                Ok(())
+            }
+        })
+    };
+    let mut steps: Vec<_> = backends
+        .iter()
+        .map(|backend| match usable(backend) {
+            Ok(()) => Ok(format!("lock backend {backend} is available")),
+            Err(err) => Ok(format!("lock backend {backend} would be skipped: {err}")),
+        })
+        .collect();
+    if by_user {
+        steps.push(match sessions_of(users) {
+            Ok(sessions) if sessions.is_empty() => Ok(format!(
+                "no sessions of {} to lock right now",
+                users.join(", ")
+            )),
+            Ok(sessions) => Ok(format!(
+                "would lock sessions {} of {}",
+                sessions.join(", "),
+                users.join(", ")
+            )),
+            Err(err) => Err(err),
+        });
+    }
+    if !backends.iter().any(|backend| usable(backend).is_ok()) {
+        steps.push(Err("no configured lock backend is available".to_string()));
+    }
+    steps
+}
+
+pub(crate) fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    command::run(command, HELPER_TIMEOUT)
+}
+
+fn spawn(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|mut child| {
+            thread::spawn(move || child.wait());
+        })
+        .map_err(|err| format!("failed to run {program}: {err}"))
+}
+
+/// IDs of the logind sessions belonging to `users`, each a user name or
+/// UID.
+fn sessions_of(users: &[String]) -> Result<Vec<String>, String> {
+    let output = command::output(
+        Command::new("loginctl").args(["list-sessions", "--no-legend"]),
+        HELPER_TIMEOUT,
+    )
+    .map_err(|err| format!("failed to list sessions: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "loginctl list-sessions exited with status {status}",
+            status = output.status
+        ));
+    }
+
+    Ok(parse_sessions(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .filter(|session| {
+            users
+                .iter()
+                .any(|wanted| *wanted == session.user || *wanted == session.uid)
+        })
+        .map(|session| session.id)
+        .collect())
+}
+
+fn lock_all_sessions() -> Result<(), String> {
+    let output = command::output(
+        Command::new("loginctl").arg("list-sessions"),
+        HELPER_TIMEOUT,
+    )
+    .map_err(|err| format!("failed to list sessions: {err}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "loginctl list-sessions exited with status {status}",
+            status = output.status
+        ));
+    }
+
+    for line in String::from_utf8_lossy(&output.stdout).lines().skip(1) {
+        let session_id = match line.split_whitespace().next() {
+            Some(id) => id,
+            None => continue,
+        };
+
+        match run(Command::new("loginctl").arg("lock-session").arg(session_id)) {
+            Ok(()) => info!(session = session_id, "locked session"),
+            Err(err) => warn!(session = session_id, error = %err, "lock-session failed"),
+        }
+    }
+
+    Ok(())
+}
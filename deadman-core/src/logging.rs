@@ -0,0 +1,531 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::{
+    EnvFilter, Registry, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+use crate::otlp::OtlpLayer;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+const SYSLOG_IDENTIFIER: &str = "deadmand";
+/// Tether changes are security events, so they go to the authpriv facility.
+const SYSLOG_FACILITY_AUTHPRIV: u8 = 10;
+
+/// Handle for swapping the active filter at runtime, set by `init`.
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Where daemon logs are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogTarget {
+    /// journald when started by systemd, otherwise stderr.
+    #[default]
+    Auto,
+    Stderr,
+    Journald,
+    Syslog,
+    /// A size-rotated file, for systems without a system logger.
+    File,
+}
+
+impl std::str::FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(LogTarget::Auto),
+            "stderr" => Ok(LogTarget::Stderr),
+            "journald" => Ok(LogTarget::Journald),
+            "syslog" => Ok(LogTarget::Syslog),
+            "file" => Ok(LogTarget::File),
+            other => Err(format!(
+                "unknown log target: {other} (expected auto, stderr, journald, syslog or file)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LogTarget::Auto => "auto",
+            LogTarget::Stderr => "stderr",
+            LogTarget::Journald => "journald",
+            LogTarget::Syslog => "syslog",
+            LogTarget::File => "file",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The `[log]` config table. Changes apply on the next daemon start.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub target: LogTarget,
+    /// Log file for the `file` target.
+    pub file: PathBuf,
+    /// Rotate the log file once it grows past this many bytes.
+    pub max_bytes: u64,
+    /// Number of rotated files to keep alongside the active one.
+    pub keep: usize,
+    /// OpenTelemetry collector that tether and trigger spans are exported
+    /// to as OTLP/HTTP, e.g. `http://localhost:4318`; none by default.
+    pub otlp_endpoint: Option<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            target: LogTarget::Auto,
+            file: PathBuf::from("/var/log/deadman.log"),
+            max_bytes: 10 * 1024 * 1024,
+            keep: 5,
+            otlp_endpoint: None,
+        }
+    }
+}
+
+/// Install the global subscriber. Falls back to stderr if the chosen target
+/// can't be opened, and goes without OTLP export if that can't start,
+/// returning why so it can be logged once tracing is up.
+pub fn init(config: &LogConfig) -> Vec<String> {
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (env_filter, filter_handle) = reload::Layer::new(env_filter);
+    let _ = FILTER.set(filter_handle);
+
+    let target = match config.target {
+        // systemd sets JOURNAL_STREAM when stderr is connected to the journal
+        LogTarget::Auto if env::var_os("JOURNAL_STREAM").is_some() => LogTarget::Journald,
+        LogTarget::Auto => LogTarget::Stderr,
+        target => target,
+    };
+
+    let mut fallback = None;
+    let mut journald = None;
+    let mut syslog = None;
+    let mut file = None;
+    match target {
+        LogTarget::Journald => match JournaldLayer::connect() {
+            Ok(layer) => journald = Some(layer),
+            Err(err) => fallback = Some(format!("failed to connect to journald: {err}")),
+        },
+        LogTarget::Syslog => match SyslogLayer::connect() {
+            Ok(layer) => syslog = Some(layer),
+            Err(err) => fallback = Some(format!("failed to connect to syslog: {err}")),
+        },
+        LogTarget::File => match RotatingFile::open(&config.file, config.max_bytes, config.keep) {
+            Ok(writer) => {
+                file = Some(
+                    fmt::layer()
+                        .with_target(false)
+                        .with_line_number(true)
+                        .with_thread_names(true)
+                        .with_ansi(false)
+                        .with_file(true)
+                        .with_writer(Mutex::new(writer)),
+                )
+            }
+            Err(err) => {
+                fallback = Some(format!(
+                    "failed to open log file {}: {err}",
+                    config.file.display()
+                ))
+            }
+        },
+        LogTarget::Auto | LogTarget::Stderr => {}
+    }
+
+    let mut problems: Vec<String> = fallback
+        .map(|err| format!("{err}; falling back to stderr"))
+        .into_iter()
+        .collect();
+    let otlp = match config.otlp_endpoint.as_deref().map(OtlpLayer::connect) {
+        Some(Ok(layer)) => Some(layer),
+        Some(Err(err)) => {
+            problems.push(err);
+            None
+        }
+        None => None,
+    };
+
+    let stderr = (journald.is_none() && syslog.is_none() && file.is_none()).then(|| {
+        fmt::layer()
+            .with_target(false)
+            .with_line_number(true)
+            .with_thread_names(true)
+            .with_ansi(false)
+            .with_file(true)
+    });
+
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(journald)
+        .with(syslog)
+        .with(file)
+        .with(stderr)
+        .with(otlp)
+        .init();
+
+    problems
+}
+
+/// The active filter directives, e.g. `info` or `deadman_core=debug`.
+pub fn filter() -> Result<String, String> {
+    FILTER
+        .get()
+        .ok_or_else(|| "logging is not initialised".to_string())?
+        .with_current(|filter| filter.to_string())
+        .map_err(|err| format!("failed to read log filter: {err}"))
+}
+
+/// Replace the filter with new `EnvFilter` directives without restarting.
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives)
+        .map_err(|err| format!("invalid log filter {directives}: {err}"))?;
+    FILTER
+        .get()
+        .ok_or_else(|| "logging is not initialised".to_string())?
+        .reload(filter)
+        .map_err(|err| format!("failed to apply log filter: {err}"))
+}
+
+/// Writes events to journald's native socket so fields such as `DEVICE`,
+/// `ACTION`, `BUS` and `ADDRESS` stay queryable with `journalctl DEVICE=...`.
+struct JournaldLayer {
+    socket: UnixDatagram,
+}
+
+impl JournaldLayer {
+    fn connect() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNALD_SOCKET)?;
+        Ok(Self { socket })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for JournaldLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+
+        let mut payload = Vec::new();
+        push_journal_field(&mut payload, "MESSAGE", &fields.message);
+        push_journal_field(
+            &mut payload,
+            "PRIORITY",
+            &severity(metadata.level()).to_string(),
+        );
+        push_journal_field(&mut payload, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+        push_journal_field(
+            &mut payload,
+            "SYSLOG_FACILITY",
+            &SYSLOG_FACILITY_AUTHPRIV.to_string(),
+        );
+        if let Some(file) = metadata.file() {
+            push_journal_field(&mut payload, "CODE_FILE", file);
+        }
+        if let Some(line) = metadata.line() {
+            push_journal_field(&mut payload, "CODE_LINE", &line.to_string());
+        }
+        for (name, value) in &fields.extra {
+            push_journal_field(&mut payload, &journal_field_name(name), value);
+        }
+
+        // nothing sensible to do if the journal is gone; don't log about logging
+        let _ = self.socket.send(&payload);
+    }
+}
+
+/// Append one field in journald's native format, using the length-prefixed
+/// form for values containing newlines.
+fn push_journal_field(payload: &mut Vec<u8>, name: &str, value: &str) {
+    payload.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value.as_bytes());
+    payload.push(b'\n');
+}
+
+/// Journal field names are upper case ASCII letters, digits and underscores.
+fn journal_field_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Sends RFC 3164 messages to the local syslog daemon.
+struct SyslogLayer {
+    socket: UnixDatagram,
+}
+
+impl SyslogLayer {
+    fn connect() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(SYSLOG_SOCKET)?;
+        Ok(Self { socket })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SyslogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+
+        let line = syslog_line(event.metadata().level(), &fields);
+        let _ = self.socket.send(line.as_bytes());
+    }
+}
+
+/// An event as an RFC 3164 message: priority, tag and pid, then the message
+/// and its fields as `name=value`.
+fn syslog_line(level: &Level, fields: &EventFields) -> String {
+    let priority = SYSLOG_FACILITY_AUTHPRIV * 8 + severity(level);
+    let mut line = format!(
+        "<{priority}>{SYSLOG_IDENTIFIER}[{}]: {}",
+        std::process::id(),
+        fields.message
+    );
+    for (name, value) in &fields.extra {
+        let _ = write!(line, " {name}={value}");
+    }
+    line
+}
+
+/// Syslog severity for a tracing level.
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// An event's message and its remaining fields, formatted as strings.
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    extra: Vec<(String, String)>,
+}
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.extra
+                .push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.extra
+                .push((field.name().to_string(), format!("{value:?}")));
+        }
+    }
+}
+
+/// Log file that moves itself to `<file>.1` (shifting older copies up to
+/// `<file>.<keep>`) once it grows past `max_bytes`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            written,
+            max_bytes,
+            keep,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let _ = fs::rename(self.rotated(index), self.rotated(index + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_bytes > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(message: &str, extra: &[(&str, &str)]) -> EventFields {
+        EventFields {
+            message: message.to_string(),
+            extra: extra
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_syslog_priority_is_authpriv() {
+        let pid = std::process::id();
+        assert_eq!(
+            syslog_line(&Level::ERROR, &fields("action failed", &[])),
+            format!("<83>deadmand[{pid}]: action failed")
+        );
+        assert!(syslog_line(&Level::WARN, &fields("", &[])).starts_with("<84>"));
+        assert!(syslog_line(&Level::INFO, &fields("", &[])).starts_with("<86>"));
+        assert!(syslog_line(&Level::DEBUG, &fields("", &[])).starts_with("<87>"));
+        assert!(syslog_line(&Level::TRACE, &fields("", &[])).starts_with("<87>"));
+    }
+
+    #[test]
+    fn test_syslog_line_appends_fields() {
+        let line = syslog_line(
+            &Level::INFO,
+            &fields("tether armed", &[("device", "YubiKey"), ("bus", "1")]),
+        );
+        assert!(
+            line.ends_with("]: tether armed device=YubiKey bus=1"),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn test_journal_field_single_line() {
+        let mut payload = Vec::new();
+        push_journal_field(&mut payload, "MESSAGE", "tether armed");
+        assert_eq!(payload, b"MESSAGE=tether armed\n");
+    }
+
+    #[test]
+    fn test_journal_field_multi_line_is_length_prefixed() {
+        let mut payload = Vec::new();
+        push_journal_field(&mut payload, "ERROR", "exit 1\nstderr: nope");
+        let mut expected = b"ERROR\n".to_vec();
+        expected.extend_from_slice(&19u64.to_le_bytes());
+        expected.extend_from_slice(b"exit 1\nstderr: nope\n");
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    fn test_journal_field_names() {
+        assert_eq!(journal_field_name("grace_secs"), "GRACE_SECS");
+        assert_eq!(journal_field_name("request.id"), "REQUEST_ID");
+        assert_eq!(journal_field_name("Device"), "DEVICE");
+    }
+
+    /// A log file of its own in a fresh directory.
+    fn log_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("deadman-log-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("deadman.log")
+    }
+
+    #[test]
+    fn test_rotation_shifts_older_files_up() {
+        let path = log_path("rotate");
+        let mut file = RotatingFile::open(&path, 8, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(path.clone()), "fourth\n");
+        assert_eq!(read(file.rotated(1)), "third\n");
+        assert_eq!(read(file.rotated(2)), "second\n");
+        // only `keep` rotated files are kept
+        assert!(!file.rotated(3).exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_without_keep_truncates() {
+        let path = log_path("truncate");
+        let mut file = RotatingFile::open(&path, 8, 0).unwrap();
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        assert!(!file.rotated(1).exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_counts_an_existing_file() {
+        let path = log_path("existing");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "earlier\n").unwrap();
+        let mut file = RotatingFile::open(&path, 10, 1).unwrap();
+        file.write_all(b"later\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "later\n");
+        assert_eq!(fs::read_to_string(file.rotated(1)).unwrap(), "earlier\n");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
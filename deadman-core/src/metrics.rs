@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+use deadman_ipc::events::Event;
+
+use crate::events::EVENTS;
+
+/// Counters kept across the daemon's lifetime, and rendered along with a
+/// snapshot of its state by every exporter.
+pub static REGISTRY: Registry = Registry::new();
+
+pub struct Registry {
+    /// Published events by kind, e.g. `triggered`.
+    events: Mutex<BTreeMap<String, u64>>,
+}
+
+/// What the daemon looks like right now.
+pub struct Snapshot {
+    /// `watching`, `grace`, `triggered` or `disconnected` for each tether.
+    pub tether_states: Vec<&'static str>,
+    /// `armed`, `degraded` or `unarmed`.
+    pub protection: &'static str,
+    pub monitor_restarts: u64,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            events: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Count every event published from now on.
+    pub fn count_events(&'static self) {
+        let events = EVENTS.subscribe();
+        thread::spawn(move || {
+            for line in events {
+                let Ok(event) = serde_json::from_str::<Event>(&line) else {
+                    continue;
+                };
+                *self
+                    .events
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .entry(event.kind.to_string())
+                    .or_default() += 1;
+            }
+        });
+    }
+
+    /// The counters and `snapshot` in the Prometheus text format, which is
+    /// what node_exporter's textfile collector reads.
+    pub fn render(&self, snapshot: &Snapshot) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP deadman_tethers Tethers by state.");
+        let _ = writeln!(out, "# TYPE deadman_tethers gauge");
+        for state in [
+            "watching",
+            "degraded (polling)",
+            "grace",
+            "triggered",
+            "disconnected",
+        ] {
+            let count = snapshot
+                .tether_states
+                .iter()
+                .filter(|tether| **tether == state)
+                .count();
+            let _ = writeln!(
+                out,
+                "deadman_tethers{{state=\"{}\"}} {count}",
+                escape_label(state)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP deadman_protection Protection level; 1 for the current one."
+        );
+        let _ = writeln!(out, "# TYPE deadman_protection gauge");
+        for level in ["armed", "degraded", "unarmed"] {
+            let current = u8::from(snapshot.protection == level);
+            let _ = writeln!(
+                out,
+                "deadman_protection{{level=\"{}\"}} {current}",
+                escape_label(level)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP deadman_monitor_restarts Monitor threads restarted by the watchdog."
+        );
+        let _ = writeln!(out, "# TYPE deadman_monitor_restarts gauge");
+        let _ = writeln!(
+            out,
+            "deadman_monitor_restarts {}",
+            snapshot.monitor_restarts
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP deadman_events_total Events published, by kind."
+        );
+        let _ = writeln!(out, "# TYPE deadman_events_total counter");
+        let events = self.events.lock().unwrap_or_else(|err| err.into_inner());
+        for (kind, count) in events.iter() {
+            let _ = writeln!(
+                out,
+                "deadman_events_total{{kind=\"{}\"}} {count}",
+                escape_label(kind)
+            );
+        }
+        out
+    }
+}
+
+/// A label value with the backslashes, quotes and newlines the text format
+/// requires escaped.
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replace the textfile at `path` in one step, as node_exporter's textfile
+/// collector needs so it never reads a half-written file.
+pub fn write_textfile(path: &Path, contents: &str) -> Result<(), String> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    fs::write(&partial, contents)
+        .map_err(|err| format!("failed to write {}: {err}", Path::new(&partial).display()))?;
+    fs::rename(&partial, path).map_err(|err| format!("failed to replace {}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            tether_states: vec!["watching", "grace", "watching"],
+            protection: "armed",
+            monitor_restarts: 2,
+        }
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("triggered"), "triggered");
+        assert_eq!(escape_label("a\"b"), "a\\\"b");
+        assert_eq!(escape_label("a\\b"), "a\\\\b");
+        assert_eq!(escape_label("a\nb"), "a\\nb");
+    }
+
+    #[test]
+    fn test_render_escapes_event_kinds() {
+        let registry = Registry::new();
+        registry
+            .events
+            .lock()
+            .unwrap()
+            .insert("odd \"kind\"\\\n".to_string(), 1);
+        let rendered = registry.render(&snapshot());
+        assert!(
+            rendered.contains("deadman_events_total{kind=\"odd \\\"kind\\\"\\\\\\n\"} 1\n"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn test_render_exact() {
+        let registry = Registry::new();
+        registry
+            .events
+            .lock()
+            .unwrap()
+            .extend([("triggered".to_string(), 3), ("removed".to_string(), 4)]);
+        assert_eq!(
+            registry.render(&snapshot()),
+            "\
+# HELP deadman_tethers Tethers by state.
+# TYPE deadman_tethers gauge
+deadman_tethers{state=\"watching\"} 2
+deadman_tethers{state=\"degraded (polling)\"} 0
+deadman_tethers{state=\"grace\"} 1
+deadman_tethers{state=\"triggered\"} 0
+deadman_tethers{state=\"disconnected\"} 0
+# HELP deadman_protection Protection level; 1 for the current one.
+# TYPE deadman_protection gauge
+deadman_protection{level=\"armed\"} 1
+deadman_protection{level=\"degraded\"} 0
+deadman_protection{level=\"unarmed\"} 0
+# HELP deadman_monitor_restarts Monitor threads restarted by the watchdog.
+# TYPE deadman_monitor_restarts gauge
+deadman_monitor_restarts 2
+# HELP deadman_events_total Events published, by kind.
+# TYPE deadman_events_total counter
+deadman_events_total{kind=\"removed\"} 4
+deadman_events_total{kind=\"triggered\"} 3
+"
+        );
+    }
+
+    #[test]
+    fn test_every_metric_has_help_then_type_before_samples() {
+        let rendered = Registry::new().render(&snapshot());
+        let mut described = Vec::new();
+        let mut typed = Vec::new();
+        for line in rendered.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                described.push(rest.split(' ').next().unwrap());
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let name = rest.split(' ').next().unwrap();
+                assert_eq!(described.last(), Some(&name), "TYPE before HELP for {name}");
+                typed.push(name);
+            } else {
+                let name = line.split(['{', ' ']).next().unwrap();
+                assert_eq!(typed.last(), Some(&name), "{name} sampled before its TYPE");
+            }
+        }
+        assert_eq!(described.len(), 4);
+    }
+}
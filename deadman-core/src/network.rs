@@ -0,0 +1,70 @@
+use std::path::Path;
+use std::process::Command;
+
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+use crate::lock::on_path;
+
+/// Bring down each of `interfaces`, WireGuard tunnels included. Interfaces
+/// NetworkManager manages are disconnected through it so it doesn't bring
+/// them straight back up; anything else is set down with `ip link`. Every
+/// interface is tried even when an earlier one fails.
+pub fn kill(interfaces: &[String]) -> Result<(), String> {
+    if interfaces.is_empty() {
+        return Err("kill_interfaces is empty; nothing to bring down".to_string());
+    }
+
+    let use_nmcli = on_path("nmcli");
+    let mut errors = Vec::new();
+    for interface in interfaces {
+        if use_nmcli && run(Command::new("nmcli").args(["device", "disconnect", interface])).is_ok()
+        {
+            info!(interface = %interface, "disconnected through NetworkManager");
+            continue;
+        }
+        match run(Command::new("ip").args(["link", "set", "dev", interface, "down"])) {
+            Ok(()) => info!(interface = %interface, "interface down"),
+            Err(err) => {
+                warn!(interface = %interface, error = %err, "failed to bring interface down");
+                errors.push(format!("{interface}: {err}"));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("some interfaces stayed up ({})", errors.join("; ")))
+    }
+}
+
+/// Dry-run steps: each interface exists, and the tool that would bring it
+/// down is installed.
+pub fn dry_run(interfaces: &[String]) -> Vec<Result<String, String>> {
+    if interfaces.is_empty() {
+        return vec![Err(
+            "kill_interfaces is empty; nothing would be brought down".to_string(),
+        )];
+    }
+
+    let tool = if on_path("nmcli") {
+        "nmcli or ip"
+    } else {
+        "ip"
+    };
+    interfaces
+        .iter()
+        .map(|interface| {
+            if Path::new("/sys/class/net").join(interface).exists() {
+                Ok(format!("would bring {interface} down with {tool}"))
+            } else {
+                Err(format!("interface {interface} does not exist"))
+            }
+        })
+        .collect()
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    command::run(command, HELPER_TIMEOUT)
+}
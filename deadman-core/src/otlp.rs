@@ -0,0 +1,401 @@
+//! Sends the daemon's tracing spans to an OpenTelemetry collector as
+//! OTLP/HTTP with JSON encoding, for fleets that gather endpoint security
+//! telemetry.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Write as _;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Finished spans waiting for the exporter; more are dropped rather than
+/// hold up the daemon behind a slow collector.
+const QUEUE: usize = 1024;
+
+/// Spans sent per request, and the longest a finished span waits.
+const BATCH: usize = 64;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// OTLP `SPAN_KIND_INTERNAL`, and the `STATUS_CODE_ERROR` of a span that
+/// logged an error.
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+pub struct OtlpLayer {
+    spans: SyncSender<SpanData>,
+}
+
+/// A span as it is built up, kept in the registry's extensions.
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_id: Option<u64>,
+    name: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(String, String)>,
+    events: Vec<SpanEvent>,
+    failed: bool,
+}
+
+/// Something logged while the span was entered.
+struct SpanEvent {
+    at: SystemTime,
+    message: String,
+    attributes: Vec<(String, String)>,
+}
+
+impl OtlpLayer {
+    /// Export to the collector at `endpoint`, e.g. `http://localhost:4318`.
+    /// Only plain HTTP is spoken; send TLS through a local collector.
+    pub fn connect(endpoint: &str) -> Result<Self, String> {
+        let rest = endpoint.strip_prefix("http://").ok_or_else(|| {
+            format!("unsupported OTLP endpoint {endpoint} (expected http://host:port)")
+        })?;
+        let (authority, base) = match rest.split_once('/') {
+            Some((authority, path)) => (authority, format!("/{}", path.trim_end_matches('/'))),
+            None => (rest, String::new()),
+        };
+        let authority = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:4318")
+        };
+        let path = format!("{base}/v1/traces");
+
+        let (spans, queue) = mpsc::sync_channel(QUEUE);
+        thread::Builder::new()
+            .name("otlp".to_string())
+            .spawn(move || export(&queue, &authority, &path))
+            .map_err(|err| format!("failed to start the OTLP exporter: {err}"))?;
+        Ok(Self { spans })
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span.parent().and_then(|parent| {
+            let extensions = parent.extensions();
+            extensions
+                .get::<SpanData>()
+                .map(|data| (data.trace_id, data.span_id))
+        });
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanData {
+            trace_id: parent.map_or_else(
+                || (u128::from(random()) << 64) | u128::from(random()),
+                |(trace, _)| trace,
+            ),
+            span_id: random(),
+            parent_id: parent.map(|(_, span_id)| span_id),
+            name: attrs.metadata().name(),
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: fields.0,
+            events: Vec::new(),
+            failed: false,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+            data.attributes.extend(fields.0);
+        }
+    }
+
+    /// Events logged inside a span become its span events; others are only
+    /// logged.
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let (message, attributes): (Vec<_>, Vec<_>) = fields
+            .0
+            .into_iter()
+            .partition(|(name, _)| name == "message");
+        let name = message
+            .into_iter()
+            .next()
+            .map_or_else(|| event.metadata().name().to_string(), |(_, value)| value);
+        if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+            data.failed |= *event.metadata().level() == Level::ERROR;
+            data.events.push(SpanEvent {
+                at: SystemTime::now(),
+                message: name,
+                attributes,
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(mut data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        data.end = SystemTime::now();
+        // a full queue means the collector is unreachable; the logs still have it all
+        let _ = self.spans.try_send(data);
+    }
+}
+
+#[derive(Default)]
+struct Fields(Vec<(String, String)>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{value:?}")));
+    }
+}
+
+/// Send finished spans in batches until the daemon exits. Nothing here may
+/// log: it would come straight back through the layer.
+fn export(queue: &Receiver<SpanData>, authority: &str, path: &str) {
+    let mut batch = Vec::new();
+    let mut oldest = Instant::now();
+    loop {
+        match queue.recv_timeout(FLUSH_INTERVAL) {
+            Ok(span) => {
+                if batch.is_empty() {
+                    oldest = Instant::now();
+                }
+                batch.push(span);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if batch.len() >= BATCH || (!batch.is_empty() && oldest.elapsed() >= FLUSH_INTERVAL) {
+            let body = request_body(&batch).to_string();
+            // a collector that is down loses these spans; there is no one to tell
+            let _ = post(authority, path, &body);
+            batch.clear();
+        }
+    }
+}
+
+fn request_body(spans: &[SpanData]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(span_json).collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": attributes_json(&[
+                    ("service.name".to_string(), "deadmand".to_string()),
+                    ("service.version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+                    ("host.name".to_string(), crate::hostname()),
+                ]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": "deadman", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn span_json(span: &SpanData) -> Value {
+    let events: Vec<Value> = span
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "timeUnixNano": unix_nanos(event.at),
+                "name": event.message,
+                "attributes": attributes_json(&event.attributes),
+            })
+        })
+        .collect();
+    let mut value = json!({
+        "traceId": format!("{:032x}", span.trace_id),
+        "spanId": format!("{:016x}", span.span_id),
+        "name": span.name,
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": unix_nanos(span.start),
+        "endTimeUnixNano": unix_nanos(span.end),
+        "attributes": attributes_json(&span.attributes),
+        "events": events,
+    });
+    if let Some(parent) = span.parent_id {
+        value["parentSpanId"] = json!(format!("{parent:016x}"));
+    }
+    if span.failed {
+        value["status"] = json!({ "code": STATUS_CODE_ERROR });
+    }
+    value
+}
+
+fn attributes_json(attributes: &[(String, String)]) -> Value {
+    attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+/// OTLP/JSON carries 64-bit nanosecond times as strings.
+fn unix_nanos(at: SystemTime) -> String {
+    at.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn post(authority: &str, path: &str, body: &str) -> Result<(), String> {
+    let address = authority
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or_else(|| format!("{authority} did not resolve"))?;
+    let mut stream =
+        TcpStream::connect_timeout(&address, TIMEOUT).map_err(|err| err.to_string())?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|err| err.to_string())?;
+
+    let mut request = String::new();
+    let _ = write!(
+        request,
+        "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|err| err.to_string())?;
+
+    let mut status = [0u8; 12];
+    stream
+        .read_exact(&mut status)
+        .map_err(|err| err.to_string())?;
+    // `HTTP/1.1 200`
+    match &status[9..12] {
+        [b'2', _, _] => Ok(()),
+        code => Err(format!(
+            "collector answered {}",
+            String::from_utf8_lossy(code)
+        )),
+    }
+}
+
+/// Span and trace IDs need only be unique, not unpredictable.
+fn random() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish().max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn span() -> SpanData {
+        SpanData {
+            trace_id: 0xabc,
+            span_id: 0x12,
+            parent_id: None,
+            name: "tether",
+            start: at(1),
+            end: at(2),
+            attributes: Vec::new(),
+            events: Vec::new(),
+            failed: false,
+        }
+    }
+
+    #[test]
+    fn test_span_without_optional_fields() {
+        assert_eq!(
+            span_json(&span()).to_string(),
+            concat!(
+                r#"{"attributes":[],"endTimeUnixNano":"2000000000","events":[],"kind":1,"#,
+                r#""name":"tether","spanId":"0000000000000012","#,
+                r#""startTimeUnixNano":"1000000000","#,
+                r#""traceId":"00000000000000000000000000000abc"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_span_with_parent_events_and_error() {
+        let mut span = span();
+        span.parent_id = Some(0x34);
+        span.failed = true;
+        span.attributes = vec![("device".to_string(), "Key \"A\"\nB\\C".to_string())];
+        span.events = vec![SpanEvent {
+            at: at(3),
+            message: "action failed".to_string(),
+            attributes: vec![("error".to_string(), "exit 1".to_string())],
+        }];
+        assert_eq!(
+            span_json(&span).to_string(),
+            concat!(
+                r#"{"attributes":[{"key":"device","value":{"stringValue":"Key \"A\"\nB\\C"}}],"#,
+                r#""endTimeUnixNano":"2000000000","#,
+                r#""events":[{"attributes":[{"key":"error","value":{"stringValue":"exit 1"}}],"#,
+                r#""name":"action failed","timeUnixNano":"3000000000"}],"#,
+                r#""kind":1,"name":"tether","parentSpanId":"0000000000000034","#,
+                r#""spanId":"0000000000000012","startTimeUnixNano":"1000000000","#,
+                r#""status":{"code":2},"traceId":"00000000000000000000000000000abc"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_request_body_wraps_spans_in_a_resource() {
+        let body = request_body(&[span()]);
+        let resource = &body["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0],
+            json!({ "key": "service.name", "value": { "stringValue": "deadmand" } })
+        );
+        assert_eq!(resource["scopeSpans"][0]["scope"]["name"], "deadman");
+        assert_eq!(resource["scopeSpans"][0]["spans"][0], span_json(&span()));
+    }
+
+    #[test]
+    fn test_times_before_the_epoch_are_zero() {
+        assert_eq!(unix_nanos(UNIX_EPOCH - Duration::from_secs(1)), "0");
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use nix::unistd::Uid;
+
+use crate::actions::Action;
+
+/// Bit for CAP_DAC_OVERRIDE in the capability masks, see capabilities(7).
+pub const CAP_DAC_OVERRIDE: u32 = 1;
+pub const CAP_NET_ADMIN: u32 = 12;
+
+pub fn is_root() -> bool {
+    Uid::effective().is_root()
+}
+
+/// Whether `capability` is in this process's effective set.
+pub fn has_capability(capability: u32) -> bool {
+    effective_capabilities().is_some_and(|mask| mask & (1 << capability) != 0)
+}
+
+/// The `CapEff` mask from /proc/self/status.
+fn effective_capabilities() -> Option<u64> {
+    parse_effective_capabilities(&fs::read_to_string("/proc/self/status").ok()?)
+}
+
+/// The `CapEff:` line of a `/proc/<pid>/status` file, which the kernel
+/// writes as 16 hex digits; anything else is not trusted.
+fn parse_effective_capabilities(status: &str) -> Option<u64> {
+    let mask = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?
+        .trim();
+    if mask.is_empty() || mask.len() > 16 || !mask.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(mask, 16).ok()
+}
+
+/// Check that the daemon may run `action` once a device is removed. Root can
+/// run anything; otherwise lock and suspend need polkit authorization for
+/// logind, and custom scripts must be executable. A user daemon only locks
+/// its own session, which needs no authorization.
+pub fn check_action(action: &Action) -> Result<(), String> {
+    if is_root() {
+        return Ok(());
+    }
+
+    match action {
+        Action::Lock | Action::Scrub if deadman_ipc::user_mode() => Ok(()),
+        Action::Lock | Action::Scrub => require_polkit("org.freedesktop.login1.lock-sessions"),
+        Action::Suspend => require_polkit("org.freedesktop.login1.suspend"),
+        // `ip link` needs CAP_NET_ADMIN; NetworkManager asks polkit instead
+        Action::KillNetwork if has_capability(CAP_NET_ADMIN) => Ok(()),
+        Action::KillNetwork => require_polkit("org.freedesktop.NetworkManager.network-control"),
+        // logind lets users end their own sessions, which is all a user daemon touches
+        Action::TerminateRemote if deadman_ipc::user_mode() => Ok(()),
+        Action::TerminateRemote => require_polkit("org.freedesktop.login1.manage"),
+        Action::Vm(_) if deadman_ipc::user_mode() => Ok(()),
+        Action::Vm(_) => require_polkit("org.libvirt.unix.manage"),
+        // the engine socket's group decides; test-action shows whether it lets us in
+        Action::Containers(_) => Ok(()),
+        Action::Systemd(_) if deadman_ipc::user_mode() => Ok(()),
+        Action::Systemd(units) => {
+            require_polkit("org.freedesktop.systemd1.manage-units")?;
+            if units.mask {
+                require_polkit("org.freedesktop.systemd1.manage-unit-files")?;
+            }
+            Ok(())
+        }
+        Action::Custom(path) => {
+            let metadata = fs::metadata(path)
+                .map_err(|err| format!("cannot access {}: {err}", path.display()))?;
+            if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+                Ok(())
+            } else {
+                Err(format!("{} is not an executable file", path.display()))
+            }
+        }
+    }
+}
+
+/// Ask polkit whether this process holds `action_id` without prompting.
+fn require_polkit(action_id: &str) -> Result<(), String> {
+    let status = Command::new("pkcheck")
+        .args(["--action-id", action_id, "--process"])
+        .arg(std::process::id().to_string())
+        .status()
+        .map_err(|err| format!("failed to run pkcheck to verify {action_id}: {err}"))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "not authorized for {action_id}; run as root or add a polkit rule for this user"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_effective_capabilities() {
+        let status = "Name:\tdeadmand\nCapInh:\t0000000000000000\nCapEff:\t0000000000001002\n";
+        let mask = parse_effective_capabilities(status).unwrap();
+        assert_eq!(mask, 0x1002);
+        assert_ne!(mask & (1 << CAP_DAC_OVERRIDE), 0);
+        assert_ne!(mask & (1 << CAP_NET_ADMIN), 0);
+
+        assert_eq!(
+            parse_effective_capabilities("CapEff:\t000001ffffffffff"),
+            Some(0x1ff_ffff_ffff)
+        );
+    }
+
+    #[test]
+    fn test_parse_effective_capabilities_short_lines() {
+        for status in [
+            "",
+            "CapEff:",
+            "CapEff:\t",
+            "CapEff\t1002",
+            "Name:\tdeadmand\n",
+        ] {
+            assert_eq!(
+                parse_effective_capabilities(status),
+                None,
+                "{status:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_effective_capabilities_rejects_non_hex() {
+        for mask in ["+1002", "-1", "0x1002", "10 02", "xyz", "00000000000010020"] {
+            assert_eq!(
+                parse_effective_capabilities(&format!("CapEff:\t{mask}\n")),
+                None,
+                "{mask:?} should not parse"
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::env;
+#[cfg(unix)]
+use std::ffi::OsString;
+use std::fs;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+/// Set for the replacement daemon to the file holding its predecessor's
+/// state.
+pub const STATE_ENV: &str = "DEADMAN_REEXEC_STATE";
+
+/// What the replacement daemon needs to carry on where this one stopped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedState {
+    pub tethers: Vec<SavedTether>,
+    /// Recent action runs per device, for cooldowns and trigger limits.
+    pub triggers: Vec<SavedTriggers>,
+    #[serde(default)]
+    pub rules: Vec<SavedRule>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedTether {
+    pub id: u64,
+    pub bus: u8,
+    pub address: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    #[serde(default)]
+    pub product: Option<String>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    pub action: String,
+    pub grace_secs: u64,
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub rearm: bool,
+    #[serde(default)]
+    pub poll: bool,
+    pub owner: u32,
+    /// The rule that tethered the device, if one did.
+    #[serde(default)]
+    pub rule: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedRule {
+    pub id: u64,
+    pub pattern: String,
+    pub action: String,
+    pub grace_secs: u64,
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub poll: bool,
+    pub owner: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedTriggers {
+    pub bus: u8,
+    pub address: u8,
+    /// How long ago each run was, in milliseconds.
+    pub ages_ms: Vec<u64>,
+}
+
+/// Whether this process was started by [`exec`] rather than by a user or
+/// service manager.
+pub fn restoring() -> bool {
+    env::var_os(STATE_ENV).is_some()
+}
+
+/// Write `state` out and replace this process with the daemon binary now
+/// on disk, keeping the pid and arguments. Only returns on failure.
+#[cfg(unix)]
+pub fn exec(state: &SavedState) -> Result<(), String> {
+    let binary = current_binary()?;
+    let path = state_path();
+    let contents = serde_json::to_vec(state)
+        .map_err(|err| format!("failed to serialize daemon state: {err}"))?;
+    let _ = fs::remove_file(&path);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&contents))
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+
+    let err = Command::new(&binary)
+        .args(env::args_os().skip(1))
+        .env(STATE_ENV, &path)
+        .exec();
+    let _ = fs::remove_file(&path);
+    Err(format!("failed to exec {}: {err}", binary.display()))
+}
+
+/// The state left by the daemon this one replaced, if any. The file is
+/// removed either way so it is only ever restored once.
+pub fn take() -> Option<Result<SavedState, String>> {
+    let path = PathBuf::from(env::var_os(STATE_ENV)?);
+    // SAFETY: called at startup, before the daemon spawns any threads
+    unsafe { env::remove_var(STATE_ENV) };
+    let contents = fs::read(&path);
+    let _ = fs::remove_file(&path);
+    Some(
+        contents
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))
+            .and_then(|contents| {
+                serde_json::from_slice(&contents)
+                    .map_err(|err| format!("invalid saved state {}: {err}", path.display()))
+            }),
+    )
+}
+
+/// Next to the pidfile, where only the daemon's user can write.
+#[cfg(unix)]
+fn state_path() -> PathBuf {
+    beside_pidfile(".state")
+}
+
+/// The pidfile's path with `suffix` appended.
+#[cfg(unix)]
+pub fn beside_pidfile(suffix: &str) -> PathBuf {
+    let mut path = OsString::from(crate::default_pidfile());
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The daemon's executable. A package upgrade replaces the file, after
+/// which Linux reports the old one's path with ` (deleted)` appended.
+#[cfg(unix)]
+fn current_binary() -> Result<PathBuf, String> {
+    let path =
+        env::current_exe().map_err(|err| format!("failed to find the daemon binary: {err}"))?;
+    let path = match path
+        .to_str()
+        .and_then(|path| path.strip_suffix(" (deleted)"))
+    {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    };
+    if !path.exists() {
+        return Err(format!("{} no longer exists", path.display()));
+    }
+    Ok(path)
+}
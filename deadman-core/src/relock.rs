@@ -0,0 +1,63 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use tracing::{info, warn};
+
+use crate::lock::{self, LockBackend};
+
+/// Locks sessions again as soon as logind reports one unlocked, until
+/// dropped. Held after a lock action while the tethered device stays away,
+/// so the device becomes a hardware token needed to use the machine.
+pub struct Relock {
+    monitor: Child,
+}
+
+impl Relock {
+    /// Follow logind's session signals through `gdbus monitor`.
+    pub fn start(backends: Vec<LockBackend>, users: Vec<String>) -> Result<Self, String> {
+        let mut monitor = Command::new("gdbus")
+            .args(["monitor", "--system", "--dest", "org.freedesktop.login1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("failed to run gdbus monitor: {err}"))?;
+        let stdout = monitor
+            .stdout
+            .take()
+            .ok_or_else(|| "gdbus monitor has no stdout".to_string())?;
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if !is_unlock(&line) {
+                    continue;
+                }
+                warn!("session unlocked while the tethered device is absent; locking again");
+                if let Err(err) = lock::lock(&backends, &users) {
+                    warn!(error = %err, "failed to lock the session again");
+                }
+            }
+        });
+
+        info!("locking any session unlocked before the device returns");
+        Ok(Self { monitor })
+    }
+}
+
+impl Drop for Relock {
+    fn drop(&mut self) {
+        let _ = self.monitor.kill();
+        let _ = self.monitor.wait();
+        info!("stopped re-locking unlocked sessions");
+    }
+}
+
+/// A session's `Unlock` signal, or its `LockedHint` going false, e.g.
+/// `/org/freedesktop/login1/session/_32: org.freedesktop.DBus.Properties.PropertiesChanged ('org.freedesktop.login1.Session', {'LockedHint': <false>}, @as [])`.
+fn is_unlock(line: &str) -> bool {
+    line.contains(".login1.Session.Unlock ")
+        || (line.contains("org.freedesktop.login1.Session")
+            && line.contains("'LockedHint': <false>"))
+}
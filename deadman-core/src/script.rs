@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::command;
+
+/// The only variables a script inherits unless its config adds more.
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// How a `custom:` script runs, from its `[scripts."<path>"]` table.
+/// Scripts start with a clean environment rather than the daemon's.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// Run as this user and their primary group instead of the daemon's
+    /// user. Supplementary groups are dropped.
+    pub run_as: Option<String>,
+    /// Working directory; `/` when unset.
+    pub working_dir: Option<PathBuf>,
+    /// Extra environment variables, on top of `PATH` and the user's `HOME`,
+    /// `USER` and `LOGNAME`.
+    pub env: BTreeMap<String, String>,
+}
+
+/// A pre-trigger hook's answer, from its exit status.
+pub enum Verdict {
+    /// Exit 0, or anything unexpected: run the action.
+    Proceed,
+    /// Exit 1: don't run it, for the reason the hook printed, if any.
+    Veto(Option<String>),
+    /// Exit 2: ask again after the seconds the hook printed.
+    Delay(Duration),
+}
+
+/// A delay from a hook that printed no number.
+const DEFAULT_HOOK_DELAY: Duration = Duration::from_secs(30);
+
+/// What set a script off, exported to it as `DEADMAN_TETHER_ID`,
+/// `DEADMAN_VID`, `DEADMAN_PID`, `DEADMAN_SERIAL`, `DEADMAN_EVENT` and
+/// `DEADMAN_TIMESTAMP` so it can tell devices apart without reading logs.
+#[derive(Clone, Debug, Default)]
+pub struct Trigger {
+    pub tether_id: Option<u64>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial: Option<String>,
+    /// The event kind, e.g. `triggered`.
+    pub event: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Trigger {
+    /// The variables, with unknown values left empty.
+    fn env(&self) -> [(&'static str, String); 6] {
+        [
+            (
+                "DEADMAN_TETHER_ID",
+                self.tether_id.map(|id| id.to_string()).unwrap_or_default(),
+            ),
+            ("DEADMAN_VID", format!("{:04x}", self.vendor_id)),
+            ("DEADMAN_PID", format!("{:04x}", self.product_id)),
+            ("DEADMAN_SERIAL", self.serial.clone().unwrap_or_default()),
+            ("DEADMAN_EVENT", self.event.clone()),
+            ("DEADMAN_TIMESTAMP", self.timestamp.to_string()),
+        ]
+    }
+}
+
+/// The account a script runs as.
+struct Account {
+    name: String,
+    home: PathBuf,
+    #[cfg(unix)]
+    uid: u32,
+    #[cfg(unix)]
+    gid: u32,
+}
+
+/// Run the script at `path` as configured, for `trigger`, and wait up to
+/// `timeout` for it. Its output is saved to
+/// `<audit_dir>/<timestamp>-<script>.log`.
+pub fn run(
+    path: &Path,
+    config: &ScriptConfig,
+    trigger: &Trigger,
+    audit_dir: &Path,
+    timeout: Duration,
+) -> Result<(), String> {
+    let output = command::output(command(path, config)?.envs(trigger.env()), timeout)?;
+
+    if let Err(err) = save_output(path, audit_dir, &output.stdout, &output.stderr) {
+        warn!(script = %path.display(), error = %err, "failed to save script output");
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} exited with status {}: {}",
+            path.display(),
+            output.status,
+            stderr.trim()
+        ));
+    }
+    info!(script = %path.display(), user = config.run_as.as_deref(), "action script completed");
+    Ok(())
+}
+
+/// Ask the pre-trigger hook at `path` whether the action should run for
+/// `trigger`. A hook that cannot run, times out or exits otherwise lets the
+/// action go ahead, so a broken hook never leaves a machine unprotected.
+pub fn consult(
+    path: &Path,
+    config: &ScriptConfig,
+    trigger: &Trigger,
+    timeout: Duration,
+) -> Verdict {
+    let output = match command(path, config)
+        .and_then(|mut command| command::output(command.envs(trigger.env()), timeout))
+    {
+        Ok(output) => output,
+        Err(err) => {
+            warn!(hook = %path.display(), error = %err, "pre-trigger hook failed; running the action");
+            return Verdict::Proceed;
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let said = stdout.lines().next().map(str::trim).unwrap_or_default();
+    match output.status.code() {
+        Some(0) => Verdict::Proceed,
+        Some(1) => Verdict::Veto((!said.is_empty()).then(|| said.to_string())),
+        Some(2) => Verdict::Delay(said.parse().map_or(DEFAULT_HOOK_DELAY, Duration::from_secs)),
+        _ => {
+            warn!(hook = %path.display(), status = %output.status, "pre-trigger hook exited unexpectedly; running the action");
+            Verdict::Proceed
+        }
+    }
+}
+
+/// Everything but the script itself is in place to run it.
+pub fn check(config: &ScriptConfig) -> Result<(), String> {
+    if let Some(user) = &config.run_as {
+        let account = account(user)?;
+        check_switch(&account)?;
+    }
+    if let Some(dir) = &config.working_dir
+        && !dir.is_dir()
+    {
+        return Err(format!(
+            "working directory {} does not exist",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// How the script would be run, for dry runs.
+pub fn describe(path: &Path, config: &ScriptConfig) -> String {
+    let mut description = format!("would run {}", path.display());
+    if let Some(user) = &config.run_as {
+        description.push_str(&format!(" as {user}"));
+    }
+    if let Some(dir) = &config.working_dir {
+        description.push_str(&format!(" in {}", dir.display()));
+    }
+    description
+}
+
+fn command(path: &Path, config: &ScriptConfig) -> Result<Command, String> {
+    let mut command = Command::new(path);
+    command
+        .env_clear()
+        .env("PATH", DEFAULT_PATH)
+        .current_dir(config.working_dir.as_deref().unwrap_or(Path::new("/")));
+
+    if let Some(user) = &config.run_as {
+        let account = account(user)?;
+        check_switch(&account)?;
+        command
+            .env("HOME", &account.home)
+            .env("USER", &account.name)
+            .env("LOGNAME", &account.name);
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // std drops supplementary groups when root sets the uid
+            command.gid(account.gid).uid(account.uid);
+        }
+    }
+    command.envs(&config.env);
+    Ok(command)
+}
+
+#[cfg(unix)]
+fn account(user: &str) -> Result<Account, String> {
+    let user = nix::unistd::User::from_name(user)
+        .map_err(|err| format!("failed to look up user {user}: {err}"))?
+        .ok_or_else(|| format!("no such user: {user}"))?;
+    Ok(Account {
+        name: user.name,
+        home: user.dir,
+        uid: user.uid.as_raw(),
+        gid: user.gid.as_raw(),
+    })
+}
+
+#[cfg(not(unix))]
+fn account(user: &str) -> Result<Account, String> {
+    Err(format!("run_as = \"{user}\" is only supported on Unix"))
+}
+
+/// Only root may start a script as somebody else.
+#[cfg(unix)]
+fn check_switch(account: &Account) -> Result<(), String> {
+    let uid = nix::unistd::Uid::effective().as_raw();
+    if uid == 0 || uid == account.uid {
+        Ok(())
+    } else {
+        Err(format!(
+            "running scripts as {} needs a root daemon",
+            account.name
+        ))
+    }
+}
+
+#[cfg(not(unix))]
+fn check_switch(_account: &Account) -> Result<(), String> {
+    Ok(())
+}
+
+fn save_output(path: &Path, audit_dir: &Path, stdout: &[u8], stderr: &[u8]) -> Result<(), String> {
+    if stdout.is_empty() && stderr.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(audit_dir)
+        .map_err(|err| format!("failed to create {}: {err}", audit_dir.display()))?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "script".to_string());
+    let log = audit_dir.join(format!("{timestamp}-{name}.log"));
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&log)
+        .map_err(|err| format!("failed to open {}: {err}", log.display()))?;
+    writeln!(file, "== stdout ==")
+        .and_then(|()| file.write_all(stdout))
+        .and_then(|()| writeln!(file, "== stderr =="))
+        .and_then(|()| file.write_all(stderr))
+        .map_err(|err| format!("failed to write {}: {err}", log.display()))?;
+    info!(path = %log.display(), "saved script output");
+    Ok(())
+}
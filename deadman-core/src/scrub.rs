@@ -0,0 +1,179 @@
+use std::process::{Command, Stdio};
+
+use tracing::{debug, info, warn};
+
+use crate::lock::on_path;
+
+/// Clear the clipboard and lock the session's secret stores. Everything here
+/// talks to the daemon's own session, so it is only effective for a user
+/// daemon; each step is best effort and failures are only logged, since the
+/// screen lock that follows matters more.
+pub fn scrub() {
+    clear_clipboard();
+    lock_secret_service();
+    close_kwallet();
+}
+
+/// Clear both the clipboard and the primary selection, on Wayland with
+/// `wl-copy` and on X11 with `xsel`.
+fn clear_clipboard() {
+    let commands: &[(&str, &[&str])] = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        &[
+            ("wl-copy", &["--clear"]),
+            ("wl-copy", &["--primary", "--clear"]),
+        ]
+    } else {
+        &[
+            ("xsel", &["--clipboard", "--clear"]),
+            ("xsel", &["--primary", "--clear"]),
+        ]
+    };
+
+    for (program, args) in commands {
+        match quiet(Command::new(program).args(*args)) {
+            Ok(()) => debug!(program, "cleared selection"),
+            Err(err) => warn!(error = %err, "failed to clear the clipboard"),
+        }
+    }
+}
+
+/// Lock every Secret Service collection, which covers GNOME Keyring and
+/// KeePassXC's Secret Service integration.
+fn lock_secret_service() {
+    let output = Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--dest",
+            "org.freedesktop.secrets",
+            "--object-path",
+            "/org/freedesktop/secrets",
+            "--method",
+            "org.freedesktop.DBus.Properties.Get",
+            "org.freedesktop.Secret.Service",
+            "Collections",
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let collections = match output {
+        Ok(output) if output.status.success() => {
+            // e.g. `(<[objectpath '/org/freedesktop/secrets/collection/login']>,)`
+            String::from_utf8_lossy(&output.stdout)
+                .split('\'')
+                .skip(1)
+                .step_by(2)
+                .map(|path| format!("objectpath '{path}'"))
+                .collect::<Vec<_>>()
+        }
+        Ok(_) => {
+            debug!("no Secret Service on the session bus");
+            return;
+        }
+        Err(err) => {
+            warn!(error = %err, "failed to run gdbus");
+            return;
+        }
+    };
+    if collections.is_empty() {
+        return;
+    }
+
+    let result = quiet(Command::new("gdbus").args([
+        "call",
+        "--session",
+        "--dest",
+        "org.freedesktop.secrets",
+        "--object-path",
+        "/org/freedesktop/secrets",
+        "--method",
+        "org.freedesktop.Secret.Service.Lock",
+        &format!("[{}]", collections.join(", ")),
+    ]));
+    match result {
+        Ok(()) => info!(count = collections.len(), "locked keyring collections"),
+        Err(err) => warn!(error = %err, "failed to lock the keyring"),
+    }
+}
+
+/// Close every open KWallet, trying kwalletd6 before kwalletd5.
+fn close_kwallet() {
+    for daemon in ["kwalletd6", "kwalletd5"] {
+        let result = quiet(Command::new("gdbus").args([
+            "call",
+            "--session",
+            "--dest",
+            &format!("org.kde.{daemon}"),
+            "--object-path",
+            &format!("/modules/{daemon}"),
+            "--method",
+            "org.kde.KWallet.closeAllWallets",
+        ]));
+        if result.is_ok() {
+            info!(daemon, "closed KWallet wallets");
+            return;
+        }
+    }
+    debug!("no KWallet daemon on the session bus");
+}
+
+/// Dry-run steps: which clipboard tool and secret stores `scrub` would use.
+/// Missing stores are skipped rather than failures.
+pub fn dry_run() -> Vec<Result<String, String>> {
+    let clipboard = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        "wl-copy"
+    } else {
+        "xsel"
+    };
+    let mut steps = vec![if on_path(clipboard) {
+        Ok(format!("would clear the clipboard with {clipboard}"))
+    } else {
+        Err(format!(
+            "{clipboard} not found in PATH; the clipboard would stay"
+        ))
+    }];
+
+    for (name, dest, path) in [
+        (
+            "Secret Service",
+            "org.freedesktop.secrets",
+            "/org/freedesktop/secrets",
+        ),
+        ("kwalletd6", "org.kde.kwalletd6", "/modules/kwalletd6"),
+        ("kwalletd5", "org.kde.kwalletd5", "/modules/kwalletd5"),
+    ] {
+        steps.push(Ok(if session_service_responds(dest, path) {
+            format!("would lock {name}")
+        } else {
+            format!("{name} is not running; skipped")
+        }));
+    }
+    steps
+}
+
+fn session_service_responds(dest: &str, path: &str) -> bool {
+    quiet(Command::new("gdbus").args([
+        "introspect",
+        "--session",
+        "--dest",
+        dest,
+        "--object-path",
+        path,
+    ]))
+    .is_ok()
+}
+
+fn quiet(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| format!("failed to run {program}: {err}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{program} exited with status {status}"))
+    }
+}
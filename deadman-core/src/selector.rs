@@ -0,0 +1,150 @@
+use std::fmt;
+use std::str::FromStr;
+
+pub use deadman_ipc::devices::Pattern;
+use deadman_ipc::devices::parse_id;
+use deadman_ipc::router::{Args, FromArgs};
+
+/// Picks tethers out by what is tethered rather than where it is plugged in:
+/// `id=<tether id>`, `<vid>:<pid>` in hex, or `serial:<serial>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selector {
+    Id(u64),
+    Device { vendor_id: u16, product_id: u16 },
+    Serial(String),
+}
+
+impl Selector {
+    pub fn matches(&self, id: u64, vendor_id: u16, product_id: u16, serial: Option<&str>) -> bool {
+        match self {
+            Selector::Id(wanted) => *wanted == id,
+            Selector::Device {
+                vendor_id: vendor,
+                product_id: product,
+            } => *vendor == vendor_id && *product == product_id,
+            Selector::Serial(wanted) => serial == Some(wanted.as_str()),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(id) = value.strip_prefix("id=") {
+            return id
+                .parse()
+                .map(Selector::Id)
+                .map_err(|_| format!("invalid tether id: {id}"));
+        }
+        if let Some(serial) = value.strip_prefix("serial:") {
+            if serial.is_empty() {
+                return Err("missing serial after serial:".to_string());
+            }
+            return Ok(Selector::Serial(serial.to_string()));
+        }
+        let invalid = || {
+            format!("invalid selector: {value} (expected id=<n>, <vid>:<pid> or serial:<serial>)")
+        };
+        let (vendor, product) = value.split_once(':').ok_or_else(invalid)?;
+        Ok(Selector::Device {
+            vendor_id: parse_id(vendor).ok_or_else(invalid)?,
+            product_id: parse_id(product).ok_or_else(invalid)?,
+        })
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Id(id) => write!(f, "id={id}"),
+            Selector::Device {
+                vendor_id,
+                product_id,
+            } => write!(f, "{vendor_id:04x}:{product_id:04x}"),
+            Selector::Serial(serial) => write!(f, "serial:{serial}"),
+        }
+    }
+}
+
+/// Which tethers a command applies to: `[<selector>...] [except <selector>...]`.
+/// With no selectors before `except`, every tether is in scope.
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    only: Vec<Selector>,
+    except: Vec<Selector>,
+}
+
+impl Scope {
+    /// Whether no selector narrows the scope.
+    pub fn is_everything(&self) -> bool {
+        self.only.is_empty() && self.except.is_empty()
+    }
+
+    pub fn includes(&self, id: u64, vendor_id: u16, product_id: u16, serial: Option<&str>) -> bool {
+        let matches = |selector: &Selector| selector.matches(id, vendor_id, product_id, serial);
+        (self.only.is_empty() || self.only.iter().any(matches)) && !self.except.iter().any(matches)
+    }
+}
+
+impl FromArgs for Scope {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        let mut scope = Scope::default();
+        let mut except = false;
+        while let Some(word) = args.next_word() {
+            if word == "except" {
+                except = true;
+            } else if except {
+                scope.except.push(word.parse()?);
+            } else {
+                scope.only.push(word.parse()?);
+            }
+        }
+        Ok(scope)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |selectors: &[Selector]| {
+            selectors
+                .iter()
+                .map(Selector::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match (self.only.is_empty(), self.except.is_empty()) {
+            (true, true) => f.write_str("all tethers"),
+            (false, true) => write!(f, "{}", join(&self.only)),
+            (true, false) => write!(f, "all tethers except {}", join(&self.except)),
+            (false, false) => write!(f, "{} except {}", join(&self.only), join(&self.except)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selector_forms() {
+        assert_eq!("id=3".parse(), Ok(Selector::Id(3)));
+        assert_eq!(
+            "serial:ABC".parse(),
+            Ok(Selector::Serial("ABC".to_string()))
+        );
+        assert_eq!(
+            "1050:0407".parse(),
+            Ok(Selector::Device {
+                vendor_id: 0x1050,
+                product_id: 0x0407,
+            })
+        );
+        for malformed in ["id=", "id=x", "serial:", "1050", "1050:*", "+1050:0407"] {
+            assert!(
+                malformed.parse::<Selector>().is_err(),
+                "{malformed:?} should not parse"
+            );
+        }
+    }
+}
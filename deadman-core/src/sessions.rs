@@ -0,0 +1,229 @@
+use std::process::Command;
+
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+use crate::lock::on_path;
+
+/// A row of `loginctl list-sessions --no-legend`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Session {
+    pub id: String,
+    pub uid: String,
+    pub user: String,
+    /// None for sessions without a seat, such as ssh logins.
+    pub seat: Option<String>,
+}
+
+/// Parse `loginctl list-sessions --no-legend`: `SESSION UID USER SEAT TTY`
+/// and more. Older versions leave SEAT blank rather than print `-`, so only a
+/// column named like a seat is taken for one. Lines without a session, a
+/// numeric UID and a user are skipped.
+pub fn parse_sessions(list: &str) -> Vec<Session> {
+    list.lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let (id, uid, user) = (columns.next()?, columns.next()?, columns.next()?);
+            if !uid.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            Some(Session {
+                id: id.to_string(),
+                uid: uid.to_string(),
+                user: user.to_string(),
+                seat: columns
+                    .next()
+                    .filter(|seat| seat.starts_with("seat"))
+                    .map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// A logind session someone is using from another machine.
+struct RemoteSession {
+    id: String,
+    user: String,
+    host: String,
+}
+
+/// Terminate every remote session logind knows of, ssh logins included,
+/// which locking the screen leaves untouched. A user daemon only ends its
+/// own user's sessions. Every session is tried even when an earlier one
+/// fails; having none to end is not an error.
+pub fn terminate_remote() -> Result<(), String> {
+    let sessions = remote_sessions()?;
+    if sessions.is_empty() {
+        info!("no remote sessions to terminate");
+        return Ok(());
+    }
+
+    let mut errors = Vec::new();
+    for session in &sessions {
+        match command::run(
+            Command::new("loginctl")
+                .arg("terminate-session")
+                .arg(&session.id),
+            HELPER_TIMEOUT,
+        ) {
+            Ok(()) => {
+                info!(session = %session.id, user = %session.user, host = %session.host, "terminated remote session");
+            }
+            Err(err) => {
+                warn!(session = %session.id, user = %session.user, error = %err, "failed to terminate remote session");
+                errors.push(format!("session {}: {err}", session.id));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "some remote sessions survived ({})",
+            errors.join("; ")
+        ))
+    }
+}
+
+/// Dry-run steps: the remote sessions that would be terminated right now.
+pub fn dry_run() -> Vec<Result<String, String>> {
+    if !on_path("loginctl") {
+        return vec![Err("loginctl not found in PATH".to_string())];
+    }
+    match remote_sessions() {
+        Ok(sessions) if sessions.is_empty() => {
+            vec![Ok("no remote sessions to terminate right now".to_string())]
+        }
+        Ok(sessions) => sessions
+            .iter()
+            .map(|session| {
+                Ok(format!(
+                    "would terminate session {} of {} from {}",
+                    session.id, session.user, session.host
+                ))
+            })
+            .collect(),
+        Err(err) => vec![Err(err)],
+    }
+}
+
+/// Sessions logind marks remote, or that sshd opened.
+fn remote_sessions() -> Result<Vec<RemoteSession>, String> {
+    let list = loginctl(&["list-sessions", "--no-legend"])?;
+    let own_uid = deadman_ipc::user_mode().then(current_uid).flatten();
+
+    let mut sessions = Vec::new();
+    for Session { id, uid, user, .. } in parse_sessions(&list) {
+        if own_uid.as_deref().is_some_and(|own| own != uid) {
+            continue;
+        }
+
+        let properties = loginctl(&[
+            "show-session",
+            &id,
+            "-p",
+            "Remote",
+            "-p",
+            "RemoteHost",
+            "-p",
+            "Service",
+        ])?;
+        let property = |name: &str| {
+            properties.lines().find_map(|line| {
+                line.strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix('='))
+            })
+        };
+        if property("Remote") == Some("yes") || property("Service") == Some("sshd") {
+            sessions.push(RemoteSession {
+                id,
+                user,
+                host: property("RemoteHost")
+                    .filter(|host| !host.is_empty())
+                    .unwrap_or("unknown host")
+                    .to_string(),
+            });
+        }
+    }
+    Ok(sessions)
+}
+
+fn loginctl(args: &[&str]) -> Result<String, String> {
+    let output = command::output(Command::new("loginctl").args(args), HELPER_TIMEOUT)
+        .map_err(|err| format!("failed to run loginctl {}: {err}", args[0]))?;
+    if !output.status.success() {
+        return Err(format!(
+            "loginctl {} exited with status {status}",
+            args[0],
+            status = output.status
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(unix)]
+fn current_uid() -> Option<String> {
+    Some(nix::unistd::Uid::current().to_string())
+}
+
+#[cfg(not(unix))]
+fn current_uid() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, uid: &str, user: &str, seat: Option<&str>) -> Session {
+        Session {
+            id: id.to_string(),
+            uid: uid.to_string(),
+            user: user.to_string(),
+            seat: seat.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_sessions() {
+        let list = "\
+     2 1000 alice seat0 tty2    active no  -
+     7 1001 bob   -     pts/0   active no  -
+";
+        assert_eq!(
+            parse_sessions(list),
+            [
+                session("2", "1000", "alice", Some("seat0")),
+                session("7", "1001", "bob", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_sessions_with_a_blank_seat_column() {
+        // older loginctl prints nothing for the seat, so the TTY comes fourth
+        assert_eq!(
+            parse_sessions("c1 1000 alice        pts/1\n"),
+            [session("c1", "1000", "alice", None)]
+        );
+    }
+
+    #[test]
+    fn test_parse_sessions_empty_output() {
+        assert!(parse_sessions("").is_empty());
+        assert!(parse_sessions("\n  \n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_sessions_skips_malformed_lines() {
+        let list = "\
+SESSION  UID USER  SEAT  TTY
+2
+3 1000
+No sessions.
+4 1000 alice
+";
+        assert_eq!(parse_sessions(list), [session("4", "1000", "alice", None)]);
+    }
+}
@@ -0,0 +1,389 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use deadman_ipc::events::{ActionResult, Event, EventKind, format_utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::events::EVENTS;
+
+/// How long to wait for a TCP collector to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `authpriv.notice`, as for the daemon's own syslog output.
+const SYSLOG_PRIORITY: u8 = 10 * 8 + 5;
+
+/// How events reach the collector.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    #[default]
+    Udp,
+    /// Newline-framed, as collectors expect on port 514.
+    Tcp,
+    /// TCP through `openssl s_client`, checking the collector's certificate.
+    Tls,
+}
+
+impl std::str::FromStr for Transport {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "udp" => Ok(Transport::Udp),
+            "tcp" => Ok(Transport::Tcp),
+            "tls" => Ok(Transport::Tls),
+            other => Err(format!(
+                "unknown transport: {other} (expected udp, tcp or tls)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+            Transport::Tls => "tls",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The message format inside each syslog record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// ArcSight Common Event Format.
+    #[default]
+    Cef,
+    /// QRadar Log Event Extended Format 1.0.
+    Leef,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "cef" => Ok(Format::Cef),
+            "leef" => Ok(Format::Leef),
+            other => Err(format!("unknown format: {other} (expected cef or leef)")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Cef => "cef",
+            Format::Leef => "leef",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The `[siem]` config table. Trigger events are only sent once `address`
+/// is set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiemConfig {
+    /// Collector as `host:port`.
+    pub address: Option<String>,
+    pub transport: Transport,
+    pub format: Format,
+    /// CA bundle for checking a `tls` collector's certificate instead of
+    /// the system's.
+    pub ca_file: Option<PathBuf>,
+}
+
+/// Send trigger events to the collector in the current `[siem]` config,
+/// as returned by `config`, reconnecting whenever it changes.
+pub fn forward(config: impl Fn() -> SiemConfig + Send + 'static) {
+    let events = EVENTS.subscribe();
+    thread::spawn(move || {
+        let mut connection: Option<(SiemConfig, Connection)> = None;
+        for line in events {
+            let config = config();
+            let Some(address) = config.address.clone() else {
+                connection = None;
+                continue;
+            };
+            let Ok(event) = serde_json::from_str::<Event>(&line) else {
+                continue;
+            };
+            if !matches!(event.kind, EventKind::Triggered | EventKind::Suppressed) {
+                continue;
+            }
+            let record = record(&event, config.format);
+
+            if connection
+                .as_ref()
+                .is_none_or(|(connected, _)| *connected != config)
+            {
+                connection = match Connection::open(&address, &config) {
+                    Ok(opened) => Some((config.clone(), opened)),
+                    Err(err) => {
+                        warn!(collector = %address, error = %err, "failed to connect to the SIEM collector");
+                        continue;
+                    }
+                };
+            }
+            let Some((_, open)) = connection.as_mut() else {
+                continue;
+            };
+            if let Err(err) = open.send(&record) {
+                // the collector may have closed an idle stream; retry once on a fresh one
+                debug!(error = %err, "SIEM connection failed; reconnecting");
+                let sent = Connection::open(&address, &config).and_then(|mut reopened| {
+                    reopened.send(&record)?;
+                    Ok(reopened)
+                });
+                match sent {
+                    Ok(reopened) => connection = Some((config, reopened)),
+                    Err(err) => {
+                        warn!(collector = %address, error = %err, "failed to send event to the SIEM collector");
+                        connection = None;
+                    }
+                }
+            }
+        }
+    });
+}
+
+enum Connection {
+    Udp(UdpSocket, String),
+    Tcp(TcpStream),
+    Tls(Child),
+}
+
+impl Connection {
+    fn open(address: &str, config: &SiemConfig) -> io::Result<Self> {
+        match config.transport {
+            Transport::Udp => {
+                let socket = UdpSocket::bind("[::]:0").or_else(|_| UdpSocket::bind("0.0.0.0:0"))?;
+                Ok(Connection::Udp(socket, address.to_string()))
+            }
+            Transport::Tcp => {
+                let target = address
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| io::Error::other(format!("{address} did not resolve")))?;
+                Ok(Connection::Tcp(TcpStream::connect_timeout(
+                    &target,
+                    CONNECT_TIMEOUT,
+                )?))
+            }
+            Transport::Tls => {
+                let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+                let mut command = Command::new("openssl");
+                command.args([
+                    "s_client",
+                    "-quiet",
+                    "-verify_return_error",
+                    "-connect",
+                    address,
+                    "-servername",
+                    host,
+                    "-verify_hostname",
+                    host,
+                ]);
+                if let Some(ca_file) = &config.ca_file {
+                    command.arg("-CAfile").arg(ca_file);
+                }
+                command
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map(Connection::Tls)
+            }
+        }
+    }
+
+    fn send(&mut self, record: &str) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket, address) => {
+                socket.send_to(record.as_bytes(), &*address).map(drop)
+            }
+            Connection::Tcp(stream) => writeln!(stream, "{record}"),
+            Connection::Tls(child) => {
+                if let Some(status) = child.try_wait()? {
+                    return Err(io::Error::other(format!("openssl exited with {status}")));
+                }
+                let stdin = child
+                    .stdin
+                    .as_mut()
+                    .ok_or_else(|| io::Error::other("openssl has no stdin"))?;
+                writeln!(stdin, "{record}")?;
+                stdin.flush()
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Connection::Tls(child) = self {
+            drop(child.stdin.take());
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// An RFC 5424 syslog record carrying `event` as CEF or LEEF.
+fn record(event: &Event, format: Format) -> String {
+    let host = crate::hostname();
+    let message = match format {
+        Format::Cef => cef(event, &host),
+        Format::Leef => leef(event, &host),
+    };
+    format!(
+        "<{SYSLOG_PRIORITY}>1 {} {host} deadmand {} - - {message}",
+        rfc3339(event.timestamp),
+        std::process::id()
+    )
+}
+
+fn cef(event: &Event, host: &str) -> String {
+    let (name, severity) = summary(event);
+    let mut extension = vec![
+        format!("rt={}", event.timestamp * 1000),
+        format!("dvchost={}", cef_value(host)),
+    ];
+    if let Some(action) = &event.action {
+        extension.push(format!("act={}", cef_value(action)));
+    }
+    if let Some(device) = &event.device {
+        extension.push("cs1Label=device".to_string());
+        extension.push(format!("cs1={}", cef_value(device)));
+    }
+    if let Some(uid) = event.uid {
+        extension.push(format!("suid={uid}"));
+    }
+    if let Some(confidence) = &event.confidence {
+        extension.push("cs2Label=confidence".to_string());
+        extension.push(format!("cs2={}", cef_value(confidence)));
+    }
+    if let Some(message) = &event.message {
+        extension.push(format!("msg={}", cef_value(message)));
+    }
+    format!(
+        "CEF:0|deadman|deadman|{}|{}|{name}|{severity}|{}",
+        env!("CARGO_PKG_VERSION"),
+        event.kind,
+        extension.join(" ")
+    )
+}
+
+fn leef(event: &Event, host: &str) -> String {
+    let (_, severity) = summary(event);
+    let mut attributes = vec![
+        format!("devTime={}", event.timestamp * 1000),
+        format!("sev={severity}"),
+        format!("identHostName={}", leef_value(host)),
+    ];
+    if let Some(action) = &event.action {
+        attributes.push(format!("action={}", leef_value(action)));
+    }
+    if let Some(device) = &event.device {
+        attributes.push(format!("resource={}", leef_value(device)));
+    }
+    if let Some(uid) = event.uid {
+        attributes.push(format!("usrName={uid}"));
+    }
+    if let Some(confidence) = &event.confidence {
+        attributes.push(format!("confidence={}", leef_value(confidence)));
+    }
+    if let Some(message) = &event.message {
+        attributes.push(format!("reason={}", leef_value(message)));
+    }
+    format!(
+        "LEEF:1.0|deadman|deadman|{}|{}|{}",
+        env!("CARGO_PKG_VERSION"),
+        event.kind,
+        attributes.join("\t")
+    )
+}
+
+/// Event name and severity (0-10). A trigger whose action failed is the
+/// worst case: the machine was left as it was.
+fn summary(event: &Event) -> (&'static str, u8) {
+    match (event.kind, event.result) {
+        (EventKind::Triggered, Some(ActionResult::Failed)) => ("Removal action failed", 10),
+        (EventKind::Triggered, Some(ActionResult::FallbackRan)) => {
+            ("Removal fallback action ran", 9)
+        }
+        (EventKind::Triggered, _) => ("Removal action ran", 8),
+        _ => ("Removal action suppressed", 5),
+    }
+}
+
+/// Escape a CEF extension value.
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// LEEF values may not contain the tab delimiter or line breaks.
+fn leef_value(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Format Unix seconds as an RFC 3339 UTC timestamp.
+fn rfc3339(timestamp: u64) -> String {
+    format!("{}Z", format_utc(timestamp, 'T'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cef_value_escapes_extension_delimiters() {
+        assert_eq!(cef_value(r"C:\keys"), r"C:\\keys");
+        assert_eq!(cef_value("a=b"), r"a\=b");
+        assert_eq!(cef_value("line\nbreak\r"), r"line\nbreak\r");
+        // pipes only delimit the header, and tabs are not special
+        assert_eq!(cef_value("a|b\tc"), "a|b\tc");
+    }
+
+    #[test]
+    fn test_leef_value_replaces_delimiters() {
+        assert_eq!(leef_value("a\tb\nc\rd"), "a b c d");
+        assert_eq!(leef_value(r"a=b|c\d"), r"a=b|c\d");
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1_709_208_000), "2024-02-29T12:00:00Z");
+        assert_eq!(rfc3339(1_704_067_199), "2023-12-31T23:59:59Z");
+        assert_eq!(rfc3339(1_704_067_200), "2024-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_summary_follows_action_result() {
+        let mut event = Event::new(EventKind::Triggered);
+        event.message = Some("swaylock failed; ran fallback action suspend".to_string());
+        event.result = Some(ActionResult::FallbackRan);
+        assert_eq!(summary(&event), ("Removal fallback action ran", 9));
+
+        event.result = Some(ActionResult::Failed);
+        assert_eq!(summary(&event), ("Removal action failed", 10));
+
+        event.result = Some(ActionResult::Ran);
+        event.message = None;
+        assert_eq!(summary(&event), ("Removal action ran", 8));
+    }
+}
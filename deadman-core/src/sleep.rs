@@ -0,0 +1,74 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use crate::inhibit::SleepInhibitor;
+
+/// When the system last went to sleep or woke up, as far as logind said.
+static LAST_TRANSITION: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// How long ago the system last went to sleep or resumed, if it has since
+/// the daemon started watching.
+pub fn since_transition() -> Option<Duration> {
+    LAST_TRANSITION
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .map(|at| at.elapsed())
+}
+
+/// Watch logind's `PrepareForSleep` signal through `gdbus monitor`. A delay
+/// inhibitor is held while awake so `before_sleep` can run to completion
+/// before the machine is allowed to go down.
+pub fn watch(before_sleep: impl Fn() + Send + 'static) -> Result<(), String> {
+    let mut monitor = Command::new("gdbus")
+        .args([
+            "monitor",
+            "--system",
+            "--dest",
+            "org.freedesktop.login1",
+            "--object-path",
+            "/org/freedesktop/login1",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("failed to run gdbus monitor: {err}"))?;
+    let stdout = monitor
+        .stdout
+        .take()
+        .ok_or_else(|| "gdbus monitor has no stdout".to_string())?;
+
+    thread::spawn(move || {
+        let mut inhibitor = SleepInhibitor::take("lock before sleep if a tethered device is gone");
+
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            // e.g. `/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)`
+            let Some((_, args)) = line.split_once(".Manager.PrepareForSleep ") else {
+                continue;
+            };
+            *LAST_TRANSITION
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()) = Some(Instant::now());
+
+            if args.starts_with("(true") {
+                info!("system is preparing to sleep");
+                before_sleep();
+                drop(inhibitor.take());
+            } else {
+                debug!("system resumed from sleep");
+                inhibitor = SleepInhibitor::take("lock before sleep if a tethered device is gone");
+            }
+        }
+
+        warn!("gdbus monitor exited; sleep is no longer checked for missing devices");
+        let _ = monitor.wait();
+    });
+
+    Ok(())
+}
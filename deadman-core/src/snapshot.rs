@@ -0,0 +1,301 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::command::{self, HELPER_TIMEOUT};
+use crate::config::Config;
+
+/// Bumped when the snapshot layout changes incompatibly.
+const FORMAT_VERSION: u32 = 1;
+
+/// Everything needed to set another machine up the same way: the daemon's
+/// settings and its tethers, identified by device rather than bus address.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    pub deadman_snapshot: u32,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub host: String,
+    pub config: Config,
+    pub tethers: Vec<TetherSpec>,
+    /// Hex-encoded SHA-256 signature over the rest of the snapshot, made
+    /// with `snapshot_signing_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A tether to recreate on whichever connected device matches.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TetherSpec {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Only a device with this serial matches, when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    pub action: String,
+    pub grace_secs: u64,
+    pub cooldown_secs: u64,
+    /// Tether the device again when it returns after the action ran.
+    #[serde(default)]
+    pub rearm: bool,
+    /// Watch by polling the bus instead of through hotplug callbacks.
+    #[serde(default)]
+    pub poll: bool,
+}
+
+impl Snapshot {
+    pub fn new(created: u64, host: String, config: Config, tethers: Vec<TetherSpec>) -> Self {
+        Self {
+            deadman_snapshot: FORMAT_VERSION,
+            created,
+            host,
+            config,
+            tethers,
+            signature: None,
+        }
+    }
+
+    /// Sign with the PEM private key at `key`.
+    pub fn sign(&mut self, key: &Path) -> Result<(), String> {
+        self.signature = None;
+        let payload = self.payload()?;
+        let scratch = Scratch::new()?;
+        let (data, signature) = (scratch.file("payload"), scratch.file("signature"));
+        write_private(&data, payload.as_bytes())?;
+        command::run(
+            Command::new("openssl")
+                .args(["dgst", "-sha256", "-sign"])
+                .arg(key)
+                .arg("-out")
+                .arg(&signature)
+                .arg(&data),
+            HELPER_TIMEOUT,
+        )?;
+        let signature =
+            fs::read(&signature).map_err(|err| format!("failed to read the signature: {err}"))?;
+        self.signature = Some(signature.iter().map(|byte| format!("{byte:02x}")).collect());
+        Ok(())
+    }
+
+    /// Check the signature against the PEM public key at `key`.
+    pub fn verify(&self, key: &Path) -> Result<(), String> {
+        let signature = self
+            .signature
+            .as_deref()
+            .ok_or_else(|| "snapshot is not signed".to_string())?;
+        let signature = decode_hex(signature)?;
+        let payload = Snapshot {
+            signature: None,
+            ..self.clone()
+        }
+        .payload()?;
+
+        let scratch = Scratch::new()?;
+        let (data, signature_file) = (scratch.file("payload"), scratch.file("signature"));
+        write_private(&data, payload.as_bytes())?;
+        write_private(&signature_file, &signature)?;
+        command::run(
+            Command::new("openssl")
+                .args(["dgst", "-sha256", "-verify"])
+                .arg(key)
+                .arg("-signature")
+                .arg(&signature_file)
+                .arg(&data),
+            HELPER_TIMEOUT,
+        )
+        .map_err(|_| "snapshot signature does not match snapshot_trusted_key".to_string())
+    }
+
+    /// The bytes that are signed: compact JSON with object keys sorted, so
+    /// the same snapshot always serializes the same way.
+    fn payload(&self) -> Result<String, String> {
+        let value: Value = serde_json::to_value(self)
+            .map_err(|err| format!("failed to serialize snapshot: {err}"))?;
+        Ok(value.to_string())
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|err| format!("failed to serialize snapshot: {err}"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+        let snapshot: Snapshot = serde_json::from_str(&text)
+            .map_err(|err| format!("invalid snapshot {}: {err}", path.display()))?;
+        if snapshot.deadman_snapshot != FORMAT_VERSION {
+            return Err(format!(
+                "unsupported snapshot format {} (expected {FORMAT_VERSION})",
+                snapshot.deadman_snapshot
+            ));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// A private directory for handing files to openssl, removed when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> Result<Self, String> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "deadman-snapshot-{}-{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder
+            .create(&dir)
+            .map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+        Ok(Self(dir))
+    }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    fs::write(path, contents).map_err(|err| format!("failed to write {}: {err}", path.display()))
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    let invalid = || "snapshot signature is not hex".to_string();
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|start| {
+            // from_str_radix would also take a sign, as in `+1`
+            text.get(start..start + 2)
+                .filter(|pair| pair.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot::new(
+            1_700_000_000,
+            "host".to_string(),
+            Config::default(),
+            vec![TetherSpec {
+                vendor_id: 0x1050,
+                product_id: 0x0407,
+                serial: Some("123".to_string()),
+                product: Some("YubiKey".to_string()),
+                action: "lock".to_string(),
+                grace_secs: 5,
+                cooldown_secs: 0,
+                rearm: false,
+                poll: false,
+            }],
+        )
+    }
+
+    /// A fresh EC key pair in `scratch`, or `None` without openssl.
+    fn key_pair(scratch: &Scratch) -> Option<(PathBuf, PathBuf)> {
+        let (private, public) = (scratch.file("key.pem"), scratch.file("key.pub"));
+        let generated = Command::new("openssl")
+            .args([
+                "ecparam",
+                "-name",
+                "prime256v1",
+                "-genkey",
+                "-noout",
+                "-out",
+            ])
+            .arg(&private)
+            .stderr(process::Stdio::null())
+            .status()
+            .ok()?
+            .success()
+            && Command::new("openssl")
+                .args(["ec", "-pubout", "-in"])
+                .arg(&private)
+                .arg("-out")
+                .arg(&public)
+                .stderr(process::Stdio::null())
+                .status()
+                .ok()?
+                .success();
+        generated.then_some((private, public))
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut snapshot = snapshot();
+        snapshot.signature = Some("00ff".to_string());
+        let scratch = Scratch::new().unwrap();
+        let path = scratch.file("snapshot.json");
+        fs::write(&path, snapshot.to_json().unwrap()).unwrap();
+
+        let loaded = Snapshot::load(&path).unwrap();
+        assert_eq!(loaded.payload().unwrap(), snapshot.payload().unwrap());
+        assert_eq!(loaded.signature.as_deref(), Some("00ff"));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("00ff7a").unwrap(), [0x00, 0xff, 0x7a]);
+        assert_eq!(decode_hex("").unwrap(), Vec::<u8>::new());
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert!(decode_hex("+1").is_err());
+        // two bytes, but not two hex digits
+        assert!(decode_hex("é").is_err());
+    }
+
+    #[test]
+    fn test_tampered_snapshot_fails_verification() {
+        let scratch = Scratch::new().unwrap();
+        let Some((private, public)) = key_pair(&scratch) else {
+            eprintln!("skipping: openssl cannot generate a key");
+            return;
+        };
+        let mut snapshot = snapshot();
+        snapshot.sign(&private).unwrap();
+        snapshot.verify(&public).unwrap();
+
+        let mut tampered = snapshot.clone();
+        tampered.tethers[0].grace_secs = 60;
+        assert_eq!(
+            tampered.verify(&public).unwrap_err(),
+            "snapshot signature does not match snapshot_trusted_key"
+        );
+
+        let mut unsigned = snapshot;
+        unsigned.signature = None;
+        assert_eq!(
+            unsigned.verify(&public).unwrap_err(),
+            "snapshot is not signed"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+
+/// Systemd units a `systemd:` action stops, e.g.
+/// `systemd:stop:sshd.service,postgresql.service`. `mask` also runtime-masks
+/// them so nothing can start them again, and a `+restart` suffix on the verb
+/// undoes it all once the device is back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnitAction {
+    pub mask: bool,
+    pub restart: bool,
+    pub units: Vec<String>,
+}
+
+impl UnitAction {
+    /// Stop (and mask) every unit, trying all of them even after a failure.
+    pub fn stop(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for unit in &self.units {
+            // mask first so socket or path activation can't restart it
+            if self.mask
+                && let Err(err) = manager(&["MaskUnitFiles", "asbb", "1", unit, "true", "true"])
+            {
+                errors.push(format!("{unit}: {err}"));
+            }
+            match manager(&["StopUnit", "ss", unit, "replace"]) {
+                Ok(()) => info!(unit = %unit, masked = self.mask, "stopped unit"),
+                Err(err) => {
+                    warn!(unit = %unit, error = %err, "failed to stop unit");
+                    errors.push(format!("{unit}: {err}"));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "some units were not stopped ({})",
+                errors.join("; ")
+            ))
+        }
+    }
+
+    /// Dry-run steps: every unit is known to systemd.
+    pub fn dry_run(&self) -> Vec<Result<String, String>> {
+        let verb = if self.mask { "stop and mask" } else { "stop" };
+        self.units
+            .iter()
+            .map(|unit| match load_state(unit) {
+                Ok(state) if state == "loaded" => Ok(format!("would {verb} {unit}")),
+                Ok(state) => Err(format!("unit {unit} is {state}")),
+                Err(err) => Err(format!("{unit}: {err}")),
+            })
+            .collect()
+    }
+
+    /// Unmask and start the units again after the device returns.
+    pub fn restart(&self) {
+        for unit in &self.units {
+            if self.mask
+                && let Err(err) = manager(&["UnmaskUnitFiles", "asb", "1", unit, "true"])
+            {
+                warn!(unit = %unit, error = %err, "failed to unmask unit");
+            }
+            match manager(&["StartUnit", "ss", unit, "replace"]) {
+                Ok(()) => info!(unit = %unit, "restarted unit"),
+                Err(err) => warn!(unit = %unit, error = %err, "failed to restart unit"),
+            }
+        }
+    }
+}
+
+impl FromStr for UnitAction {
+    type Err = String;
+
+    /// Parse what follows `systemd:`: a verb, then the units separated by
+    /// commas or, when written in the config file, spaces.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (verb, units) = value
+            .split_once([':', ' '])
+            .ok_or_else(|| format!("systemd action needs units: systemd:{value}"))?;
+        let (verb, restart) = match verb.strip_suffix("+restart") {
+            Some(verb) => (verb, true),
+            None => (verb, false),
+        };
+        let mask = match verb {
+            "stop" => false,
+            "mask" => true,
+            other => return Err(format!("unknown systemd verb {other}; use stop or mask")),
+        };
+        let units: Vec<String> = units
+            .split([',', ' '])
+            .filter(|unit| !unit.is_empty())
+            .map(str::to_string)
+            .collect();
+        if units.is_empty() {
+            return Err(format!("systemd action needs units: systemd:{value}"));
+        }
+        // unit names are passed to busctl as arguments, so none may pass for an option
+        if let Some(bad) = units.iter().find(|unit| {
+            unit.starts_with('-')
+                || !unit
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '@' | '_' | '.' | '-' | '\\'))
+        }) {
+            return Err(format!("invalid unit name: {bad}"));
+        }
+
+        Ok(Self {
+            mask,
+            restart,
+            units,
+        })
+    }
+}
+
+impl fmt::Display for UnitAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.mask { "mask" } else { "stop" };
+        let restart = if self.restart { "+restart" } else { "" };
+        write!(f, "{verb}{restart}:{}", self.units.join(","))
+    }
+}
+
+/// A unit's `LoadState`, e.g. `loaded` or `not-found`.
+fn load_state(unit: &str) -> Result<String, String> {
+    let mut command = Command::new("systemctl");
+    if deadman_ipc::user_mode() {
+        command.arg("--user");
+    }
+    let output = command::output(
+        command.args(["show", "--property=LoadState", "--value", unit]),
+        HELPER_TIMEOUT,
+    )?;
+    if !output.status.success() {
+        return Err(format!("systemctl exited with status {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Call a method on systemd's manager, on the user bus for a user daemon.
+fn manager(args: &[&str]) -> Result<(), String> {
+    let mut command = Command::new("busctl");
+    if deadman_ipc::user_mode() {
+        command.arg("--user");
+    }
+    command::run(
+        command
+            .args([
+                "call",
+                "org.freedesktop.systemd1",
+                "/org/freedesktop/systemd1",
+                "org.freedesktop.systemd1.Manager",
+            ])
+            .args(args),
+        HELPER_TIMEOUT,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_action_forms() {
+        let action: UnitAction = "stop:sshd.service,postgresql.service".parse().unwrap();
+        assert_eq!(
+            action,
+            UnitAction {
+                mask: false,
+                restart: false,
+                units: vec!["sshd.service".to_string(), "postgresql.service".to_string()],
+            }
+        );
+        assert_eq!(action.to_string(), "stop:sshd.service,postgresql.service");
+
+        let action: UnitAction = "mask+restart getty@tty1.service".parse().unwrap();
+        assert!(action.mask && action.restart);
+        assert_eq!(action.to_string(), "mask+restart:getty@tty1.service");
+    }
+
+    #[test]
+    fn test_unit_action_edge_separators() {
+        let action: UnitAction = "stop:a.service,,b.service c.service,".parse().unwrap();
+        assert_eq!(action.units, ["a.service", "b.service", "c.service"]);
+    }
+
+    #[test]
+    fn test_unit_action_rejects_malformed() {
+        for malformed in [
+            "",
+            "stop",
+            "stop:",
+            "stop:,",
+            "restart:sshd.service",
+            "+restart:sshd.service",
+            "stop:--now",
+            "stop:sshd.service;reboot",
+            "stop:/etc/passwd",
+        ] {
+            assert!(
+                malformed.parse::<UnitAction>().is_err(),
+                "{malformed:?} should not parse"
+            );
+        }
+    }
+}
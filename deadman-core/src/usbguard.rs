@@ -0,0 +1,215 @@
+use std::process::Command;
+
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+
+/// Daemon parameters set to `block` while the tethered device is away, so
+/// nothing plugged in meanwhile is authorized, whatever the rules say.
+const PARAMETERS: [&str; 2] = ["InsertedDevicePolicy", "ImplicitPolicyTarget"];
+
+/// `applyDevicePolicy` target that authorizes a device.
+const TARGET_ALLOW: &str = "0";
+
+/// USBGuard's block-all policy, in force until dropped, when the daemon's
+/// previous parameters come back.
+pub struct Block {
+    previous: Vec<(&'static str, String)>,
+}
+
+impl Block {
+    /// Switch usbguard to blocking every newly inserted device.
+    pub fn start() -> Result<Self, String> {
+        let mut block = Self {
+            previous: Vec::new(),
+        };
+        for parameter in PARAMETERS {
+            // on failure, dropping `block` restores what was already changed
+            let previous = set_parameter(parameter, "block")?;
+            block.previous.push((parameter, previous));
+        }
+        info!("usbguard is blocking newly inserted devices");
+        Ok(block)
+    }
+
+    /// Authorize the tethered device again, which usbguard blocked when it
+    /// returned. Matching on the serial too keeps a lookalike blocked.
+    pub fn allow(&self, vendor_id: u16, product_id: u16, serial: Option<&str>) {
+        let devices = match list_devices(&blocked_query(vendor_id, product_id, serial)) {
+            Ok(devices) => devices,
+            Err(err) => {
+                warn!(error = %err, "failed to list devices blocked by usbguard");
+                return;
+            }
+        };
+        for id in devices {
+            let allowed = command::run(
+                Command::new("busctl").args([
+                    "call",
+                    "org.usbguard1",
+                    "/org/usbguard1/Devices",
+                    "org.usbguard.Devices1",
+                    "applyDevicePolicy",
+                    "uub",
+                    &id.to_string(),
+                    TARGET_ALLOW,
+                    "false",
+                ]),
+                HELPER_TIMEOUT,
+            );
+            match allowed {
+                Ok(()) => info!(usbguard_id = id, "allowed the returned device in usbguard"),
+                Err(err) => {
+                    warn!(usbguard_id = id, error = %err, "failed to allow the returned device")
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Block {
+    fn drop(&mut self) {
+        for (parameter, value) in self.previous.iter().rev() {
+            if let Err(err) = set_parameter(parameter, value) {
+                warn!(parameter, error = %err, "failed to restore usbguard parameter");
+            }
+        }
+        info!("restored the usbguard policy");
+    }
+}
+
+/// The rule matching the blocked devices with these IDs and serial.
+fn blocked_query(vendor_id: u16, product_id: u16, serial: Option<&str>) -> String {
+    let mut query = format!("block id {vendor_id:04x}:{product_id:04x}");
+    if let Some(serial) = serial {
+        let escaped = serial.replace('\\', "\\\\").replace('"', "\\\"");
+        query.push_str(&format!(" serial \"{escaped}\""));
+    }
+    query
+}
+
+/// What enabling `usbguard_block` would do, for dry runs.
+pub fn dry_run() -> Result<String, String> {
+    busctl(&[
+        "call",
+        "org.usbguard1",
+        "/org/usbguard1",
+        "org.usbguard1",
+        "getParameter",
+        "s",
+        PARAMETERS[0],
+    ])
+    .map(|_| "would switch usbguard to blocking new devices".to_string())
+    .map_err(|err| format!("usbguard is not reachable: {err}"))
+}
+
+/// Set a usbguard daemon parameter, returning its previous value.
+fn set_parameter(parameter: &str, value: &str) -> Result<String, String> {
+    let reply = busctl(&[
+        "call",
+        "org.usbguard1",
+        "/org/usbguard1",
+        "org.usbguard1",
+        "setParameter",
+        "ss",
+        parameter,
+        value,
+    ])?;
+    reply["data"][0]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("unexpected reply to setParameter {parameter}: {reply}"))
+}
+
+/// IDs of the devices matching the rule `query`.
+fn list_devices(query: &str) -> Result<Vec<u32>, String> {
+    let reply = busctl(&[
+        "call",
+        "org.usbguard1",
+        "/org/usbguard1/Devices",
+        "org.usbguard.Devices1",
+        "listDevices",
+        "s",
+        query,
+    ])?;
+    device_ids(&reply)
+}
+
+/// The device IDs in busctl's JSON for a `listDevices` reply, an array of
+/// `(id, rule)` pairs.
+fn device_ids(reply: &Value) -> Result<Vec<u32>, String> {
+    let devices = reply["data"][0]
+        .as_array()
+        .ok_or_else(|| format!("unexpected reply to listDevices: {reply}"))?;
+    Ok(devices
+        .iter()
+        .filter_map(|device| device[0].as_u64())
+        .filter_map(|id| u32::try_from(id).ok())
+        .collect())
+}
+
+fn busctl(args: &[&str]) -> Result<Value, String> {
+    let output = command::output(
+        Command::new("busctl").arg("--json=short").args(args),
+        HELPER_TIMEOUT,
+    )?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "busctl exited with status {}: {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|err| format!("failed to parse busctl output: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_blocked_query() {
+        assert_eq!(blocked_query(0x1050, 0x0407, None), "block id 1050:0407");
+        assert_eq!(
+            blocked_query(0x1050, 0x0407, Some("0001")),
+            "block id 1050:0407 serial \"0001\""
+        );
+    }
+
+    #[test]
+    fn test_blocked_query_escapes_the_serial() {
+        assert_eq!(
+            blocked_query(0x1050, 0x0407, Some("a\"b\\")),
+            "block id 1050:0407 serial \"a\\\"b\\\\\""
+        );
+    }
+
+    #[test]
+    fn test_device_ids() {
+        let reply = json!({
+            "type": "a(us)",
+            "data": [[[12, "block id 1050:0407"], [13, "block id 1050:0407"]]],
+        });
+        assert_eq!(device_ids(&reply), Ok(vec![12, 13]));
+        assert_eq!(device_ids(&json!({ "data": [[]] })), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_device_ids_skips_malformed_entries() {
+        let reply = json!({
+            "data": [[[-1, "r"], ["12", "r"], [4294967296u64, "r"], [], [7, "r"]]],
+        });
+        assert_eq!(device_ids(&reply), Ok(vec![7]));
+    }
+
+    #[test]
+    fn test_device_ids_rejects_unexpected_replies() {
+        for reply in [json!({}), json!({ "data": [] }), json!({ "data": ["x"] })] {
+            assert!(device_ids(&reply).is_err(), "{reply} was accepted");
+        }
+    }
+}
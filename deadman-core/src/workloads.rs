@@ -0,0 +1,449 @@
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::command::{self, HELPER_TIMEOUT};
+
+/// Seconds a container gets to stop before the engine kills it.
+const STOP_GRACE_SECS: u64 = 10;
+
+/// How long a request to the container engine may take; a stop waits for
+/// the container's own grace period.
+const ENGINE_TIMEOUT: Duration = Duration::from_secs(STOP_GRACE_SECS + 10);
+
+/// Libvirt domains a `vm:` action pauses, e.g. `vm:pause:win11,build`. A
+/// `+resume` suffix on the verb resumes them once the device is back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmAction {
+    pub resume: bool,
+    pub domains: Vec<String>,
+}
+
+impl VmAction {
+    /// Pause every domain, trying all of them even after a failure.
+    pub fn pause(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for domain in &self.domains {
+            match virsh(&["suspend", domain]) {
+                Ok(_) => info!(domain = %domain, "paused domain"),
+                Err(err) => {
+                    warn!(domain = %domain, error = %err, "failed to pause domain");
+                    errors.push(format!("{domain}: {err}"));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "some domains were not paused ({})",
+                errors.join("; ")
+            ))
+        }
+    }
+
+    /// Dry-run steps: every domain is defined, and in which state.
+    pub fn dry_run(&self) -> Vec<Result<String, String>> {
+        self.domains
+            .iter()
+            .map(|domain| match virsh(&["domstate", domain]) {
+                Ok(state) => Ok(format!("would pause {domain} ({})", state.trim())),
+                Err(err) => Err(format!("{domain}: {err}")),
+            })
+            .collect()
+    }
+
+    /// Resume the domains after the device returns.
+    pub fn resume(&self) {
+        for domain in &self.domains {
+            match virsh(&["resume", domain]) {
+                Ok(_) => info!(domain = %domain, "resumed domain"),
+                Err(err) => warn!(domain = %domain, error = %err, "failed to resume domain"),
+            }
+        }
+    }
+}
+
+impl FromStr for VmAction {
+    type Err = String;
+
+    /// Parse what follows `vm:`: a verb, then the domains separated by
+    /// commas or, when written in the config file, spaces.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (verb, resume, domains) = parse_targets("vm", "+resume", value)?;
+        if verb != "pause" {
+            return Err(format!("unknown vm verb {verb}; use pause"));
+        }
+        Ok(Self { resume, domains })
+    }
+}
+
+impl fmt::Display for VmAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resume = if self.resume { "+resume" } else { "" };
+        write!(f, "pause{resume}:{}", self.domains.join(","))
+    }
+}
+
+/// Docker or Podman containers a `container:` action stops or pauses, e.g.
+/// `container:stop:vault,db`. A `+start` suffix on the verb starts or
+/// unpauses them again once the device is back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerAction {
+    pub pause: bool,
+    pub start: bool,
+    pub containers: Vec<String>,
+}
+
+impl ContainerAction {
+    /// Stop (or pause) every container, trying all of them even after a
+    /// failure.
+    pub fn stop(&self) -> Result<(), String> {
+        let socket = engine_socket()?;
+        let mut errors = Vec::new();
+        for container in &self.containers {
+            let path = if self.pause {
+                format!("/containers/{container}/pause")
+            } else {
+                format!("/containers/{container}/stop?t={STOP_GRACE_SECS}")
+            };
+            match engine(&socket, "POST", &path) {
+                // 304: already stopped
+                Ok(204 | 304) => {
+                    info!(container = %container, paused = self.pause, "stopped container");
+                }
+                Ok(status) => {
+                    let err = engine_error(status);
+                    warn!(container = %container, error = %err, "failed to stop container");
+                    errors.push(format!("{container}: {err}"));
+                }
+                Err(err) => {
+                    warn!(container = %container, error = %err, "failed to stop container");
+                    errors.push(format!("{container}: {err}"));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "some containers were not stopped ({})",
+                errors.join("; ")
+            ))
+        }
+    }
+
+    /// Whether there is a container engine to ask.
+    pub fn check(&self) -> Result<(), String> {
+        engine_socket().map(|_| ())
+    }
+
+    /// Dry-run steps: the engine answers, and knows every container.
+    pub fn dry_run(&self) -> Vec<Result<String, String>> {
+        let socket = match engine_socket() {
+            Ok(socket) => socket,
+            Err(err) => return vec![Err(err)],
+        };
+        let verb = if self.pause { "pause" } else { "stop" };
+        self.containers
+            .iter()
+            .map(|container| {
+                match engine(&socket, "GET", &format!("/containers/{container}/json")) {
+                    Ok(200) => Ok(format!("would {verb} {container} through {socket}")),
+                    Ok(status) => Err(format!("{container}: {}", engine_error(status))),
+                    Err(err) => Err(format!("{container}: {err}")),
+                }
+            })
+            .collect()
+    }
+
+    /// Start or unpause the containers after the device returns.
+    pub fn start(&self) {
+        let socket = match engine_socket() {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!(error = %err, "cannot start containers again");
+                return;
+            }
+        };
+        let verb = if self.pause { "unpause" } else { "start" };
+        for container in &self.containers {
+            match engine(&socket, "POST", &format!("/containers/{container}/{verb}")) {
+                Ok(204 | 304) => info!(container = %container, "started container"),
+                Ok(status) => {
+                    warn!(container = %container, error = %engine_error(status), "failed to start container");
+                }
+                Err(err) => {
+                    warn!(container = %container, error = %err, "failed to start container")
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ContainerAction {
+    type Err = String;
+
+    /// Parse what follows `container:`: a verb, then the containers
+    /// separated by commas or, when written in the config file, spaces.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (verb, start, containers) = parse_targets("container", "+start", value)?;
+        let pause = match verb {
+            "stop" => false,
+            "pause" => true,
+            other => return Err(format!("unknown container verb {other}; use stop or pause")),
+        };
+        // names go into request paths as they are
+        if let Some(bad) = containers.iter().find(|name| {
+            !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        }) {
+            return Err(format!("invalid container name or id: {bad}"));
+        }
+        Ok(Self {
+            pause,
+            start,
+            containers,
+        })
+    }
+}
+
+impl fmt::Display for ContainerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.pause { "pause" } else { "stop" };
+        let start = if self.start { "+start" } else { "" };
+        write!(f, "{verb}{start}:{}", self.containers.join(","))
+    }
+}
+
+/// Split `verb[undo]:target,...` into the verb, whether `undo` was given,
+/// and the targets.
+fn parse_targets<'a>(
+    kind: &str,
+    undo: &str,
+    value: &'a str,
+) -> Result<(&'a str, bool, Vec<String>), String> {
+    let (verb, targets) = value
+        .split_once([':', ' '])
+        .ok_or_else(|| format!("{kind} action needs targets: {kind}:{value}"))?;
+    let (verb, undone) = match verb.strip_suffix(undo) {
+        Some(verb) => (verb, true),
+        None => (verb, false),
+    };
+    let targets: Vec<String> = targets
+        .split([',', ' '])
+        .filter(|target| !target.is_empty())
+        .map(str::to_string)
+        .collect();
+    if targets.is_empty() {
+        return Err(format!("{kind} action needs targets: {kind}:{value}"));
+    }
+    Ok((verb, undone, targets))
+}
+
+/// Run `virsh` against the system libvirt, or the session one for a user
+/// daemon, returning its output.
+fn virsh(args: &[&str]) -> Result<String, String> {
+    let uri = if deadman_ipc::user_mode() {
+        "qemu:///session"
+    } else {
+        "qemu:///system"
+    };
+    let output = command::output(
+        Command::new("virsh").args(["--connect", uri]).args(args),
+        HELPER_TIMEOUT,
+    )?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!(
+            "virsh exited with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn engine_error(status: u16) -> String {
+    match status {
+        404 => "no such container".to_string(),
+        409 => "container is not in a state to do that".to_string(),
+        status => format!("container engine answered {status}"),
+    }
+}
+
+/// The Docker API socket: `DOCKER_HOST` when it names one, else the first
+/// of Docker's and Podman's usual sockets that exists.
+#[cfg(unix)]
+fn engine_socket() -> Result<String, String> {
+    if let Some(socket) = std::env::var("DOCKER_HOST")
+        .ok()
+        .and_then(|host| host.strip_prefix("unix://").map(str::to_string))
+    {
+        return Ok(socket);
+    }
+
+    let candidates: Vec<String> = if deadman_ipc::user_mode() {
+        let runtime = std::env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+        vec![
+            format!("{runtime}/docker.sock"),
+            format!("{runtime}/podman/podman.sock"),
+        ]
+    } else {
+        vec![
+            "/var/run/docker.sock".to_string(),
+            "/run/podman/podman.sock".to_string(),
+        ]
+    };
+    candidates
+        .into_iter()
+        .find(|socket| std::path::Path::new(socket).exists())
+        .ok_or_else(|| "no Docker or Podman API socket found".to_string())
+}
+
+#[cfg(not(unix))]
+fn engine_socket() -> Result<String, String> {
+    Err("container actions need the Docker or Podman API socket".to_string())
+}
+
+/// Send a bodiless request over the engine's socket and return the status.
+#[cfg(unix)]
+fn engine(socket: &str, method: &str, path: &str) -> Result<u16, String> {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket).map_err(|err| format!("{socket}: {err}"))?;
+    stream
+        .set_read_timeout(Some(ENGINE_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(ENGINE_TIMEOUT)))
+        .map_err(|err| err.to_string())?;
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    )
+    .map_err(|err| err.to_string())?;
+
+    let mut status = [0u8; 12];
+    stream
+        .read_exact(&mut status)
+        .map_err(|err| err.to_string())?;
+    status_code(&status).ok_or_else(|| "malformed answer from the container engine".to_string())
+}
+
+/// The status code from the start of an HTTP/1.x status line, e.g.
+/// `HTTP/1.1 204`.
+fn status_code(line: &[u8]) -> Option<u16> {
+    let code = line
+        .strip_prefix(b"HTTP/1.")?
+        .get(1..)?
+        .strip_prefix(b" ")?
+        .get(..3)?;
+    if !code.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(code).ok()?.parse().ok()
+}
+
+#[cfg(not(unix))]
+fn engine(_socket: &str, _method: &str, _path: &str) -> Result<u16, String> {
+    engine_socket().map(|_| 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vm_action_forms() {
+        let action: VmAction = "pause:win11,build".parse().unwrap();
+        assert_eq!(
+            action,
+            VmAction {
+                resume: false,
+                domains: vec!["win11".to_string(), "build".to_string()],
+            }
+        );
+        assert_eq!(action.to_string(), "pause:win11,build");
+
+        // the config file may separate with spaces
+        let action: VmAction = "pause+resume win11 build".parse().unwrap();
+        assert!(action.resume);
+        assert_eq!(action.to_string(), "pause+resume:win11,build");
+    }
+
+    #[test]
+    fn test_vm_action_rejects_malformed() {
+        for malformed in [
+            "",
+            "pause",
+            "pause:",
+            "pause:,",
+            "stop:win11",
+            "+resume:win11",
+        ] {
+            assert!(
+                malformed.parse::<VmAction>().is_err(),
+                "{malformed:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_container_action_forms() {
+        let action: ContainerAction = "stop:vault,db".parse().unwrap();
+        assert_eq!(
+            action,
+            ContainerAction {
+                pause: false,
+                start: false,
+                containers: vec!["vault".to_string(), "db".to_string()],
+            }
+        );
+        assert_eq!(action.to_string(), "stop:vault,db");
+
+        let action: ContainerAction = "pause+start:my_app.1-a".parse().unwrap();
+        assert!(action.pause && action.start);
+        assert_eq!(action.to_string(), "pause+start:my_app.1-a");
+    }
+
+    #[test]
+    fn test_container_action_rejects_malformed() {
+        for malformed in [
+            "",
+            "stop",
+            "stop:",
+            "kill:vault",
+            "stop:../images",
+            "stop:a/b",
+            "stop:vault?force=1",
+        ] {
+            assert!(
+                malformed.parse::<ContainerAction>().is_err(),
+                "{malformed:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(status_code(b"HTTP/1.1 204"), Some(204));
+        assert_eq!(status_code(b"HTTP/1.0 404 Not Found"), Some(404));
+        for malformed in [
+            &b""[..],
+            b"HTTP/1.1",
+            b"HTTP/1.1 20",
+            b"HTTP/1.1 +04",
+            b"HTTP/1.1  204",
+            b"HTTP/2 204 x",
+            b"garbage here",
+        ] {
+            assert_eq!(status_code(malformed), None, "{malformed:?}");
+        }
+    }
+}
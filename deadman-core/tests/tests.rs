@@ -0,0 +1,77 @@
+use deadman_core::{Lifecycle, Phase};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[test]
+fn test_cleared_tether_never_triggers() {
+    for _ in 0..500 {
+        let lifecycle = Lifecycle::default();
+        lifecycle.departed();
+        let start = Arc::new(Barrier::new(3));
+
+        // the device bouncing on the bus while severe and the countdown race
+        let hotplug = thread::spawn({
+            let lifecycle = lifecycle.clone();
+            let start = Arc::clone(&start);
+            move || {
+                start.wait();
+                for _ in 0..50 {
+                    lifecycle.arrived();
+                    lifecycle.departed();
+                }
+            }
+        });
+        let monitor = thread::spawn({
+            let lifecycle = lifecycle.clone();
+            let start = Arc::clone(&start);
+            move || {
+                start.wait();
+                lifecycle.trigger() == Phase::Triggered
+            }
+        });
+        start.wait();
+        let before = lifecycle.clear();
+
+        let triggered = monitor.join().unwrap();
+        hotplug.join().unwrap();
+        assert_eq!(
+            triggered,
+            matches!(before, Phase::Triggered | Phase::Rearmed),
+            "the action ran after its tether was cleared"
+        );
+        assert_eq!(lifecycle.phase(), Phase::Cleared);
+        assert_eq!(lifecycle.trigger(), Phase::Cleared);
+    }
+}
+
+#[test]
+fn test_reattach_ends_grace_period() {
+    let lifecycle = Lifecycle::default();
+    lifecycle.departed();
+    assert_eq!(lifecycle.phase(), Phase::GracePeriod);
+    lifecycle.arrived();
+    assert_eq!(lifecycle.phase(), Phase::Armed);
+
+    lifecycle.departed();
+    assert_eq!(lifecycle.trigger(), Phase::Triggered);
+    lifecycle.arrived();
+    assert_eq!(lifecycle.phase(), Phase::Rearmed);
+}
+
+#[test]
+fn test_return_during_hook_stops_trigger() {
+    let lifecycle = Lifecycle::default();
+    lifecycle.departed();
+    // the grace period is over and the pre-trigger hook is still deciding
+    lifecycle.arrived();
+    assert_eq!(lifecycle.trigger(), Phase::Armed);
+    assert_eq!(lifecycle.phase(), Phase::Armed);
+
+    // likewise for a tether that already fired and was waiting again
+    lifecycle.departed();
+    assert_eq!(lifecycle.trigger(), Phase::Triggered);
+    lifecycle.arrived();
+    lifecycle.departed();
+    lifecycle.arrived();
+    assert_eq!(lifecycle.trigger(), Phase::Rearmed);
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use adw::prelude::*;
+use adw::{gio, glib};
+use libadwaita as adw;
+use tracing::info;
+
+use deadman_ipc::i18n::gettext;
+
+/// Whether the GUI runs inside a Flatpak sandbox, where the host's pkexec,
+/// sudo and `deadman` are out of reach and autostart goes through a portal.
+pub fn sandboxed() -> bool {
+    static SANDBOXED: OnceLock<bool> = OnceLock::new();
+    *SANDBOXED.get_or_init(|| Path::new("/.flatpak-info").exists())
+}
+
+/// Ask the Background portal to let the GUI keep running with its window
+/// closed and, with `autostart`, to start it hidden on login. The portal may
+/// ask the user; its answer arrives later and is only logged.
+pub async fn request_background(autostart: bool) {
+    let options = glib::VariantDict::new(None);
+    options.insert(
+        "reason",
+        gettext("Keep watching for removals with the window closed"),
+    );
+    options.insert("autostart", autostart);
+    options.insert("commandline", vec!["deadman-gui", "--background"]);
+    let parameters = glib::Variant::tuple_from_iter(["".to_variant(), options.end()]);
+
+    let reply = match gio::bus_get_future(gio::BusType::Session).await {
+        Ok(connection) => {
+            connection
+                .call_future(
+                    Some("org.freedesktop.portal.Desktop"),
+                    "/org/freedesktop/portal/desktop",
+                    "org.freedesktop.portal.Background",
+                    "RequestBackground",
+                    Some(&parameters),
+                    None,
+                    gio::DBusCallFlags::NONE,
+                    -1,
+                )
+                .await
+        }
+        Err(err) => Err(err),
+    };
+    match reply {
+        Ok(_) => info!(autostart, "requested background permission from the portal"),
+        Err(err) => info!(error=%err, "background portal request failed"),
+    }
+}
@@ -1,346 +1,1667 @@
-use adw::gio::Settings;
-use adw::glib;
+use adw::gtk::accessible::{Property, Relation};
 use adw::gtk::{
-    Application, Box, Button, Label, ListBox, MessageDialog, Orientation, ResponseType, Switch,
+    AccessibleAnnouncementPriority, AccessibleRole, Align, Application, Box, Button, DropDown,
+    Entry, Image, Label, ListBox, ListBoxRow, MenuButton, Orientation, Overlay, ScrolledWindow,
+    SearchEntry, SelectionMode, ShortcutsGroup, ShortcutsSection, ShortcutsShortcut,
+    ShortcutsWindow, SpinButton, Switch, Widget,
 };
 use adw::prelude::*;
-use adw::{ActionRow, ApplicationWindow};
+use adw::{gio, glib};
+use adw::{
+    ActionRow, AlertDialog, ApplicationWindow, Banner, ExpanderRow, HeaderBar, ResponseAppearance,
+    StatusPage, Toast, ToastOverlay, ToolbarView, ViewStack, ViewSwitcher, ViewSwitcherPolicy,
+};
 use libadwaita as adw;
-use rusb::Context;
-use rusb::UsbContext;
 use tracing::{info, Level};
 
 const APP_ID: &str = "com.dominicegginton.deadman";
-const APP_NAME: &str = "Deadman";
-const APP_DESCRIPTION: &str = "";
+const PAGE_DEVICES: &str = "devices";
+const PAGE_EVENTS: &str = "events";
+const NOTIFICATION_GRACE: &str = "grace";
+/// How often the header re-reads the protection level between events, to
+/// notice a daemon that stopped or started without saying so.
+const PROTECTION_REFRESH_SECS: u32 = 30;
+/// How long the started service has to answer on its socket.
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(10);
+
+mod flatpak;
+mod preferences;
+mod search_provider;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
 use std::io;
-use std::process::Command;
+use std::ops::ControlFlow;
+use std::process::{Command, Output};
 use std::rc::Rc;
+use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
+
+use deadman_ipc::async_client;
+use deadman_ipc::client::{self, TetherOptions};
+use deadman_ipc::devices::{self, class_name};
+use deadman_ipc::i18n::{gettext, gettext_f, ngettext_f};
 
-use deadman_ipc::client;
+use crate::preferences::GuiSettings;
+use deadman_ipc::events::{Event, EventKind};
+use deadman_ipc::testing::MockDaemon;
 
 fn main() {
+    deadman_ipc::i18n::init();
     tracing_subscriber::fmt()
         .with_max_level(Level::INFO)
         .with_thread_ids(true)
         .init();
 
+    let _mock = start_mock_daemon();
+
     let application = Application::builder().application_id(APP_ID).build();
 
-    application.connect_startup(|_| {
+    application.add_main_option(
+        "background",
+        glib::Char::from(b'b'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        &gettext("Start hidden, watching for removals in the background"),
+        None,
+    );
+    application.add_main_option(
+        "instance",
+        glib::Char::from(b'i'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        &gettext("Talk to the named daemon instance instead of the default one"),
+        Some(&gettext("NAME")),
+    );
+    application.add_main_option(
+        "user",
+        glib::Char::from(b'u'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        &gettext("Talk to the daemon of this login session (deadmand --user)"),
+        None,
+    );
+
+    let start_hidden = Rc::new(Cell::new(false));
+    let start_hidden_for_options = Rc::clone(&start_hidden);
+    application.connect_handle_local_options(move |_, options| {
+        if options.contains("background") {
+            start_hidden_for_options.set(true);
+        }
+        // a sandbox cannot see the system daemon's socket under /tmp, only
+        // the user daemon's in $XDG_RUNTIME_DIR
+        if options.contains("user") || flatpak::sandboxed() {
+            deadman_ipc::set_user_mode();
+        }
+        if let Ok(Some(name)) = options.lookup::<String>("instance") {
+            if let Err(err) = deadman_ipc::set_instance(&name) {
+                eprintln!("{err}");
+                return ControlFlow::Break(glib::ExitCode::FAILURE);
+            }
+        }
+        ControlFlow::Continue(())
+    });
+
+    application.connect_startup(|app| {
         adw::init().expect("Failed to initialize libadwaita");
+        search_provider::register(app);
     });
 
-    application.connect_activate(|app| {
-        // helper to show a modal error dialog
-        let show_error = move |parent: &adw::gtk::Window, text: &str| {
-            let dialog = MessageDialog::builder()
-                .text(text)
-                .modal(true)
-                .build();
-            dialog.set_transient_for(Some(parent));
-            dialog.show();
+    application.connect_activate(move |app| {
+        // a second launch just brings back the (possibly hidden) existing window
+        if let Some(window) = app.windows().first() {
+            window.present();
+            return;
+        }
+        // failures are shown inline in a banner, successes as toasts
+        let feedback = Feedback {
+            toasts: ToastOverlay::new(),
+            banner: Banner::builder().button_label(gettext("Dismiss")).build(),
         };
-        let app_for_click = app.clone();
-        // single device list UI. status is reflected by each device row background.
+        feedback
+            .banner
+            .connect_button_clicked(|banner| banner.set_revealed(false));
+
+        // single device list UI. status is reflected by each device row tether button.
         let list = ListBox::builder()
             .margin_top(32)
             .margin_end(32)
             .margin_bottom(32)
             .margin_start(32)
+            .selection_mode(SelectionMode::None)
             .build();
 
-        let devices_container = Box::new(Orientation::Vertical, 6);
-
         // Severe button: clears active tethers (requires privilege)
-        let btn_severe = Button::with_label("Severe");
-        list.append(&btn_severe);
+        let btn_severe = Button::with_mnemonic(&gettext("_Severe"));
+        btn_severe.update_property(&[
+            Property::Label(&gettext("Clear all tethers")),
+            Property::Description(&gettext(
+                "Asks for confirmation, then stops every tether protecting this machine",
+            )),
+        ]);
+        append_plain_row(&list, &btn_severe);
+
+        // filter bar: free-text search plus a toggle between the hub tree and
+        // a flat list without hubs
+        let filter_bar = Box::new(Orientation::Horizontal, 12);
+        let search = SearchEntry::builder()
+            .placeholder_text(gettext("Filter by name, VID:PID or serial"))
+            .hexpand(true)
+            .build();
+        search.update_property(&[Property::Label(&gettext("Filter devices"))]);
+        let grouped = Switch::builder().active(true).valign(Align::Center).build();
+        let grouped_label = Label::new(Some(&gettext("Group by port")));
+        grouped.update_relation(&[Relation::LabelledBy(&[grouped_label.upcast_ref()])]);
+        filter_bar.append(&search);
+        filter_bar.append(&grouped_label);
+        filter_bar.append(&grouped);
+        append_plain_row(&list, &filter_bar);
+
+        let devices = DeviceList::new(&feedback, &search, &grouped);
+        append_plain_row(&list, &devices.container);
+        devices.reload();
+
+        // severe button handler: ask for confirmation, then call IPC (with elevation fallback)
+        let feedback_for_severe = feedback.clone();
+        btn_severe.connect_clicked(move |btn| {
+            let dialog = AlertDialog::new(
+                Some(&gettext("Clear all tethers?")),
+                Some(&gettext(
+                    "Every tethered device will stop protecting this machine.",
+                )),
+            );
+            dialog.add_responses(&[
+                ("cancel", &gettext("Cancel")),
+                ("severe", &gettext("Clear Tethers")),
+            ]);
+            dialog.set_response_appearance("severe", ResponseAppearance::Destructive);
+            dialog.set_default_response(Some("cancel"));
+            dialog.set_close_response("cancel");
+
+            let feedback = feedback_for_severe.clone();
+            dialog.connect_response(Some("severe"), move |_, _| {
+                let feedback = feedback.clone();
+                glib::spawn_future_local(async move {
+                    let result =
+                        call_daemon("severe", &["severe", "--yes"], async_client::severe()).await;
+                    feedback.report(result);
+                });
+            });
+            dialog.present(Some(btn));
+        });
+
+        // devices and the daemon event log live on separate pages
+        let stack = ViewStack::new();
+        stack.add_titled_with_icon(
+            &list,
+            Some(PAGE_DEVICES),
+            &gettext("Devices"),
+            "drive-removable-media-symbolic",
+        );
+        let event_log = EventLog::new(&feedback);
+        let event_log_for_refresh = Rc::clone(&event_log);
+        stack.add_titled_with_icon(
+            &event_log.root,
+            Some(PAGE_EVENTS),
+            &gettext("Events"),
+            "document-open-recent-symbolic",
+        );
+        stack.connect_visible_child_name_notify(move |stack| {
+            if stack.visible_child_name().as_deref() == Some(PAGE_EVENTS) {
+                event_log.reload();
+            }
+        });
+
+        let content = Box::new(Orientation::Vertical, 0);
+        content.append(&feedback.banner);
+        content.append(&stack);
+
+        // countdown overlay covers the whole window while a grace period runs
+        let overlay = Overlay::new();
+        overlay.set_child(Some(&content));
+        let countdown = CountdownOverlay::new(&feedback);
+        overlay.add_overlay(&countdown.root);
+        let shield = ProtectionShield::new();
+        watch_events(app, countdown, Rc::clone(&shield));
+
+        let switcher = ViewSwitcher::builder()
+            .stack(&stack)
+            .policy(ViewSwitcherPolicy::Wide)
+            .build();
+        let menu = gio::Menu::new();
+        menu.append(Some(&gettext("Preferences")), Some("app.preferences"));
+        menu.append(Some(&gettext("Keyboard Shortcuts")), Some("app.shortcuts"));
+        let menu_button = MenuButton::builder()
+            .icon_name("open-menu-symbolic")
+            .menu_model(&menu)
+            .build();
+        menu_button.update_property(&[Property::Label(&gettext("Main menu"))]);
+        let header = HeaderBar::builder().title_widget(&switcher).build();
+        header.pack_end(&menu_button);
+        header.pack_start(&shield.root);
+
+        let toolbar = ToolbarView::new();
+        toolbar.add_top_bar(&header);
+        toolbar.set_content(Some(&overlay));
+
+        feedback.toasts.set_child(Some(&toolbar));
+
+        // in background mode closing the window only hides it, so the event
+        // stream and notifications stay alive
+        let settings = GuiSettings::load();
+        let window = ApplicationWindow::builder()
+            .application(app)
+            .content(&feedback.toasts)
+            .hide_on_close(settings.run_in_background || start_hidden.get())
+            .build();
+
+        let preferences = gio::SimpleAction::new("preferences", None);
+        let window_for_preferences = window.clone();
+        let feedback_for_preferences = feedback.clone();
+        preferences.connect_activate(move |_, _| {
+            let window = window_for_preferences.clone();
+            preferences::show_preferences(
+                &window_for_preferences,
+                &feedback_for_preferences,
+                move |background| window.set_hide_on_close(background),
+            );
+        });
+        app.add_action(&preferences);
+
+        // notification button target for cancelling a countdown while hidden
+        let cancel = gio::SimpleAction::new("cancel", None);
+        let feedback_for_cancel = feedback.clone();
+        cancel.connect_activate(move |_, _| {
+            let feedback = feedback_for_cancel.clone();
+            glib::spawn_future_local(async move {
+                feedback.report(call_daemon("cancel", &["cancel"], async_client::cancel()).await);
+            });
+        });
+        app.add_action(&cancel);
+
+        // Ctrl+R reloads whichever page is showing
+        let refresh = gio::SimpleAction::new("refresh", None);
+        let devices_for_refresh = Rc::clone(&devices);
+        refresh.connect_activate(move |_, _| {
+            if stack.visible_child_name().as_deref() == Some(PAGE_EVENTS) {
+                event_log_for_refresh.reload();
+            } else {
+                devices_for_refresh.reload();
+            }
+        });
+        app.add_action(&refresh);
+
+        let tether = gio::SimpleAction::new("tether", None);
+        tether.connect_activate(move |_, _| devices.tether_selected());
+        app.add_action(&tether);
+
+        // the same confirmation as clicking the button
+        let severe = gio::SimpleAction::new("severe", None);
+        severe.connect_activate(move |_, _| btn_severe.emit_clicked());
+        app.add_action(&severe);
+
+        let shortcuts = gio::SimpleAction::new("shortcuts", None);
+        let window_for_shortcuts = window.clone();
+        shortcuts.connect_activate(move |_, _| show_shortcuts(&window_for_shortcuts));
+        app.add_action(&shortcuts);
+
+        for (action, accel, _) in shortcut_list() {
+            app.set_accels_for_action(action, &[accel]);
+        }
+
+        if !start_hidden.get() {
+            window.present();
+        }
+    });
+
+    application.run();
+}
+
+/// With `DEADMAN_MOCK=1`, serve canned responses on the `mock` instance so
+/// the window can be worked on without a root daemon.
+fn start_mock_daemon() -> Option<MockDaemon> {
+    if std::env::var("DEADMAN_MOCK").ok().as_deref() != Some("1") {
+        return None;
+    }
+    if let Err(err) = deadman_ipc::set_instance("mock") {
+        eprintln!("{err}");
+        return None;
+    }
+
+    let mock = MockDaemon::start_with_path(&deadman_ipc::socket_path());
+    mock.respond("status", "")
+        .respond("protection", "unarmed\nno devices are tethered")
+        .respond(
+            "list-devices",
+            concat!(
+                r#"{"bus":1,"address":4,"ports":[2],"vendor_id":4176,"product_id":1031,"product":"YubiKey OTP+FIDO+CCID","manufacturer":"Yubico","usb_version":"2.0.0","device_version":"5.4.3","class_code":0,"sub_class_code":0,"protocol_code":0,"classes":[3,11],"max_packet_size":64,"num_configurations":1,"speed":"Full"}"#,
+                "\n",
+                r#"{"bus":1,"address":6,"ports":[3,2],"vendor_id":1133,"product_id":50475,"usb_version":"2.0.0","device_version":"12.3.0","class_code":0,"sub_class_code":0,"protocol_code":0,"classes":[3],"max_packet_size":8,"num_configurations":1,"speed":"Full"}"#,
+                "\n",
+                r#"{"bus":1,"address":5,"ports":[3],"vendor_id":1507,"product_id":1552,"product":"USB2.1 Hub","manufacturer":"GenesysLogic","usb_version":"2.1.0","device_version":"6.63.0","class_code":9,"sub_class_code":0,"protocol_code":2,"classes":[9],"max_packet_size":64,"num_configurations":1,"speed":"High"}"#,
+                "\n",
+                r#"{"bus":1,"address":1,"vendor_id":7531,"product_id":2,"product":"xHCI Host Controller","manufacturer":"Linux Foundation","usb_version":"2.0.0","device_version":"6.1.0","class_code":9,"sub_class_code":0,"protocol_code":1,"classes":[9],"max_packet_size":64,"num_configurations":1,"speed":"High"}"#
+            ),
+        )
+        .respond("tether", "tether active (mock)")
+        .respond("cancel", "cancelled 0 pending action(s)")
+        .respond("severe", "cleared 0 tether(s)");
+    mock.emit(Event::new(EventKind::ConfigChanged));
+    info!(socket = mock.socket_path(), "serving a mock daemon");
+    Some(mock)
+}
+
+/// Where the outcome of a daemon request is shown to the user.
+#[derive(Clone)]
+struct Feedback {
+    toasts: ToastOverlay,
+    banner: Banner,
+}
+
+impl Feedback {
+    fn success(&self, message: &str) {
+        self.banner.set_revealed(false);
+        self.toasts.add_toast(Toast::new(message));
+    }
+
+    fn error(&self, message: &str) {
+        self.banner.set_title(message);
+        self.banner.set_revealed(true);
+    }
+
+    fn report(&self, result: Result<String, String>) {
+        match result {
+            Ok(message) => self.success(&message),
+            Err(message) => self.error(&message),
+        }
+    }
+}
+
+/// Descriptor details for a single attached USB device.
+#[derive(Clone)]
+struct UsbDevice {
+    bus: u8,
+    address: u8,
+    ports: Vec<u8>,
+    vendor_id: u16,
+    product_id: u16,
+    product_name: String,
+    manufacturer: Option<String>,
+    serial: Option<String>,
+    usb_version: String,
+    device_version: String,
+    class_code: u8,
+    sub_class_code: u8,
+    protocol_code: u8,
+    classes: Vec<u8>,
+    max_packet_size: u8,
+    num_configurations: u8,
+    speed: String,
+    tethered: bool,
+}
+
+/// Row icons by class, most telling first: a YubiKey's smartcard interface
+/// says more than its HID one.
+const CLASS_ICONS: &[(u8, &str)] = &[
+    (0x0b, "auth-smartcard-symbolic"),
+    (0x0d, "channel-secure-symbolic"),
+    (0x08, "drive-removable-media-symbolic"),
+    (0x09, "view-grid-symbolic"),
+    (0x0e, "camera-web-symbolic"),
+    (0x01, "audio-card-symbolic"),
+    (0x07, "printer-symbolic"),
+    (0x06, "camera-photo-symbolic"),
+    (0xe0, "network-wireless-symbolic"),
+    (0x02, "network-wired-symbolic"),
+    (0x03, "input-keyboard-symbolic"),
+];
+const DEFAULT_DEVICE_ICON: &str = "media-removable-symbolic";
+
+impl UsbDevice {
+    fn subtitle(&self) -> String {
+        let mut subtitle = format!(
+            "{} · {:04x}:{:04x}",
+            gettext_f(
+                "bus {} address {}",
+                &[&format!("{:03}", self.bus), &format!("{:03}", self.address)],
+            ),
+            self.vendor_id,
+            self.product_id
+        );
+        if let Some(serial) = &self.serial {
+            subtitle.push_str(" · ");
+            subtitle.push_str(&gettext_f("serial {}", &[serial]));
+        }
+        subtitle
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        let mut details = Vec::new();
+        if !self.ports.is_empty() {
+            let ports: Vec<String> = self.ports.iter().map(u8::to_string).collect();
+            details.push((gettext("Port"), format!("{}-{}", self.bus, ports.join("."))));
+        }
+        if let Some(manufacturer) = &self.manufacturer {
+            details.push((gettext("Manufacturer"), manufacturer.clone()));
+        }
+        if let Some(serial) = &self.serial {
+            details.push((gettext("Serial"), serial.clone()));
+        }
+        details.push((gettext("USB version"), self.usb_version.clone()));
+        details.push((gettext("Device version"), self.device_version.clone()));
+        details.push((
+            gettext("Class / subclass / protocol"),
+            format!(
+                "{:02x} / {:02x} / {:02x}",
+                self.class_code, self.sub_class_code, self.protocol_code
+            ),
+        ));
+        details.push((gettext("Max packet size"), self.max_packet_size.to_string()));
+        details.push((
+            gettext("Configurations"),
+            self.num_configurations.to_string(),
+        ));
+        if !self.classes.is_empty() {
+            let names: Vec<String> = self.classes.iter().map(|&c| class_name(c)).collect();
+            details.push((gettext("Classes"), names.join(", ")));
+        }
+        details.push((gettext("Speed"), self.speed.clone()));
+        details
+    }
+
+    /// Case-insensitive match against the product name, VID:PID, and serial.
+    fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+
+        let id = format!("{:04x}:{:04x}", self.vendor_id, self.product_id);
+        self.product_name.to_lowercase().contains(&query)
+            || id.contains(&query)
+            || self
+                .serial
+                .as_deref()
+                .is_some_and(|serial| serial.to_lowercase().contains(&query))
+    }
+
+    /// The symbolic icon for the most telling of the device's classes, and
+    /// that class for the icon's tooltip.
+    fn icon(&self) -> (&'static str, Option<u8>) {
+        CLASS_ICONS
+            .iter()
+            .find(|(code, _)| self.class_code == *code || self.classes.contains(code))
+            .map_or((DEFAULT_DEVICE_ICON, None), |&(code, icon)| {
+                (icon, Some(code))
+            })
+    }
 
-        list.append(&devices_container);
+    /// Whether `device` is plugged straight into this hub.
+    fn is_parent_of(&self, device: &UsbDevice) -> bool {
+        self.is_hub()
+            && self.bus == device.bus
+            && device
+                .ports
+                .split_last()
+                .is_some_and(|(_, upstream)| upstream == self.ports)
+    }
 
-        // shared selected device state (used if user selects a device row)
-        let selected_device: Rc<RefCell<Option<(u8, u8)>>> = Rc::new(RefCell::new(None));
+    fn is_hub(&self) -> bool {
+        self.class_code == 0x09
+    }
+}
+
+/// The device rows under the filter bar, reloaded from the daemon on demand.
+/// Grouped, each device sits in its hub's row, under its root hub, so the
+/// rows follow the physical ports of a dock; otherwise hubs are left out.
+struct DeviceList {
+    container: ListBox,
+    search: SearchEntry,
+    grouped: Switch,
+    feedback: Feedback,
+    devices: RefCell<Vec<UsbDevice>>,
+    /// Rows in the order shown, parents first, with the index of the hub
+    /// row each is nested in.
+    rows: RefCell<Vec<(ExpanderRow, UsbDevice, Option<usize>)>>,
+}
+
+impl DeviceList {
+    fn new(feedback: &Feedback, search: &SearchEntry, grouped: &Switch) -> Rc<Self> {
+        // rows are selectable so the tether shortcut has a target
+        let container = ListBox::builder()
+            .selection_mode(SelectionMode::Single)
+            .css_classes(["boxed-list"])
+            .build();
 
-        // Populate devices list. status is obtained from IPC `get_status` and used to
-        // determine which devices are currently tethered (highlighted background).
-        if let Ok(ctx) = Context::new() {
-            if let Ok(devices) = ctx.devices() {
-                if devices.len() == 0 {
-                    let label = Label::new(Some("no USB devices found"));
-                    devices_container.append(&label);
+        let list = Rc::new(Self {
+            container,
+            search: search.clone(),
+            grouped: grouped.clone(),
+            feedback: feedback.clone(),
+            devices: RefCell::new(Vec::new()),
+            rows: RefCell::new(Vec::new()),
+        });
+
+        let list_for_search = Rc::clone(&list);
+        search.connect_search_changed(move |_| list_for_search.apply_filter());
+        let list_for_grouping = Rc::clone(&list);
+        grouped.connect_active_notify(move |_| list_for_grouping.render());
+
+        list
+    }
+
+    /// Populate from the daemon, which also says which devices are tethered.
+    fn reload(self: &Rc<Self>) {
+        let list = Rc::clone(self);
+        glib::spawn_future_local(async move {
+            let devices = read_devices().await;
+            list.show(devices);
+        });
+    }
+
+    fn show(self: &Rc<Self>, devices: io::Result<Vec<UsbDevice>>) {
+        match devices {
+            Ok(devices) => {
+                *self.devices.borrow_mut() = devices;
+                self.render();
+            }
+            Err(err) => {
+                self.devices.borrow_mut().clear();
+                self.clear();
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+                ) {
+                    info!(error=%err, "deadmand is not running");
+                    append_plain_row(&self.container, &self.stopped_page());
                 } else {
-                    // query daemon status once and parse tethered device summaries
-                    let mut tethered_summaries = Vec::new();
-                    // Try IPC first, but if permission denied, try elevating to run the CLI (`deadman status`).
-                    let status_text_res = client::get_status();
-                    let mut status_text = String::new();
-                    match status_text_res {
-                        Ok(s) => status_text = s,
-                        Err(err) => {
-                            if matches!(err.kind(), io::ErrorKind::PermissionDenied) {
-                                info!("permission denied contacting daemon for status — attempting elevation");
-                                let elevated = Command::new("pkexec")
-                                    .arg("deadman")
-                                    .arg("status")
-                                    .env_remove("SHELL")
-                                    .output()
-                                    .or_else(|_| {
-                                        Command::new("sudo")
-                                            .arg("deadman")
-                                            .arg("status")
-                                            .env_remove("SHELL")
-                                            .output()
-                                    });
-
-                                match elevated {
-                                    Ok(output) if output.status.success() => {
-                                        status_text = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                                        info!(elev_out=%status_text, "elevated status succeeded");
-                                    }
-                                    Ok(output) => {
-                                        let err_text = String::from_utf8_lossy(&output.stderr).trim().to_string();
-                                        info!(error=%err_text, "elevated status failed");
-                                    }
-                                    Err(e) => {
-                                        info!(error=%e.to_string(), "elevation attempt for status failed");
-                                    }
-                                }
-                            } else {
-                                info!(error=%err.to_string(), "failed to get status from daemon");
-                            }
-                        }
-                    }
+                    info!(error=%err, "failed to list USB devices from the daemon");
+                    let label = Label::new(Some(&gettext_f(
+                        "could not list USB devices: {}",
+                        &[&err.to_string()],
+                    )));
+                    append_plain_row(&self.container, &label);
+                }
+            }
+        }
+    }
 
-                    for line in status_text.lines() {
-                        // status lines are like: "bus 001 address 002 1234:abcd - name [watching]"
-                        // we only care about the product name (after " - ") so we can
-                        // display only the device name and match tethered devices by name.
-                        if let Some(idx) = line.find(" - ") {
-                            let after = &line[idx + 3..];
-                            // strip trailing status in brackets if present
-                            let name = if let Some(br) = after.rfind('[') {
-                                after[..br].trim()
-                            } else {
-                                after.trim()
-                            };
-                            if !name.is_empty() {
-                                tethered_summaries.push(name.to_string());
-                            }
-                        }
+    fn clear(&self) {
+        while let Some(child) = self.container.first_child() {
+            self.container.remove(&child);
+        }
+        self.rows.borrow_mut().clear();
+    }
+
+    /// Lay out the last devices listed, as a tree or flat.
+    fn render(&self) {
+        self.clear();
+        let devices = self.devices.borrow();
+        if devices.is_empty() {
+            let label = Label::new(Some(&gettext("no USB devices found")));
+            append_plain_row(&self.container, &label);
+            return;
+        }
+
+        // shorter port paths first, so every hub's row exists before the
+        // devices plugged into it
+        let mut ordered: Vec<&UsbDevice> = devices.iter().collect();
+        ordered.sort_by(|a, b| {
+            (a.bus, a.ports.len(), &a.ports).cmp(&(b.bus, b.ports.len(), &b.ports))
+        });
+
+        let grouped = self.grouped.is_active();
+        let mut rows = self.rows.borrow_mut();
+        for device in ordered {
+            if !grouped && device.is_hub() {
+                continue;
+            }
+            let row = device_row(&self.feedback, device, device.tethered);
+            let parent = if grouped {
+                rows.iter().position(|(_, hub, _)| hub.is_parent_of(device))
+            } else {
+                None
+            };
+            match parent {
+                Some(index) => rows[index].0.add_row(&row),
+                None => self.container.append(&row),
+            }
+            rows.push((row, device.clone(), parent));
+        }
+        drop(rows);
+        self.apply_filter();
+    }
+
+    /// Explains that nothing is listening and offers to start the service,
+    /// reloading the list once it answers.
+    fn stopped_page(self: &Rc<Self>) -> StatusPage {
+        let btn_start = Button::builder()
+            .label(gettext("Start service"))
+            .halign(Align::Center)
+            .css_classes(["pill", "suggested-action"])
+            .build();
+        let page = StatusPage::builder()
+            .icon_name("system-shutdown-symbolic")
+            .title(gettext("deadmand is not running"))
+            .description(gettext(
+                "Nothing is watching for removals. Start the service to list and tether devices.",
+            ))
+            .child(&btn_start)
+            .build();
+
+        let list = Rc::clone(self);
+        btn_start.connect_clicked(move |btn| {
+            btn.set_sensitive(false);
+            let (list, btn) = (Rc::clone(&list), btn.clone());
+            glib::spawn_future_local(async move {
+                match start_daemon().await {
+                    Ok(()) => list.reload(),
+                    Err(err) => {
+                        info!(error=%err, "failed to start deadmand");
+                        btn.set_sensitive(true);
+                        list.feedback
+                            .error(&gettext_f("could not start deadmand: {}", &[&err]));
                     }
+                }
+            });
+        });
+        page
+    }
 
-                    for device in devices.iter() {
-                        let bus = device.bus_number();
-                        let addr = device.address();
-                        let desc = match device.device_descriptor() {
-                            Ok(d) => d,
-                            Err(_) => continue,
-                        };
+    /// Show the rows that match and the hubs leading to them, expanding
+    /// those hubs while a query is typed.
+    fn apply_filter(&self) {
+        let query = self.search.text();
+        let rows = self.rows.borrow();
+        let mut visible: Vec<bool> = rows
+            .iter()
+            .map(|(_, device, _)| device.matches(&query))
+            .collect();
+        let mut leads_to_match = vec![false; rows.len()];
+        // children come after their parents, so walking back settles each
+        // row before its hub
+        for (index, (_, _, parent)) in rows.iter().enumerate().rev() {
+            if let Some(parent) = *parent {
+                if visible[index] {
+                    visible[parent] = true;
+                    leads_to_match[parent] = true;
+                }
+            }
+        }
+        let searching = !query.trim().is_empty();
+        for (index, (row, _, _)) in rows.iter().enumerate() {
+            row.set_visible(visible[index]);
+            if searching && leads_to_match[index] {
+                row.set_expanded(true);
+            }
+        }
+    }
 
-                        let name = match device.open() {
-                            Ok(handle) => match handle.read_product_string_ascii(&desc) {
-                                Ok(n) => Some(n),
-                                Err(_) => None,
-                            },
-                            Err(_) => None,
-                        };
+    /// Open the tether dialog for the focused or selected row, as its button
+    /// would. Rows nested under a hub are never selected, only focused.
+    fn tether_selected(&self) {
+        let focused = self
+            .container
+            .root()
+            .and_then(|root| root.focus())
+            .filter(|widget| widget.is_ancestor(&self.container));
+        let selected = focused
+            .or_else(|| self.container.selected_row().map(|row| row.upcast()))
+            .filter(|widget| widget.is_visible())
+            .and_then(|widget| widget.activate_action("device.tether", None).ok());
+        if selected.is_none() {
+            self.feedback
+                .error(&gettext("select a device to tether first"));
+        }
+    }
+}
 
-                        // Only display devices for which we could read a product name
-                        let product_name = match name {
-                            Some(n) => n,
-                            None => continue,
-                        };
+async fn read_devices() -> io::Result<Vec<UsbDevice>> {
+    Ok(async_client::list_devices()
+        .await?
+        .into_iter()
+        .map(read_device)
+        .collect())
+}
 
-                        let label_text = product_name.clone();
-                        let btn = Button::with_label(&label_text);
-                        let sel_inner = selected_device.clone();
-                        // highlight tethered devices by matching the product name
-                        if tethered_summaries.iter().any(|s| s == &product_name) {
-                            btn.add_css_class("suggested-action");
-                        }
+fn read_device(device: devices::UsbDevice) -> UsbDevice {
+    // devices without a readable product string are labelled by VID:PID
+    let product_name = device.product.unwrap_or_else(|| {
+        gettext_f(
+            "Unknown device {}",
+            &[&format!(
+                "{:04x}:{:04x}",
+                device.vendor_id, device.product_id
+            )],
+        )
+    });
 
-                        // clicking a row will attempt to tether that device via IPC
-                        let label_for_err = Label::new(None);
-                        let label_text_clone = label_text.clone();
-                        let app_for_click = app_for_click.clone();
-                        btn.connect_clicked(move |b| {
-                            *sel_inner.borrow_mut() = Some((bus, addr));
-
-                            let bus_s = bus.to_string();
-                            let dev_s = addr.to_string();
-                            match client::tether(&bus_s, &dev_s) {
-                                Ok(resp) => {
-                                    info!(response=%resp, "tether command succeeded");
-                                    // mark button as highlighted to reflect tether
-                                    b.add_css_class("suggested-action");
-                                    // quit the application after successful tether
-                                    app_for_click.quit();
-                                }
-                                Err(err) => {
-                                    // If we failed due to permission, try to elevate and run the CLI via pkexec or sudo
-                                    let try_elevate = matches!(err.kind(), io::ErrorKind::PermissionDenied);
-                                    if try_elevate {
-                                        info!("permission denied contacting daemon — attempting elevation");
-                                        // try pkexec first
-                                        let elevated = Command::new("pkexec")
-                                            .arg("deadman")
-                                            .arg("tether")
-                                            .arg(&bus_s)
-                                            .arg(&dev_s)
-                                            .env_remove("SHELL")
-                                            .output()
-                                            .or_else(|_| {
-                                                // fallback to sudo if pkexec not available
-                                                Command::new("sudo")
-                                                    .arg("deadman")
-                                                    .arg("tether")
-                                                    .arg(&bus_s)
-                                                    .arg(&dev_s)
-                                                    .env_remove("SHELL")
-                                                    .output()
-                                            });
-
-                                        match elevated {
-                                            Ok(output) if output.status.success() => {
-                                                let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                                                info!(elev_out=%out, "elevated tether succeeded");
-                                                b.add_css_class("suggested-action");
-                                                app_for_click.quit();
-                                            }
-                                            Ok(output) => {
-                                                let err_text = String::from_utf8_lossy(&output.stderr).trim().to_string();
-                                                let msg = if err_text.is_empty() {
-                                                    format!("elevated command failed (exit {})", output.status)
-                                                } else {
-                                                    format!("elevated error: {}", err_text)
-                                                };
-                                                info!(error=%msg, "elevated tether failed");
-                                                if let Some(window) = app_for_click.active_window() {
-                                                    show_error(&window, &msg);
-                                                }
-                                            }
-                                            Err(e) => {
-                                                let msg = format!("failed to launch elevation helper: {}", e);
-                                                info!(error=%msg, "elevation attempt failed");
-                                                if let Some(window) = app_for_click.active_window() {
-                                                    show_error(&window, &msg);
-                                                }
-                                            }
-                                        }
-                                        } else {
-                                            let msg = format!("tether error: {}", err);
-                                            info!(error=%msg, device=%label_text_clone, "tether failed");
-                                            if let Some(window) = app_for_click.active_window() {
-                                                show_error(&window, &msg);
-                                            }
-                                        }
-                                }
-                            }
-                        });
+    UsbDevice {
+        bus: device.bus,
+        address: device.address,
+        ports: device.ports,
+        vendor_id: device.vendor_id,
+        product_id: device.product_id,
+        product_name,
+        manufacturer: device.manufacturer,
+        serial: device.serial,
+        usb_version: device.usb_version,
+        device_version: device.device_version,
+        class_code: device.class_code,
+        sub_class_code: device.sub_class_code,
+        protocol_code: device.protocol_code,
+        classes: device.classes,
+        max_packet_size: device.max_packet_size,
+        num_configurations: device.num_configurations,
+        speed: device.speed,
+        tethered: device.tether_id.is_some(),
+    }
+}
 
-                        devices_container.append(&btn);
-                    }
+fn device_row(feedback: &Feedback, device: &UsbDevice, tethered: bool) -> ExpanderRow {
+    let row = ExpanderRow::builder()
+        .title(device.product_name.as_str())
+        .subtitle(device.subtitle())
+        .use_markup(false)
+        .build();
+
+    let (icon_name, class) = device.icon();
+    // the title already names the device for screen readers
+    let icon = Image::builder()
+        .icon_name(icon_name)
+        .accessible_role(AccessibleRole::Presentation)
+        .build();
+    if let Some(class) = class {
+        icon.set_tooltip_text(Some(&class_name(class)));
+    }
+    row.add_prefix(&icon);
+
+    let btn = Button::builder().valign(Align::Center).build();
+    show_tethered(&btn, &device.product_name, tethered);
+    row.add_suffix(&btn);
+
+    // lets the app's tether shortcut press this button for the selected row
+    let actions = gio::SimpleActionGroup::new();
+    let tether = gio::SimpleAction::new("tether", None);
+    let btn_for_action = btn.clone();
+    tether.connect_activate(move |_, _| btn_for_action.emit_clicked());
+    actions.add_action(&tether);
+    row.insert_action_group("device", Some(&actions));
+
+    for (title, value) in device.details() {
+        let detail = ActionRow::builder()
+            .title(title.as_str())
+            .subtitle(value)
+            .subtitle_selectable(true)
+            .use_markup(false)
+            .build();
+        detail.add_css_class("property");
+        row.add_row(&detail);
+    }
+
+    // clicking the tether button asks for the removal action, then tethers via IPC
+    // and asks again with a summary, so a stray click and Enter tether nothing
+    let device = device.clone();
+    let feedback = feedback.clone();
+    btn.connect_clicked(move |b| {
+        let (feedback, btn, device) = (feedback.clone(), b.clone(), device.clone());
+        let name = device.product_name.clone();
+        choose_tether_options(b, &name, move |options| {
+            let (feedback, btn_for_tether) = (feedback.clone(), btn.clone());
+            let (target, tether_options) = (device.clone(), options.clone());
+            confirm_tether(&btn, &device, &options, move || {
+                tether_device(
+                    &feedback,
+                    &btn_for_tether,
+                    &target.product_name,
+                    target.bus,
+                    target.address,
+                    &tether_options,
+                );
+            });
+        });
+    });
+
+    row
+}
+
+/// Label a device's tether button, for sight and for screen readers, which
+/// would otherwise hear only "Tether" with no device.
+fn show_tethered(btn: &Button, device_name: &str, tethered: bool) {
+    if tethered {
+        btn.set_label(&gettext("Tethered"));
+        btn.add_css_class("suggested-action");
+        btn.update_property(&[
+            Property::Label(&gettext_f("{} is tethered", &[device_name])),
+            Property::Description(&gettext("Choose a new removal action for this device")),
+        ]);
+    } else {
+        btn.set_label(&gettext("Tether"));
+        btn.update_property(&[
+            Property::Label(&gettext_f("Tether {}", &[device_name])),
+            Property::Description(&gettext("Choose what happens when this device is removed")),
+        ]);
+    }
+}
+
+/// Append `child` in a row that takes no focus of its own, so Tab moves
+/// straight between the controls inside.
+fn append_plain_row(list: &ListBox, child: &impl IsA<Widget>) {
+    let row = ListBoxRow::builder()
+        .activatable(false)
+        .focusable(false)
+        .child(child)
+        .build();
+    list.append(&row);
+}
+
+/// App actions, their accelerators, and how the shortcuts window names them.
+fn shortcut_list() -> [(&'static str, &'static str, String); 6] {
+    [
+        (
+            "app.refresh",
+            "<Primary>r",
+            gettext("Refresh the current page"),
+        ),
+        (
+            "app.tether",
+            "<Primary>t",
+            gettext("Tether the selected device"),
+        ),
+        (
+            "app.severe",
+            "<Primary><Shift>s",
+            gettext("Clear all tethers"),
+        ),
+        ("app.preferences", "<Primary>comma", gettext("Preferences")),
+        (
+            "app.shortcuts",
+            "<Primary>question",
+            gettext("Keyboard shortcuts"),
+        ),
+        ("window.close", "<Primary>w", gettext("Close the window")),
+    ]
+}
+
+fn show_shortcuts(window: &ApplicationWindow) {
+    let group = ShortcutsGroup::builder().title(gettext("General")).build();
+    for (_, accel, title) in shortcut_list() {
+        let shortcut = ShortcutsShortcut::builder()
+            .accelerator(accel)
+            .title(title)
+            .build();
+        group.add_shortcut(&shortcut);
+    }
+    let section = ShortcutsSection::builder().section_name("main").build();
+    section.add_group(&group);
+
+    let shortcuts = ShortcutsWindow::builder()
+        .transient_for(window)
+        .modal(true)
+        .build();
+    shortcuts.add_section(&section);
+    shortcuts.present();
+}
+
+/// Ask which action to run on removal and how long the grace period is.
+fn choose_tether_options(
+    parent: &impl IsA<Widget>,
+    device_name: &str,
+    on_confirm: impl Fn(TetherOptions) + 'static,
+) {
+    let dialog = AlertDialog::new(
+        Some(&gettext_f("Tether {}", &[device_name])),
+        Some(&gettext("Choose what happens when this device is removed.")),
+    );
+    dialog.add_responses(&[
+        ("cancel", &gettext("Cancel")),
+        ("continue", &gettext("Continue")),
+    ]);
+    dialog.set_response_appearance("continue", ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("continue"));
+    dialog.set_close_response("cancel");
+
+    let actions = TETHER_ACTIONS.map(action_label);
+    let action = DropDown::from_strings(&actions.each_ref().map(String::as_str));
+    let script = Entry::builder()
+        .placeholder_text(gettext("/path/to/script"))
+        .visible(false)
+        .build();
+    let grace = SpinButton::with_range(0.0, 3600.0, 1.0);
+
+    // a custom action needs an absolute script path before it can be confirmed
+    let update_valid = {
+        let dialog = dialog.clone();
+        let action = action.clone();
+        let script = script.clone();
+        move || {
+            let custom = action.selected() == ACTION_CUSTOM;
+            script.set_visible(custom);
+            dialog.set_response_enabled("continue", !custom || script.text().starts_with('/'));
+        }
+    };
+    let update_for_action = update_valid.clone();
+    action.connect_selected_notify(move |_| update_for_action());
+    script.connect_changed(move |_| update_valid());
+
+    let action_label = Label::new(Some(&gettext("Action")));
+    action.update_relation(&[Relation::LabelledBy(&[action_label.upcast_ref()])]);
+    script.update_property(&[Property::Label(&gettext("Custom script path"))]);
+    let grace_label = Label::new(Some(&gettext("Grace period (seconds)")));
+    grace.update_relation(&[Relation::LabelledBy(&[grace_label.upcast_ref()])]);
+
+    let form = Box::new(Orientation::Vertical, 6);
+    form.append(&action_label);
+    form.append(&action);
+    form.append(&script);
+    form.append(&grace_label);
+    form.append(&grace);
+    dialog.set_extra_child(Some(&form));
+
+    dialog.connect_response(Some("continue"), move |_, _| {
+        let action = match TETHER_ACTIONS.get(action.selected() as usize) {
+            Some(&action) if action != "custom" => action.to_string(),
+            _ => format!("custom:{}", script.text()),
+        };
+
+        on_confirm(TetherOptions {
+            action: Some(action),
+            grace_secs: Some(grace.value_as_int().max(0) as u64),
+            ..TetherOptions::default()
+        });
+    });
+
+    dialog.present(Some(parent));
+}
+
+/// The removal actions offered, in dropdown order; `custom` asks for a script.
+const TETHER_ACTIONS: [&str; 6] = [
+    "lock",
+    "scrub",
+    "suspend",
+    "kill-network",
+    "terminate-remote",
+    "custom",
+];
+const ACTION_CUSTOM: u32 = 5;
+
+/// How the dialogs name a removal action, including `custom:<script>`.
+fn action_label(action: &str) -> String {
+    match action {
+        "lock" => gettext("Lock sessions"),
+        "scrub" => gettext("Clear secrets and lock"),
+        "suspend" => gettext("Suspend"),
+        "kill-network" => gettext("Disconnect network"),
+        "terminate-remote" => gettext("End remote sessions"),
+        "custom" => gettext("Run custom script"),
+        other => match other.strip_prefix("custom:") {
+            Some(script) => gettext_f("Run {}", &[script]),
+            None => other.to_string(),
+        },
+    }
+}
+
+/// Summarize the device, its removal action and grace period, and tether
+/// only once the user agrees.
+fn confirm_tether(
+    parent: &impl IsA<Widget>,
+    device: &UsbDevice,
+    options: &TetherOptions,
+    on_confirm: impl Fn() + 'static,
+) {
+    let action = match &options.action {
+        Some(action) => action_label(action),
+        None => gettext("the daemon's default action"),
+    };
+    let grace = match options.grace_secs {
+        Some(0) => gettext("none, the action runs at once"),
+        Some(secs) => ngettext_f("{} second", "{} seconds", secs, &[&secs.to_string()]),
+        None => gettext("the daemon's default"),
+    };
+    let body = [
+        device.subtitle(),
+        gettext_f("On removal: {}", &[&action]),
+        gettext_f("Grace period: {}", &[&grace]),
+    ]
+    .join("\n");
+
+    let dialog = AlertDialog::new(
+        Some(&gettext_f("Tether {}?", &[&device.product_name])),
+        Some(&body),
+    );
+    dialog.add_responses(&[
+        ("cancel", &gettext("Cancel")),
+        ("tether", &gettext("Tether")),
+    ]);
+    dialog.set_response_appearance("tether", ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("tether"));
+    dialog.set_close_response("cancel");
+    dialog.connect_response(Some("tether"), move |_, _| on_confirm());
+    dialog.present(Some(parent));
+}
+
+fn tether_device(
+    feedback: &Feedback,
+    btn: &Button,
+    device_name: &str,
+    bus: u8,
+    addr: u8,
+    options: &TetherOptions,
+) {
+    let bus_s = bus.to_string();
+    let dev_s = addr.to_string();
+
+    let mut args = vec!["tether".to_string(), bus_s.clone(), dev_s.clone()];
+    if let Some(action) = &options.action {
+        args.extend(["--action".to_string(), action.clone()]);
+    }
+    if let Some(grace) = options.grace_secs {
+        args.extend(["--grace".to_string(), grace.to_string()]);
+    }
+
+    let request = async_client::tether_with_options(&bus_s, &dev_s, options);
+    let (feedback, btn, device_name) = (feedback.clone(), btn.clone(), device_name.to_string());
+    glib::spawn_future_local(async move {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let result = call_daemon("tether", &args, request).await;
+        if result.is_ok() {
+            // mark button as highlighted to reflect tether
+            show_tethered(&btn, &device_name, true);
+        }
+        feedback.report(result);
+    });
+}
+
+/// Full-window countdown shown while a removed device's grace period runs.
+struct CountdownOverlay {
+    root: Box,
+    device: Label,
+    remaining: Label,
+    cancel: Button,
+    seconds_left: Cell<u64>,
+    timer: RefCell<Option<glib::SourceId>>,
+}
+
+impl CountdownOverlay {
+    fn new(feedback: &Feedback) -> Rc<Self> {
+        let root = Box::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .halign(Align::Fill)
+            .valign(Align::Fill)
+            .hexpand(true)
+            .vexpand(true)
+            .css_classes(["osd"])
+            .visible(false)
+            .accessible_role(AccessibleRole::Alert)
+            .build();
+
+        let inner = Box::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .halign(Align::Center)
+            .valign(Align::Center)
+            .vexpand(true)
+            .build();
+
+        let title = Label::builder()
+            .label(gettext("Tethered device removed"))
+            .css_classes(["title-1"])
+            .build();
+        let device = Label::new(None);
+        let remaining = Label::builder().css_classes(["title-2"]).build();
+        let btn_cancel = Button::builder()
+            .label(gettext("Cancel — I'm here"))
+            .halign(Align::Center)
+            .css_classes(["pill", "suggested-action"])
+            .build();
+
+        inner.append(&title);
+        inner.append(&device);
+        inner.append(&remaining);
+        inner.append(&btn_cancel);
+        root.append(&inner);
+
+        let overlay = Rc::new(Self {
+            root,
+            device,
+            remaining,
+            cancel: btn_cancel.clone(),
+            seconds_left: Cell::new(0),
+            timer: RefCell::new(None),
+        });
+
+        let overlay_for_cancel = Rc::clone(&overlay);
+        let feedback = feedback.clone();
+        btn_cancel.connect_clicked(move |_| {
+            let overlay = Rc::clone(&overlay_for_cancel);
+            let feedback = feedback.clone();
+            glib::spawn_future_local(async move {
+                let result = call_daemon("cancel", &["cancel"], async_client::cancel()).await;
+                if result.is_ok() {
+                    overlay.stop();
                 }
+                feedback.report(result);
+            });
+        });
+
+        overlay
+    }
+
+    fn start(self: &Rc<Self>, device: &str, seconds: u64) {
+        self.stop();
+
+        self.device.set_text(device);
+        self.seconds_left.set(seconds);
+        self.update_remaining();
+        self.root.set_visible(true);
+        // Enter or Space cancels straight away
+        self.cancel.grab_focus();
+        self.root.announce(
+            &gettext_f(
+                "Tethered device removed: {}. Locking in {} seconds",
+                &[device, &seconds.to_string()],
+            ),
+            AccessibleAnnouncementPriority::High,
+        );
+
+        let overlay = Rc::clone(self);
+        let timer = glib::timeout_add_seconds_local(1, move || {
+            let left = overlay.seconds_left.get().saturating_sub(1);
+            overlay.seconds_left.set(left);
+            overlay.update_remaining();
+            // the label changes every second; only the milestones are spoken
+            if left > 0 && (left <= 5 || left.is_multiple_of(10)) {
+                overlay.remaining.announce(
+                    &gettext_f("Locking in {} seconds", &[&left.to_string()]),
+                    AccessibleAnnouncementPriority::Medium,
+                );
+            }
+            if left == 0 {
+                // the source is removed by returning Break, so just forget its id
+                overlay.timer.borrow_mut().take();
+                return glib::ControlFlow::Break;
             }
+            glib::ControlFlow::Continue
+        });
+        *self.timer.borrow_mut() = Some(timer);
+    }
+
+    fn stop(&self) {
+        self.root.set_visible(false);
+        if let Some(timer) = self.timer.borrow_mut().take() {
+            timer.remove();
         }
+    }
 
-        // severe button handler: ask for confirmation, then call IPC (with elevation fallback)
-        let app_for_severe = app_for_click.clone();
-        let show_error_for_severe = show_error.clone();
-        btn_severe.connect_clicked(move |_| {
-            let app_for_severe = app_for_severe.clone();
-            let show_error_for_severe = show_error_for_severe.clone();
-            if let Some(window) = app_for_severe.active_window() {
-                let dialog = MessageDialog::builder()
-                    .text("Are you sure?")
-                    .modal(true)
-                    .build();
-                dialog.add_button("Cancel", ResponseType::Cancel);
-                dialog.add_button("Proceed", ResponseType::Ok);
-                dialog.set_transient_for(Some(&window));
-                dialog.connect_response(move |d, resp| {
-                    d.close();
-                    if resp == ResponseType::Ok {
-                        // attempt IPC severe
-                        match client::severe() {
-                            Ok(resp) => {
-                                info!(response=%resp, "severe command succeeded");
-                                if let Some(w) = app_for_severe.active_window() {
-                                    show_error_for_severe(&w, &resp);
-                                }
-                                app_for_severe.quit();
-                            }
-                            Err(err) => {
-                                // try elevation on permission denied
-                                if matches!(err.kind(), io::ErrorKind::PermissionDenied) {
-                                    info!("permission denied contacting daemon for severe — attempting elevation");
-                                    let elevated = Command::new("pkexec")
-                                        .arg("deadman")
-                                        .arg("severe")
-                                        .env_remove("SHELL")
-                                        .output()
-                                        .or_else(|_| {
-                                            Command::new("sudo")
-                                                .arg("deadman")
-                                                .arg("severe")
-                                                .env_remove("SHELL")
-                                                .output()
-                                        });
-
-                                    match elevated {
-                                        Ok(output) if output.status.success() => {
-                                            let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                                            info!(elev_out=%out, "elevated severe succeeded");
-                                            if let Some(w) = app_for_severe.active_window() {
-                                                show_error_for_severe(&w, &out);
-                                            }
-                                            app_for_severe.quit();
-                                        }
-                                        Ok(output) => {
-                                            let err_text = String::from_utf8_lossy(&output.stderr).trim().to_string();
-                                            let msg = if err_text.is_empty() {
-                                                format!("elevated command failed (exit {})", output.status)
-                                            } else {
-                                                format!("elevated error: {}", err_text)
-                                            };
-                                            if let Some(w) = app_for_severe.active_window() {
-                                                show_error_for_severe(&w, &msg);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            let msg = format!("failed to launch elevation helper: {}", e);
-                                            if let Some(w) = app_for_severe.active_window() {
-                                                show_error_for_severe(&w, &msg);
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    let msg = format!("severe error: {}", err);
-                                    if let Some(w) = app_for_severe.active_window() {
-                                        show_error_for_severe(&w, &msg);
-                                    }
-                                }
+    fn update_remaining(&self) {
+        self.remaining.set_text(&gettext_f(
+            "Locking in {} s",
+            &[&self.seconds_left.get().to_string()],
+        ));
+    }
+}
+
+/// Recent daemon events pulled via the `log` IPC command, newest first.
+struct EventLog {
+    root: Box,
+    list: ListBox,
+    search: SearchEntry,
+    feedback: Feedback,
+    rows: RefCell<Vec<(ActionRow, String)>>,
+}
+
+impl EventLog {
+    fn new(feedback: &Feedback) -> Rc<Self> {
+        let root = Box::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .margin_top(32)
+            .margin_end(32)
+            .margin_bottom(32)
+            .margin_start(32)
+            .build();
+
+        let toolbar = Box::new(Orientation::Horizontal, 12);
+        let search = SearchEntry::builder()
+            .placeholder_text(gettext("Filter by event, device or message"))
+            .hexpand(true)
+            .build();
+        let btn_refresh = Button::builder()
+            .icon_name("view-refresh-symbolic")
+            .tooltip_text(gettext("Refresh"))
+            .build();
+        btn_refresh.update_property(&[Property::Label(&gettext("Refresh events"))]);
+        search.update_property(&[Property::Label(&gettext("Filter events"))]);
+        toolbar.append(&search);
+        toolbar.append(&btn_refresh);
+
+        let list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .valign(Align::Start)
+            .build();
+        let scrolled = ScrolledWindow::builder().vexpand(true).child(&list).build();
+
+        root.append(&toolbar);
+        root.append(&scrolled);
+
+        let log = Rc::new(Self {
+            root,
+            list,
+            search,
+            feedback: feedback.clone(),
+            rows: RefCell::new(Vec::new()),
+        });
+
+        let log_for_search = Rc::clone(&log);
+        log.search
+            .connect_search_changed(move |_| log_for_search.apply_filter());
+
+        let log_for_refresh = Rc::clone(&log);
+        btn_refresh.connect_clicked(move |_| log_for_refresh.reload());
+
+        log
+    }
+
+    fn reload(self: &Rc<Self>) {
+        let log = Rc::clone(self);
+        glib::spawn_future_local(async move {
+            let events = async_client::get_log().await;
+            log.show(events);
+        });
+    }
+
+    fn show(&self, events: io::Result<Vec<Event>>) {
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        self.rows.borrow_mut().clear();
+
+        let events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                self.feedback
+                    .error(&gettext_f("log error: {}", &[&err.to_string()]));
+                return;
+            }
+        };
+
+        if events.is_empty() {
+            self.list
+                .append(&Label::new(Some(&gettext("no events recorded"))));
+            return;
+        }
+
+        let mut rows = self.rows.borrow_mut();
+        for event in events.iter().rev() {
+            let mut subtitle = format_timestamp(event.timestamp);
+            for detail in [&event.device, &event.action, &event.message]
+                .into_iter()
+                .flatten()
+            {
+                subtitle.push_str(" · ");
+                subtitle.push_str(detail);
+            }
+
+            let row = ActionRow::builder()
+                .title(event_label(event.kind))
+                .subtitle(subtitle.as_str())
+                .use_markup(false)
+                .build();
+            self.list.append(&row);
+
+            let haystack = format!("{} {}", event_label(event.kind), subtitle).to_lowercase();
+            rows.push((row, haystack));
+        }
+        drop(rows);
+
+        self.apply_filter();
+    }
+
+    fn apply_filter(&self) {
+        let query = self.search.text().trim().to_lowercase();
+        for (row, haystack) in self.rows.borrow().iter() {
+            row.set_visible(haystack.contains(&query));
+        }
+    }
+}
+
+fn event_label(kind: EventKind) -> String {
+    match kind {
+        EventKind::Tethered => gettext("Tethered"),
+        EventKind::Removed => gettext("Removed"),
+        EventKind::Reattached => gettext("Reattached"),
+        EventKind::Cancelled => gettext("Cancelled"),
+        EventKind::Triggered => gettext("Action triggered"),
+        EventKind::Cleared => gettext("Cleared"),
+        EventKind::ConfigChanged => gettext("Configuration changed"),
+        EventKind::ConfigRejected => gettext("Configuration rejected"),
+        EventKind::Degraded => gettext("Monitoring degraded"),
+        EventKind::Suppressed => gettext("Action suppressed"),
+        EventKind::Rearmed => gettext("Re-armed"),
+    }
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    glib::DateTime::from_unix_local(timestamp as i64)
+        .and_then(|time| time.format("%Y-%m-%d %H:%M:%S"))
+        .map(|time| time.to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+/// Follow the daemon event stream on a background thread and drive the
+/// countdown overlay and protection shield from the main loop.
+fn watch_events(app: &Application, countdown: Rc<CountdownOverlay>, shield: Rc<ProtectionShield>) {
+    let (sender, receiver) = mpsc::channel::<Event>();
+
+    thread::spawn(move || loop {
+        match client::subscribe() {
+            Ok(events) => {
+                for event in events {
+                    match event {
+                        Ok(event) => {
+                            if sender.send(event).is_err() {
+                                return;
                             }
                         }
+                        Err(err) => {
+                            info!(error=%err, "daemon event stream failed");
+                            break;
+                        }
                     }
-                });
-                dialog.show();
+                }
             }
-        });
+            Err(err) => {
+                info!(error=%err, "failed to subscribe to daemon events");
+            }
+        }
+        thread::sleep(Duration::from_secs(5));
+    });
 
-        let content = Box::new(Orientation::Vertical, 0);
-        content.append(&list);
+    let app = app.clone();
+    glib::timeout_add_local(Duration::from_millis(200), move || {
+        let mut changed = false;
+        while let Ok(event) = receiver.try_recv() {
+            changed = true;
+            let device = event.device.as_deref().unwrap_or_default();
+            let hidden = app
+                .windows()
+                .first()
+                .is_none_or(|window| !window.is_visible());
+            match event.kind {
+                EventKind::Removed => {
+                    let seconds = event.grace_secs.unwrap_or(0);
+                    if seconds > 0 {
+                        countdown.start(device, seconds);
+                        if hidden {
+                            let notification =
+                                gio::Notification::new(&gettext("Tethered device removed"));
+                            notification.set_body(Some(&format!(
+                                "{device}\n{}",
+                                gettext_f("Locking in {} s", &[&seconds.to_string()])
+                            )));
+                            notification.set_priority(gio::NotificationPriority::Urgent);
+                            notification.add_button(&gettext("Cancel — I'm here"), "app.cancel");
+                            app.send_notification(Some(NOTIFICATION_GRACE), &notification);
+                        }
+                    }
+                }
+                EventKind::Reattached | EventKind::Cancelled | EventKind::Cleared => {
+                    countdown.stop();
+                    app.withdraw_notification(NOTIFICATION_GRACE);
+                }
+                EventKind::Triggered | EventKind::Suppressed => {
+                    countdown.stop();
+                    app.withdraw_notification(NOTIFICATION_GRACE);
+                    if hidden {
+                        let title = if event.kind == EventKind::Suppressed {
+                            gettext("Removal action suppressed")
+                        } else {
+                            gettext("Removal action ran")
+                        };
+                        let notification = gio::Notification::new(&title);
+                        notification.set_body(Some(device));
+                        app.send_notification(None, &notification);
+                    }
+                }
+                EventKind::Tethered
+                | EventKind::Rearmed
+                | EventKind::ConfigChanged
+                | EventKind::ConfigRejected
+                | EventKind::Degraded => {}
+            }
+        }
+        // any event may change the level; one refresh covers a burst
+        if changed {
+            shield.refresh();
+        }
+        glib::ControlFlow::Continue
+    });
+}
 
-        let window = ApplicationWindow::builder()
-            .application(app)
-            .content(&content)
+/// Header shield showing the daemon's protection level by icon, colour and
+/// word, with the reasons as its tooltip. Refreshed on every daemon event
+/// and on a slow poll. Refreshing never elevates; an unreachable daemon
+/// shows as unknown.
+struct ProtectionShield {
+    root: Box,
+    icon: Image,
+    label: Label,
+}
+
+impl ProtectionShield {
+    fn new() -> Rc<Self> {
+        let icon = Image::builder()
+            .accessible_role(AccessibleRole::Presentation)
             .build();
+        let label = Label::new(None);
+        let root = Box::new(Orientation::Horizontal, 6);
+        root.append(&icon);
+        root.append(&label);
 
-        window.show();
-    });
+        let shield = Rc::new(Self { root, icon, label });
+        shield.refresh();
+        let shield_for_poll = Rc::clone(&shield);
+        glib::timeout_add_seconds_local(PROTECTION_REFRESH_SECS, move || {
+            shield_for_poll.refresh();
+            glib::ControlFlow::Continue
+        });
+        shield
+    }
 
-    application.run();
+    fn refresh(self: &Rc<Self>) {
+        let shield = Rc::clone(self);
+        glib::spawn_future_local(async move {
+            // daemons from before `protection` can't report a level
+            let supported = async_client::server_info()
+                .await
+                .map_or(true, |info| info.supports("protection"));
+            shield.root.set_visible(supported);
+            if !supported {
+                return;
+            }
+            let reply = async_client::protection()
+                .await
+                .ok()
+                .filter(|reply| !reply.starts_with("ERR: "));
+            shield.show(reply.as_deref().map(str::trim));
+        });
+    }
+
+    fn show(&self, reply: Option<&str>) {
+        let (text, icon, class, tooltip) = match reply {
+            Some(reply) => {
+                let (level, reasons) = reply.split_once('\n').unwrap_or((reply, ""));
+                match level {
+                    "degraded" => (
+                        gettext("Degraded"),
+                        "security-medium-symbolic",
+                        "warning",
+                        reasons.to_string(),
+                    ),
+                    "unarmed" => (
+                        gettext("Unarmed"),
+                        "security-low-symbolic",
+                        "error",
+                        reasons.to_string(),
+                    ),
+                    _ => (
+                        gettext("Armed"),
+                        "security-high-symbolic",
+                        "success",
+                        level.to_string(),
+                    ),
+                }
+            }
+            None => (
+                gettext("Unknown"),
+                "dialog-question-symbolic",
+                "dim-label",
+                gettext("deadmand could not be reached"),
+            ),
+        };
+        self.icon.set_icon_name(Some(icon));
+        self.label.set_label(&text);
+        self.root.set_css_classes(&[class]);
+        self.root.set_tooltip_text(Some(&tooltip));
+        self.root.update_property(&[
+            Property::Label(&gettext_f("Protection: {}", &[&text])),
+            Property::Description(&tooltip),
+        ]);
+    }
+}
+
+/// Run an IPC request, falling back to `deadman <cli_args>` through pkexec or
+/// sudo when the socket refuses us. Returns the daemon's reply or an error
+/// suitable for showing to the user.
+async fn call_daemon(
+    what: &str,
+    cli_args: &[&str],
+    request: impl Future<Output = io::Result<String>>,
+) -> Result<String, String> {
+    match request.await {
+        Ok(resp) => match resp.strip_prefix("ERR: ") {
+            Some(err) => {
+                let id = client::last_request_id().unwrap_or_default();
+                info!(error=%err, request_id=%id, "{what} command rejected by daemon");
+                Err(format!("{what} error: {} (request {id})", err.trim()))
+            }
+            None => {
+                info!(response=%resp, "{what} command succeeded");
+                Ok(resp)
+            }
+        },
+        // If we failed due to permission, try to elevate and run the CLI via pkexec or sudo
+        Err(err) if matches!(err.kind(), io::ErrorKind::PermissionDenied) => {
+            info!("permission denied contacting daemon for {what} — attempting elevation");
+            let cli_args: Vec<String> = cli_args.iter().map(|arg| arg.to_string()).collect();
+            let elevated = async_client::spawn_blocking(move || {
+                let cli_args: Vec<&str> = cli_args.iter().map(String::as_str).collect();
+                run_elevated(&cli_args)
+            });
+            match elevated.await {
+                Ok(output) if output.status.success() => {
+                    let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                    info!(elev_out=%out, "elevated {what} succeeded");
+                    Ok(out)
+                }
+                Ok(output) => {
+                    let msg = elevated_failure_message(&output);
+                    info!(error=%msg, "elevated {what} failed");
+                    Err(msg)
+                }
+                Err(e) => {
+                    let msg = format!("failed to launch elevation helper: {}", e);
+                    info!(error=%msg, "elevation attempt failed");
+                    Err(msg)
+                }
+            }
+        }
+        Err(err) => {
+            let msg = format!("{what} error: {}", err);
+            info!(error=%msg, "{what} failed");
+            Err(msg)
+        }
+    }
+}
+
+/// Ask systemd to start the daemon's unit, on the session bus for
+/// `--user`, letting polkit prompt for the system one. Then wait for the
+/// socket to answer, which StartUnit returning does not promise.
+async fn start_daemon() -> Result<(), String> {
+    let bus = if deadman_ipc::user_mode() {
+        gio::BusType::Session
+    } else {
+        gio::BusType::System
+    };
+    let connection = gio::bus_get_future(bus)
+        .await
+        .map_err(|err| err.message().to_string())?;
+    let unit = format!("{}.service", deadman_ipc::unit_name());
+    connection
+        .call_future(
+            Some("org.freedesktop.systemd1"),
+            "/org/freedesktop/systemd1",
+            "org.freedesktop.systemd1.Manager",
+            "StartUnit",
+            Some(&(unit.as_str(), "replace").to_variant()),
+            Some(glib::VariantTy::new("(o)").expect("valid variant type")),
+            gio::DBusCallFlags::ALLOW_INTERACTIVE_AUTHORIZATION,
+            -1,
+        )
+        .await
+        .map_err(|err| err.message().to_string())?;
+
+    let deadline = Instant::now() + DAEMON_START_TIMEOUT;
+    while Instant::now() < deadline {
+        if async_client::server_info().await.is_ok() {
+            return Ok(());
+        }
+        glib::timeout_future(Duration::from_millis(250)).await;
+    }
+    Err(gettext_f("{} started but did not answer", &[&unit]))
+}
+
+/// Run `deadman <args>` through pkexec, falling back to sudo if pkexec is unavailable.
+fn run_elevated(args: &[&str]) -> io::Result<Output> {
+    if flatpak::sandboxed() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            gettext_f(
+                "not available inside Flatpak; run `deadman {}` on the host",
+                &[&args.join(" ")],
+            ),
+        ));
+    }
+
+    // pkexec scrubs the environment, so pass the instance on the command line
+    let mut full_args = Vec::new();
+    if let Some(name) = deadman_ipc::instance() {
+        full_args.extend(["--instance", name]);
+    }
+    full_args.extend_from_slice(args);
+
+    Command::new("pkexec")
+        .arg("deadman")
+        .args(&full_args)
+        .env_remove("SHELL")
+        .output()
+        .or_else(|_| {
+            Command::new("sudo")
+                .arg("deadman")
+                .args(&full_args)
+                .env_remove("SHELL")
+                .output()
+        })
+}
+
+fn elevated_failure_message(output: &Output) -> String {
+    let err_text = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if err_text.is_empty() {
+        format!("elevated command failed (exit {})", output.status)
+    } else {
+        format!("elevated error: {}", err_text)
+    }
 }
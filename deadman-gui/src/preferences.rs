@@ -0,0 +1,160 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use adw::glib;
+use adw::gtk::Widget;
+use adw::prelude::*;
+use adw::{PreferencesDialog, PreferencesGroup, PreferencesPage, SwitchRow};
+use libadwaita as adw;
+use tracing::info;
+
+use deadman_ipc::i18n::{gettext, gettext_f};
+
+use crate::{flatpak, Feedback, APP_ID};
+
+const AUTOSTART_ENTRY: &str = "[Desktop Entry]
+Type=Application
+Name=Deadman
+Comment=Keep the deadman status and notifications available
+Exec=deadman-gui --background
+Icon=dev.dominicegginton.deadman
+NoDisplay=true
+X-GNOME-Autostart-enabled=true
+";
+
+/// GUI preferences persisted as `key=value` lines under the user config dir.
+#[derive(Clone, Copy, Default)]
+pub struct GuiSettings {
+    /// Keep running with the window hidden instead of quitting on close.
+    pub run_in_background: bool,
+}
+
+impl GuiSettings {
+    pub fn load() -> Self {
+        let mut settings = Self::default();
+        let Ok(contents) = fs::read_to_string(settings_path()) else {
+            return settings;
+        };
+
+        for line in contents.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "run_in_background" {
+                    settings.run_in_background = value.trim() == "true";
+                }
+            }
+        }
+
+        settings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = settings_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            path,
+            format!("run_in_background={}\n", self.run_in_background),
+        )
+    }
+}
+
+fn settings_path() -> PathBuf {
+    glib::user_config_dir().join("deadman").join("gui.conf")
+}
+
+fn autostart_path() -> PathBuf {
+    glib::user_config_dir()
+        .join("autostart")
+        .join(format!("{APP_ID}.desktop"))
+}
+
+pub fn autostart_enabled() -> bool {
+    autostart_path().exists()
+}
+
+/// Install or remove the XDG autostart entry that starts the GUI hidden on login.
+/// Inside Flatpak the entry lands in the sandbox's own config dir, where it
+/// only records the choice, and the Background portal writes the real one.
+pub fn set_autostart(enabled: bool) -> io::Result<()> {
+    if flatpak::sandboxed() {
+        glib::spawn_future_local(flatpak::request_background(enabled));
+    }
+    let path = autostart_path();
+    if enabled {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, AUTOSTART_ENTRY)?;
+        info!(path=%path.display(), "installed autostart entry");
+    } else {
+        match fs::remove_file(&path) {
+            Ok(()) => info!(path=%path.display(), "removed autostart entry"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Show the preferences dialog. `on_background_changed` is called with the new
+/// value whenever the background toggle changes.
+pub fn show_preferences(
+    parent: &impl IsA<Widget>,
+    feedback: &Feedback,
+    on_background_changed: impl Fn(bool) + 'static,
+) {
+    let autostart = SwitchRow::builder()
+        .title(gettext("Start on login"))
+        .subtitle(gettext("Launch hidden in the background when you log in"))
+        .active(autostart_enabled())
+        .build();
+    let background = SwitchRow::builder()
+        .title(gettext("Run in background"))
+        .subtitle(gettext(
+            "Keep watching for removals and showing notifications when the window is closed",
+        ))
+        .active(GuiSettings::load().run_in_background)
+        .build();
+
+    let feedback_for_autostart = feedback.clone();
+    autostart.connect_active_notify(move |row| {
+        if let Err(err) = set_autostart(row.is_active()) {
+            feedback_for_autostart.error(&gettext_f(
+                "failed to update autostart entry: {}",
+                &[&err.to_string()],
+            ));
+        }
+    });
+
+    let feedback_for_background = feedback.clone();
+    background.connect_active_notify(move |row| {
+        let settings = GuiSettings {
+            run_in_background: row.is_active(),
+        };
+        if let Err(err) = settings.save() {
+            feedback_for_background.error(&gettext_f(
+                "failed to save preferences: {}",
+                &[&err.to_string()],
+            ));
+        }
+        if row.is_active() && flatpak::sandboxed() {
+            glib::spawn_future_local(flatpak::request_background(autostart_enabled()));
+        }
+        on_background_changed(row.is_active());
+    });
+
+    let group = PreferencesGroup::builder()
+        .title(gettext("Startup"))
+        .build();
+    group.add(&autostart);
+    group.add(&background);
+
+    let page = PreferencesPage::new();
+    page.add(&group);
+
+    let dialog = PreferencesDialog::new();
+    dialog.add(&page);
+    dialog.present(Some(parent));
+}
@@ -0,0 +1,227 @@
+use adw::gtk::Application;
+use adw::prelude::*;
+use adw::{gio, glib};
+use libadwaita as adw;
+use std::collections::HashMap;
+use tracing::{error, info};
+
+use deadman_ipc::async_client;
+use deadman_ipc::i18n::{gettext, gettext_f};
+
+use deadman_ipc::client::TetherOptions;
+
+use crate::{call_daemon, choose_tether_options, confirm_tether, read_devices, UsbDevice};
+
+/// Object path named in the `.search-provider.ini` installed alongside the app.
+pub const OBJECT_PATH: &str = "/com/dominicegginton/deadman/SearchProvider";
+
+const INTERFACE_XML: &str = r#"
+<node>
+  <interface name="org.gnome.Shell.SearchProvider2">
+    <method name="GetInitialResultSet">
+      <arg type="as" name="terms" direction="in"/>
+      <arg type="as" name="results" direction="out"/>
+    </method>
+    <method name="GetSubsearchResultSet">
+      <arg type="as" name="previous_results" direction="in"/>
+      <arg type="as" name="terms" direction="in"/>
+      <arg type="as" name="results" direction="out"/>
+    </method>
+    <method name="GetResultMetas">
+      <arg type="as" name="identifiers" direction="in"/>
+      <arg type="aa{sv}" name="metas" direction="out"/>
+    </method>
+    <method name="ActivateResult">
+      <arg type="s" name="identifier" direction="in"/>
+      <arg type="as" name="terms" direction="in"/>
+      <arg type="u" name="timestamp" direction="in"/>
+    </method>
+    <method name="LaunchSearch">
+      <arg type="as" name="terms" direction="in"/>
+      <arg type="u" name="timestamp" direction="in"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// Export the GNOME Shell search provider on the application's bus connection
+/// so the Activities overview can offer "Tether <device>" results.
+pub fn register(app: &Application) {
+    let Some(connection) = app.dbus_connection() else {
+        info!("no session bus connection — search provider not registered");
+        return;
+    };
+
+    let node = match gio::DBusNodeInfo::for_xml(INTERFACE_XML) {
+        Ok(node) => node,
+        Err(err) => {
+            error!(error=%err, "invalid search provider interface");
+            return;
+        }
+    };
+    let Some(interface) = node.lookup_interface("org.gnome.Shell.SearchProvider2") else {
+        return;
+    };
+
+    let app = app.clone();
+    let registration = connection
+        .register_object(OBJECT_PATH, &interface)
+        .method_call(move |_, _, _, _, method, params, invocation| {
+            handle_method(&app, method, &params, invocation)
+        })
+        .build();
+    if let Err(err) = registration {
+        error!(error=%err, "failed to register search provider");
+    }
+}
+
+fn handle_method(
+    app: &Application,
+    method: &str,
+    params: &glib::Variant,
+    invocation: gio::DBusMethodInvocation,
+) {
+    match method {
+        "GetInitialResultSet" => {
+            let (terms,) = params.get::<(Vec<String>,)>().unwrap_or_default();
+            glib::spawn_future_local(async move {
+                invocation.return_value(Some(&(search(&terms).await,).to_variant()));
+            });
+        }
+        "GetSubsearchResultSet" => {
+            let (_, terms) = params
+                .get::<(Vec<String>, Vec<String>)>()
+                .unwrap_or_default();
+            glib::spawn_future_local(async move {
+                invocation.return_value(Some(&(search(&terms).await,).to_variant()));
+            });
+        }
+        "GetResultMetas" => {
+            let (ids,) = params.get::<(Vec<String>,)>().unwrap_or_default();
+            glib::spawn_future_local(async move {
+                invocation.return_value(Some(&(result_metas(&ids).await,).to_variant()));
+            });
+        }
+        "ActivateResult" => {
+            let (id, _, _) = params
+                .get::<(String, Vec<String>, u32)>()
+                .unwrap_or_default();
+            invocation.return_value(None);
+            activate_result(app, &id);
+        }
+        "LaunchSearch" => {
+            invocation.return_value(None);
+            app.activate();
+        }
+        _ => invocation.return_dbus_error(
+            "org.freedesktop.DBus.Error.UnknownMethod",
+            &format!("unknown method {method}"),
+        ),
+    }
+}
+
+/// Result identifiers are `<bus>:<address>` of every non-hub device matching
+/// all of the search terms.
+async fn search(terms: &[String]) -> Vec<String> {
+    let devices = match read_devices().await {
+        Ok(devices) => devices,
+        Err(err) => {
+            info!(error=%err.to_string(), "failed to read USB devices for search");
+            return Vec::new();
+        }
+    };
+
+    devices
+        .iter()
+        .filter(|device| !device.is_hub() && terms.iter().all(|term| device.matches(term)))
+        .map(result_id)
+        .collect()
+}
+
+async fn result_metas(ids: &[String]) -> Vec<HashMap<String, glib::Variant>> {
+    let devices = read_devices().await.unwrap_or_default();
+    ids.iter()
+        .filter_map(|id| {
+            let device = devices.iter().find(|device| result_id(device) == *id)?;
+            let mut meta = HashMap::new();
+            meta.insert("id".to_string(), id.to_variant());
+            meta.insert(
+                "name".to_string(),
+                gettext_f("Tether {}", &[&device.product_name]).to_variant(),
+            );
+            meta.insert("description".to_string(), device.subtitle().to_variant());
+            meta.insert("gicon".to_string(), device.icon().0.to_variant());
+            Some(meta)
+        })
+        .collect()
+}
+
+/// Bring the window up and ask, as the device's tether button would, for the
+/// removal action and then for confirmation before tethering.
+fn activate_result(app: &Application, id: &str) {
+    app.activate();
+    let Some(window) = app
+        .active_window()
+        .or_else(|| app.windows().first().cloned())
+    else {
+        return;
+    };
+
+    let (app, id) = (app.clone(), id.to_string());
+    glib::spawn_future_local(async move {
+        let devices = read_devices().await.unwrap_or_default();
+        let Some(device) = devices.into_iter().find(|device| result_id(device) == id) else {
+            notify(&app, Err(gettext("The device is no longer connected")));
+            return;
+        };
+
+        let (name, parent) = (device.product_name.clone(), window.clone());
+        choose_tether_options(&parent, &name, move |options| {
+            let (app, device_for_tether, tether_options) =
+                (app.clone(), device.clone(), options.clone());
+            confirm_tether(&window, &device, &options, move || {
+                tether(&app, &device_for_tether, &tether_options);
+            });
+        });
+    });
+}
+
+fn tether(app: &Application, device: &UsbDevice, options: &TetherOptions) {
+    let (bus, address) = (device.bus.to_string(), device.address.to_string());
+    let mut args = vec!["tether".to_string(), bus.clone(), address.clone()];
+    if let Some(action) = &options.action {
+        args.extend(["--action".to_string(), action.clone()]);
+    }
+    if let Some(grace) = options.grace_secs {
+        args.extend(["--grace".to_string(), grace.to_string()]);
+    }
+
+    // keep the application alive while a pkexec prompt may be showing
+    let hold = app.hold();
+    let app = app.clone();
+    let request = async_client::tether_with_options(&bus, &address, options);
+    glib::spawn_future_local(async move {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let result = call_daemon("tether", &args, request).await;
+        if result.is_ok() {
+            // the window's device list shows the new tether
+            app.activate_action("refresh", None);
+        }
+        notify(&app, result);
+        drop(hold);
+    });
+}
+
+fn notify(app: &Application, result: Result<String, String>) {
+    let (title, body) = match result {
+        Ok(message) => (gettext("Device tethered"), message),
+        Err(message) => (gettext("Tether failed"), message),
+    };
+    let notification = gio::Notification::new(&title);
+    notification.set_body(Some(&body));
+    app.send_notification(None, &notification);
+}
+
+fn result_id(device: &UsbDevice) -> String {
+    format!("{}:{}", device.bus, device.address)
+}
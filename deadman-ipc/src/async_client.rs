@@ -0,0 +1,179 @@
+//! Futures for the [`client`](crate::client) calls, for event loops that must
+//! not block, such as the glib main context (`glib::spawn_future_local`) or
+//! a tokio runtime. Each call runs the blocking client on a thread of its
+//! own and its future completes when that returns, so no particular runtime
+//! is required.
+//!
+//! [`client::last_request_id`] reports the request of the last call awaited
+//! on the current thread.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::client::{self, Chunks, ServerInfo, TetherOptions, TetherTarget};
+use crate::devices::UsbDevice;
+use crate::events::Event;
+
+/// A blocking call running on its own thread.
+pub struct Call<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+struct Shared<T> {
+    result: Option<(T, Option<String>)>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for Call<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+        match shared.result.take() {
+            Some((result, request_id)) => {
+                client::set_last_request_id(request_id);
+                Poll::Ready(result)
+            }
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Run `call` on a new thread, for blocking work of the caller's own (such
+/// as an elevated helper) alongside the IPC calls.
+pub fn spawn_blocking<T, F>(call: F) -> Call<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let completed = Arc::clone(&shared);
+    thread::spawn(move || {
+        let result = call();
+        let mut shared = completed.lock().unwrap_or_else(|err| err.into_inner());
+        shared.result = Some((result, client::last_request_id()));
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    Call { shared }
+}
+
+pub fn get_status() -> Call<io::Result<String>> {
+    spawn_blocking(client::get_status)
+}
+
+/// The `status` response in chunks. The chunks are read lazily, so iterate
+/// them off the main thread or await [`get_status`] instead.
+pub fn status_chunks() -> Call<io::Result<Chunks>> {
+    spawn_blocking(client::status_chunks)
+}
+
+pub fn tether(bus: &str, device_id: &str) -> Call<io::Result<String>> {
+    let (bus, device_id) = (bus.to_string(), device_id.to_string());
+    spawn_blocking(move || client::tether(&bus, &device_id))
+}
+
+pub fn tether_with_options(
+    bus: &str,
+    device_id: &str,
+    options: &TetherOptions,
+) -> Call<io::Result<String>> {
+    let (bus, device_id, options) = (bus.to_string(), device_id.to_string(), options.clone());
+    spawn_blocking(move || client::tether_with_options(&bus, &device_id, &options))
+}
+
+pub fn set(bus: &str, device_id: &str, options: &TetherOptions) -> Call<io::Result<String>> {
+    let (bus, device_id, options) = (bus.to_string(), device_id.to_string(), options.clone());
+    spawn_blocking(move || client::set(&bus, &device_id, &options))
+}
+
+pub fn set_target(target: &TetherTarget, options: &TetherOptions) -> Call<io::Result<String>> {
+    let (target, options) = (*target, options.clone());
+    spawn_blocking(move || client::set_target(&target, &options))
+}
+
+pub fn untether(target: &TetherTarget) -> Call<io::Result<String>> {
+    let target = *target;
+    spawn_blocking(move || client::untether(&target))
+}
+
+pub fn ping() -> Call<io::Result<String>> {
+    spawn_blocking(client::ping)
+}
+
+pub fn doctor() -> Call<io::Result<String>> {
+    spawn_blocking(client::doctor)
+}
+
+pub fn protection() -> Call<io::Result<String>> {
+    spawn_blocking(client::protection)
+}
+
+pub fn test_action(
+    tether: Option<&TetherTarget>,
+    action: Option<&str>,
+) -> Call<io::Result<String>> {
+    let (tether, action) = (tether.copied(), action.map(str::to_string));
+    spawn_blocking(move || client::test_action(tether.as_ref(), action.as_deref()))
+}
+
+pub fn cancel() -> Call<io::Result<String>> {
+    spawn_blocking(client::cancel)
+}
+
+pub fn severe() -> Call<io::Result<String>> {
+    spawn_blocking(client::severe)
+}
+
+pub fn severe_matching(selectors: &[&str], except: &[&str]) -> Call<io::Result<String>> {
+    let selectors: Vec<String> = selectors
+        .iter()
+        .map(|selector| selector.to_string())
+        .collect();
+    let except: Vec<String> = except.iter().map(|selector| selector.to_string()).collect();
+    spawn_blocking(move || {
+        let selectors: Vec<&str> = selectors.iter().map(String::as_str).collect();
+        let except: Vec<&str> = except.iter().map(String::as_str).collect();
+        client::severe_matching(&selectors, &except)
+    })
+}
+
+pub fn get_log() -> Call<io::Result<Vec<Event>>> {
+    spawn_blocking(client::get_log)
+}
+
+pub fn list_devices() -> Call<io::Result<Vec<UsbDevice>>> {
+    spawn_blocking(client::list_devices)
+}
+
+pub fn export() -> Call<io::Result<String>> {
+    spawn_blocking(client::export)
+}
+
+pub fn log_level(filter: Option<&str>) -> Call<io::Result<String>> {
+    let filter = filter.map(str::to_string);
+    spawn_blocking(move || client::log_level(filter.as_deref()))
+}
+
+pub fn config(args: &[&str]) -> Call<io::Result<String>> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    spawn_blocking(move || {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        client::config(&args)
+    })
+}
+
+pub fn server_info() -> Call<io::Result<ServerInfo>> {
+    spawn_blocking(client::server_info)
+}
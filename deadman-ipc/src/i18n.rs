@@ -0,0 +1,140 @@
+//! Message catalog shared by the CLI and the GUI. Both translate through the
+//! `deadman` gettext domain, so one `po/deadman.pot` covers every
+//! user-facing string. Where there is no libintl (anything but glibc) the
+//! messages are passed through untranslated.
+
+/// The gettext domain, and the name of the `.mo` files under
+/// `<localedir>/<lang>/LC_MESSAGES/`.
+pub const DOMAIN: &str = "deadman";
+
+/// Where the catalogs are installed, unless `DEADMAN_LOCALEDIR` is set at
+/// build time (packagers) or run time (trying out a catalog).
+pub const DEFAULT_LOCALEDIR: &str = match option_env!("DEADMAN_LOCALEDIR") {
+    Some(dir) => dir,
+    None => "/usr/share/locale",
+};
+
+/// Set the locale from the environment and bind [`DOMAIN`] to its catalogs.
+/// Call once at startup, before any message is translated.
+pub fn init() {
+    let dir = std::env::var("DEADMAN_LOCALEDIR").unwrap_or_else(|_| DEFAULT_LOCALEDIR.to_string());
+    imp::init(&dir);
+}
+
+/// `msgid` in the user's language.
+pub fn gettext(msgid: &str) -> String {
+    imp::translate(msgid)
+}
+
+/// `singular` or `plural` in the user's language, chosen by `n`.
+pub fn ngettext(singular: &str, plural: &str, n: u64) -> String {
+    imp::translate_plural(singular, plural, n)
+}
+
+/// [`gettext`], with each `{}` in the translation replaced by the next of
+/// `args`. Translators may not reorder the placeholders.
+pub fn gettext_f(msgid: &str, args: &[&str]) -> String {
+    fill(&gettext(msgid), args)
+}
+
+/// [`ngettext`], filled in like [`gettext_f`].
+pub fn ngettext_f(singular: &str, plural: &str, n: u64, args: &[&str]) -> String {
+    fill(&ngettext(singular, plural, n), args)
+}
+
+fn fill(template: &str, args: &[&str]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut rest = template;
+    while let Some(at) = rest.find("{}") {
+        out.push_str(&rest[..at]);
+        out.push_str(args.next().copied().unwrap_or("{}"));
+        rest = &rest[at + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+mod imp {
+    use std::ffi::{CStr, CString, c_char, c_ulong};
+
+    use super::DOMAIN;
+
+    // libintl is part of glibc, so these need no extra link.
+    unsafe extern "C" {
+        fn bindtextdomain(domain: *const c_char, dir: *const c_char) -> *mut c_char;
+        fn bind_textdomain_codeset(domain: *const c_char, codeset: *const c_char) -> *mut c_char;
+        fn dgettext(domain: *const c_char, msgid: *const c_char) -> *mut c_char;
+        fn dngettext(
+            domain: *const c_char,
+            msgid: *const c_char,
+            plural: *const c_char,
+            n: c_ulong,
+        ) -> *mut c_char;
+    }
+
+    fn domain() -> CString {
+        CString::new(DOMAIN).expect("domain has no NUL")
+    }
+
+    pub fn init(dir: &str) {
+        let domain = domain();
+        let Ok(dir) = CString::new(dir) else {
+            return;
+        };
+        // SAFETY: every argument is a valid NUL-terminated string, and the
+        // returned pointers are owned by libc.
+        unsafe {
+            libc::setlocale(libc::LC_ALL, c"".as_ptr());
+            bindtextdomain(domain.as_ptr(), dir.as_ptr());
+            bind_textdomain_codeset(domain.as_ptr(), c"UTF-8".as_ptr());
+        }
+    }
+
+    pub fn translate(msgid: &str) -> String {
+        let Ok(id) = CString::new(msgid) else {
+            return msgid.to_string();
+        };
+        let domain = domain();
+        // SAFETY: the result is either `id` itself or a string in the loaded
+        // catalog, both valid until the copy below.
+        let translated = unsafe { CStr::from_ptr(dgettext(domain.as_ptr(), id.as_ptr())) };
+        translated.to_str().unwrap_or(msgid).to_string()
+    }
+
+    pub fn translate_plural(singular: &str, plural: &str, n: u64) -> String {
+        let fallback = || if n == 1 { singular } else { plural }.to_string();
+        let (Ok(id), Ok(id_plural)) = (CString::new(singular), CString::new(plural)) else {
+            return fallback();
+        };
+        let domain = domain();
+        let n = c_ulong::try_from(n).unwrap_or(c_ulong::MAX);
+        // SAFETY: as for `translate`.
+        let translated = unsafe {
+            CStr::from_ptr(dngettext(
+                domain.as_ptr(),
+                id.as_ptr(),
+                id_plural.as_ptr(),
+                n,
+            ))
+        };
+        translated
+            .to_str()
+            .map(str::to_string)
+            .unwrap_or_else(|_| fallback())
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+mod imp {
+    pub fn init(_dir: &str) {}
+
+    pub fn translate(msgid: &str) -> String {
+        msgid.to_string()
+    }
+
+    pub fn translate_plural(singular: &str, plural: &str, n: u64) -> String {
+        if n == 1 { singular } else { plural }.to_string()
+    }
+}
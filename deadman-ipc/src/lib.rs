@@ -1,24 +1,744 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod i18n;
+pub mod router;
+#[cfg(not(target_vendor = "apple"))]
+pub mod seqpacket;
+#[cfg(feature = "testing")]
+pub mod testing;
+
 pub const DEFAULT_SOCKET_PATH: &str = "/tmp/deadman-ipc.sock";
 
+/// Version of the IPC protocol, exchanged by `hello`. Daemons that predate
+/// `hello` are protocol 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional protocol features this build implements, advertised by `hello`.
+pub fn features() -> Vec<&'static str> {
+    let mut features = vec!["request-id", "chunked"];
+    if cfg!(not(target_vendor = "apple")) {
+        features.push("seqpacket");
+    }
+    features
+}
+
+static INSTANCE: OnceLock<String> = OnceLock::new();
+static USER_MODE: OnceLock<bool> = OnceLock::new();
+
+/// Address the named daemon instance for the rest of the process: clients
+/// connect to, and servers listen on, that instance's socket. Must be called
+/// before the first client or server call. Without it `DEADMAN_INSTANCE` is
+/// consulted, then the default instance is used.
+pub fn set_instance(name: &str) -> Result<(), String> {
+    validate_instance(name)?;
+    INSTANCE
+        .set(name.to_string())
+        .map_err(|_| "the daemon instance is already set".to_string())
+}
+
+/// The selected daemon instance, or `None` for the default one.
+pub fn instance() -> Option<&'static str> {
+    static FROM_ENV: OnceLock<Option<String>> = OnceLock::new();
+    INSTANCE.get().map(String::as_str).or_else(|| {
+        FROM_ENV
+            .get_or_init(|| {
+                env::var("DEADMAN_INSTANCE")
+                    .ok()
+                    .filter(|name| !name.is_empty() && validate_instance(name).is_ok())
+            })
+            .as_deref()
+    })
+}
+
+/// Run or address the unprivileged daemon of the current login session
+/// (`deadmand --user`) rather than the system one, for the rest of the
+/// process. Like [`set_instance`], call it before any client or server call.
+pub fn set_user_mode() {
+    let _ = USER_MODE.set(true);
+}
+
+pub fn user_mode() -> bool {
+    USER_MODE.get().copied().unwrap_or(false)
+}
+
+/// The systemd unit of the selected instance, without a suffix: `deadmand`,
+/// or `deadmand-<instance>` for a named instance.
+pub fn unit_name() -> String {
+    match instance() {
+        Some(name) => format!("deadmand-{name}"),
+        None => "deadmand".to_string(),
+    }
+}
+
+/// Socket of the selected instance: under `/tmp` for the system daemon, or
+/// in [`runtime_dir`] in user mode.
+pub fn socket_path() -> String {
+    let file_name = match instance() {
+        Some(name) => format!("deadman-ipc-{name}.sock"),
+        None => "deadman-ipc.sock".to_string(),
+    };
+    if user_mode() {
+        runtime_dir().join(file_name).display().to_string()
+    } else {
+        format!("/tmp/{file_name}")
+    }
+}
+
+/// Where a user daemon keeps its socket and pidfile: `$XDG_RUNTIME_DIR`,
+/// or `/run/user/<uid>` when that is unset.
+pub fn runtime_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        // SAFETY: getuid has no preconditions and cannot fail
+        _ => PathBuf::from(format!("/run/user/{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// Instance names end up in file names, so keep them to `[A-Za-z0-9_-]`.
+fn validate_instance(name: &str) -> Result<(), String> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid instance name {name:?}; use letters, digits, '-' and '_'"
+        ))
+    }
+}
+
+pub mod events {
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// What happened to a tether. Serialized in kebab-case on the wire.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum EventKind {
+        Tethered,
+        Removed,
+        Reattached,
+        Cancelled,
+        Triggered,
+        Cleared,
+        /// The config file changed and was applied; `message` lists the changes.
+        ConfigChanged,
+        /// The config file changed but failed to parse; `message` says why.
+        ConfigRejected,
+        /// A monitor hit a libusb error and is recreating its USB context;
+        /// `message` has the error and retry count.
+        Degraded,
+        /// A removal action was not run because of its cooldown, the
+        /// hourly trigger limit or a pre-trigger hook's veto; `message` says
+        /// which.
+        Suppressed,
+        /// A fired `rearm` tether's device returned and was tethered again;
+        /// `message` names the old and new tether IDs.
+        #[serde(rename = "re-armed")]
+        Rearmed,
+    }
+
+    impl fmt::Display for EventKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                EventKind::Tethered => "tethered",
+                EventKind::Removed => "removed",
+                EventKind::Reattached => "reattached",
+                EventKind::Cancelled => "cancelled",
+                EventKind::Triggered => "triggered",
+                EventKind::Cleared => "cleared",
+                EventKind::ConfigChanged => "config-changed",
+                EventKind::ConfigRejected => "config-rejected",
+                EventKind::Degraded => "degraded",
+                EventKind::Suppressed => "suppressed",
+                EventKind::Rearmed => "re-armed",
+            };
+            f.write_str(name)
+        }
+    }
+
+    /// The outcome of a removal action. Serialized in kebab-case.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum ActionResult {
+        Ran,
+        /// The action failed and `fallback_action` ran instead.
+        FallbackRan,
+        /// The action failed, and so did any fallback.
+        Failed,
+    }
+
+    /// A single daemon event, sent to subscribers as one JSON object per line.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Event {
+        /// Seconds since the Unix epoch.
+        pub timestamp: u64,
+        pub kind: EventKind,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub bus: Option<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub address: Option<u8>,
+        /// Human readable device summary, as shown by `status`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub device: Option<String>,
+        /// Seconds left before the action runs, for `removed` events.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub grace_secs: Option<u64>,
+        /// The removal action, for `triggered` events.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub action: Option<String>,
+        /// How that action went, for `triggered` events; `message` has the
+        /// error when it failed or the fallback ran.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub result: Option<ActionResult>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub message: Option<String>,
+        /// How sure the daemon is that a `removed` device was unplugged
+        /// rather than dropped by its controller: `high` or `low`, with
+        /// `message` saying why.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub confidence: Option<String>,
+        /// UID of the client whose request caused the event.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub uid: Option<u32>,
+        /// ID of the request that caused the event, as in the daemon's logs.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub request_id: Option<String>,
+    }
+
+    impl Event {
+        pub fn new(kind: EventKind) -> Self {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default();
+
+            Self {
+                timestamp,
+                kind,
+                bus: None,
+                address: None,
+                device: None,
+                grace_secs: None,
+                action: None,
+                result: None,
+                message: None,
+                confidence: None,
+                uid: None,
+                request_id: None,
+            }
+        }
+    }
+
+    /// Format Unix seconds as UTC `YYYY-MM-DD<separator>HH:MM:SS`, e.g. a
+    /// space for people or `T` for RFC 3339.
+    pub fn format_utc(timestamp: u64, separator: char) -> String {
+        let days = (timestamp / 86_400) as i64;
+        let secs = timestamp % 86_400;
+
+        // civil-from-days, proleptic Gregorian calendar
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        format!(
+            "{year:04}-{month:02}-{day:02}{separator}{:02}:{:02}:{:02}",
+            secs / 3_600,
+            secs % 3_600 / 60,
+            secs % 60
+        )
+    }
+}
+
+pub mod devices {
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::str::FromStr;
+
+    /// A connected USB device as the daemon sees it, sent by `list-devices`
+    /// as one JSON object per line. The daemon can open devices that clients
+    /// usually cannot, so it fills in the strings.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct UsbDevice {
+        pub bus: u8,
+        pub address: u8,
+        /// Ports from the root hub down, e.g. `[2, 4]` for port 4 of the hub
+        /// on root port 2, as in the sysfs name `1-2.4`. Empty for root hubs.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub ports: Vec<u8>,
+        pub vendor_id: u16,
+        pub product_id: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub product: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub manufacturer: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub serial: Option<String>,
+        /// e.g. `2.0.0`.
+        pub usb_version: String,
+        pub device_version: String,
+        pub class_code: u8,
+        pub sub_class_code: u8,
+        pub protocol_code: u8,
+        /// The device class, or the interface classes for devices that
+        /// declare theirs per interface (class 0).
+        #[serde(default)]
+        pub classes: Vec<u8>,
+        pub max_packet_size: u8,
+        pub num_configurations: u8,
+        /// e.g. `High`, as libusb names it.
+        pub speed: String,
+        /// ID of the tether on this device, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tether_id: Option<u64>,
+        /// State of that tether, as shown by `status` (`watching`,
+        /// `degraded (polling)`, `grace`, `triggered` or `disconnected`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tether_state: Option<String>,
+    }
+
+    impl UsbDevice {
+        pub fn is_hub(&self) -> bool {
+            self.class_code == 0x09
+        }
+
+        /// The sysfs name of the port, e.g. `1-2.4`, or `None` for root hubs.
+        pub fn port_path(&self) -> Option<String> {
+            if self.ports.is_empty() {
+                return None;
+            }
+            let ports: Vec<String> = self.ports.iter().map(u8::to_string).collect();
+            Some(format!("{}-{}", self.bus, ports.join(".")))
+        }
+    }
+
+    /// One device in full, sent by `describe` as a single JSON line: what
+    /// `list-devices` shows plus every configuration the device offers.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct DeviceDescription {
+        #[serde(flatten)]
+        pub device: UsbDevice,
+        #[serde(default)]
+        pub configurations: Vec<Configuration>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Configuration {
+        pub number: u8,
+        /// Whether this is the configuration the device is in now.
+        pub active: bool,
+        /// Current drawn from the bus, in mA.
+        pub max_power: u16,
+        pub self_powered: bool,
+        pub remote_wakeup: bool,
+        /// Every alternate setting of every interface, in descriptor order.
+        pub interfaces: Vec<Interface>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Interface {
+        pub number: u8,
+        pub alternate_setting: u8,
+        pub class_code: u8,
+        pub sub_class_code: u8,
+        pub protocol_code: u8,
+        pub endpoints: Vec<Endpoint>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Endpoint {
+        /// e.g. `0x81` for endpoint 1 IN.
+        pub address: u8,
+        /// `in` or `out`.
+        pub direction: String,
+        /// `control`, `isochronous`, `bulk` or `interrupt`.
+        pub transfer_type: String,
+        pub max_packet_size: u16,
+        /// Polling interval, in frames or microframes as the speed has it.
+        pub interval: u8,
+    }
+
+    /// USB class codes by the names the CLI and rule tethers accept.
+    pub const CLASS_NAMES: &[(u8, &str)] = &[
+        (0x01, "audio"),
+        (0x02, "comm"),
+        (0x03, "hid"),
+        (0x05, "physical"),
+        (0x06, "image"),
+        (0x07, "printer"),
+        (0x08, "storage"),
+        (0x09, "hub"),
+        (0x0a, "cdc-data"),
+        (0x0b, "smartcard"),
+        (0x0d, "security"),
+        (0x0e, "video"),
+        (0x0f, "healthcare"),
+        (0x10, "av"),
+        (0xdc, "diagnostic"),
+        (0xe0, "wireless"),
+        (0xef, "misc"),
+        (0xfe, "app"),
+        (0xff, "vendor"),
+    ];
+
+    pub fn class_name(class: u8) -> String {
+        CLASS_NAMES
+            .iter()
+            .find(|(code, _)| *code == class)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| format!("{class:02x}"))
+    }
+
+    /// Accept a class name from [`CLASS_NAMES`] or a hex class code.
+    pub fn parse_class(value: &str) -> Result<u8, String> {
+        let value = value.to_lowercase();
+        if let Some((code, _)) = CLASS_NAMES.iter().find(|(_, name)| *name == value) {
+            return Ok(*code);
+        }
+        let code = value.trim_start_matches("0x");
+        is_hex(code)
+            .then(|| u8::from_str_radix(code, 16).ok())
+            .flatten()
+            .ok_or_else(|| {
+                let names: Vec<_> = CLASS_NAMES.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "unknown device class '{value}' (expected a hex code or one of {})",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// A vendor or product ID: one to four hex digits, without the sign
+    /// `from_str_radix` would also take.
+    pub fn parse_id(text: &str) -> Option<u16> {
+        if !is_hex(text) {
+            return None;
+        }
+        u16::from_str_radix(text, 16).ok()
+    }
+
+    /// Which attached devices a rule tethers: `<vid>:<pid>` in hex where either
+    /// half may be `*`, or `class:<name or hex code>`.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Pattern {
+        Ids {
+            vendor_id: Option<u16>,
+            product_id: Option<u16>,
+        },
+        Class(u8),
+    }
+
+    impl Pattern {
+        /// `classes` are the device class, or its interface classes when it
+        /// declares them per interface.
+        pub fn matches(&self, vendor_id: u16, product_id: u16, classes: &[u8]) -> bool {
+            match self {
+                Pattern::Ids {
+                    vendor_id: vendor,
+                    product_id: product,
+                } => {
+                    vendor.is_none_or(|vendor| vendor == vendor_id)
+                        && product.is_none_or(|product| product == product_id)
+                }
+                Pattern::Class(class) => classes.contains(class),
+            }
+        }
+    }
+
+    impl FromStr for Pattern {
+        type Err = String;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            if let Some(class) = value.strip_prefix("class:") {
+                return parse_class(class).map(Pattern::Class);
+            }
+            let invalid = || {
+                format!(
+                    "invalid pattern: {value} (expected <vid>:<pid> with * for any, or class:<name>)"
+                )
+            };
+            let (vendor, product) = value.split_once(':').ok_or_else(invalid)?;
+            let part = |part: &str| {
+                if part == "*" {
+                    return Ok(None);
+                }
+                parse_id(part).map(Some).ok_or_else(invalid)
+            };
+            let pattern = Pattern::Ids {
+                vendor_id: part(vendor)?,
+                product_id: part(product)?,
+            };
+            if pattern
+                == (Pattern::Ids {
+                    vendor_id: None,
+                    product_id: None,
+                })
+            {
+                return Err("pattern *:* would match every device; use tether --all".to_string());
+            }
+            Ok(pattern)
+        }
+    }
+
+    impl fmt::Display for Pattern {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Pattern::Ids {
+                    vendor_id,
+                    product_id,
+                } => {
+                    match vendor_id {
+                        Some(id) => write!(f, "{id:04x}")?,
+                        None => f.write_str("*")?,
+                    }
+                    match product_id {
+                        Some(id) => write!(f, ":{id:04x}"),
+                        None => f.write_str(":*"),
+                    }
+                }
+                Pattern::Class(class) => write!(f, "class:{}", class_name(*class)),
+            }
+        }
+    }
+
+    /// Hex digits and nothing else.
+    fn is_hex(text: &str) -> bool {
+        !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_hexdigit())
+    }
+}
+
+pub mod fleet {
+    use serde::{Deserialize, Serialize};
+
+    /// One machine's protection at a glance, sent by the read-only `report`
+    /// command as a single JSON line for dashboards polling many machines.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Report {
+        pub host: String,
+        /// Seconds since the Unix epoch.
+        pub timestamp: u64,
+        /// `armed`, `degraded` or `unarmed`, as `protection` reports it.
+        pub level: String,
+        /// Why the level is not `armed`.
+        #[serde(default)]
+        pub reasons: Vec<String>,
+        #[serde(default)]
+        pub tethers: Vec<TetherReport>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct TetherReport {
+        pub id: u64,
+        /// Device summary, as shown by `status`.
+        pub device: String,
+        /// `watching`, `degraded (polling)`, `grace`, `triggered` or
+        /// `disconnected`.
+        pub state: String,
+    }
+}
+
 pub mod server {
-    use super::DEFAULT_SOCKET_PATH;
+    #[cfg(not(target_vendor = "apple"))]
+    use super::seqpacket::{SeqpacketListener, SeqpacketStream, seqpacket_socket_path};
+    use super::socket_path;
+    use std::env;
+    use std::fmt;
     use std::fs;
     use std::io::{self, Read, Write};
-    use std::os::fd::AsRawFd;
+    use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+    use std::os::unix::fs::{PermissionsExt, chown};
     use std::os::unix::net::{UnixListener, UnixStream};
-    use std::sync::Arc;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::process;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::mpsc::Receiver;
+    use std::sync::{Arc, OnceLock};
     use std::thread;
-    use tracing::{debug, error, info, warn};
+    use tracing::{debug, error, info, info_span, warn};
+
+    /// A handler's successful response: either a single message, or a stream of
+    /// lines written to the client until the sender side is dropped or the
+    /// client disconnects.
+    pub enum Reply {
+        Message(String),
+        Stream(Receiver<String>),
+    }
 
-    pub fn start_ipc_server_once_with_path<F>(socket_path: &str, handler: F)
+    impl From<String> for Reply {
+        fn from(message: String) -> Self {
+            Reply::Message(message)
+        }
+    }
+
+    /// A command received from a client: the first word names the command and
+    /// the remaining whitespace separated words are its arguments. A client
+    /// may put `@<id>` before the command to choose the request's ID, which is
+    /// then echoed at the start of the response; otherwise the server numbers
+    /// the request itself. A `+chunked` word there asks for the response in
+    /// chunks ending with a terminator, see `write_chunked`.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Request {
+        text: String,
+        id: String,
+        echo_id: bool,
+        chunked: bool,
+    }
+
+    impl Request {
+        pub fn new(text: &str) -> Self {
+            static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+            let mut text = text.trim();
+            let mut id = None;
+            let mut chunked = false;
+            loop {
+                let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+                match word.strip_prefix('@') {
+                    Some(tag) if id.is_none() && is_valid_request_id(tag) => id = Some(tag),
+                    _ if word == "+chunked" => chunked = true,
+                    _ => break,
+                }
+                text = rest.trim_start();
+            }
+
+            Self {
+                text: text.to_string(),
+                echo_id: id.is_some(),
+                id: id.map_or_else(
+                    || format!("d{}", NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+                    str::to_string,
+                ),
+                chunked,
+            }
+        }
+
+        /// The request's correlation ID, for logs and events.
+        pub fn id(&self) -> &str {
+            &self.id
+        }
+
+        /// The full command line as sent by the client.
+        pub fn as_str(&self) -> &str {
+            &self.text
+        }
+
+        /// The command name, or an empty string for an empty request.
+        pub fn command(&self) -> &str {
+            self.text.split_whitespace().next().unwrap_or_default()
+        }
+
+        pub fn args(&self) -> impl Iterator<Item = &str> {
+            self.text.split_whitespace().skip(1)
+        }
+    }
+
+    impl fmt::Display for Request {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.text)
+        }
+    }
+
+    /// Credentials of the process on the other end of a connection, from
+    /// SO_PEERCRED, or `getpeereid` on the BSDs where `pid` is 0.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct PeerInfo {
+        pub uid: u32,
+        pub gid: u32,
+        pub pid: i32,
+    }
+
+    impl PeerInfo {
+        pub fn is_root(&self) -> bool {
+            self.uid == 0
+        }
+    }
+
+    /// Local users besides the daemon's own and root who may connect.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct ClientGroup {
+        pub gid: u32,
+        /// UIDs listed as members of the group, who are admitted even when
+        /// it isn't their effective group.
+        pub members: Vec<u32>,
+    }
+
+    impl ClientGroup {
+        fn admits(&self, peer: &PeerInfo) -> bool {
+            peer.gid == self.gid || self.members.contains(&peer.uid)
+        }
+    }
+
+    static CLIENT_GROUP: OnceLock<ClientGroup> = OnceLock::new();
+
+    /// Open the sockets to `group` as well: they are bound group-owned by it
+    /// with mode 0660, and its members are accepted as clients. Handlers then
+    /// tell clients apart by [`PeerInfo::uid`]. Takes effect for servers
+    /// started afterwards and can only be set once; a socket passed by
+    /// systemd keeps the `SocketGroup=` and `SocketMode=` of its unit.
+    pub fn share_with_group(group: ClientGroup) {
+        if CLIENT_GROUP.set(group).is_err() {
+            warn!("client group already set");
+        }
+    }
+
+    /// Give the socket at `socket_path` to the client group, if there is one.
+    fn open_to_group(socket_path: &str) {
+        let Some(group) = CLIENT_GROUP.get() else {
+            return;
+        };
+        let opened = chown(socket_path, None, Some(group.gid))
+            .and_then(|_| fs::set_permissions(socket_path, fs::Permissions::from_mode(0o660)));
+        if let Err(err) = opened {
+            error!("Failed to open {socket_path} to group {}: {err}", group.gid);
+        }
+    }
+
+    /// Request IDs are short and limited to `[A-Za-z0-9_-]`, so they are safe
+    /// to put in log lines and responses.
+    fn is_valid_request_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Rough size of the chunks a message reply is split into.
+    const CHUNK_SIZE: usize = 4096;
+
+    type Handler = Arc<dyn Fn(&Request, &PeerInfo) -> Result<Reply, String> + Send + Sync>;
+
+    fn into_handler<F, R>(handler: F) -> Handler
+    where
+        F: Fn(&Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+        R: Into<Reply>,
+    {
+        Arc::new(move |request: &Request, peer: &PeerInfo| handler(request, peer).map(Into::into))
+    }
+
+    pub fn start_ipc_server_once_with_path<F, R>(socket_path: &str, handler: F)
     where
-        F: Fn(&str) -> Result<String, String> + Send + Sync + 'static,
+        F: Fn(&Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+        R: Into<Reply>,
     {
         let _ = fs::remove_file(socket_path);
         let listener = UnixListener::bind(socket_path).expect("Failed to bind to socket");
         info!("IPC server (once) listening on {socket_path}");
 
-        let handler = Arc::new(handler);
+        let handler = into_handler(handler);
 
         if let Ok((stream, _addr)) = listener.accept() {
             handle_client(stream, handler);
@@ -27,15 +747,26 @@ pub mod server {
         let _ = fs::remove_file(socket_path);
     }
 
-    pub fn start_ipc_server_with_path<F>(socket_path: &str, handler: F)
+    pub fn start_ipc_server_with_path<F, R>(socket_path: &str, handler: F)
     where
-        F: Fn(&str) -> Result<String, String> + Send + Sync + 'static,
+        F: Fn(&Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+        R: Into<Reply>,
     {
-        let _ = fs::remove_file(socket_path);
-        let listener = UnixListener::bind(socket_path).expect("Failed to bind to socket");
-        info!("IPC server listening on {socket_path}");
+        let listener = match activated_listener() {
+            Some(listener) => {
+                info!("IPC server listening on socket passed by systemd");
+                listener
+            }
+            None => {
+                let _ = fs::remove_file(socket_path);
+                let listener = UnixListener::bind(socket_path).expect("Failed to bind to socket");
+                open_to_group(socket_path);
+                info!("IPC server listening on {socket_path}");
+                listener
+            }
+        };
 
-        let handler = Arc::new(handler);
+        let handler = into_handler(handler);
 
         for stream in listener.incoming() {
             match stream {
@@ -52,37 +783,211 @@ pub mod server {
         }
     }
 
-    pub fn start_ipc_server<F>(handler: F)
+    pub fn start_ipc_server<F, R>(handler: F)
     where
-        F: Fn(&str) -> Result<String, String> + Send + Sync + 'static,
+        F: Fn(&Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+        R: Into<Reply>,
     {
-        start_ipc_server_with_path(DEFAULT_SOCKET_PATH, handler)
+        start_ipc_server_with_path(&socket_path(), handler)
     }
 
-    fn handle_client(
-        mut stream: UnixStream,
-        handler: Arc<dyn Fn(&str) -> Result<String, String> + Send + Sync>,
-    ) {
-        if let Err(err) = ensure_same_user(&stream) {
-            warn!("Rejected client: {err}");
-            return;
+    /// The listening socket handed over by systemd socket activation, if any.
+    fn activated_listener() -> Option<UnixListener> {
+        // first passed descriptor, see sd_listen_fds(3)
+        const SD_LISTEN_FDS_START: i32 = 3;
+
+        let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+        let fds = env::var("LISTEN_FDS").ok()?.parse::<u32>().ok()?;
+        if pid != process::id() || fds == 0 {
+            return None;
         }
 
+        // SAFETY: systemd guarantees the descriptor is open and owned by this process
+        Some(unsafe { UnixListener::from_raw_fd(SD_LISTEN_FDS_START) })
+    }
+
+    /// Serve seqpacket clients on `socket_path`, one request and one response
+    /// packet per connection; streamed replies are sent a packet per line.
+    /// Blocks like [`start_ipc_server_with_path`], but returns an error
+    /// rather than panicking when the socket can't be bound.
+    #[cfg(not(target_vendor = "apple"))]
+    pub fn start_seqpacket_server_with_path<F, R>(socket_path: &str, handler: F) -> io::Result<()>
+    where
+        F: Fn(&Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+        R: Into<Reply>,
+    {
+        let _ = fs::remove_file(socket_path);
+        let listener = SeqpacketListener::bind(socket_path)?;
+        open_to_group(socket_path);
+        info!("IPC seqpacket server listening on {socket_path}");
+
+        let handler = into_handler(handler);
+        loop {
+            match listener.accept() {
+                Ok(connection) => {
+                    let handler = Arc::clone(&handler);
+                    thread::spawn(move || handle_seqpacket_client(connection, handler));
+                }
+                Err(err) => error!("Failed to accept seqpacket connection: {err}"),
+            }
+        }
+    }
+
+    #[cfg(not(target_vendor = "apple"))]
+    pub fn start_seqpacket_server<F, R>(handler: F) -> io::Result<()>
+    where
+        F: Fn(&Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+        R: Into<Reply>,
+    {
+        start_seqpacket_server_with_path(&seqpacket_socket_path(), handler)
+    }
+
+    #[cfg(not(target_vendor = "apple"))]
+    fn handle_seqpacket_client(connection: SeqpacketStream, handler: Handler) {
+        let peer = match authorize_peer(connection.as_raw_fd()) {
+            Ok(peer) => peer,
+            Err(err) => {
+                warn!("Rejected client: {err}");
+                return;
+            }
+        };
+
+        let message = match connection.recv() {
+            Ok(Some(packet)) => String::from_utf8_lossy(&packet).into_owned(),
+            Ok(None) => return,
+            Err(err) => {
+                error!("Failed to read from client: {err}");
+                return;
+            }
+        };
+
+        let (request, reply) = answer(&message, &peer, &handler);
+        match reply {
+            Reply::Message(response) => {
+                let response = echo_id(&request, response);
+                let sent = connection.send(response.as_bytes()).or_else(|err| {
+                    connection.send(format!("ERR: {err}; use the stream socket").as_bytes())
+                });
+                if let Err(err) = sent {
+                    error!("Failed to send response: {err}");
+                }
+            }
+            Reply::Stream(lines) => {
+                for line in lines {
+                    if let Err(err) = connection.send(line.as_bytes()) {
+                        debug!("Stream client went away: {err}");
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run `handler` on one request inside a span carrying its ID. Errors
+    /// become `ERR: ` messages, and so does a panicking handler, so the
+    /// client gets an answer rather than a dropped connection.
+    fn answer(message: &str, peer: &PeerInfo, handler: &Handler) -> (Request, Reply) {
+        let request = Request::new(message);
+        let span = info_span!("request", id = %request.id());
+        let _entered = span.enter();
+        debug!("Received IPC message: {message}");
+
+        let handled = panic::catch_unwind(AssertUnwindSafe(|| handler(&request, peer)));
+        let reply = match handled {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(err)) => {
+                warn!("Handler reported error: {err}");
+                Reply::Message(format!("ERR: {err}"))
+            }
+            Err(payload) => {
+                let cause = payload
+                    .downcast_ref::<&str>()
+                    .map(|cause| cause.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown cause".to_string());
+                error!(command = request.command(), cause = %cause, "handler panicked");
+                Reply::Message(format!(
+                    "ERR: internal error handling {}",
+                    request.command()
+                ))
+            }
+        };
+        (request, reply)
+    }
+
+    /// Put the request's ID in front of `response` if the client chose it.
+    fn echo_id(request: &Request, response: String) -> String {
+        if request.echo_id {
+            format!("@{} {response}", request.id())
+        } else {
+            response
+        }
+    }
+
+    /// Write `reply` as chunks: each is its length in hex on a line of its
+    /// own followed by that many bytes, and a `0` line ends the response, so
+    /// the client can tell a complete response from a dropped connection.
+    /// The echoed ID, if any, comes first on a line of its own. Messages are
+    /// split on line boundaries into chunks of about [`CHUNK_SIZE`] bytes;
+    /// streamed replies send a chunk per line.
+    fn write_chunked(stream: &mut UnixStream, request: &Request, reply: Reply) -> io::Result<()> {
+        fn frame(stream: &mut UnixStream, chunk: &str) -> io::Result<()> {
+            writeln!(stream, "{:x}", chunk.len())?;
+            stream.write_all(chunk.as_bytes())
+        }
+
+        if request.echo_id {
+            writeln!(stream, "@{}", request.id())?;
+        }
+        match reply {
+            Reply::Message(body) => {
+                let mut chunk = String::new();
+                for line in body.split_inclusive('\n') {
+                    if !chunk.is_empty() && chunk.len() + line.len() > CHUNK_SIZE {
+                        frame(stream, &chunk)?;
+                        chunk.clear();
+                    }
+                    chunk.push_str(line);
+                }
+                if !chunk.is_empty() {
+                    frame(stream, &chunk)?;
+                }
+            }
+            Reply::Stream(lines) => {
+                for line in lines {
+                    frame(stream, &format!("{line}\n"))?;
+                }
+            }
+        }
+        stream.write_all(b"0\n")
+    }
+
+    fn handle_client(mut stream: UnixStream, handler: Handler) {
+        let peer = match authorize_peer(stream.as_raw_fd()) {
+            Ok(peer) => peer,
+            Err(err) => {
+                warn!("Rejected client: {err}");
+                return;
+            }
+        };
+
         let mut buffer = [0; 512];
         match stream.read(&mut buffer) {
             Ok(size) => {
                 let message = String::from_utf8_lossy(&buffer[..size]);
-                debug!("Received IPC message: {message}");
-
-                let response = match handler(message.trim()) {
-                    Ok(body) => body,
-                    Err(err) => {
-                        warn!("Handler reported error: {err}");
-                        format!("ERR: {err}")
+                let (request, reply) = answer(&message, &peer, &handler);
+                let written = match reply {
+                    reply if request.chunked => write_chunked(&mut stream, &request, reply),
+                    Reply::Message(response) => {
+                        stream.write_all(echo_id(&request, response).as_bytes())
+                    }
+                    Reply::Stream(lines) => {
+                        stream_lines(&mut stream, lines);
+                        return;
                     }
                 };
 
-                if let Err(err) = stream.write_all(response.as_bytes()) {
+                if let Err(err) = written {
                     error!("Failed to send response: {err}");
                 }
             }
@@ -92,8 +997,20 @@ pub mod server {
         }
     }
 
-    fn ensure_same_user(stream: &UnixStream) -> io::Result<()> {
-        let fd = stream.as_raw_fd();
+    fn stream_lines(stream: &mut UnixStream, lines: Receiver<String>) {
+        for line in lines {
+            let written = stream
+                .write_all(line.as_bytes())
+                .and_then(|_| stream.write_all(b"\n"));
+            if let Err(err) = written {
+                debug!("Stream client went away: {err}");
+                return;
+            }
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_info(fd: RawFd) -> io::Result<PeerInfo> {
         let mut credentials = libc::ucred {
             pid: 0,
             uid: 0,
@@ -116,43 +1033,493 @@ pub mod server {
         }
 
         if len as usize != std::mem::size_of::<libc::ucred>() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Unexpected credential size from socket",
-            ));
+            return Err(io::Error::other("Unexpected credential size from socket"));
         }
 
+        Ok(PeerInfo {
+            uid: credentials.uid,
+            gid: credentials.gid,
+            pid: credentials.pid,
+        })
+    }
+
+    /// The BSDs only report the peer's effective ids, through
+    /// `getpeereid` (LOCAL_PEERCRED underneath), so `pid` is left as 0.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_info(fd: RawFd) -> io::Result<PeerInfo> {
+        let mut uid = 0;
+        let mut gid = 0;
+
+        let rc = unsafe { libc::getpeereid(fd, &mut uid, &mut gid) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(PeerInfo { uid, gid, pid: 0 })
+    }
+
+    /// Accept clients running as the daemon's user, root, or members of the
+    /// group set with [`share_with_group`].
+    fn authorize_peer(fd: RawFd) -> io::Result<PeerInfo> {
+        let peer = peer_info(fd)?;
         let current_uid = unsafe { libc::geteuid() };
-        if credentials.uid != current_uid {
-            return Err(io::Error::new(
+        if admitted(&peer, current_uid, CLIENT_GROUP.get()) {
+            Ok(peer)
+        } else {
+            Err(io::Error::new(
                 io::ErrorKind::PermissionDenied,
-                "Client UID does not match daemon UID",
-            ));
+                "Client is not the daemon's user, root or in its client group",
+            ))
         }
+    }
 
-        Ok(())
+    fn admitted(peer: &PeerInfo, daemon_uid: u32, group: Option<&ClientGroup>) -> bool {
+        // root may always talk to a daemon running as a less privileged user
+        peer.uid == daemon_uid || peer.is_root() || group.is_some_and(|group| group.admits(peer))
     }
 }
 
 pub mod client {
-    use super::DEFAULT_SOCKET_PATH;
-    use std::io::{self, Read, Write};
+    use super::devices::{DeviceDescription, UsbDevice};
+    use super::events::Event;
+    use super::fleet::Report;
+    use super::{PROTOCOL_VERSION, USER_MODE, features, runtime_dir, socket_path};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::io::{self, BufRead, BufReader, Read, Write};
     use std::net::Shutdown;
     use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use std::process;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Duration;
+
+    thread_local! {
+        static LAST_REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    /// The ID of the last request this thread sent, to quote when reporting
+    /// a failure so it can be found in the daemon's logs and events.
+    pub fn last_request_id() -> Option<String> {
+        LAST_REQUEST_ID.with(|id| id.borrow().clone())
+    }
+
+    /// Carry a request ID over from the thread that sent the request.
+    #[cfg(feature = "async")]
+    pub(crate) fn set_last_request_id(id: Option<String>) {
+        LAST_REQUEST_ID.with(|last| *last.borrow_mut() = id);
+    }
+
+    /// `<pid>-<n>`, unique among this process's requests.
+    fn next_request_id() -> String {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        format!("{}-{}", process::id(), NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Optional settings appended to a `tether` command as `key=value` pairs.
+    #[derive(Clone, Debug, Default)]
+    pub struct TetherOptions {
+        /// Removal action: `lock`, `scrub`, `suspend`, `kill-network`,
+        /// `terminate-remote`, `systemd:stop:UNIT,...`, `vm:pause:DOMAIN,...`,
+        /// `container:stop:NAME,...`, or `custom:/absolute/path`.
+        pub action: Option<String>,
+        /// Seconds to wait after removal before running the action.
+        pub grace_secs: Option<u64>,
+        /// Minimum seconds between two runs of the action.
+        pub cooldown_secs: Option<u64>,
+        /// Tether the device again when it returns after the action ran.
+        pub rearm: Option<bool>,
+        /// Watch by polling the bus instead of through hotplug callbacks.
+        pub poll: Option<bool>,
+        /// Swap the settings of an existing tether on the device in place.
+        pub replace: bool,
+    }
+
+    impl TetherOptions {
+        fn to_args(&self) -> String {
+            let mut args = String::new();
+            if let Some(action) = &self.action {
+                args.push_str(&format!(" action={action}"));
+            }
+            if let Some(grace) = self.grace_secs {
+                args.push_str(&format!(" grace={grace}"));
+            }
+            if let Some(cooldown) = self.cooldown_secs {
+                args.push_str(&format!(" cooldown={cooldown}"));
+            }
+            if let Some(rearm) = self.rearm {
+                args.push_str(&format!(" rearm={rearm}"));
+            }
+            if let Some(poll) = self.poll {
+                args.push_str(&format!(" poll={poll}"));
+            }
+            if self.replace {
+                args.push_str(" replace=true");
+            }
+            args
+        }
+    }
+
+    /// A tether named in a management command: by the ID `tether` reported,
+    /// or by its device's bus number and address.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum TetherTarget {
+        Id(u64),
+        Device { bus: u8, address: u8 },
+    }
+
+    impl TetherTarget {
+        fn to_args(self) -> String {
+            match self {
+                TetherTarget::Id(id) => format!("id={id}"),
+                TetherTarget::Device { bus, address } => format!("{bus} {address}"),
+            }
+        }
+    }
+
+    impl fmt::Display for TetherTarget {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                TetherTarget::Id(id) => write!(f, "tether {id}"),
+                TetherTarget::Device { bus, address } => write!(f, "{bus:03}:{address:03}"),
+            }
+        }
+    }
+
+    /// Iterator over events streamed by the daemon after a `subscribe` command.
+    pub struct Subscription {
+        reader: BufReader<UnixStream>,
+    }
+
+    impl Subscription {
+        /// Make `next` yield a `WouldBlock`/`TimedOut` error if no event arrives
+        /// within `timeout`; `None` blocks forever.
+        pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            self.reader.get_ref().set_read_timeout(timeout)
+        }
+    }
+
+    impl Iterator for Subscription {
+        type Item = io::Result<Event>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => None,
+                Ok(_) => {
+                    let line = line.trim();
+                    if let Some(err) = line.strip_prefix("ERR: ") {
+                        return Some(Err(io::Error::other(err.to_string())));
+                    }
+                    Some(serde_json::from_str(line).map_err(io::Error::other))
+                }
+                Err(err) => Some(Err(err)),
+            }
+        }
+    }
+
+    /// Iterator over the chunks of a response sent with `+chunked`, read one
+    /// at a time as the daemon writes them. Ends with an `UnexpectedEof`
+    /// error if the connection closes before the terminator.
+    pub struct Chunks {
+        source: ChunkSource,
+        first: bool,
+        done: bool,
+    }
+
+    enum ChunkSource {
+        Framed(BufReader<UnixStream>),
+        /// The whole response from a daemon without the `chunked` feature.
+        Whole(Option<String>),
+    }
+
+    impl Chunks {
+        fn new(source: ChunkSource) -> Self {
+            Self {
+                source,
+                first: true,
+                done: false,
+            }
+        }
+
+        fn read_chunk(&mut self) -> io::Result<Option<String>> {
+            let reader = match &mut self.source {
+                ChunkSource::Framed(reader) => reader,
+                ChunkSource::Whole(body) => return Ok(body.take().filter(|body| !body.is_empty())),
+            };
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "deadmand closed the connection before the end of the response",
+                ));
+            }
+            let len = usize::from_str_radix(header.trim(), 16).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid chunk header: {}", header.trim()),
+                )
+            })?;
+            if len == 0 {
+                return Ok(None);
+            }
+
+            let mut chunk = vec![0u8; len];
+            reader.read_exact(&mut chunk)?;
+            String::from_utf8(chunk)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        }
+    }
+
+    impl Iterator for Chunks {
+        type Item = io::Result<String>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.done {
+                return None;
+            }
+            let chunk = self.read_chunk();
+            let first = std::mem::replace(&mut self.first, false);
+            match chunk {
+                Ok(Some(chunk)) => match chunk.strip_prefix("ERR: ").filter(|_| first) {
+                    Some(err) => {
+                        self.done = true;
+                        Some(Err(io::Error::other(err.trim().to_string())))
+                    }
+                    None => Some(Ok(chunk)),
+                },
+                Ok(None) => {
+                    self.done = true;
+                    None
+                }
+                Err(err) => {
+                    self.done = true;
+                    Some(Err(err))
+                }
+            }
+        }
+    }
+
+    /// [`socket_path`](super::socket_path), except that when user mode was
+    /// not asked for and no system daemon is listening, a user daemon in this
+    /// session is used instead.
+    fn client_socket_path() -> String {
+        let path = socket_path();
+        if USER_MODE.get().is_some() || Path::new(&path).exists() {
+            return path;
+        }
+        let file_name = Path::new(&path).file_name().unwrap_or_default();
+        let user_path = runtime_dir().join(file_name);
+        if user_path.exists() {
+            user_path.display().to_string()
+        } else {
+            path
+        }
+    }
+
+    /// Prefix `message` with a fresh request ID, remembering it for
+    /// [`last_request_id`]. Returns the ID and the tagged message.
+    fn tag_request(message: &str) -> (String, String) {
+        let id = next_request_id();
+        LAST_REQUEST_ID.with(|last| *last.borrow_mut() = Some(id.clone()));
+        let tagged = format!("@{id} {message}");
+        (id, tagged)
+    }
+
+    /// Strip the echoed request ID from a response. Daemons that predate
+    /// request IDs don't echo one.
+    fn untag_response(id: &str, response: &[u8]) -> String {
+        let response = String::from_utf8_lossy(response);
+        let response = response
+            .strip_prefix(&format!("@{id}"))
+            .unwrap_or(&response);
+        response.trim().to_string()
+    }
 
     fn send_ipc_message_with_path(socket_path: &str, message: &str) -> io::Result<String> {
+        let (id, tagged) = tag_request(message);
+
         let mut stream = UnixStream::connect(socket_path)?;
-        stream.write_all(message.as_bytes())?;
+        stream.write_all(tagged.as_bytes())?;
         let _ = stream.shutdown(Shutdown::Write);
 
         let mut buffer = Vec::new();
         stream.read_to_end(&mut buffer)?;
 
-        Ok(String::from_utf8_lossy(&buffer).trim().to_string())
+        Ok(untag_response(&id, &buffer))
+    }
+
+    /// What a daemon said about itself in reply to `hello`.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct ServerInfo {
+        pub protocol: u32,
+        /// The daemon's version; `None` for daemons that predate `hello`.
+        pub version: Option<String>,
+        pub features: Vec<String>,
+        pub commands: Vec<String>,
+    }
+
+    impl ServerInfo {
+        /// Commands every daemon has understood, assumed for ones that
+        /// predate `hello`.
+        const LEGACY_COMMANDS: [&str; 3] = ["status", "tether", "severe"];
+
+        fn legacy() -> Self {
+            Self {
+                protocol: 0,
+                version: None,
+                features: Vec::new(),
+                commands: Self::LEGACY_COMMANDS.map(str::to_string).to_vec(),
+            }
+        }
+
+        /// Parse the `key: value` lines of a `hello` response.
+        fn parse(response: &str) -> io::Result<Self> {
+            let mut info = Self {
+                protocol: 0,
+                version: None,
+                features: Vec::new(),
+                commands: Vec::new(),
+            };
+            for line in response.lines() {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let words = || value.split_whitespace().map(str::to_string).collect();
+                match key.trim() {
+                    "protocol" => {
+                        info.protocol = value.trim().parse().map_err(|_| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("invalid protocol version: {}", value.trim()),
+                            )
+                        })?
+                    }
+                    "version" => info.version = Some(value.trim().to_string()),
+                    "features" => info.features = words(),
+                    "commands" => info.commands = words(),
+                    _ => {}
+                }
+            }
+            Ok(info)
+        }
+
+        pub fn has_feature(&self, feature: &str) -> bool {
+            self.features.iter().any(|known| known == feature)
+        }
+
+        /// Whether the daemon understands `command`, so a client can leave
+        /// out what it can't do instead of getting `unknown command`.
+        pub fn supports(&self, command: &str) -> bool {
+            self.commands.iter().any(|known| known == command)
+        }
+    }
+
+    /// Introduce this client to the daemon and learn its protocol version,
+    /// features and commands. A daemon that predates `hello` is reported as
+    /// protocol 0 with only the original commands.
+    pub fn hello() -> io::Result<ServerInfo> {
+        hello_with_path(&client_socket_path())
+    }
+
+    pub fn hello_with_path(socket_path: &str) -> io::Result<ServerInfo> {
+        let message = format!("hello {PROTOCOL_VERSION} {}", features().join(" "));
+        let response = send_ipc_message_with_path(socket_path, &message)?;
+        // daemons that predate `hello` reject it as an unknown command
+        if response.starts_with("ERR: ") {
+            return Ok(ServerInfo::legacy());
+        }
+        ServerInfo::parse(&response)
+    }
+
+    /// [`hello_with_path`], asked once per socket for the life of the
+    /// process.
+    pub fn server_info_with_path(socket_path: &str) -> io::Result<ServerInfo> {
+        static KNOWN: OnceLock<Mutex<HashMap<String, ServerInfo>>> = OnceLock::new();
+        let known = KNOWN.get_or_init(Default::default);
+
+        if let Some(info) = known
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(socket_path)
+        {
+            return Ok(info.clone());
+        }
+        let info = hello_with_path(socket_path)?;
+        known
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(socket_path.to_string(), info.clone());
+        Ok(info)
+    }
+
+    pub fn server_info() -> io::Result<ServerInfo> {
+        server_info_with_path(&client_socket_path())
+    }
+
+    /// Send any command and read its response in chunks as they arrive
+    /// rather than all at once. An `ERR: ` response is yielded as an error.
+    /// A daemon without the `chunked` feature is sent a plain request, and
+    /// its whole response is yielded as one chunk.
+    pub fn request_chunked(message: &str) -> io::Result<Chunks> {
+        request_chunked_with_path(&client_socket_path(), message)
+    }
+
+    pub fn request_chunked_with_path(socket_path: &str, message: &str) -> io::Result<Chunks> {
+        if !server_info_with_path(socket_path)?.has_feature("chunked") {
+            let response = send_ipc_message_with_path(socket_path, message)?;
+            return Ok(Chunks::new(ChunkSource::Whole(Some(response))));
+        }
+
+        let (id, tagged) = tag_request(&format!("+chunked {message}"));
+
+        let mut stream = UnixStream::connect(socket_path)?;
+        stream.write_all(tagged.as_bytes())?;
+        let _ = stream.shutdown(Shutdown::Write);
+
+        let mut reader = BufReader::new(stream);
+        let mut echoed = String::new();
+        reader.read_line(&mut echoed)?;
+        if echoed.trim_end() != format!("@{id}") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected response to request {id}: {}", echoed.trim()),
+            ));
+        }
+
+        Ok(Chunks::new(ChunkSource::Framed(reader)))
+    }
+
+    /// Send any command over the seqpacket socket, where the request and the
+    /// response each arrive as one packet. Commands that stream (`subscribe`)
+    /// are not supported here.
+    #[cfg(not(target_vendor = "apple"))]
+    pub fn request_seqpacket(message: &str) -> io::Result<String> {
+        request_seqpacket_with_path(&super::seqpacket::seqpacket_socket_path(), message)
+    }
+
+    #[cfg(not(target_vendor = "apple"))]
+    pub fn request_seqpacket_with_path(socket_path: &str, message: &str) -> io::Result<String> {
+        let (id, tagged) = tag_request(message);
+
+        let connection = super::seqpacket::SeqpacketStream::connect(socket_path)?;
+        connection.send(tagged.as_bytes())?;
+        connection.shutdown_write();
+
+        let response = connection.recv()?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "deadmand closed the connection without responding",
+            )
+        })?;
+        Ok(untag_response(&id, &response))
     }
 
     fn send_ipc_message(message: &str) -> io::Result<String> {
-        send_ipc_message_with_path(DEFAULT_SOCKET_PATH, message)
+        send_ipc_message_with_path(&client_socket_path(), message)
     }
 
     pub fn get_status() -> io::Result<String> {
@@ -163,6 +1530,18 @@ pub mod client {
         send_ipc_message_with_path(socket_path, "status")
     }
 
+    /// The `status` response in chunks of whole lines, as the daemon sends
+    /// them.
+    pub fn status_chunks() -> io::Result<Chunks> {
+        request_chunked("status")
+    }
+
+    /// [`status_chunks`] with the libusb version and how each device is
+    /// watched.
+    pub fn verbose_status_chunks() -> io::Result<Chunks> {
+        request_chunked("status verbose")
+    }
+
     pub fn tether(bus: &str, device_id: &str) -> io::Result<String> {
         let message = format!("{} {} {}", "tether", bus, device_id);
         send_ipc_message(&message)
@@ -173,6 +1552,314 @@ pub mod client {
         send_ipc_message_with_path(socket_path, &message)
     }
 
+    pub fn tether_with_options(
+        bus: &str,
+        device_id: &str,
+        options: &TetherOptions,
+    ) -> io::Result<String> {
+        tether_with_options_and_path(&client_socket_path(), bus, device_id, options)
+    }
+
+    pub fn tether_with_options_and_path(
+        socket_path: &str,
+        bus: &str,
+        device_id: &str,
+        options: &TetherOptions,
+    ) -> io::Result<String> {
+        let message = format!("tether {} {}{}", bus, device_id, options.to_args());
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// Tether the one device matching `selector` (`vvvv:pppp`, `serial:S` or
+    /// `id=N`) with `options` unless an equivalent tether exists. The reply
+    /// is a JSON object whose `changed` says which. `replace` is ignored.
+    pub fn ensure_tether(selector: &str, options: &TetherOptions) -> io::Result<String> {
+        ensure_tether_with_path(&client_socket_path(), selector, options)
+    }
+
+    pub fn ensure_tether_with_path(
+        socket_path: &str,
+        selector: &str,
+        options: &TetherOptions,
+    ) -> io::Result<String> {
+        let options = TetherOptions {
+            replace: false,
+            ..options.clone()
+        };
+        let message = format!("ensure-tether {selector}{}", options.to_args());
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// Tether every device matching `pattern` (`vvvv:pppp` with `*` for
+    /// either half, or `class:NAME`), those attached now and later, until
+    /// the rule is removed. `replace` and `rearm` are ignored.
+    pub fn tether_rule(pattern: &str, options: &TetherOptions) -> io::Result<String> {
+        tether_rule_with_path(&client_socket_path(), pattern, options)
+    }
+
+    pub fn tether_rule_with_path(
+        socket_path: &str,
+        pattern: &str,
+        options: &TetherOptions,
+    ) -> io::Result<String> {
+        let options = TetherOptions {
+            rearm: None,
+            replace: false,
+            ..options.clone()
+        };
+        let message = format!("tether-rule {pattern}{}", options.to_args());
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// Remove the rule with `id` and clear the tethers it made.
+    pub fn untether_rule(id: u64) -> io::Result<String> {
+        untether_rule_with_path(&client_socket_path(), id)
+    }
+
+    pub fn untether_rule_with_path(socket_path: &str, id: u64) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, &format!("untether-rule {id}"))
+    }
+
+    /// Change the action and/or grace period of an existing tether without
+    /// untethering it. `replace` is ignored.
+    pub fn set(bus: &str, device_id: &str, options: &TetherOptions) -> io::Result<String> {
+        set_with_path(&client_socket_path(), bus, device_id, options)
+    }
+
+    pub fn set_with_path(
+        socket_path: &str,
+        bus: &str,
+        device_id: &str,
+        options: &TetherOptions,
+    ) -> io::Result<String> {
+        let options = TetherOptions {
+            replace: false,
+            ..options.clone()
+        };
+        let message = format!("set {} {}{}", bus, device_id, options.to_args());
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// [`set`] for a tether named by ID or device.
+    pub fn set_target(target: &TetherTarget, options: &TetherOptions) -> io::Result<String> {
+        set_target_with_path(&client_socket_path(), target, options)
+    }
+
+    pub fn set_target_with_path(
+        socket_path: &str,
+        target: &TetherTarget,
+        options: &TetherOptions,
+    ) -> io::Result<String> {
+        let options = TetherOptions {
+            replace: false,
+            ..options.clone()
+        };
+        let message = format!("set {}{}", target.to_args(), options.to_args());
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// Clear one tether, leaving the others in place.
+    pub fn untether(target: &TetherTarget) -> io::Result<String> {
+        untether_with_path(&client_socket_path(), target)
+    }
+
+    pub fn untether_with_path(socket_path: &str, target: &TetherTarget) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, &format!("untether {}", target.to_args()))
+    }
+
+    /// Dismiss the fired tether with `id` from `status`, or every fired
+    /// tether the caller may manage.
+    pub fn ack(id: Option<u64>) -> io::Result<String> {
+        ack_with_path(&client_socket_path(), id)
+    }
+
+    pub fn ack_with_path(socket_path: &str, id: Option<u64>) -> io::Result<String> {
+        match id {
+            Some(id) => send_ipc_message_with_path(socket_path, &format!("ack id={id}")),
+            None => send_ipc_message_with_path(socket_path, "ack"),
+        }
+    }
+
+    /// Daemon health: `ok: ...`, or `degraded` followed by one line per
+    /// tether whose monitor thread stalled or was restarted.
+    pub fn ping() -> io::Result<String> {
+        ping_with_path(&client_socket_path())
+    }
+
+    pub fn ping_with_path(socket_path: &str) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, "ping")
+    }
+
+    /// How the daemon runs and the health of each configured lock backend,
+    /// one `key: value` line each.
+    pub fn doctor() -> io::Result<String> {
+        doctor_with_path(&client_socket_path())
+    }
+
+    pub fn doctor_with_path(socket_path: &str) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, "doctor")
+    }
+
+    /// The daemon's protection level: a first line of `armed: ...`,
+    /// `degraded` or `unarmed`, then one line per reason.
+    pub fn protection() -> io::Result<String> {
+        protection_with_path(&client_socket_path())
+    }
+
+    pub fn protection_with_path(socket_path: &str) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, "protection")
+    }
+
+    /// Whether `user` may authenticate to the PAM `service`: `allow`, or
+    /// `deny` and one line per tethered device that is absent.
+    pub fn pam_check(service: &str, user: &str) -> io::Result<String> {
+        pam_check_with_path(&client_socket_path(), service, user)
+    }
+
+    pub fn pam_check_with_path(socket_path: &str, service: &str, user: &str) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, &format!("pam-check {service} {user}"))
+    }
+
+    /// Dry-run the removal action of the `tether`, or `action`, or the
+    /// default action. The reply has a header line, then one `ok: ` or
+    /// `fail: ` line per step.
+    pub fn test_action(tether: Option<&TetherTarget>, action: Option<&str>) -> io::Result<String> {
+        test_action_with_path(&client_socket_path(), tether, action)
+    }
+
+    pub fn test_action_with_path(
+        socket_path: &str,
+        tether: Option<&TetherTarget>,
+        action: Option<&str>,
+    ) -> io::Result<String> {
+        let mut message = "test-action".to_string();
+        if let Some(tether) = tether {
+            message.push_str(&format!(" {}", tether.to_args()));
+        }
+        if let Some(action) = action {
+            message.push_str(&format!(" action={action}"));
+        }
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    pub fn cancel() -> io::Result<String> {
+        send_ipc_message("cancel")
+    }
+
+    pub fn cancel_with_path(socket_path: &str) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, "cancel")
+    }
+
+    /// Recent daemon events, oldest first.
+    pub fn get_log() -> io::Result<Vec<Event>> {
+        get_log_with_path(&client_socket_path())
+    }
+
+    pub fn get_log_with_path(socket_path: &str) -> io::Result<Vec<Event>> {
+        let mut events = Vec::new();
+        for chunk in request_chunked_with_path(socket_path, "log")? {
+            for line in chunk?.lines().filter(|line| !line.trim().is_empty()) {
+                events.push(serde_json::from_str(line).map_err(io::Error::other)?);
+            }
+        }
+        Ok(events)
+    }
+
+    /// Every connected USB device, read by the daemon.
+    pub fn list_devices() -> io::Result<Vec<UsbDevice>> {
+        list_devices_with_path(&client_socket_path())
+    }
+
+    pub fn list_devices_with_path(socket_path: &str) -> io::Result<Vec<UsbDevice>> {
+        let mut devices = Vec::new();
+        for chunk in request_chunked_with_path(socket_path, "list-devices")? {
+            for line in chunk?.lines().filter(|line| !line.trim().is_empty()) {
+                devices.push(serde_json::from_str(line).map_err(io::Error::other)?);
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Every descriptor of the one connected device `selector` matches, and
+    /// the tether on it, if any.
+    pub fn describe(selector: &str) -> io::Result<DeviceDescription> {
+        describe_with_path(&client_socket_path(), selector)
+    }
+
+    pub fn describe_with_path(socket_path: &str, selector: &str) -> io::Result<DeviceDescription> {
+        let response = send_ipc_message_with_path(socket_path, &format!("describe {selector}"))?;
+        if let Some(err) = response.trim().strip_prefix("ERR: ") {
+            return Err(io::Error::other(err.trim().to_string()));
+        }
+        serde_json::from_str(response.trim()).map_err(io::Error::other)
+    }
+
+    /// The protection level and tethers, from the read-only `report`
+    /// command.
+    pub fn report() -> io::Result<Report> {
+        report_with_path(&client_socket_path())
+    }
+
+    pub fn report_with_path(socket_path: &str) -> io::Result<Report> {
+        let response = send_ipc_message_with_path(socket_path, "report")?;
+        if let Some(err) = response.trim().strip_prefix("ERR: ") {
+            return Err(io::Error::other(err.trim().to_string()));
+        }
+        serde_json::from_str(response.trim()).map_err(io::Error::other)
+    }
+
+    /// The daemon's settings and tethers as snapshot JSON, for `import` on
+    /// another machine.
+    pub fn export() -> io::Result<String> {
+        export_with_path(&client_socket_path())
+    }
+
+    pub fn export_with_path(socket_path: &str) -> io::Result<String> {
+        request_chunked_with_path(socket_path, "export")?.collect()
+    }
+
+    /// Apply the snapshot at `path`, which must be absolute since the daemon
+    /// reads it. Unsigned snapshots are refused unless `allow_unsigned`.
+    pub fn import(path: &str, allow_unsigned: bool) -> io::Result<String> {
+        import_with_path(&client_socket_path(), path, allow_unsigned)
+    }
+
+    pub fn import_with_path(
+        socket_path: &str,
+        path: &str,
+        allow_unsigned: bool,
+    ) -> io::Result<String> {
+        let mut message = format!("import {path}");
+        if allow_unsigned {
+            message.push_str(" allow-unsigned");
+        }
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// Have the daemon replace itself with the binary now on disk, keeping
+    /// its tethers. It replies before restarting.
+    pub fn re_exec() -> io::Result<String> {
+        re_exec_with_path(&client_socket_path())
+    }
+
+    pub fn re_exec_with_path(socket_path: &str) -> io::Result<String> {
+        send_ipc_message_with_path(socket_path, "re-exec")
+    }
+
+    pub fn subscribe() -> io::Result<Subscription> {
+        subscribe_with_path(&client_socket_path())
+    }
+
+    pub fn subscribe_with_path(socket_path: &str) -> io::Result<Subscription> {
+        let mut stream = UnixStream::connect(socket_path)?;
+        stream.write_all(b"subscribe")?;
+        let _ = stream.shutdown(Shutdown::Write);
+
+        Ok(Subscription {
+            reader: BufReader::new(stream),
+        })
+    }
+
     pub fn severe() -> io::Result<String> {
         send_ipc_message("severe")
     }
@@ -180,4 +1867,55 @@ pub mod client {
     pub fn severe_with_path(socket_path: &str) -> io::Result<String> {
         send_ipc_message_with_path(socket_path, "severe")
     }
+
+    /// Clear only the tethers matching any of `selectors` (every tether when
+    /// empty) and none of `except`. Selectors are `id=<n>`, `<vid>:<pid>` or
+    /// `serial:<serial>`.
+    pub fn severe_matching(selectors: &[&str], except: &[&str]) -> io::Result<String> {
+        severe_matching_with_path(&client_socket_path(), selectors, except)
+    }
+
+    pub fn severe_matching_with_path(
+        socket_path: &str,
+        selectors: &[&str],
+        except: &[&str],
+    ) -> io::Result<String> {
+        let mut message = String::from("severe");
+        for selector in selectors {
+            message.push(' ');
+            message.push_str(selector);
+        }
+        if !except.is_empty() {
+            message.push_str(" except ");
+            message.push_str(&except.join(" "));
+        }
+        send_ipc_message_with_path(socket_path, &message)
+    }
+
+    /// Read the daemon's log filter, or replace it when `filter` is given
+    /// (`EnvFilter` syntax, e.g. `debug` or `deadman_core=trace`).
+    pub fn log_level(filter: Option<&str>) -> io::Result<String> {
+        log_level_with_path(&client_socket_path(), filter)
+    }
+
+    pub fn log_level_with_path(socket_path: &str, filter: Option<&str>) -> io::Result<String> {
+        match filter {
+            Some(filter) => send_ipc_message_with_path(socket_path, &format!("log-level {filter}")),
+            None => send_ipc_message_with_path(socket_path, "log-level"),
+        }
+    }
+
+    /// Send a `config` subcommand, e.g. `["set", "default_action", "suspend"]`.
+    pub fn config(args: &[&str]) -> io::Result<String> {
+        config_with_path(&client_socket_path(), args)
+    }
+
+    pub fn config_with_path(socket_path: &str, args: &[&str]) -> io::Result<String> {
+        let mut message = String::from("config");
+        for arg in args {
+            message.push(' ');
+            message.push_str(arg);
+        }
+        send_ipc_message_with_path(socket_path, &message)
+    }
 }
@@ -0,0 +1,164 @@
+//! Dispatch requests to handlers by command name. Each handler declares its
+//! arguments as a type implementing [`FromArgs`], so missing, malformed and
+//! extra arguments are rejected before the handler runs.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tracing::{debug, warn};
+
+use crate::server::{PeerInfo, Reply, Request};
+use crate::{PROTOCOL_VERSION, features};
+
+type Handler = Arc<dyn Fn(&Request, &PeerInfo) -> Result<Reply, String> + Send + Sync>;
+
+/// The arguments of a request, taken front to back while parsing.
+pub struct Args<'a> {
+    words: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(request: &'a Request) -> Self {
+        Self {
+            words: request.args().collect(),
+            next: 0,
+        }
+    }
+
+    /// Take the next argument as is.
+    pub fn next_word(&mut self) -> Option<&'a str> {
+        let word = self.words.get(self.next).copied();
+        self.next += usize::from(word.is_some());
+        word
+    }
+
+    /// Take and parse the next argument, described as `what` in errors
+    /// (`missing bus number`, `invalid bus number: x`).
+    pub fn required<T: FromStr>(&mut self, what: &str) -> Result<T, String> {
+        self.optional(what)?
+            .ok_or_else(|| format!("missing {what}"))
+    }
+
+    /// Like [`required`](Self::required), but `None` when the arguments have
+    /// run out.
+    pub fn optional<T: FromStr>(&mut self, what: &str) -> Result<Option<T>, String> {
+        self.next_word()
+            .map(|word| word.parse().map_err(|_| format!("invalid {what}: {word}")))
+            .transpose()
+    }
+
+    /// Take every remaining argument.
+    pub fn rest(&mut self) -> Vec<&'a str> {
+        let rest = self.words[self.next..].to_vec();
+        self.next = self.words.len();
+        rest
+    }
+
+    /// Fail if any argument was left unparsed.
+    fn finish(&self) -> Result<(), String> {
+        match self.words.get(self.next) {
+            Some(extra) => Err(format!("unexpected argument: {extra}")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Arguments a command handler takes, parsed from the request.
+pub trait FromArgs: Sized {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String>;
+}
+
+/// No arguments.
+impl FromArgs for () {
+    fn from_args(_args: &mut Args<'_>) -> Result<Self, String> {
+        Ok(())
+    }
+}
+
+/// Every remaining argument, for commands that parse their own.
+impl FromArgs for Vec<String> {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        Ok(args.rest().into_iter().map(str::to_string).collect())
+    }
+}
+
+impl<A: FromArgs, B: FromArgs> FromArgs for (A, B) {
+    fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+        Ok((A::from_args(args)?, B::from_args(args)?))
+    }
+}
+
+/// Command handlers by name, usable as the handler of
+/// [`start_ipc_server`](crate::server::start_ipc_server) through
+/// [`handle`](Self::handle). Every router answers `hello` with the protocol
+/// version, features and registered commands, so clients can tell what the
+/// daemon supports before relying on it.
+#[derive(Clone, Default)]
+pub struct Router {
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `handler` for `command`, replacing any earlier one. It is
+    /// called with its parsed arguments, the request and the peer.
+    pub fn on<A, R, F>(mut self, command: &'static str, handler: F) -> Self
+    where
+        A: FromArgs,
+        R: Into<Reply>,
+        F: Fn(A, &Request, &PeerInfo) -> Result<R, String> + Send + Sync + 'static,
+    {
+        let handler = move |request: &Request, peer: &PeerInfo| {
+            let mut args = Args::new(request);
+            let parsed = A::from_args(&mut args)?;
+            args.finish()?;
+            handler(parsed, request, peer).map(Into::into)
+        };
+        self.handlers.insert(command, Arc::new(handler));
+        self
+    }
+
+    /// Run the handler registered for the request's command.
+    pub fn handle(&self, request: &Request, peer: &PeerInfo) -> Result<Reply, String> {
+        match request.command() {
+            "" => Err("empty command".to_string()),
+            command => match self.handlers.get(command) {
+                Some(handler) => handler(request, peer),
+                None if command == "hello" => self.hello(request),
+                None => {
+                    warn!(command, "unknown command");
+                    Err(format!("unknown command: {command}"))
+                }
+            },
+        }
+    }
+
+    /// `hello [<protocol> [<feature>...]]`: the client may say what it
+    /// speaks, which is only logged; the reply is one `key: value` line each
+    /// for the protocol, version, features and commands.
+    fn hello(&self, request: &Request) -> Result<Reply, String> {
+        let mut args = Args::new(request);
+        let protocol: Option<u32> = args.optional("protocol version")?;
+        let client_features = args.rest().join(" ");
+        debug!(
+            protocol = protocol.unwrap_or(0),
+            features = client_features,
+            "client hello"
+        );
+
+        let mut commands: Vec<_> = self.handlers.keys().copied().collect();
+        commands.push("hello");
+        commands.sort_unstable();
+        Ok(Reply::Message(format!(
+            "protocol: {PROTOCOL_VERSION}\nversion: {}\nfeatures: {}\ncommands: {}",
+            env!("CARGO_PKG_VERSION"),
+            features().join(" "),
+            commands.join(" ")
+        )))
+    }
+}
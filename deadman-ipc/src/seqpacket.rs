@@ -0,0 +1,192 @@
+//! Unix `SOCK_SEQPACKET` sockets. Each send is delivered as one packet, so a
+//! request or response is never split across reads or merged with the next
+//! one, unlike the stream socket where the server reads into a fixed buffer.
+
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+
+use super::socket_path;
+
+/// Largest packet either side will send or accept.
+pub const MAX_PACKET: usize = 64 * 1024;
+
+/// The seqpacket socket next to [`socket_path`], e.g.
+/// `/tmp/deadman-ipc.seq.sock`.
+pub fn seqpacket_socket_path() -> String {
+    let path = socket_path();
+    match path.strip_suffix(".sock") {
+        Some(stem) => format!("{stem}.seq.sock"),
+        None => format!("{path}.seq"),
+    }
+}
+
+/// A listening seqpacket socket.
+pub struct SeqpacketListener {
+    fd: OwnedFd,
+}
+
+impl SeqpacketListener {
+    pub fn bind(path: &str) -> io::Result<Self> {
+        let fd = new_socket()?;
+        let (address, len) = socket_address(path)?;
+        // SAFETY: address is a valid sockaddr_un of `len` bytes
+        let rc = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_un as *const libc::sockaddr,
+                len,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a bound socket
+        if unsafe { libc::listen(fd.as_raw_fd(), 128) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    pub fn accept(&self) -> io::Result<SeqpacketStream> {
+        // SAFETY: fd is a listening socket and the peer address is not wanted
+        let fd = unsafe {
+            libc::accept4(
+                self.fd.as_raw_fd(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                libc::SOCK_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: accept4 returned a new descriptor that nothing else owns
+        Ok(SeqpacketStream {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+}
+
+/// One end of a seqpacket connection.
+pub struct SeqpacketStream {
+    fd: OwnedFd,
+}
+
+impl SeqpacketStream {
+    pub fn connect(path: &str) -> io::Result<Self> {
+        let fd = new_socket()?;
+        let (address, len) = socket_address(path)?;
+        // SAFETY: address is a valid sockaddr_un of `len` bytes
+        let rc = unsafe {
+            libc::connect(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_un as *const libc::sockaddr,
+                len,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    /// Send `packet` as a single message.
+    pub fn send(&self, packet: &[u8]) -> io::Result<()> {
+        if packet.len() > MAX_PACKET {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} byte message exceeds the {MAX_PACKET} byte packet limit",
+                    packet.len()
+                ),
+            ));
+        }
+        // SAFETY: the pointer and length describe `packet`
+        let sent = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                packet.as_ptr().cast(),
+                packet.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Receive the next packet, or `None` once the peer has closed.
+    pub fn recv(&self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = vec![0u8; MAX_PACKET];
+        let mut iov = libc::iovec {
+            iov_base: buffer.as_mut_ptr().cast(),
+            iov_len: buffer.len(),
+        };
+        // SAFETY: msghdr is plain data; zeroed is a valid empty header
+        let mut header: libc::msghdr = unsafe { mem::zeroed() };
+        header.msg_iov = &mut iov;
+        header.msg_iovlen = 1;
+
+        // SAFETY: header points at one iovec covering `buffer`
+        let received = unsafe { libc::recvmsg(self.fd.as_raw_fd(), &mut header, 0) };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if header.msg_flags & libc::MSG_TRUNC != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("packet exceeds the {MAX_PACKET} byte limit"),
+            ));
+        }
+        if received == 0 {
+            return Ok(None);
+        }
+        buffer.truncate(received as usize);
+        Ok(Some(buffer))
+    }
+
+    /// Stop sending; the peer's next `recv` returns `None`.
+    pub fn shutdown_write(&self) {
+        // SAFETY: fd is a connected socket
+        unsafe { libc::shutdown(self.fd.as_raw_fd(), libc::SHUT_WR) };
+    }
+}
+
+impl AsRawFd for SeqpacketStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+fn new_socket() -> io::Result<OwnedFd> {
+    // SAFETY: socket has no preconditions
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: socket returned a new descriptor that nothing else owns
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn socket_address(path: &str) -> io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    // SAFETY: sockaddr_un is plain data; zeroed leaves sun_path terminated
+    let mut address: libc::sockaddr_un = unsafe { mem::zeroed() };
+    address.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    let bytes = path.as_bytes();
+    if bytes.len() >= address.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("socket path is too long: {path}"),
+        ));
+    }
+    for (slot, byte) in address.sun_path.iter_mut().zip(bytes) {
+        *slot = *byte as libc::c_char;
+    }
+
+    let len = mem::offset_of!(libc::sockaddr_un, sun_path) + bytes.len() + 1;
+    Ok((address, len as libc::socklen_t))
+}
@@ -0,0 +1,160 @@
+//! A stand-in for deadmand, for tests and for working on clients without a
+//! root daemon or USB hardware.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::events::Event;
+use crate::server::{self, PeerInfo, Reply, Request};
+use crate::{PROTOCOL_VERSION, features};
+
+/// An IPC server on its own socket that answers each command with a
+/// scripted response and records every request it receives.
+///
+/// Unscripted commands are answered as deadmand answers unknown ones, except
+/// that `hello` lists the scripted commands, `subscribe` streams the events
+/// passed to [`emit`](Self::emit), and `log` replays them. The socket is
+/// removed when the mock is dropped.
+pub struct MockDaemon {
+    socket_path: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    responses: HashMap<String, Result<String, String>>,
+    received: Vec<String>,
+    events: Vec<Event>,
+    subscribers: Vec<Sender<String>>,
+}
+
+impl MockDaemon {
+    /// Serve on a fresh socket in the temporary directory.
+    pub fn start() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        let socket_path = env::temp_dir().join(format!(
+            "deadman-mock-{}-{}.sock",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self::start_with_path(&socket_path.display().to_string())
+    }
+
+    /// Serve on `socket_path`, e.g. [`socket_path`](crate::socket_path) of
+    /// an instance set aside for the mock, so unmodified clients reach it.
+    pub fn start_with_path(socket_path: &str) -> Self {
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let _ = fs::remove_file(socket_path);
+
+        thread::spawn({
+            let socket_path = socket_path.to_string();
+            let state = Arc::clone(&state);
+            move || {
+                server::start_ipc_server_with_path(&socket_path, move |request, peer| {
+                    answer(&state, request, peer)
+                })
+            }
+        });
+        // the server binds on its own thread; wait for the socket to appear
+        for _ in 0..100 {
+            if fs::metadata(socket_path).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Self {
+            socket_path: socket_path.to_string(),
+            state,
+        }
+    }
+
+    pub fn socket_path(&self) -> &str {
+        &self.socket_path
+    }
+
+    /// Answer `command` (the first word of a request) with `response`.
+    pub fn respond(&self, command: &str, response: impl Into<String>) -> &Self {
+        self.lock()
+            .responses
+            .insert(command.to_string(), Ok(response.into()));
+        self
+    }
+
+    /// Answer `command` with an `ERR: <error>` response.
+    pub fn fail(&self, command: &str, error: impl Into<String>) -> &Self {
+        self.lock()
+            .responses
+            .insert(command.to_string(), Err(error.into()));
+        self
+    }
+
+    /// Send `event` to every subscriber and add it to the `log`.
+    pub fn emit(&self, event: Event) {
+        let mut state = self.lock();
+        if let Ok(line) = serde_json::to_string(&event) {
+            state
+                .subscribers
+                .retain(|subscriber| subscriber.send(line.clone()).is_ok());
+        }
+        state.events.push(event);
+    }
+
+    /// Every request received so far, oldest first, without the request ID.
+    pub fn received(&self) -> Vec<String> {
+        self.lock().received.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for MockDaemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+fn answer(state: &Mutex<MockState>, request: &Request, _peer: &PeerInfo) -> Result<Reply, String> {
+    let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+    state.received.push(request.as_str().to_string());
+
+    if let Some(response) = state.responses.get(request.command()) {
+        return response.clone().map(Reply::Message);
+    }
+    match request.command() {
+        "hello" => {
+            let mut commands: Vec<&str> = state.responses.keys().map(String::as_str).collect();
+            commands.extend(["hello", "log", "subscribe"]);
+            commands.sort_unstable();
+            commands.dedup();
+            Ok(Reply::Message(format!(
+                "protocol: {PROTOCOL_VERSION}\nversion: mock\nfeatures: {}\ncommands: {}",
+                features().join(" "),
+                commands.join(" ")
+            )))
+        }
+        "log" => state
+            .events
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map(|lines| Reply::Message(lines.join("\n")))
+            .map_err(|err| format!("failed to serialize events: {err}")),
+        "subscribe" => {
+            let (sender, receiver) = mpsc::channel();
+            state.subscribers.push(sender);
+            Ok(Reply::Stream(receiver))
+        }
+        "" => Err("empty command".to_string()),
+        other => Err(format!("unknown command: {other}")),
+    }
+}
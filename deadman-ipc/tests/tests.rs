@@ -1,9 +1,13 @@
 use deadman_ipc::client;
-use deadman_ipc::server;
+use deadman_ipc::devices::{Pattern, parse_class, parse_id};
+use deadman_ipc::events::{Event, EventKind};
+use deadman_ipc::i18n;
+use deadman_ipc::router::{Args, FromArgs, Router};
+use deadman_ipc::server::{self, PeerInfo, Reply, Request};
 use rand::distr::{Alphanumeric, SampleString};
 use std::fs;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
@@ -20,8 +24,8 @@ fn test_ipc_server_and_client_status() {
         let _ = fs::remove_file(&socket_path);
     }
     let handle = thread::spawn(move || {
-        server::start_ipc_server_once_with_path(&socket_path_clone, |msg| {
-            if msg == "status" {
+        server::start_ipc_server_once_with_path(&socket_path_clone, |msg, _peer| {
+            if msg.as_str() == "status" {
                 Ok("OK".to_string())
             } else {
                 Err("Unknown command".to_string())
@@ -43,8 +47,8 @@ fn test_ipc_tether_command() {
     }
     let socket_path_clone = socket_path.clone();
     let handle = thread::spawn(move || {
-        server::start_ipc_server_once_with_path(&socket_path_clone, |msg| {
-            if msg.starts_with("tether ") {
+        server::start_ipc_server_once_with_path(&socket_path_clone, |msg, _peer| {
+            if msg.command() == "tether" {
                 Ok(format!("Tethered: {}", msg))
             } else {
                 Err("Unknown command".to_string())
@@ -66,8 +70,8 @@ fn test_ipc_severe_command() {
     }
     let socket_path_clone = socket_path.clone();
     let handle = thread::spawn(move || {
-        server::start_ipc_server_once_with_path(&socket_path_clone, |msg| {
-            if msg == "severe" {
+        server::start_ipc_server_once_with_path(&socket_path_clone, |msg, _peer| {
+            if msg.as_str() == "severe" {
                 Ok("Severe mode enabled".to_string())
             } else {
                 Err("Unknown command".to_string())
@@ -80,3 +84,319 @@ fn test_ipc_severe_command() {
     let _ = fs::remove_file(&socket_path);
     let _ = handle.join();
 }
+
+#[test]
+fn test_ipc_handler_receives_peer_credentials() {
+    let socket_path = unique_socket_path();
+    if Path::new(&socket_path).exists() {
+        let _ = fs::remove_file(&socket_path);
+    }
+    let socket_path_clone = socket_path.clone();
+    let handle = thread::spawn(move || {
+        server::start_ipc_server_once_with_path(&socket_path_clone, |_msg, peer| {
+            Ok(format!("{} {}", peer.uid, peer.pid))
+        });
+    });
+    thread::sleep(Duration::from_millis(50));
+    let response = client::get_status_with_path(&socket_path).unwrap();
+    let expected = format!("{} {}", unsafe { libc::geteuid() }, std::process::id());
+    assert_eq!(response, expected);
+    let _ = fs::remove_file(&socket_path);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_ipc_subscribe_streams_events() {
+    let socket_path = unique_socket_path();
+    if Path::new(&socket_path).exists() {
+        let _ = fs::remove_file(&socket_path);
+    }
+    let socket_path_clone = socket_path.clone();
+    let handle = thread::spawn(move || {
+        server::start_ipc_server_once_with_path(&socket_path_clone, |msg, _peer| {
+            if msg.as_str() == "subscribe" {
+                let (sender, receiver) = mpsc::channel();
+                for kind in [EventKind::Tethered, EventKind::Removed] {
+                    let line = serde_json::to_string(&Event::new(kind)).unwrap();
+                    sender.send(line).unwrap();
+                }
+                Ok(Reply::Stream(receiver))
+            } else {
+                Err("Unknown command".to_string())
+            }
+        });
+    });
+    thread::sleep(Duration::from_millis(50));
+    let kinds: Vec<EventKind> = client::subscribe_with_path(&socket_path)
+        .unwrap()
+        .map(|event| event.unwrap().kind)
+        .collect();
+    assert_eq!(kinds, vec![EventKind::Tethered, EventKind::Removed]);
+    let _ = fs::remove_file(&socket_path);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_ipc_log_command() {
+    let socket_path = unique_socket_path();
+    if Path::new(&socket_path).exists() {
+        let _ = fs::remove_file(&socket_path);
+    }
+    let socket_path_clone = socket_path.clone();
+    thread::spawn(move || {
+        server::start_ipc_server_with_path(&socket_path_clone, |msg, _peer| {
+            if msg.as_str() == "log" {
+                let lines: Vec<String> = [EventKind::Tethered, EventKind::Triggered]
+                    .into_iter()
+                    .map(|kind| serde_json::to_string(&Event::new(kind)).unwrap())
+                    .collect();
+                Ok(lines.join("\n"))
+            } else {
+                Err("Unknown command".to_string())
+            }
+        });
+    });
+    thread::sleep(Duration::from_millis(50));
+    let events = client::get_log_with_path(&socket_path).unwrap();
+    let kinds: Vec<EventKind> = events.into_iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, vec![EventKind::Tethered, EventKind::Triggered]);
+    let _ = fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_ipc_panicking_handler_gets_an_error_reply() {
+    let socket_path = unique_socket_path();
+    let socket_path_clone = socket_path.clone();
+    thread::spawn(move || {
+        server::start_ipc_server_with_path(&socket_path_clone, |msg, _peer| {
+            if msg.as_str() == "status" {
+                panic!("state went away");
+            }
+            Ok(format!("Tethered: {}", msg))
+        });
+    });
+    thread::sleep(Duration::from_millis(50));
+    let response = client::get_status_with_path(&socket_path).unwrap();
+    assert_eq!(response, "ERR: internal error handling status");
+    // the server keeps answering
+    let response = client::tether_with_path(&socket_path, "bus1", "dev42").unwrap();
+    assert!(response.contains("Tethered: tether bus1 dev42"));
+    let _ = fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_ipc_request_id_is_echoed() {
+    let socket_path = unique_socket_path();
+    let socket_path_clone = socket_path.clone();
+    let handle = thread::spawn(move || {
+        server::start_ipc_server_once_with_path(&socket_path_clone, |msg, _peer| {
+            Ok(format!("{} {}", msg.as_str(), msg.id()))
+        });
+    });
+    thread::sleep(Duration::from_millis(50));
+    let response = client::get_status_with_path(&socket_path).unwrap();
+    let id = client::last_request_id().expect("client should record the request id");
+    assert_eq!(response, format!("status {id}"));
+    let _ = fs::remove_file(&socket_path);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_ipc_seqpacket_request() {
+    let socket_path = unique_socket_path();
+    let socket_path_clone = socket_path.clone();
+    thread::spawn(move || {
+        server::start_seqpacket_server_with_path(&socket_path_clone, |msg, _peer| {
+            Ok(format!("{} {}", msg.command(), "x".repeat(8192)))
+        })
+    });
+    thread::sleep(Duration::from_millis(50));
+    let response = client::request_seqpacket_with_path(&socket_path, "status").unwrap();
+    assert_eq!(response, format!("status {}", "x".repeat(8192)));
+    let _ = fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_ipc_chunked_response() {
+    let socket_path = unique_socket_path();
+    let socket_path_clone = socket_path.clone();
+    let body: String = (0..1000).map(|n| format!("line {n}\n")).collect();
+    let expected = body.clone();
+    let router = Router::new().on("log", move |(), _: &Request, _: &PeerInfo| Ok(body.clone()));
+    thread::spawn(move || {
+        server::start_ipc_server_with_path(&socket_path_clone, move |request, peer| {
+            router.handle(request, peer)
+        })
+    });
+    thread::sleep(Duration::from_millis(50));
+    let chunks: Vec<String> = client::request_chunked_with_path(&socket_path, "log")
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| chunk.ends_with('\n')));
+    assert_eq!(chunks.concat(), expected);
+    let _ = fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_router_parses_arguments() {
+    struct Pair(u8, u8);
+
+    impl FromArgs for Pair {
+        fn from_args(args: &mut Args<'_>) -> Result<Self, String> {
+            Ok(Pair(
+                args.required("bus number")?,
+                args.required("device id")?,
+            ))
+        }
+    }
+
+    let router = Router::new()
+        .on("ping", |(), _: &Request, _: &PeerInfo| {
+            Ok("pong".to_string())
+        })
+        .on("sum", |Pair(a, b), _: &Request, _: &PeerInfo| {
+            Ok(format!("{}", u16::from(a) + u16::from(b)))
+        });
+    let socket_path = unique_socket_path();
+    let socket_path_clone = socket_path.clone();
+    thread::spawn(move || {
+        server::start_ipc_server_with_path(&socket_path_clone, move |request, peer| {
+            router.handle(request, peer)
+        })
+    });
+    thread::sleep(Duration::from_millis(50));
+
+    let reply = |message: &str| -> Result<String, String> {
+        client::request_chunked_with_path(&socket_path, message)
+            .map_err(|err| err.to_string())?
+            .collect::<Result<String, _>>()
+            .map_err(|err| err.to_string())
+    };
+    assert_eq!(reply("ping").unwrap(), "pong");
+    assert_eq!(reply("sum 3 4").unwrap(), "7");
+    assert_eq!(reply("sum 3").unwrap_err(), "missing device id");
+    assert_eq!(reply("sum 3 x").unwrap_err(), "invalid device id: x");
+    assert_eq!(reply("ping now").unwrap_err(), "unexpected argument: now");
+    assert_eq!(reply("pong").unwrap_err(), "unknown command: pong");
+
+    let info = client::hello_with_path(&socket_path).unwrap();
+    assert_eq!(info.protocol, deadman_ipc::PROTOCOL_VERSION);
+    assert!(info.has_feature("chunked"));
+    assert!(info.supports("sum") && info.supports("hello"));
+    assert!(!info.supports("pong"));
+    let _ = fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_i18n_untranslated_messages_pass_through() {
+    // no catalog is installed for the test domain, so msgids come back as is
+    assert_eq!(
+        i18n::gettext("no USB devices found"),
+        "no USB devices found"
+    );
+    assert_eq!(
+        i18n::gettext_f("bus {} address {}", &["001", "004"]),
+        "bus 001 address 004"
+    );
+    assert_eq!(
+        i18n::ngettext_f("{} device", "{} devices", 1, &["1"]),
+        "1 device"
+    );
+    assert_eq!(
+        i18n::ngettext_f("{} device", "{} devices", 3, &["3"]),
+        "3 devices"
+    );
+    assert_eq!(i18n::gettext_f("{} and {}", &["one"]), "one and {}");
+}
+
+#[test]
+fn test_pattern_parses_ids() {
+    assert_eq!(
+        "1050:0407".parse(),
+        Ok(Pattern::Ids {
+            vendor_id: Some(0x1050),
+            product_id: Some(0x0407),
+        })
+    );
+    assert_eq!(
+        "1050:0407".parse::<Pattern>().unwrap().to_string(),
+        "1050:0407"
+    );
+}
+
+#[test]
+fn test_pattern_wildcards() {
+    let vendor: Pattern = "1050:*".parse().unwrap();
+    assert_eq!(
+        vendor,
+        Pattern::Ids {
+            vendor_id: Some(0x1050),
+            product_id: None,
+        }
+    );
+    assert!(vendor.matches(0x1050, 0x0407, &[]));
+    assert!(vendor.matches(0x1050, 0x0010, &[]));
+    assert!(!vendor.matches(0x046d, 0x0407, &[]));
+    assert_eq!(vendor.to_string(), "1050:*");
+
+    let product: Pattern = "*:0407".parse().unwrap();
+    assert!(product.matches(0x1050, 0x0407, &[]));
+    assert!(!product.matches(0x1050, 0x0408, &[]));
+    assert_eq!(product.to_string(), "*:0407");
+}
+
+#[test]
+fn test_pattern_classes() {
+    let by_name: Pattern = "class:hid".parse().unwrap();
+    assert_eq!(by_name, Pattern::Class(0x03));
+    assert_eq!("class:0b".parse(), Ok(Pattern::Class(0x0b)));
+    assert!(by_name.matches(0x1050, 0x0407, &[0x03, 0x0b]));
+    assert!(!by_name.matches(0x1050, 0x0407, &[0x08]));
+    assert_eq!(by_name.to_string(), "class:hid");
+}
+
+#[test]
+fn test_pattern_rejects_malformed() {
+    for malformed in [
+        "",
+        "1050",
+        "1050:",
+        ":0407",
+        "xyz:0407",
+        "1050:10000",
+        "+1050:*",
+        "1050:0407:1",
+        "class:",
+        "class:nope",
+    ] {
+        assert!(
+            malformed.parse::<Pattern>().is_err(),
+            "{malformed:?} should not parse"
+        );
+    }
+    assert_eq!(
+        "*:*".parse::<Pattern>(),
+        Err("pattern *:* would match every device; use tether --all".to_string())
+    );
+}
+
+#[test]
+fn test_hex_ids_reject_signs() {
+    assert_eq!(parse_id("1d6b"), Some(0x1d6b));
+    assert_eq!(parse_id("FFFF"), Some(0xffff));
+    for malformed in ["", "+1d6b", "-1", "1d6b0", "0x1d6b", " 1d6b"] {
+        assert_eq!(parse_id(malformed), None, "{malformed:?} should not parse");
+    }
+
+    assert_eq!(parse_class("hid"), Ok(0x03));
+    assert_eq!(parse_class("03"), Ok(0x03));
+    assert_eq!(parse_class("0x0b"), Ok(0x0b));
+    for malformed in ["", "0x", "+03", "-3", "100", "nope"] {
+        assert!(
+            parse_class(malformed).is_err(),
+            "{malformed:?} should not parse"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use deadman_ipc::{instance, socket_path, unit_name};
+
+/// Polkit action that lets `pkexec deadman` run without a root shell.
+const POLKIT_ACTION_ID: &str = "com.dominicegginton.deadman.manage";
+
+/// A generated file and where it belongs.
+struct InstallFile {
+    path: PathBuf,
+    contents: String,
+}
+
+/// Write (or with `dry_run`, print) the service and socket units, and for
+/// system installs the polkit policy and udev rule.
+pub fn run_install(user: bool, dry_run: bool, bin_dir: Option<PathBuf>) -> Result<()> {
+    let bin_dir = match bin_dir {
+        Some(dir) => dir,
+        None => default_bin_dir()?,
+    };
+
+    let files = if user {
+        user_files(&bin_dir)?
+    } else {
+        system_files(&bin_dir)
+    };
+
+    if dry_run {
+        for file in &files {
+            println!("# {}", file.path.display());
+            println!("{}", file.contents);
+        }
+        return Ok(());
+    }
+
+    for file in &files {
+        if let Some(parent) = file.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&file.path, &file.contents)
+            .with_context(|| format!("failed to write {}", file.path.display()))?;
+        println!("wrote {}", file.path.display());
+    }
+
+    let unit = unit_name();
+    if user {
+        println!(
+            "run `systemctl --user daemon-reload && systemctl --user enable --now {unit}.socket` to start"
+        );
+    } else {
+        println!(
+            "run `systemctl daemon-reload && systemctl enable --now {unit}.socket && udevadm control --reload` to start"
+        );
+    }
+    Ok(())
+}
+
+/// The directory holding the running `deadman`, which is expected to also
+/// contain `deadmand`.
+fn default_bin_dir() -> Result<PathBuf> {
+    let exe = env::current_exe().context("failed to locate the deadman executable")?;
+    exe.parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| anyhow!("failed to locate the deadman executable directory"))
+}
+
+fn system_files(bin_dir: &Path) -> Vec<InstallFile> {
+    let unit = unit_name();
+    vec![
+        InstallFile {
+            path: PathBuf::from(format!("/etc/systemd/system/{unit}.service")),
+            contents: service_unit(bin_dir, false),
+        },
+        InstallFile {
+            path: PathBuf::from(format!("/etc/systemd/system/{unit}.socket")),
+            contents: socket_unit(),
+        },
+        InstallFile {
+            path: PathBuf::from(format!(
+                "/usr/share/polkit-1/actions/{POLKIT_ACTION_ID}.policy"
+            )),
+            contents: polkit_policy(bin_dir),
+        },
+        InstallFile {
+            path: PathBuf::from("/etc/udev/rules.d/70-deadman.rules"),
+            contents: udev_rules(),
+        },
+    ]
+}
+
+fn user_files(bin_dir: &Path) -> Result<Vec<InstallFile>> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config"))
+            .ok_or_else(|| anyhow!("neither XDG_CONFIG_HOME nor HOME is set"))?,
+    };
+    let unit_dir = config_dir.join("systemd").join("user");
+    let unit = unit_name();
+
+    Ok(vec![
+        InstallFile {
+            path: unit_dir.join(format!("{unit}.service")),
+            contents: service_unit(bin_dir, true),
+        },
+        InstallFile {
+            path: unit_dir.join(format!("{unit}.socket")),
+            contents: socket_unit(),
+        },
+    ])
+}
+
+fn service_unit(bin_dir: &Path, user: bool) -> String {
+    let unit = unit_name();
+    let (user_flag, wanted_by, unprivileged) = if user {
+        (" --user", "default.target", "")
+    } else {
+        (
+            "",
+            "multi-user.target",
+            "# To run without root, uncomment and grant the user polkit rights for
+# org.freedesktop.login1.lock-sessions (and .suspend if used):
+#User=deadman
+#AmbientCapabilities=CAP_DAC_OVERRIDE
+",
+        )
+    };
+    let environment = match instance() {
+        Some(name) => format!("Environment=DEADMAN_INSTANCE={name}\n"),
+        None => String::new(),
+    };
+    format!(
+        "[Unit]
+Description=Deadman daemon
+Requires={unit}.socket
+After={unit}.socket
+
+[Service]
+{environment}ExecStart={deadmand}{user_flag}
+Restart=on-failure
+RestartSec=5
+{unprivileged}
+[Install]
+WantedBy={wanted_by}
+",
+        deadmand = bin_dir.join("deadmand").display()
+    )
+}
+
+fn socket_unit() -> String {
+    format!(
+        "[Unit]
+Description=Deadman daemon IPC socket
+
+[Socket]
+ListenStream={socket}
+SocketMode=0600
+RemoveOnStop=true
+
+[Install]
+WantedBy=sockets.target
+",
+        socket = socket_path()
+    )
+}
+
+fn polkit_policy(bin_dir: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE policyconfig PUBLIC
+ "-//freedesktop//DTD PolicyKit Policy Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/PolicyKit/1/policyconfig.dtd">
+<policyconfig>
+  <vendor>deadman</vendor>
+  <vendor_url>https://github.com/dominicegginton/deadman</vendor_url>
+  <action id="{POLKIT_ACTION_ID}">
+    <description>Manage deadman tethers</description>
+    <message>Authentication is required to change deadman tethers</message>
+    <defaults>
+      <allow_any>auth_admin</allow_any>
+      <allow_inactive>auth_admin</allow_inactive>
+      <allow_active>auth_admin_keep</allow_active>
+    </defaults>
+    <annotate key="org.freedesktop.policykit.exec.path">{deadman}</annotate>
+  </action>
+</policyconfig>
+"#,
+        deadman = bin_dir.join("deadman").display()
+    )
+}
+
+fn udev_rules() -> String {
+    "# Let the logged-in user read USB descriptors so product names show up in
+# `deadman` listings and the GUI; deadmand itself runs as root.
+SUBSYSTEM==\"usb\", ENV{DEVTYPE}==\"usb_device\", TAG+=\"uaccess\"
+"
+    .to_string()
+}
@@ -1,17 +1,199 @@
-use anyhow::{Context as AnyhowContext, Result, anyhow};
-use clap::{Parser, Subcommand};
-use rusb::{Context, UsbContext};
+mod install;
+mod render;
+mod statusbar;
+mod watch;
 
-use deadman_ipc::client;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as AnyhowContext, Result, anyhow, bail};
+use clap::{Args, Parser, Subcommand};
+
+use deadman_ipc::client::{self, TetherOptions, TetherTarget};
+use deadman_ipc::devices::{self, Pattern, UsbDevice, class_name};
+use deadman_ipc::events::{Event, EventKind, format_utc};
+use deadman_ipc::fleet::Report;
+use deadman_ipc::i18n::{gettext, gettext_f, ngettext_f};
+
+use crate::render::Style;
+use crate::statusbar::{BarState, StatusFormat};
 
 fn main() -> Result<()> {
+    deadman_ipc::i18n::init();
     let cli = Cli::parse();
+    if let Some(name) = &cli.instance {
+        deadman_ipc::set_instance(name).map_err(|err| anyhow!(err))?;
+    }
+    if cli.user {
+        deadman_ipc::set_user_mode();
+    }
+    // the listing filters only apply without a subcommand; checked here rather
+    // than by clap so the global --instance can still precede a subcommand
+    if cli.command.is_some() && cli.filters.is_set() {
+        bail!("--class, --vendor and --tethered-only only apply to the device listing");
+    }
 
     match cli.command {
-        Some(Command::Status) => run_status()?,
-        Some(Command::Tether { bus, device }) => run_tether(bus, device)?,
-        Some(Command::Severe) => run_severe()?,
-        None => list_devices()?,
+        Some(Command::Status {
+            wait,
+            timeout,
+            format,
+            remote,
+            verbose,
+        }) => {
+            if !remote.is_empty() {
+                run_status_remote(&remote)
+            } else if wait {
+                run_status_wait(timeout)?
+            } else if format == StatusFormat::Text {
+                run_status(verbose, Style::detect(cli.no_color))?
+            } else {
+                run_status_bar(format)
+            }
+        }
+        Some(Command::Tether {
+            bus,
+            device,
+            all,
+            id,
+            class,
+            action,
+            grace,
+            cooldown,
+            rearm,
+            poll,
+            replace,
+        }) => {
+            let options = TetherOptions {
+                action,
+                grace_secs: grace,
+                cooldown_secs: cooldown,
+                rearm,
+                poll,
+                replace,
+            };
+            match (bus, device) {
+                (Some(bus), Some(device)) => run_tether(bus, device, &options)?,
+                _ if all => run_tether_all(id.as_deref(), class.as_deref(), &options)?,
+                _ => match (id, class) {
+                    (Some(id), _) => run_tether_rule(&id, &options)?,
+                    (None, Some(class)) => run_tether_rule(&format!("class:{class}"), &options)?,
+                    (None, None) => bail!("pass a bus and device address, --all, --id or --class"),
+                },
+            }
+        }
+        Some(Command::EnsureTether {
+            id,
+            serial,
+            action,
+            grace,
+            cooldown,
+            rearm,
+            poll,
+        }) => {
+            let selector = match (id, serial) {
+                (Some(id), _) => id,
+                (None, Some(serial)) => format!("serial:{serial}"),
+                (None, None) => bail!("pass --id or --serial"),
+            };
+            let options = TetherOptions {
+                action,
+                grace_secs: grace,
+                cooldown_secs: cooldown,
+                rearm,
+                poll,
+                ..TetherOptions::default()
+            };
+            run_ensure_tether(&selector, &options)?
+        }
+        Some(Command::Set {
+            bus,
+            device,
+            id,
+            action,
+            grace,
+            cooldown,
+            rearm,
+            poll,
+        }) => {
+            let options = TetherOptions {
+                action,
+                grace_secs: grace,
+                cooldown_secs: cooldown,
+                rearm,
+                poll,
+                ..TetherOptions::default()
+            };
+            run_set(&tether_target(bus, device, id)?, &options)?
+        }
+        Some(Command::Untether {
+            bus,
+            device,
+            id,
+            rule,
+        }) => match rule {
+            Some(rule) => run_untether_rule(rule)?,
+            None => run_untether(&tether_target(bus, device, id)?)?,
+        },
+        Some(Command::Ping) => run_ping()?,
+        Some(Command::Doctor) => run_doctor()?,
+        Some(Command::Protection) => run_protection()?,
+        Some(Command::Report) => run_report()?,
+        Some(Command::Describe { selector, json }) => run_describe(&selector, json)?,
+        Some(Command::PamCheck {
+            service,
+            login,
+            allow_unreachable,
+        }) => run_pam_check(&service, &login, allow_unreachable)?,
+        Some(Command::TestAction {
+            bus,
+            device,
+            id,
+            action,
+        }) => {
+            let tether = match (bus, device, id) {
+                (None, None, None) => None,
+                _ => Some(tether_target(bus, device, id)?),
+            };
+            run_test_action(tether.as_ref(), action.as_deref())?
+        }
+        Some(Command::Severe {
+            selectors,
+            except,
+            yes,
+        }) => run_severe(&selectors, &except, yes)?,
+        Some(Command::Cancel) => run_cancel()?,
+        Some(Command::Watch) => watch::run_watch(Style::detect(cli.no_color))?,
+        Some(Command::Ack { id }) => run_ack(id)?,
+        Some(Command::Events {
+            follow,
+            since,
+            json,
+        }) => run_events(follow, since.as_deref(), json)?,
+        Some(Command::Config { command }) => run_config(command)?,
+        Some(Command::Export { output }) => run_export(output.as_deref())?,
+        Some(Command::Import {
+            file,
+            allow_unsigned,
+        }) => run_import(&file, allow_unsigned)?,
+        Some(Command::ReExec) => run_re_exec()?,
+        Some(Command::Install {
+            system: _,
+            dry_run,
+            bin_dir,
+        }) => install::run_install(cli.user, dry_run, bin_dir)?,
+        Some(Command::LogLevel { filter }) => run_log_level(filter.as_deref())?,
+        Some(Command::Daemon {
+            foreground,
+            pidfile,
+        }) => {
+            let pidfile = pidfile.unwrap_or_else(deadman_core::default_pidfile);
+            run_daemon(foreground, &pidfile)?
+        }
+        None => list_devices(&cli.filters)?,
     }
 
     Ok(())
@@ -20,107 +202,1058 @@ fn main() -> Result<()> {
 #[derive(Parser)]
 #[command(author, version, about = "deadman daemon control tool", long_about = None)]
 struct Cli {
+    /// Talk to the named daemon instance instead of the default one
+    #[arg(long, global = true, env = "DEADMAN_INSTANCE")]
+    instance: Option<String>,
+    /// Use the unprivileged daemon of this login session (deadmand --user);
+    /// with install, write user units
+    #[arg(long, global = true)]
+    user: bool,
+    /// Never colour output; NO_COLOR does the same
+    #[arg(long, global = true)]
+    no_color: bool,
     #[command(subcommand)]
     command: Option<Command>,
+    #[command(flatten)]
+    filters: ListFilters,
+}
+
+/// Filters for the default device listing.
+#[derive(Args)]
+struct ListFilters {
+    /// Only list devices with this class, by name (hid, storage, ...) or hex code
+    #[arg(long)]
+    class: Option<String>,
+    /// Only list devices from this vendor id, in hex
+    #[arg(long)]
+    vendor: Option<String>,
+    /// Only list devices tethered by the daemon
+    #[arg(long)]
+    tethered_only: bool,
+}
+
+impl ListFilters {
+    fn is_set(&self) -> bool {
+        self.class.is_some() || self.vendor.is_some() || self.tethered_only
+    }
 }
 
 #[derive(Subcommand)]
 enum Command {
-    Status,
+    /// Show active tethers
+    Status {
+        /// Block until the tether set changes or a device is removed. Exits with
+        /// 10 tethered, 11 removed, 12 reattached, 13 cancelled, 14 triggered,
+        /// 15 cleared, 16 degraded, 17 suppressed, 18 re-armed, or 124 on timeout
+        #[arg(long)]
+        wait: bool,
+        /// Give up waiting after this many seconds
+        #[arg(long, requires = "wait")]
+        timeout: Option<u64>,
+        /// Output format; the status bar formats never fail, showing offline instead
+        #[arg(long, value_enum, default_value_t = StatusFormat::Text, conflicts_with = "wait")]
+        format: StatusFormat,
+        /// Poll `deadman report` on these hosts over ssh instead; exits with the
+        /// worst result: 1 degraded, 2 unarmed, 3 unreachable
+        #[arg(long, value_name = "HOST", conflicts_with_all = ["wait", "format"])]
+        remote: Vec<String>,
+        /// Also show the libusb version and, for each tether, whether it is watched through
+        /// hotplug or polling, its callback registration and its last event
+        #[arg(long, short, conflicts_with_all = ["wait", "format", "remote"])]
+        verbose: bool,
+    },
     Tether {
         /// USB bus number (0-255)
-        bus: u8,
+        #[arg(required_unless_present_any = ["all", "id", "class"])]
+        bus: Option<u8>,
+        /// USB device address (0-255)
+        #[arg(required_unless_present_any = ["all", "id", "class"])]
+        device: Option<u8>,
+        /// Tether every device attached now (hubs excluded), optionally filtered by --id or --class
+        #[arg(long, conflicts_with_all = ["bus", "device"])]
+        all: bool,
+        /// Devices matching VID:PID in hex, either side may be *; without --all, add a rule
+        /// that also tethers matching devices attached later
+        #[arg(long, conflicts_with_all = ["bus", "device"])]
+        id: Option<String>,
+        /// Devices of this class (e.g. smartcard, hid, or a hex code); without --all, add a
+        /// rule that also tethers matching devices attached later
+        #[arg(long, conflicts_with_all = ["bus", "device", "id"])]
+        class: Option<String>,
+        /// Action to run on removal: lock, scrub, suspend, kill-network, terminate-remote, systemd:stop:UNIT,..., vm:pause:DOMAIN,..., container:stop:NAME,..., or custom:/path/to/script
+        #[arg(long)]
+        action: Option<String>,
+        /// Seconds to wait after removal before running the action
+        #[arg(long)]
+        grace: Option<u64>,
+        /// Seconds the action stays quiet after running, so a flapping cable can't repeat it
+        #[arg(long)]
+        cooldown: Option<u64>,
+        /// Tether the device again by itself when it returns after the action
+        #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+        rearm: Option<bool>,
+        /// Watch by enumerating the bus every poll_interval_ms instead of through hotplug
+        /// events, for VMs and kernels whose hotplug events cannot be trusted
+        #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+        poll: Option<bool>,
+        /// Update the settings of a device that is already tethered
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Tether the device matching --id or --serial unless an equivalent tether
+    /// exists, printing JSON with "changed": true or false
+    #[command(group = clap::ArgGroup::new("match").required(true))]
+    EnsureTether {
+        /// Device as VID:PID in hex
+        #[arg(long, group = "match")]
+        id: Option<String>,
+        /// Device serial number
+        #[arg(long, group = "match")]
+        serial: Option<String>,
+        /// Action to run on removal; unchanged or the default when omitted
+        #[arg(long)]
+        action: Option<String>,
+        /// Seconds to wait after removal before running the action
+        #[arg(long)]
+        grace: Option<u64>,
+        /// Seconds the action stays quiet after running
+        #[arg(long)]
+        cooldown: Option<u64>,
+        /// Tether the device again by itself when it returns after the action
+        #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+        rearm: Option<bool>,
+        /// Watch by enumerating the bus every poll_interval_ms instead of through hotplug
+        /// events, for VMs and kernels whose hotplug events cannot be trusted
+        #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+        poll: Option<bool>,
+    },
+    /// Change the action or grace period of a tethered device in place
+    Set {
+        /// USB bus number (0-255)
+        #[arg(required_unless_present = "id")]
+        bus: Option<u8>,
+        /// USB device address (0-255)
+        #[arg(required_unless_present = "id")]
+        device: Option<u8>,
+        /// Tether ID from tether or status, instead of the bus and address
+        #[arg(long, conflicts_with_all = ["bus", "device"])]
+        id: Option<u64>,
+        /// Action to run on removal: lock, scrub, suspend, kill-network, terminate-remote, systemd:stop:UNIT,..., vm:pause:DOMAIN,..., container:stop:NAME,..., or custom:/path/to/script
+        #[arg(long, required_unless_present_any = ["grace", "cooldown", "rearm", "poll"])]
+        action: Option<String>,
+        /// Seconds to wait after removal before running the action
+        #[arg(long)]
+        grace: Option<u64>,
+        /// Seconds the action stays quiet after running
+        #[arg(long)]
+        cooldown: Option<u64>,
+        /// Tether the device again by itself when it returns after the action
+        #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+        rearm: Option<bool>,
+        /// Watch by enumerating the bus every poll_interval_ms instead of through hotplug
+        /// events, for VMs and kernels whose hotplug events cannot be trusted
+        #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+        poll: Option<bool>,
+    },
+    /// Clear one tether, leaving the others in place
+    Untether {
+        /// USB bus number (0-255)
+        #[arg(required_unless_present_any = ["id", "rule"])]
+        bus: Option<u8>,
         /// USB device address (0-255)
-        device: u8,
+        #[arg(required_unless_present_any = ["id", "rule"])]
+        device: Option<u8>,
+        /// Tether ID from tether or status, instead of the bus and address
+        #[arg(long, conflicts_with_all = ["bus", "device"])]
+        id: Option<u64>,
+        /// Remove the rule with this ID from status, and clear the tethers it made
+        #[arg(long, conflicts_with_all = ["bus", "device", "id"])]
+        rule: Option<u64>,
+    },
+    /// Dismiss tethers that fired from status once they have been seen
+    Ack {
+        /// Only this tether, by its ID; otherwise every one you may manage
+        #[arg(long)]
+        id: Option<u64>,
+    },
+    /// Check daemon health; fails when a monitor thread is stalled or was restarted
+    Ping,
+    /// Show how the daemon runs and which lock backends it can use
+    Doctor,
+    /// Show whether removals would be acted on; exits 1 when degraded, 2 when unarmed
+    Protection,
+    /// Print the protection level and tethers as JSON, for `status --remote`
+    /// and dashboards; read-only, so safe as an ssh forced command
+    Report,
+    /// Print every descriptor of one connected device and the tether on it,
+    /// for choosing what to tether and for bug reports
+    Describe {
+        /// Which device: id=<tether id>, <vid>:<pid> in hex, or serial:<serial>
+        selector: String,
+        /// Print the description as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Exit 1 while a tethered device is absent, for `pam_exec.so` in the
+    /// services listed in `pam_services`
+    PamCheck {
+        /// PAM service being authenticated to
+        #[arg(long, env = "PAM_SERVICE")]
+        service: String,
+        /// User being authenticated
+        #[arg(long, env = "PAM_USER")]
+        login: String,
+        /// Allow authentication when deadmand cannot be reached
+        #[arg(long)]
+        allow_unreachable: bool,
+    },
+    /// Check what a tether's removal action would do, without running it
+    TestAction {
+        /// USB bus number of a tethered device; omit for the default action
+        #[arg(requires = "device")]
+        bus: Option<u8>,
+        /// USB device address (0-255)
+        #[arg(requires = "bus")]
+        device: Option<u8>,
+        /// Tether ID from tether or status, instead of the bus and address
+        #[arg(long, conflicts_with_all = ["bus", "device"])]
+        id: Option<u64>,
+        /// Check this action instead of the tether's
+        #[arg(long)]
+        action: Option<String>,
+    },
+    /// Clear all tethers, or only those matching a selector
+    Severe {
+        /// Only clear tethers matching one of these: id=<n>, <vid>:<pid> or serial:<serial>
+        selectors: Vec<String>,
+        /// Keep tethers matching this selector (repeatable)
+        #[arg(long, value_name = "SELECTOR")]
+        except: Vec<String>,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    Cancel,
+    /// Follow events, with a live countdown while a removal's grace period
+    /// runs; any key cancels it, q quits
+    Watch,
+    /// Print the daemon's event log
+    Events {
+        /// Keep printing new events as they happen
+        #[arg(short, long)]
+        follow: bool,
+        /// Only show events since a Unix timestamp or a relative age such as 30s, 10m, 2h, 1d
+        #[arg(long)]
+        since: Option<String>,
+        /// Print one JSON object per line instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the daemon configuration
+    Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
+    },
+    /// Write the daemon's settings and tethers as a snapshot, signed when
+    /// snapshot_signing_key is set
+    Export {
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Apply a snapshot from export: replace the settings and tether the
+    /// matching connected devices
+    Import {
+        /// Snapshot file
+        file: PathBuf,
+        /// Accept a snapshot that isn't signed for snapshot_trusted_key
+        #[arg(long)]
+        allow_unsigned: bool,
+    },
+    /// Restart the daemon in place on its upgraded binary, keeping its
+    /// tethers watched throughout
+    ReExec,
+    /// Install the systemd units, polkit policy, and udev rule
+    Install {
+        /// Install system-wide units, polkit policy, and udev rule (default;
+        /// --user installs user units under ~/.config/systemd/user instead)
+        #[arg(long, conflicts_with = "user")]
+        system: bool,
+        /// Print the files instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Directory containing the deadman and deadmand binaries
+        #[arg(long)]
+        bin_dir: Option<PathBuf>,
+    },
+    /// Show or change the daemon's log filter without restarting it
+    LogLevel {
+        /// New filter in RUST_LOG syntax, e.g. debug or deadman_core=trace
+        filter: Option<String>,
+    },
+    /// Run the daemon from this binary, detached unless --foreground is given
+    Daemon {
+        /// Stay attached to the terminal instead of starting in the background
+        #[arg(long)]
+        foreground: bool,
+        /// Where to record the pid when running in the background
+        #[arg(long)]
+        pidfile: Option<PathBuf>,
     },
-    Severe,
 }
 
-fn run_status() -> Result<()> {
-    let response = client::get_status().context("failed to request status from deadmand")?;
-    let message = parse_response(response)?;
-    if message.is_empty() {
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the active configuration
+    Show,
+    /// Print a single setting
+    Get { key: String },
+    /// Change a setting and save it to the config file
+    Set { key: String, value: String },
+    /// Check that the config file on disk parses
+    Validate,
+    /// Re-read the config file
+    Reload,
+}
+
+fn run_status(verbose: bool, style: Style) -> Result<()> {
+    let chunks = if verbose {
+        client::verbose_status_chunks()
+    } else {
+        client::status_chunks()
+    }
+    .context("failed to request status from deadmand")?;
+    // the columns are aligned over every tether, so read the whole reply
+    let mut status = String::new();
+    for chunk in chunks {
+        let chunk = chunk.map_err(|err| match client::last_request_id() {
+            Some(id) => anyhow!("{err} (request {id})"),
+            None => anyhow!("{err}"),
+        })?;
+        status.push_str(&chunk);
+    }
+    if status.trim().is_empty() {
         println!("ok");
     } else {
-        println!("{message}");
+        println!("{}", render::render_status(&status, style));
+    }
+    Ok(())
+}
+
+/// Fetch `deadman report` from each host over ssh, all at once, and print
+/// one line per host. Exits with the worst result when any is not armed.
+fn run_status_remote(hosts: &[String]) {
+    let polls: Vec<_> = hosts
+        .iter()
+        .map(|host| {
+            let host = host.clone();
+            std::thread::spawn(move || fetch_remote_report(&host))
+        })
+        .collect();
+
+    let mut worst = 0;
+    for (host, poll) in hosts.iter().zip(polls) {
+        let report = poll
+            .join()
+            .unwrap_or_else(|_| Err(anyhow!("polling thread panicked")));
+        match report {
+            Ok(report) => {
+                worst = worst.max(match report.level.as_str() {
+                    "armed" => 0,
+                    "degraded" => 1,
+                    _ => 2,
+                });
+                let mut line = format!(
+                    "{host}\t{}\t{} tether(s)",
+                    report.level,
+                    report.tethers.len()
+                );
+                if !report.reasons.is_empty() {
+                    line.push('\t');
+                    line.push_str(&report.reasons.join("; "));
+                }
+                println!("{line}");
+            }
+            Err(err) => {
+                worst = 3;
+                println!("{host}\tunreachable\t{err:#}");
+            }
+        }
+    }
+    process::exit(worst);
+}
+
+fn fetch_remote_report(host: &str) -> Result<Report> {
+    let output = process::Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10", host])
+        .args(["deadman", "report"])
+        .stdin(process::Stdio::null())
+        .output()
+        .context("failed to run ssh")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("ssh exited with {}: {}", output.status, stderr.trim());
+    }
+    serde_json::from_slice(&output.stdout).context("invalid report")
+}
+
+fn run_report() -> Result<()> {
+    let report = client::report().map_err(|err| {
+        unsupported("report").unwrap_or_else(|| anyhow!(err).context("failed to reach deadmand"))
+    })?;
+    println!("{}", serde_json::to_string(&report)?);
+    Ok(())
+}
+
+fn run_describe(selector: &str, json: bool) -> Result<()> {
+    let description = client::describe(selector).map_err(|err| {
+        unsupported("describe").unwrap_or_else(|| anyhow!(err).context("failed to describe device"))
+    })?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&description)?);
+    } else {
+        print!("{}", render::render_description(&description));
     }
     Ok(())
 }
 
-fn run_tether(bus: u8, device: u8) -> Result<()> {
-    let bus_str = bus.to_string();
-    let device_str = device.to_string();
+/// Print the tether state for a status bar module. A bar polls this, so an
+/// unreachable daemon is reported as offline rather than as an error.
+fn run_status_bar(format: StatusFormat) {
+    let status = client::get_status()
+        .map_err(|err| err.to_string())
+        .and_then(|response| parse_response(response).map_err(|err| err.to_string()));
 
-    let response = client::tether(&bus_str, &device_str)
+    let (state, count, tooltip) = match status {
+        Ok(status) => {
+            let tethers = parse_tethers(&status);
+            (BarState::from_tethers(&tethers), tethers.len(), status)
+        }
+        Err(err) => (BarState::Offline, 0, format!("deadmand unreachable: {err}")),
+    };
+
+    println!("{}", statusbar::render(format, state, count, &tooltip));
+    if format == StatusFormat::I3blocks && state.is_urgent() {
+        process::exit(statusbar::I3BLOCKS_URGENT);
+    }
+}
+
+/// Exit code for `status --wait` when no event arrived before the timeout,
+/// matching timeout(1).
+const EXIT_TIMEOUT: i32 = 124;
+
+fn run_status_wait(timeout: Option<u64>) -> Result<()> {
+    let mut subscription = client::subscribe().context("failed to subscribe to deadmand events")?;
+    let deadline = timeout.map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                println!("{}", gettext("timed out"));
+                process::exit(EXIT_TIMEOUT);
+            }
+            subscription.set_timeout(Some(remaining))?;
+        }
+
+        let event = match subscription.next() {
+            Some(Ok(event)) => event,
+            Some(Err(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                println!("{}", gettext("timed out"));
+                process::exit(EXIT_TIMEOUT);
+            }
+            Some(Err(err)) => return Err(err).context("failed to read event from deadmand"),
+            None => bail!("deadmand closed the event stream"),
+        };
+
+        let code = match event.kind {
+            EventKind::Tethered => 10,
+            EventKind::Removed => 11,
+            EventKind::Reattached => 12,
+            EventKind::Cancelled => 13,
+            EventKind::Triggered => 14,
+            EventKind::Cleared => 15,
+            EventKind::Degraded => 16,
+            EventKind::Suppressed => 17,
+            EventKind::Rearmed => 18,
+            // config reloads don't change the tether set
+            EventKind::ConfigChanged | EventKind::ConfigRejected => continue,
+        };
+        println!("{}", format_event(&event));
+        process::exit(code);
+    }
+}
+
+fn run_daemon(foreground: bool, pidfile: &Path) -> Result<()> {
+    if !foreground {
+        match client::get_status() {
+            Ok(_) => bail!("deadmand is already running"),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                bail!("deadmand is already running (as another user)")
+            }
+            Err(_) => {}
+        }
+        deadman_core::daemonize(pidfile).map_err(|err| anyhow!(err))?;
+    }
+
+    deadman_core::run();
+    Ok(())
+}
+
+fn run_tether(bus: u8, device: u8, options: &TetherOptions) -> Result<()> {
+    let message = request_tether(bus, device, options)?;
+    println!("{message}");
+    Ok(())
+}
+
+fn request_tether(bus: u8, device: u8, options: &TetherOptions) -> Result<String> {
+    let response = client::tether_with_options(&bus.to_string(), &device.to_string(), options)
         .with_context(|| format!("failed to request tether for {:03}:{:03}", bus, device))?;
+    parse_response(response)
+}
+
+fn run_ensure_tether(selector: &str, options: &TetherOptions) -> Result<()> {
+    if selector.contains(char::is_whitespace) {
+        bail!("invalid device selector '{selector}'");
+    }
+    let response = client::ensure_tether(selector, options).context("failed to reach deadmand")?;
+    println!("{}", parse_response(response)?);
+    Ok(())
+}
+
+fn run_tether_rule(pattern: &str, options: &TetherOptions) -> Result<()> {
+    if pattern.contains(char::is_whitespace) {
+        bail!("invalid device pattern '{pattern}'");
+    }
+    if options.rearm.is_some() || options.replace {
+        bail!("--rearm and --replace do not apply to rules, which tether returning devices anyway");
+    }
+    let response = client::tether_rule(pattern, options).context("failed to reach deadmand")?;
+    println!("{}", parse_response(response)?);
+    Ok(())
+}
+
+fn run_tether_all(id: Option<&str>, class: Option<&str>, options: &TetherOptions) -> Result<()> {
+    let pattern = id
+        .map(|id| id.parse::<Pattern>().map_err(|err| anyhow!(err)))
+        .transpose()?;
+    let class = class.map(parse_class).transpose()?;
+
+    let mut tethered: u64 = 0;
+    let mut failed = 0;
+    for device in fetch_devices()? {
+        if device.is_hub() {
+            continue;
+        }
+        if pattern.as_ref().is_some_and(|pattern| {
+            !pattern.matches(device.vendor_id, device.product_id, &device.classes)
+        }) {
+            continue;
+        }
+        if class.is_some_and(|class| !device.classes.contains(&class)) {
+            continue;
+        }
+
+        match request_tether(device.bus, device.address, options) {
+            Ok(message) => {
+                tethered += 1;
+                println!("{message}");
+            }
+            Err(err) => {
+                failed += 1;
+                eprintln!(
+                    "bus {:03} address {:03}: {err:#}",
+                    device.bus, device.address
+                );
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        ngettext_f(
+            "tethered {} device, {} failed",
+            "tethered {} devices, {} failed",
+            tethered,
+            &[&tethered.to_string(), &failed.to_string()],
+        )
+    );
+    if failed > 0 {
+        bail!("{failed} device(s) could not be tethered");
+    }
+    if tethered == 0 {
+        bail!("no matching devices found");
+    }
+    Ok(())
+}
+
+fn run_severe(selectors: &[String], except: &[String], yes: bool) -> Result<()> {
+    let selectors: Vec<&str> = selectors.iter().map(String::as_str).collect();
+    let except: Vec<&str> = except.iter().map(String::as_str).collect();
+    let scoped = !selectors.is_empty() || !except.is_empty();
+    if !yes && !confirm_severe(scoped)? {
+        println!("{}", gettext("aborted"));
+        return Ok(());
+    }
+
+    let response = if scoped {
+        client::severe_matching(&selectors, &except)
+    } else {
+        client::severe()
+    }
+    .context("failed to send severe command")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    Ok(())
+}
+
+/// Show the tethers that would be cleared, or that may be when `scoped`, and
+/// ask before severing them.
+fn confirm_severe(scoped: bool) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        bail!(gettext(
+            "refusing to clear tethers without confirmation; pass --yes to skip the prompt"
+        ));
+    }
+
+    let response = client::get_status().context("failed to request status from deadmand")?;
+    let status = parse_response(response)?;
+    if parse_tethers(&status).is_empty() {
+        println!("{}", gettext("no devices are tethered"));
+    } else if scoped {
+        println!(
+            "{}",
+            gettext("tethers among these that match will be cleared:")
+        );
+        for line in status.lines() {
+            println!("  {line}");
+        }
+    } else {
+        println!("{}", gettext("the following tethers will be cleared:"));
+        for line in status.lines() {
+            println!("  {line}");
+        }
+    }
+
+    print!("{} ", gettext("are you sure? [y/N]"));
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// A tether named by `--id`, or by bus and device address.
+fn tether_target(bus: Option<u8>, device: Option<u8>, id: Option<u64>) -> Result<TetherTarget> {
+    match (bus, device, id) {
+        (_, _, Some(id)) => Ok(TetherTarget::Id(id)),
+        (Some(bus), Some(address), None) => Ok(TetherTarget::Device { bus, address }),
+        _ => bail!("pass a bus and device address, or --id"),
+    }
+}
+
+fn run_set(target: &TetherTarget, options: &TetherOptions) -> Result<()> {
+    let response = client::set_target(target, options)
+        .with_context(|| format!("failed to update {target}"))?;
     let message = parse_response(response)?;
     println!("{message}");
     Ok(())
 }
 
-fn run_severe() -> Result<()> {
-    let response = client::severe().context("failed to send severe command")?;
+fn run_untether(target: &TetherTarget) -> Result<()> {
+    let response =
+        client::untether(target).with_context(|| format!("failed to untether {target}"))?;
     let message = parse_response(response)?;
     println!("{message}");
     Ok(())
 }
 
+fn run_untether_rule(id: u64) -> Result<()> {
+    let response =
+        client::untether_rule(id).with_context(|| format!("failed to remove rule {id}"))?;
+    println!("{}", parse_response(response)?);
+    Ok(())
+}
+
+fn run_ping() -> Result<()> {
+    let response = client::ping().context("failed to reach deadmand")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    if message.starts_with("degraded") {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_doctor() -> Result<()> {
+    let response = client::doctor().context("failed to reach deadmand")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    Ok(())
+}
+
+fn run_protection() -> Result<()> {
+    let response = client::protection().context("failed to reach deadmand")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    match message.lines().next() {
+        Some("degraded") => process::exit(1),
+        Some("unarmed") => process::exit(2),
+        _ => Ok(()),
+    }
+}
+
+fn run_pam_check(service: &str, user: &str, allow_unreachable: bool) -> Result<()> {
+    if service.contains(char::is_whitespace) || user.contains(char::is_whitespace) {
+        bail!("invalid PAM service or user name");
+    }
+    let response = match client::pam_check(service, user) {
+        Ok(response) => response,
+        Err(_) if allow_unreachable => return Ok(()),
+        Err(err) => return Err(err).context("failed to reach deadmand"),
+    };
+    let message = parse_response(response)?;
+    if let Some(absent) = message.strip_prefix("deny") {
+        for device in absent.lines().filter(|line| !line.is_empty()) {
+            println!("{}", gettext_f("tethered device absent: {}", &[device]));
+        }
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_test_action(tether: Option<&TetherTarget>, action: Option<&str>) -> Result<()> {
+    let response = client::test_action(tether, action).context("failed to reach deadmand")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    if message.lines().any(|line| line.starts_with("fail: ")) {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_cancel() -> Result<()> {
+    let response = client::cancel().context("failed to send cancel command")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    Ok(())
+}
+
+fn run_ack(id: Option<u64>) -> Result<()> {
+    let response = client::ack(id).context("failed to reach deadmand")?;
+    println!("{}", parse_response(response)?);
+    Ok(())
+}
+
+fn run_log_level(filter: Option<&str>) -> Result<()> {
+    let response = client::log_level(filter).context("failed to send log-level command")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    Ok(())
+}
+
+fn run_config(command: Option<ConfigCommand>) -> Result<()> {
+    let args: Vec<&str> = match &command {
+        None | Some(ConfigCommand::Show) => vec!["show"],
+        Some(ConfigCommand::Get { key }) => vec!["get", key],
+        Some(ConfigCommand::Set { key, value }) => vec!["set", key, value],
+        Some(ConfigCommand::Validate) => vec!["validate"],
+        Some(ConfigCommand::Reload) => vec!["reload"],
+    };
+
+    let response = client::config(&args).context("failed to send config command")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    Ok(())
+}
+
+fn run_export(output: Option<&Path>) -> Result<()> {
+    let response = client::export().context("failed to reach deadmand")?;
+    let snapshot = parse_response(response)?;
+    match output {
+        Some(path) => fs::write(path, format!("{snapshot}\n"))
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => println!("{snapshot}"),
+    }
+    Ok(())
+}
+
+fn run_import(file: &Path, allow_unsigned: bool) -> Result<()> {
+    // the daemon reads the file itself, so it needs the full path
+    let file =
+        fs::canonicalize(file).with_context(|| format!("failed to find {}", file.display()))?;
+    let path = file
+        .to_str()
+        .ok_or_else(|| anyhow!("snapshot path is not valid UTF-8"))?;
+    let response = client::import(path, allow_unsigned).context("failed to reach deadmand")?;
+    let message = parse_response(response)?;
+    println!("{message}");
+    Ok(())
+}
+
+fn run_re_exec() -> Result<()> {
+    let response = client::re_exec().context("failed to reach deadmand")?;
+    println!("{}", parse_response(response)?);
+
+    // the socket disappears while the new binary starts up
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        std::thread::sleep(Duration::from_millis(200));
+        match client::server_info() {
+            Ok(info) => {
+                let version = info.version.as_deref().unwrap_or("unknown");
+                println!("{}", gettext_f("deadmand is back (version {})", &[version]));
+                return Ok(());
+            }
+            Err(_) if Instant::now() < deadline => {}
+            Err(err) => return Err(anyhow!(err).context("deadmand did not come back")),
+        }
+    }
+}
+
+fn run_events(follow: bool, since: Option<&str>, json: bool) -> Result<()> {
+    let since = since.map(parse_since).transpose()?.unwrap_or(0);
+
+    // subscribe before fetching the log so nothing is missed in between
+    let subscription = if follow {
+        Some(client::subscribe().context("failed to subscribe to deadmand events")?)
+    } else {
+        None
+    };
+
+    let history = client::get_log().context("failed to request event log from deadmand")?;
+    for event in history.iter().filter(|event| event.timestamp >= since) {
+        print_event(event, json)?;
+    }
+
+    let Some(subscription) = subscription else {
+        return Ok(());
+    };
+    for event in subscription {
+        let event = event.context("failed to read event from deadmand")?;
+        if event.timestamp >= since && !history.contains(&event) {
+            print_event(&event, json)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_event(event: &Event, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(event)?);
+    } else {
+        println!("{}", format_event(event));
+    }
+    io::stdout().flush()?;
+    Ok(())
+}
+
+fn format_event(event: &Event) -> String {
+    let mut line = format!("{} {:<10}", format_utc(event.timestamp, ' '), event.kind);
+    if let Some(device) = &event.device {
+        line.push(' ');
+        line.push_str(device);
+    }
+    if let Some(grace) = event.grace_secs {
+        line.push_str(&format!(" (grace {grace}s)"));
+    }
+    if let Some(action) = &event.action {
+        line.push_str(&format!(" (action {action})"));
+    }
+    if let Some(confidence) = &event.confidence {
+        line.push_str(&format!(" ({confidence} confidence)"));
+    }
+    if let Some(uid) = event.uid {
+        line.push_str(&format!(" (by uid {uid})"));
+    }
+    if let Some(id) = &event.request_id {
+        line.push_str(&format!(" (request {id})"));
+    }
+    if let Some(message) = &event.message {
+        line.push_str(": ");
+        line.push_str(message);
+    }
+    line
+}
+
+/// Parse `--since` as either absolute Unix seconds or an age like `10m`.
+fn parse_since(value: &str) -> Result<u64> {
+    let value = value.trim();
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("invalid --since value '{value}'"))?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| anyhow!("invalid --since value '{value}'"))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        _ => bail!("invalid --since unit '{unit}' (expected s, m, h or d)"),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    Ok(now.saturating_sub(amount.saturating_mul(unit_secs)))
+}
+
 fn parse_response(response: String) -> Result<String> {
     let trimmed = response.trim();
     if let Some(err) = trimmed.strip_prefix("ERR: ") {
-        return Err(anyhow!("{err}", err = err.trim()));
+        let request_id = client::last_request_id();
+        if let Some(command) = err.trim().strip_prefix("unknown command: ")
+            && let Some(err) = unsupported(command)
+        {
+            return Err(err);
+        }
+        return Err(match request_id {
+            Some(id) => anyhow!("{err} (request {id})", err = err.trim()),
+            None => anyhow!("{err}", err = err.trim()),
+        });
     }
     Ok(trimmed.to_string())
 }
 
-fn list_devices() -> Result<()> {
-    let context = Context::new().context("failed to create USB context")?;
-    let devices = context.devices().context("failed to list USB devices")?;
+/// The error for a command the daemon says it does not implement, or `None`
+/// when it does or cannot tell.
+fn unsupported(command: &str) -> Option<anyhow::Error> {
+    let info = client::server_info()
+        .ok()
+        .filter(|info| !info.supports(command))?;
+    Some(anyhow!(
+        "deadmand {} does not support `{command}`; upgrade it to use this command",
+        info.version.as_deref().unwrap_or("(protocol 0)")
+    ))
+}
 
-    if devices.len() == 0 {
-        println!("no USB devices found");
-        return Ok(());
+/// Every connected USB device, as read by the daemon.
+fn fetch_devices() -> Result<Vec<UsbDevice>> {
+    client::list_devices().map_err(|err| {
+        unsupported("list-devices")
+            .unwrap_or_else(|| anyhow!(err).context("failed to list USB devices from deadmand"))
+    })
+}
+
+fn list_devices(filters: &ListFilters) -> Result<()> {
+    let class = filters.class.as_deref().map(parse_class).transpose()?;
+    let vendor = filters
+        .vendor
+        .as_deref()
+        .map(|vendor| {
+            devices::parse_id(vendor)
+                .ok_or_else(|| anyhow!("invalid vendor id '{vendor}' (expected hex)"))
+        })
+        .transpose()?;
+
+    let mut rows = Vec::new();
+    for device in fetch_devices()? {
+        if class.is_some_and(|class| !device.classes.contains(&class)) {
+            continue;
+        }
+        if vendor.is_some_and(|vendor| vendor != device.vendor_id) {
+            continue;
+        }
+        if filters.tethered_only && device.tether_state.is_none() {
+            continue;
+        }
+
+        rows.push([
+            format!("{:03}", device.bus),
+            format!("{:03}", device.address),
+            format!("{:04x}:{:04x}", device.vendor_id, device.product_id),
+            device
+                .classes
+                .iter()
+                .map(|class| class_name(*class))
+                .collect::<Vec<_>>()
+                .join(","),
+            device.serial.unwrap_or_default(),
+            device.tether_state.unwrap_or_default(),
+            device.product.unwrap_or_default(),
+        ]);
     }
 
-    for device in devices.iter() {
-        let descriptor = match device.device_descriptor() {
-            Ok(desc) => desc,
-            Err(err) => {
-                println!(
-                    "bus {:03} address {:03}: failed to read descriptor ({err})",
-                    device.bus_number(),
-                    device.address()
-                );
-                continue;
-            }
-        };
+    if rows.is_empty() {
+        println!("{}", gettext("no USB devices found"));
+        return Ok(());
+    }
 
-        let name = match device.open() {
-            Ok(handle) => match handle.read_product_string_ascii(&descriptor) {
-                Ok(name) => Some(name),
-                Err(_) => None,
-            },
-            Err(_) => None,
-        };
+    print_table(
+        ["BUS", "ADDR", "ID", "CLASS", "SERIAL", "TETHER", "NAME"],
+        &rows,
+    );
+    Ok(())
+}
 
-        match name {
-            Some(name) => println!(
-                "bus {:03} address {:03} {:04x}:{:04x} - {}",
-                device.bus_number(),
-                device.address(),
-                descriptor.vendor_id(),
-                descriptor.product_id(),
-                name
-            ),
-            None => println!(
-                "bus {:03} address {:03} {:04x}:{:04x}",
-                device.bus_number(),
-                device.address(),
-                descriptor.vendor_id(),
-                descriptor.product_id()
-            ),
+/// Print rows under a header with every column padded to its widest cell.
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
         }
     }
 
-    Ok(())
+    let format_row = |cells: [&str; N]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(header));
+    for row in rows {
+        println!("{}", format_row(row.each_ref().map(String::as_str)));
+    }
+}
+
+/// A device the daemon reports as tethered, with its `[state]` from `status`.
+struct TetherStatus {
+    state: String,
+}
+
+/// Parse `status` lines like `bus 001 address 002 1234:abcd - name [watching]`.
+fn parse_tethers(status: &str) -> Vec<TetherStatus> {
+    status
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            if parts.next()? != "bus" {
+                return None;
+            }
+            parts.next()?.parse::<u8>().ok()?;
+            if parts.next()? != "address" {
+                return None;
+            }
+            parts.next()?.parse::<u8>().ok()?;
+            let state = line
+                .rsplit_once('[')
+                .and_then(|(_, rest)| rest.split_once(']'))
+                .map(|(state, _)| state.to_string())
+                .unwrap_or_default();
+            Some(TetherStatus { state })
+        })
+        .collect()
+}
+
+/// Accept a class name or a hex class code.
+fn parse_class(value: &str) -> Result<u8> {
+    devices::parse_class(value).map_err(|err| anyhow!(err))
 }
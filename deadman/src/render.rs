@@ -0,0 +1,304 @@
+//! Human-friendly `deadman status`: tethers as aligned columns with their
+//! state coloured, and everything else the daemon says passed through. Also
+//! the `deadman describe` layout of one device's descriptors.
+
+use std::env;
+use std::fmt::Write;
+use std::io::{self, IsTerminal};
+
+use deadman_ipc::devices::{DeviceDescription, class_name};
+use deadman_ipc::i18n::{gettext, gettext_f};
+
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+
+/// Whether to colour output: only on a terminal, and never with
+/// `--no-color` or a non-empty `NO_COLOR` (<https://no-color.org>).
+#[derive(Clone, Copy)]
+pub struct Style {
+    color: bool,
+}
+
+impl Style {
+    pub fn detect(no_color: bool) -> Self {
+        let disabled = no_color || env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Self {
+            color: !disabled && io::stdout().is_terminal(),
+        }
+    }
+
+    fn paint(self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Text that needs attention, such as a running countdown.
+    pub fn warning(self, text: &str) -> String {
+        self.paint(text, YELLOW)
+    }
+
+    /// Green while watching, yellow while degraded or counting down, red
+    /// once the device is gone or its action has run.
+    fn state(self, padded: &str) -> String {
+        let state = padded.trim_end();
+        let code = if state == "watching" {
+            GREEN
+        } else if state == "grace" || state.starts_with("degraded") {
+            YELLOW
+        } else if state == "triggered" || state == "disconnected" {
+            RED
+        } else {
+            return padded.to_string();
+        };
+        // colour the word only, so the padding stays plain
+        format!("{}{}", self.paint(state, code), &padded[state.len()..])
+    }
+}
+
+/// One `status` line for a tether, split into the columns shown.
+struct TetherLine<'a> {
+    bus: &'a str,
+    address: &'a str,
+    id: &'a str,
+    state: &'a str,
+    name: &'a str,
+    settings: &'a str,
+    /// Indented lines the daemon sent under this one, e.g. `  watch: ...`.
+    details: Vec<&'a str>,
+}
+
+/// Parse `bus 001 address 004 046d:c52b - name [state] (settings)`, where
+/// the name and settings are optional.
+fn parse_tether_line(line: &str) -> Option<TetherLine<'_>> {
+    let rest = line.strip_prefix("bus ")?;
+    let (bus, rest) = rest.split_once(' ')?;
+    let rest = rest.strip_prefix("address ")?;
+    let (address, rest) = rest.split_once(' ')?;
+
+    let (head, settings) = match line.rfind("] (") {
+        Some(at) if line.ends_with(')') => (&line[..=at], &line[at + 3..line.len() - 1]),
+        _ => (line, ""),
+    };
+    let (device, state) = head.strip_suffix(']')?.rsplit_once(" [")?;
+    // `device` still starts with the bus and address parsed above
+    let device = device.get(line.len() - rest.len()..)?;
+    let (id, name) = device.split_once(" - ").unwrap_or((device, ""));
+
+    Some(TetherLine {
+        bus,
+        address,
+        id,
+        state,
+        name,
+        settings,
+        details: Vec::new(),
+    })
+}
+
+/// The daemon's `status` reply, with its tether lines as a table between
+/// whatever came before them (the verbose backend line) and after (rules
+/// and finished tethers).
+pub fn render_status(status: &str, style: Style) -> String {
+    let mut before = Vec::new();
+    let mut tethers: Vec<TetherLine> = Vec::new();
+    let mut after = Vec::new();
+    for line in status.lines() {
+        if let Some(tether) = parse_tether_line(line) {
+            tethers.push(tether);
+        } else if line.starts_with("  ") && after.is_empty() && !tethers.is_empty() {
+            tethers
+                .last_mut()
+                .expect("checked above")
+                .details
+                .push(line);
+        } else if tethers.is_empty() {
+            before.push(line.to_string());
+        } else {
+            after.push(line.to_string());
+        }
+    }
+    if tethers.is_empty() {
+        return status.trim_end().to_string();
+    }
+
+    let header = [
+        gettext("BUS"),
+        gettext("ADDR"),
+        gettext("ID"),
+        gettext("STATE"),
+        gettext("NAME"),
+        gettext("SETTINGS"),
+    ];
+    let rows: Vec<[&str; 6]> = tethers
+        .iter()
+        .map(|t| [t.bus, t.address, t.id, t.state, t.name, t.settings])
+        .collect();
+    let mut widths = header.each_ref().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let pad = |cell: &str, width: usize| format!("{cell:<width$}");
+
+    let mut lines = before;
+    let cells: Vec<String> = header.iter().zip(widths).map(|(c, w)| pad(c, w)).collect();
+    lines.push(cells.join("  ").trim_end().to_string());
+    for (tether, row) in tethers.iter().zip(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(column, (cell, width))| {
+                let padded = pad(cell, width);
+                if column == 3 {
+                    style.state(&padded)
+                } else {
+                    padded
+                }
+            })
+            .collect();
+        lines.push(cells.join("  ").trim_end().to_string());
+        lines.extend(tether.details.iter().map(|line| line.to_string()));
+    }
+    lines.extend(after);
+    lines.join("\n")
+}
+
+/// One device as `lsusb -v` would show it, trimmed to what helps choose a
+/// tether: identity, where it is plugged in, and its interfaces.
+pub fn render_description(description: &DeviceDescription) -> String {
+    let device = &description.device;
+    let mut out = gettext_f(
+        "bus {} address {} {}",
+        &[
+            &format!("{:03}", device.bus),
+            &format!("{:03}", device.address),
+            &format!("{:04x}:{:04x}", device.vendor_id, device.product_id),
+        ],
+    );
+    if let Some(product) = &device.product {
+        let _ = write!(out, " - {product}");
+    }
+    out.push('\n');
+
+    let classes: Vec<String> = device
+        .classes
+        .iter()
+        .map(|class| class_name(*class))
+        .collect();
+    let tether = match (device.tether_id, &device.tether_state) {
+        (Some(id), Some(state)) => gettext_f("id {} [{}]", &[&id.to_string(), state]),
+        (Some(id), None) => gettext_f("id {}", &[&id.to_string()]),
+        _ => gettext("none"),
+    };
+    let fields = [
+        (gettext("manufacturer"), device.manufacturer.clone()),
+        (gettext("serial"), device.serial.clone()),
+        (gettext("port"), device.port_path()),
+        (gettext("speed"), Some(device.speed.clone())),
+        (gettext("usb version"), Some(device.usb_version.clone())),
+        (
+            gettext("device version"),
+            Some(device.device_version.clone()),
+        ),
+        (
+            gettext("class"),
+            Some(format!(
+                "{:02x}/{:02x}/{:02x} ({})",
+                device.class_code,
+                device.sub_class_code,
+                device.protocol_code,
+                classes.join(",")
+            )),
+        ),
+        (
+            gettext("max packet size"),
+            Some(device.max_packet_size.to_string()),
+        ),
+        (gettext("tether"), Some(tether)),
+    ];
+    let width = fields
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or_default()
+        + 2;
+    for (label, value) in fields {
+        if let Some(value) = value {
+            let _ = writeln!(out, "  {label:<width$}{value}");
+        }
+    }
+
+    for config in &description.configurations {
+        let power = if config.self_powered {
+            gettext("self powered")
+        } else {
+            gettext("bus powered")
+        };
+        let mut line = if config.active {
+            gettext_f(
+                "configuration {} (active): {} mA, {}",
+                &[
+                    &config.number.to_string(),
+                    &config.max_power.to_string(),
+                    &power,
+                ],
+            )
+        } else {
+            gettext_f(
+                "configuration {}: {} mA, {}",
+                &[
+                    &config.number.to_string(),
+                    &config.max_power.to_string(),
+                    &power,
+                ],
+            )
+        };
+        if config.remote_wakeup {
+            line.push_str(", ");
+            line.push_str(&gettext("remote wakeup"));
+        }
+        let _ = writeln!(out, "{line}");
+        for interface in &config.interfaces {
+            let _ = writeln!(
+                out,
+                "  {}",
+                gettext_f(
+                    "interface {}.{}: {} ({})",
+                    &[
+                        &interface.number.to_string(),
+                        &interface.alternate_setting.to_string(),
+                        &format!(
+                            "{:02x}/{:02x}/{:02x}",
+                            interface.class_code, interface.sub_class_code, interface.protocol_code
+                        ),
+                        &class_name(interface.class_code),
+                    ],
+                )
+            );
+            for endpoint in &interface.endpoints {
+                // direction and transfer type are protocol terms, as sent
+                let _ = writeln!(
+                    out,
+                    "    {}",
+                    gettext_f(
+                        "endpoint {}: {} {}, {} bytes, interval {}",
+                        &[
+                            &format!("0x{:02x}", endpoint.address),
+                            &endpoint.transfer_type,
+                            &endpoint.direction,
+                            &endpoint.max_packet_size.to_string(),
+                            &endpoint.interval.to_string(),
+                        ],
+                    )
+                );
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,110 @@
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::TetherStatus;
+
+/// Output formats for `deadman status --format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StatusFormat {
+    /// The daemon's status lines
+    Text,
+    /// JSON for a waybar `custom` module with `return-type: json`
+    Waybar,
+    /// Full text, short text and colour lines for i3blocks
+    I3blocks,
+    /// A single line with polybar colour tags
+    Polybar,
+}
+
+/// Exit code that makes i3blocks mark the block urgent.
+pub const I3BLOCKS_URGENT: i32 = 33;
+
+/// Overall tether state shown in the bar, most urgent first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarState {
+    /// A removed device is counting down its grace period.
+    Grace,
+    /// A tethered device is gone, or its removal action has run.
+    Disconnected,
+    /// At least one device is tethered and present.
+    Watching,
+    /// The daemon is running without tethers.
+    Idle,
+    /// The daemon could not be reached.
+    Offline,
+}
+
+impl BarState {
+    pub fn from_tethers(tethers: &[TetherStatus]) -> Self {
+        let has = |state: &str| tethers.iter().any(|tether| tether.state == state);
+        if has("grace") {
+            BarState::Grace
+        } else if has("disconnected") || has("triggered") {
+            BarState::Disconnected
+        } else if tethers.is_empty() {
+            BarState::Idle
+        } else {
+            BarState::Watching
+        }
+    }
+
+    /// Name used for waybar's `class` and `alt`, so stylesheets and
+    /// `format-icons` can key off it.
+    fn class(self) -> &'static str {
+        match self {
+            BarState::Grace => "grace",
+            BarState::Disconnected => "disconnected",
+            BarState::Watching => "watching",
+            BarState::Idle => "idle",
+            BarState::Offline => "offline",
+        }
+    }
+
+    fn color(self) -> Option<&'static str> {
+        match self {
+            BarState::Grace => Some("#e01b24"),
+            BarState::Disconnected => Some("#f6d32d"),
+            BarState::Watching => Some("#33d17a"),
+            BarState::Idle | BarState::Offline => None,
+        }
+    }
+
+    pub fn is_urgent(self) -> bool {
+        self == BarState::Grace
+    }
+}
+
+/// Render the bar output for `state`. `count` is the number of tethers and
+/// `tooltip` the daemon's full status text.
+pub fn render(format: StatusFormat, state: BarState, count: usize, tooltip: &str) -> String {
+    let text = match state {
+        BarState::Grace => "deadman: grace".to_string(),
+        BarState::Disconnected => "deadman: disconnected".to_string(),
+        BarState::Watching => format!("deadman: {count} tethered"),
+        BarState::Idle => "deadman: idle".to_string(),
+        BarState::Offline => "deadman: offline".to_string(),
+    };
+
+    match format {
+        StatusFormat::Text => tooltip.to_string(),
+        StatusFormat::Waybar => json!({
+            "text": text,
+            "alt": state.class(),
+            "tooltip": tooltip,
+            "class": state.class(),
+        })
+        .to_string(),
+        StatusFormat::I3blocks => {
+            // full_text, short_text, then an optional colour
+            let mut lines = vec![text, state.class().to_string()];
+            if let Some(color) = state.color() {
+                lines.push(color.to_string());
+            }
+            lines.join("\n")
+        }
+        StatusFormat::Polybar => match state.color() {
+            Some(color) => format!("%{{F{color}}}{text}%{{F-}}"),
+            None => text,
+        },
+    }
+}
@@ -0,0 +1,205 @@
+//! `deadman watch`: events as they happen and, while a removal's grace period
+//! runs, a live countdown that any key cancels, like the GUI's overlay.
+
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use deadman_ipc::client;
+use deadman_ipc::events::{Event, EventKind};
+use deadman_ipc::i18n::{gettext, ngettext_f};
+
+use crate::render::Style;
+
+/// Ctrl-C, which raw mode delivers as a key rather than a signal.
+const INTERRUPT: u8 = 0x03;
+
+enum Input {
+    Event(io::Result<Event>),
+    /// The daemon closed the event stream.
+    Closed,
+    Key(u8),
+}
+
+/// A removal whose action runs at `deadline` unless cancelled.
+struct Countdown {
+    device: String,
+    bus: Option<u8>,
+    address: Option<u8>,
+    deadline: Instant,
+}
+
+impl Countdown {
+    fn seconds_left(&self) -> u64 {
+        self.deadline
+            .saturating_duration_since(Instant::now())
+            .as_secs_f64()
+            .ceil() as u64
+    }
+
+    /// Whether `event` is about the removed device, or names none.
+    fn concerns(&self, event: &Event) -> bool {
+        event.bus.is_none_or(|bus| Some(bus) == self.bus)
+            && event
+                .address
+                .is_none_or(|address| Some(address) == self.address)
+    }
+}
+
+pub fn run_watch(style: Style) -> Result<()> {
+    let events = client::subscribe().context("failed to subscribe to deadmand events")?;
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn({
+        let sender = sender.clone();
+        move || {
+            for event in events {
+                if sender.send(Input::Event(event)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Input::Closed);
+        }
+    });
+
+    // the countdown is redrawn in place, so only a terminal gets it, and
+    // keys only cancel when they can be read one at a time
+    let live = io::stdout().is_terminal();
+    let _raw = if live && io::stdin().is_terminal() {
+        let raw = RawMode::enable().context("failed to read keys from the terminal")?;
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else { return };
+                if sender.send(Input::Key(byte)).is_err() {
+                    return;
+                }
+            }
+        });
+        eprintln!("{}", gettext("watching for removals; press q to quit"));
+        Some(raw)
+    } else {
+        drop(sender);
+        None
+    };
+
+    let mut countdown: Option<Countdown> = None;
+    loop {
+        let input = match &countdown {
+            Some(_) => match receiver.recv_timeout(Duration::from_millis(250)) {
+                Ok(input) => Some(input),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(input) => Some(input),
+                Err(_) => break,
+            },
+        };
+
+        if live && countdown.is_some() {
+            clear_line();
+        }
+        match input {
+            None => {}
+            Some(Input::Closed) => break,
+            Some(Input::Event(event)) => {
+                let event = event.context("failed to read event from deadmand")?;
+                println!("{}", crate::format_event(&event));
+                match event.kind {
+                    EventKind::Removed => {
+                        countdown =
+                            event
+                                .grace_secs
+                                .filter(|grace| *grace > 0)
+                                .map(|grace| Countdown {
+                                    device: event.device.clone().unwrap_or_default(),
+                                    bus: event.bus,
+                                    address: event.address,
+                                    deadline: Instant::now() + Duration::from_secs(grace),
+                                });
+                    }
+                    EventKind::Reattached
+                    | EventKind::Cancelled
+                    | EventKind::Cleared
+                    | EventKind::Triggered
+                    | EventKind::Suppressed
+                        if countdown.as_ref().is_some_and(|c| c.concerns(&event)) =>
+                    {
+                        countdown = None;
+                    }
+                    _ => {}
+                }
+            }
+            Some(Input::Key(b'q' | INTERRUPT)) => break,
+            Some(Input::Key(_)) if countdown.is_some() => {
+                match client::cancel()
+                    .map_err(anyhow::Error::from)
+                    .and_then(crate::parse_response)
+                {
+                    Ok(message) => println!("{message}"),
+                    Err(err) => println!("{}: {err:#}", gettext("cancel failed")),
+                }
+            }
+            Some(Input::Key(_)) => {}
+        }
+        if live && let Some(countdown) = &countdown {
+            draw(countdown, style);
+        }
+    }
+    if live && countdown.is_some() {
+        clear_line();
+    }
+    Ok(())
+}
+
+fn draw(countdown: &Countdown, style: Style) {
+    let left = countdown.seconds_left();
+    let line = ngettext_f(
+        "{} removed: action in {} second, press any key to cancel",
+        "{} removed: action in {} seconds, press any key to cancel",
+        left,
+        &[&countdown.device, &left.to_string()],
+    );
+    print!("{}", style.warning(&line));
+    let _ = io::stdout().flush();
+}
+
+fn clear_line() {
+    print!("\r\x1b[2K");
+}
+
+/// The terminal with line editing, echo and signal keys off, so each key
+/// reaches [`run_watch`] as it is pressed. Restored when dropped; `\n` still
+/// starts a new line since output processing is left alone.
+struct RawMode {
+    saved: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // SAFETY: termios is plain data, filled in by tcgetattr before use
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: stdin is a terminal and `saved` is a valid termios
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: as above
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in `enable`
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
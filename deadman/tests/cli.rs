@@ -0,0 +1,270 @@
+use deadman_ipc::testing::MockDaemon;
+use std::process::{Command, Output};
+
+/// Run the CLI against a mock daemon serving the instance `name`.
+fn deadman(name: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_deadman"))
+        .args(["--instance", name])
+        .args(args)
+        .env_remove("DEADMAN_INSTANCE")
+        .output()
+        .expect("failed to run deadman")
+}
+
+fn mock(name: &str) -> MockDaemon {
+    MockDaemon::start_with_path(&format!("/tmp/deadman-ipc-{name}.sock"))
+}
+
+#[test]
+fn test_cli_status_prints_daemon_status() {
+    let name = format!("cli-status-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "status",
+        "bus 001 address 004 046d:c52b - Receiver [watching]",
+    );
+
+    let output = deadman(&name, &["status"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "BUS  ADDR  ID         STATE     NAME      SETTINGS\n\
+         001  004   046d:c52b  watching  Receiver"
+    );
+    assert_eq!(daemon.received().last().map(String::as_str), Some("status"));
+}
+
+#[test]
+fn test_cli_status_aligns_tethers() {
+    let name = format!("cli-status-aligned-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "status",
+        "bus 001 address 004 1050:0407 - YubiKey [grace] (id 1, owner alice, grace 10s)\n\
+         bus 002 address 011 046d:c52b [degraded (polling)] (id 2, owner alice, poll)\n\
+         rule 1: class:smartcard (owner alice, action lock, 1 tether(s))",
+    );
+
+    // not a terminal, so no colour even without --no-color
+    let output = deadman(&name, &["status"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "BUS  ADDR  ID         STATE               NAME     SETTINGS\n\
+         001  004   1050:0407  grace               YubiKey  id 1, owner alice, grace 10s\n\
+         002  011   046d:c52b  degraded (polling)           id 2, owner alice, poll\n\
+         rule 1: class:smartcard (owner alice, action lock, 1 tether(s))"
+    );
+}
+
+#[test]
+fn test_cli_status_verbose() {
+    let name = format!("cli-status-verbose-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "status",
+        "backend: libusb 1.0.27.11882, hotplug supported\nno active tethers",
+    );
+
+    let output = deadman(&name, &["status", "--verbose"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("backend: libusb"));
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("status verbose")
+    );
+}
+
+#[test]
+fn test_cli_protection_exit_code() {
+    let name = format!("cli-protection-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond("protection", "unarmed\nno devices are tethered");
+
+    let output = deadman(&name, &["protection"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_cli_reports_daemon_errors() {
+    let name = format!("cli-error-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.fail("cancel", "no grace period is running");
+
+    let output = deadman(&name, &["cancel"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no grace period is running"));
+}
+
+#[test]
+fn test_cli_untether_by_id() {
+    let name = format!("cli-untether-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond("untether", "untethered bus 001 address 004");
+
+    let output = deadman(&name, &["untether", "--id", "3"]);
+    assert!(output.status.success());
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("untether id=3")
+    );
+}
+
+#[test]
+fn test_cli_lists_devices_from_daemon() {
+    let name = format!("cli-list-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "list-devices",
+        concat!(
+            r#"{"bus":1,"address":4,"vendor_id":4176,"product_id":1031,"product":"YubiKey","usb_version":"2.0.0","device_version":"5.4.3","class_code":0,"sub_class_code":0,"protocol_code":0,"classes":[3,11],"max_packet_size":64,"num_configurations":1,"speed":"Full","tether_id":2,"tether_state":"watching"}"#,
+            "\n",
+            r#"{"bus":1,"address":5,"vendor_id":1133,"product_id":50475,"usb_version":"2.0.0","device_version":"12.3.0","class_code":0,"sub_class_code":0,"protocol_code":0,"classes":[3],"max_packet_size":8,"num_configurations":1,"speed":"Full"}"#
+        ),
+    );
+
+    let output = deadman(&name, &["--tethered-only"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1050:0407  hid,smartcard"));
+    assert!(stdout.contains("watching  YubiKey"));
+    assert!(!stdout.contains("046d:c52b"));
+}
+
+#[test]
+fn test_cli_describes_device() {
+    let name = format!("cli-describe-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "describe",
+        r#"{"bus":1,"address":4,"ports":[2,4],"vendor_id":4176,"product_id":1031,"product":"YubiKey","serial":"123","usb_version":"2.0.0","device_version":"5.4.3","class_code":0,"sub_class_code":0,"protocol_code":0,"classes":[3],"max_packet_size":64,"num_configurations":1,"speed":"Full","tether_id":2,"tether_state":"watching","configurations":[{"number":1,"active":true,"max_power":30,"self_powered":false,"remote_wakeup":true,"interfaces":[{"number":0,"alternate_setting":0,"class_code":3,"sub_class_code":1,"protocol_code":1,"endpoints":[{"address":129,"direction":"in","transfer_type":"interrupt","max_packet_size":8,"interval":10}]}]}]}"#,
+    );
+
+    let output = deadman(&name, &["describe", "serial:123"]);
+    assert!(output.status.success());
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("describe serial:123")
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("port             1-2.4"));
+    assert!(stdout.contains("tether           id 2 [watching]"));
+    assert!(stdout.contains("configuration 1 (active): 30 mA, bus powered, remote wakeup"));
+    assert!(stdout.contains("    endpoint 0x81: interrupt in, 8 bytes, interval 10"));
+}
+
+#[test]
+fn test_cli_severe_with_selectors() {
+    let name = format!("cli-severe-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond("severe", "cleared 1 tether(s)");
+
+    let output = deadman(
+        &name,
+        &["severe", "1050:0407", "--except", "serial:ABC", "--yes"],
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("severe 1050:0407 except serial:ABC")
+    );
+}
+
+#[test]
+fn test_cli_pam_check_denies_while_absent() {
+    let name = format!("cli-pam-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond("pam-check", "deny\nbus 001 address 004 1050:0407 - YubiKey");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_deadman"))
+        .args(["--instance", &name, "pam-check"])
+        .env_remove("DEADMAN_INSTANCE")
+        .env("PAM_SERVICE", "sudo")
+        .env("PAM_USER", "alice")
+        .output()
+        .expect("failed to run deadman");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("pam-check sudo alice")
+    );
+}
+
+#[test]
+fn test_cli_import_sends_absolute_path() {
+    let name = format!("cli-import-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond("import", "imported settings from build01: 2 change(s)");
+
+    let snapshot = std::env::temp_dir().join(format!("{name}.json"));
+    std::fs::write(&snapshot, "{}").expect("failed to write snapshot");
+    let output = Command::new(env!("CARGO_BIN_EXE_deadman"))
+        .args(["--instance", &name, "import", "--allow-unsigned"])
+        .arg(&snapshot)
+        .env_remove("DEADMAN_INSTANCE")
+        .output()
+        .expect("failed to run deadman");
+    let _ = std::fs::remove_file(&snapshot);
+
+    assert!(output.status.success());
+    let sent = format!(
+        "import {} allow-unsigned",
+        std::fs::canonicalize(std::env::temp_dir())
+            .expect("temp dir")
+            .join(format!("{name}.json"))
+            .display()
+    );
+    assert_eq!(daemon.received().last(), Some(&sent));
+}
+
+#[test]
+fn test_cli_ensure_tether_by_device() {
+    let name = format!("cli-ensure-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "ensure-tether",
+        r#"{"action":"lock","changed":false,"cooldown_secs":0,"device":"bus 001 address 004 1050:0407 - YubiKey","grace_secs":0,"id":2}"#,
+    );
+
+    let output = deadman(
+        &name,
+        &["ensure-tether", "--id", "1050:0407", "--action", "lock"],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""changed":false"#));
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("ensure-tether 1050:0407 action=lock")
+    );
+}
+
+#[test]
+fn test_cli_tether_by_class_adds_rule() {
+    let name = format!("cli-rule-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond(
+        "tether-rule",
+        "rule 1 tethers class:smartcard; tethered 1 attached device(s)",
+    );
+
+    let output = deadman(&name, &["tether", "--class", "smartcard", "--grace", "5"]);
+    assert!(output.status.success());
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("tether-rule class:smartcard grace=5")
+    );
+}
+
+#[test]
+fn test_cli_set_poll() {
+    let name = format!("cli-poll-{}", std::process::id());
+    let daemon = mock(&name);
+    daemon.respond("set", "tether settings updated for bus 001 address 004");
+
+    let output = deadman(&name, &["set", "--id", "3", "--poll"]);
+    assert!(output.status.success());
+    assert_eq!(
+        daemon.received().last().map(String::as_str),
+        Some("set id=3 poll=true")
+    );
+}
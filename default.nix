@@ -37,6 +37,8 @@ let
   };
 
   desktopIcon = ./deadman-gui/icon.svg;
+
+  searchProvider = ./deadman-gui/data/com.dominicegginton.deadman.search-provider.ini;
 in
 
 pkg.overrideAttrs (_: {
@@ -46,5 +48,15 @@ pkg.overrideAttrs (_: {
 
     cp ${desktopFile}/share/applications/dev.dominicegginton.${pkg.name}.desktop $out/share/applications/dev.dominicegginton.${pkg.name}.desktop
     cp ${desktopIcon} $out/share/icons/hicolor/scalable/apps/dev.dominicegginton.${pkg.name}.svg
+
+    mkdir -p $out/share/gnome-shell/search-providers
+    mkdir -p $out/share/dbus-1/services
+
+    cp ${searchProvider} $out/share/gnome-shell/search-providers/com.dominicegginton.deadman.search-provider.ini
+    cat > $out/share/dbus-1/services/com.dominicegginton.deadman.service <<EOF
+    [D-BUS Service]
+    Name=com.dominicegginton.deadman
+    Exec=$out/bin/deadman-gui --gapplication-service
+    EOF
   '';
 })
@@ -14,7 +14,9 @@
   config = lib.mkIf config.programs.deadman.enable {
     environment.systemPackages = [ config.programs.deadman.package ];
 
-    environment.pathsToLink = [ "/share/applications" "/share/icons" ];
+    environment.pathsToLink = [ "/share/applications" "/share/icons" "/share/gnome-shell/search-providers" ];
+
+    services.dbus.packages = [ config.programs.deadman.package ];
 
     systemd.packages = [ config.programs.deadman.package ];
 